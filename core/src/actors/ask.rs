@@ -0,0 +1,113 @@
+use crate::{messaging::UnpackError, prelude::*};
+use std::{error, fmt, marker::PhantomData, time::Duration};
+
+/// The reason a remote [ask](ActorPath::ask) did not produce a reply
+#[derive(Debug)]
+pub enum AskError {
+    /// No reply arrived within the given timeout
+    Timeout(Duration),
+    /// A reply arrived, but could not be deserialised as the expected type
+    Unpack(Box<UnpackError<NetMessage>>),
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskError::Timeout(timeout) => write!(f, "No reply received within {:?}", timeout),
+            AskError::Unpack(e) => write!(f, "Reply could not be deserialised: {:?}", e),
+        }
+    }
+}
+
+impl error::Error for AskError {}
+
+/// A short-lived actor that waits for the single reply to a remote ask
+///
+/// The actor is registered under its unique id, so its path can be used
+/// as the sender of the request. It kills itself after the first reply or
+/// when the timeout expires, whichever happens first. Once it has been
+/// deallocated, the stale registration is removed by the dispatcher's
+/// `ActorRefReaper`.
+#[derive(ComponentDefinition)]
+pub(crate) struct AskReplyActor<R, D>
+where
+    R: Send + 'static,
+    D: Deserialiser<R> + 'static,
+{
+    ctx: ComponentContext<Self>,
+    promise: Option<KPromise<Result<R, AskError>>>,
+    timeout: Duration,
+    timer: Option<ScheduledTimer>,
+    _deser: PhantomData<fn() -> D>,
+}
+
+impl<R, D> AskReplyActor<R, D>
+where
+    R: Send + 'static,
+    D: Deserialiser<R> + 'static,
+{
+    pub(crate) fn new(promise: KPromise<Result<R, AskError>>, timeout: Duration) -> Self {
+        AskReplyActor {
+            ctx: ComponentContext::uninitialised(),
+            promise: Some(promise),
+            timeout,
+            timer: None,
+            _deser: PhantomData,
+        }
+    }
+
+    fn complete(&mut self, res: Result<R, AskError>) {
+        if let Some(promise) = self.promise.take() {
+            promise.fulfil(res).unwrap_or_else(|e| {
+                debug!(
+                    self.log(),
+                    "Ask future was dropped before completion: {:?}", e
+                )
+            });
+        }
+    }
+}
+
+impl<R, D> ComponentLifecycle for AskReplyActor<R, D>
+where
+    R: Send + 'static,
+    D: Deserialiser<R> + 'static,
+{
+    fn on_start(&mut self) -> Handled {
+        let handle = self.schedule_once(self.timeout, move |this, _timeout| {
+            this.timer = None;
+            let timeout = this.timeout;
+            this.complete(Err(AskError::Timeout(timeout)));
+            Handled::DieNow
+        });
+        self.timer = Some(handle);
+        Handled::Ok
+    }
+
+    fn on_kill(&mut self) -> Handled {
+        if let Some(handle) = self.timer.take() {
+            self.cancel_timer(handle);
+        }
+        Handled::Ok
+    }
+}
+
+impl<R, D> Actor for AskReplyActor<R, D>
+where
+    R: Send + 'static,
+    D: Deserialiser<R> + 'static,
+{
+    type Message = Never;
+
+    fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+        unreachable!("Never type is empty")
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let res = msg
+            .try_deserialise::<R, D>()
+            .map_err(|e| AskError::Unpack(Box::new(e)));
+        self.complete(res);
+        Handled::DieNow
+    }
+}
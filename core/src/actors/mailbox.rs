@@ -0,0 +1,357 @@
+use hocon::Hocon;
+use std::{error, fmt, str::FromStr};
+
+/// What happens to a message sent via `tell` to an actor whose mailbox is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverflowPolicy {
+    /// Drop the message that was just sent
+    DropNewest,
+    /// Drop the oldest message in the mailbox to make space for the new one
+    DropOldest,
+    /// Reject the message that was just sent and hand it to the deadletter box
+    ///
+    /// Network messages are forwarded to the system's [DeadletterBox](crate::prelude::DeadletterBox).
+    /// Since the deadletter box can only accept network messages,
+    /// rejected local messages are logged as dead letters on the system logger instead.
+    RejectToDeadletter,
+}
+
+impl OverflowPolicy {
+    pub(crate) fn as_u8(self) -> u8 {
+        match self {
+            OverflowPolicy::DropNewest => 0,
+            OverflowPolicy::DropOldest => 1,
+            OverflowPolicy::RejectToDeadletter => 2,
+        }
+    }
+
+    pub(crate) fn from_u8(value: u8) -> Self {
+        match value {
+            0 => OverflowPolicy::DropNewest,
+            1 => OverflowPolicy::DropOldest,
+            2 => OverflowPolicy::RejectToDeadletter,
+            x => unreachable!("Invalid overflow policy: {}", x),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-newest" => Ok(OverflowPolicy::DropNewest),
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "reject-to-deadletter" => Ok(OverflowPolicy::RejectToDeadletter),
+            _ => Err(format!("Unknown overflow policy: {}", s)),
+        }
+    }
+}
+
+/// The priority of a message in a [prioritised](MailboxConfig::prioritised) mailbox
+///
+/// Messages with a higher priority are handled before messages with a lower priority,
+/// but every priority level is guaranteed a share of the processing
+/// according to its [weight](MailboxConfig::with_priority_weights), so no level starves.
+/// Messages with the same priority are handled in the order they were sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MessagePriority {
+    /// Messages that should be handled as soon as possible, such as control messages
+    High = 0,
+    /// The priority of all messages that don't specify one
+    #[default]
+    Normal = 1,
+    /// Messages that may be deferred in favour of others, such as bulk data
+    Low = 2,
+}
+
+impl MessagePriority {
+    /// All priority levels from highest to lowest
+    pub const ALL: [MessagePriority; 3] = [
+        MessagePriority::High,
+        MessagePriority::Normal,
+        MessagePriority::Low,
+    ];
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A message type that determines its own [priority](MessagePriority)
+///
+/// Implement this for an actor's `Message` type and call
+/// [prioritise_messages](crate::prelude::ComponentContext::prioritise_messages)
+/// to have every message sent via `tell` placed according to its priority.
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+///
+/// #[derive(Debug)]
+/// enum Command {
+///     Cancel,
+///     Work(u64),
+/// }
+///
+/// impl Prioritised for Command {
+///     fn priority(&self) -> MessagePriority {
+///         match self {
+///             Command::Cancel => MessagePriority::High,
+///             Command::Work(_) => MessagePriority::Normal,
+///         }
+///     }
+/// }
+/// ```
+pub trait Prioritised {
+    /// Returns the priority this message should be handled with
+    fn priority(&self) -> MessagePriority;
+}
+
+/// The capacity and overflow behaviour of an actor's mailbox
+///
+/// Mailboxes are unbounded by default.
+/// A bounded mailbox holds at most `capacity` messages,
+/// and applies its [OverflowPolicy](OverflowPolicy) to messages sent via `tell` once full.
+/// Messages sent via `try_tell` are instead handed back to the sender in an error,
+/// which allows the sender to apply backpressure.
+///
+/// Only messages sent to the actor are subject to the capacity, port events are not.
+///
+/// A mailbox can additionally be [prioritised](MailboxConfig::prioritised),
+/// in which case messages are handled according to their [MessagePriority](MessagePriority)
+/// instead of strictly in the order they were sent.
+///
+/// A default for all components can be set in the `mailbox` section of the system's config,
+/// and individual components can override it via
+/// [set_mailbox](crate::prelude::ComponentContext::set_mailbox).
+///
+/// ```hocon
+/// mailbox {
+///     capacity = 1000
+///     overflow_policy = "drop-oldest"
+///     priority_weights {
+///         high = 4
+///         normal = 2
+///         low = 1
+///     }
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MailboxConfig {
+    capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    priority_weights: Option<[usize; 3]>,
+}
+
+impl MailboxConfig {
+    /// A mailbox without a capacity limit
+    pub fn unbounded() -> Self {
+        MailboxConfig {
+            capacity: None,
+            overflow_policy: OverflowPolicy::DropNewest,
+            priority_weights: None,
+        }
+    }
+
+    /// A mailbox holding at most `capacity` messages, applying `overflow_policy` when full
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn bounded(capacity: usize, overflow_policy: OverflowPolicy) -> Self {
+        assert!(
+            capacity > 0,
+            "A mailbox must have space for at least one message"
+        );
+        MailboxConfig {
+            capacity: Some(capacity),
+            overflow_policy,
+            priority_weights: None,
+        }
+    }
+
+    /// Handle messages according to their [MessagePriority](MessagePriority)
+    /// using the [default weights](MailboxConfig::DEFAULT_PRIORITY_WEIGHTS)
+    pub fn prioritised(self) -> Self {
+        let [high, normal, low] = MailboxConfig::DEFAULT_PRIORITY_WEIGHTS;
+        self.with_priority_weights(high, normal, low)
+    }
+
+    /// Handle messages according to their [MessagePriority](MessagePriority)
+    /// using the given weights
+    ///
+    /// While messages of several priorities are waiting,
+    /// the mailbox hands out up to `high` high priority messages,
+    /// then up to `normal` normal priority messages,
+    /// and then up to `low` low priority messages, before starting over.
+    /// Levels without waiting messages are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any weight is 0, as that level could starve.
+    pub fn with_priority_weights(mut self, high: usize, normal: usize, low: usize) -> Self {
+        assert!(
+            high > 0 && normal > 0 && low > 0,
+            "Priority weights must be positive"
+        );
+        self.priority_weights = Some([high, normal, low]);
+        self
+    }
+
+    /// The weights used by [prioritised](MailboxConfig::prioritised) mailboxes,
+    /// in order high, normal, low
+    pub const DEFAULT_PRIORITY_WEIGHTS: [usize; 3] = [4, 2, 1];
+
+    /// Reads the mailbox settings from the `mailbox` section of `config`
+    ///
+    /// Returns an [unbounded](MailboxConfig::unbounded) mailbox if no capacity is set.
+    /// The overflow policy defaults to `"drop-newest"`.
+    /// The mailbox is only prioritised if a `priority_weights` section is present,
+    /// where missing weights take their [default](MailboxConfig::DEFAULT_PRIORITY_WEIGHTS) values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity, the overflow policy, or the priority weights are invalid.
+    pub fn from_config(config: &Hocon) -> Self {
+        let section = &config["mailbox"];
+        let mailbox = match section["capacity"].as_i64() {
+            Some(capacity) => {
+                assert!(capacity > 0, "Invalid mailbox capacity: {}", capacity);
+                let overflow_policy = match section["overflow_policy"].as_string() {
+                    Some(policy) => {
+                        OverflowPolicy::from_str(&policy).unwrap_or_else(|e| panic!("{}", e))
+                    }
+                    None => OverflowPolicy::DropNewest,
+                };
+                MailboxConfig::bounded(capacity as usize, overflow_policy)
+            }
+            None => MailboxConfig::unbounded(),
+        };
+        match section["priority_weights"] {
+            Hocon::BadValue(_) => mailbox,
+            ref weights => {
+                let [high, normal, low] = MailboxConfig::DEFAULT_PRIORITY_WEIGHTS;
+                let weight = |key: &str, default: usize| match weights[key].as_i64() {
+                    Some(weight) => {
+                        assert!(weight > 0, "Invalid {} priority weight: {}", key, weight);
+                        weight as usize
+                    }
+                    None => default,
+                };
+                mailbox.with_priority_weights(
+                    weight("high", high),
+                    weight("normal", normal),
+                    weight("low", low),
+                )
+            }
+        }
+    }
+
+    /// Returns the maximum number of messages in the mailbox, or `None` if unbounded
+    pub fn get_capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Returns what happens to messages sent via `tell` to a full mailbox
+    pub fn get_overflow_policy(&self) -> OverflowPolicy {
+        self.overflow_policy
+    }
+
+    /// Returns whether messages are handled according to their priority
+    pub fn is_prioritised(&self) -> bool {
+        self.priority_weights.is_some()
+    }
+
+    /// Returns the weight of `priority`, or `None` if the mailbox is not prioritised
+    pub fn get_priority_weight(&self, priority: MessagePriority) -> Option<usize> {
+        self.priority_weights
+            .map(|weights| weights[priority.index()])
+    }
+}
+
+impl Default for MailboxConfig {
+    fn default() -> Self {
+        MailboxConfig::unbounded()
+    }
+}
+
+/// The reason a `try_tell` did not deliver its message
+///
+/// The message is handed back in either case.
+#[derive(Debug)]
+pub enum TryTellError<M> {
+    /// The target actor's mailbox is at capacity
+    Full(M),
+    /// The target actor has been deallocated
+    Unavailable(M),
+}
+
+impl<M> TryTellError<M> {
+    /// Returns the message that could not be delivered
+    pub fn into_inner(self) -> M {
+        match self {
+            TryTellError::Full(msg) => msg,
+            TryTellError::Unavailable(msg) => msg,
+        }
+    }
+}
+
+impl<M> fmt::Display for TryTellError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryTellError::Full(_) => write!(f, "The target's mailbox is full"),
+            TryTellError::Unavailable(_) => write!(f, "The target is no longer available"),
+        }
+    }
+}
+
+impl<M: fmt::Debug> error::Error for TryTellError<M> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hocon::HoconLoader;
+
+    #[test]
+    fn mailbox_config_from_hocon() {
+        let config = HoconLoader::new()
+            .load_str(
+                r#"mailbox {
+                    capacity = 10
+                    overflow_policy = "reject-to-deadletter"
+                }"#,
+            )
+            .expect("config")
+            .hocon()
+            .expect("hocon");
+        assert_eq!(
+            MailboxConfig::bounded(10, OverflowPolicy::RejectToDeadletter),
+            MailboxConfig::from_config(&config)
+        );
+
+        let prioritised = HoconLoader::new()
+            .load_str(
+                r#"mailbox {
+                    priority_weights {
+                        high = 8
+                    }
+                }"#,
+            )
+            .expect("config")
+            .hocon()
+            .expect("hocon");
+        let config = MailboxConfig::from_config(&prioritised);
+        assert_eq!(None, config.get_capacity());
+        assert_eq!(Some(8), config.get_priority_weight(MessagePriority::High));
+        assert_eq!(Some(2), config.get_priority_weight(MessagePriority::Normal));
+        assert_eq!(Some(1), config.get_priority_weight(MessagePriority::Low));
+
+        let empty = HoconLoader::new().hocon().expect("hocon");
+        assert_eq!(
+            MailboxConfig::unbounded(),
+            MailboxConfig::from_config(&empty)
+        );
+    }
+}
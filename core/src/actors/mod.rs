@@ -8,8 +8,13 @@ use std::{
     sync::{Arc, Weak},
 };
 
+mod ask;
+mod mailbox;
 mod paths;
 mod refs;
+pub use ask::AskError;
+pub(crate) use ask::AskReplyActor;
+pub use mailbox::*;
 pub use paths::*;
 pub use refs::*;
 
@@ -161,7 +166,7 @@ where
     fn receive(&mut self, env: MsgEnvelope<M>) -> Handled {
         match env {
             MsgEnvelope::Typed(m) => self.receive_local(m),
-            MsgEnvelope::Net(nm) => self.receive_network(nm),
+            MsgEnvelope::Net(nm) => self.receive_network(*nm),
         }
     }
 }
@@ -1,7 +1,8 @@
 use super::*;
 use crate::{
-    messaging::{DispatchData, DispatchEnvelope, MsgEnvelope},
+    messaging::{DispatchData, DispatchEnvelope, MsgEnvelope, ReliableEnvelope},
     net::buffers::ChunkRef,
+    tracing,
 };
 use std::{
     convert::TryFrom,
@@ -10,6 +11,7 @@ use std::{
     net::{AddrParseError, IpAddr, SocketAddr},
     ops::Div,
     str::FromStr,
+    time::Duration,
 };
 use uuid::Uuid;
 
@@ -29,6 +31,8 @@ pub enum Transport {
     TCP = 0b01,
     /// Send messages as UDP datagrams
     UDP = 0b10,
+    /// Send messages over TLS-encrypted TCP
+    TLS = 0b11,
 }
 
 impl Transport {
@@ -49,6 +53,7 @@ impl fmt::Display for Transport {
             &Transport::LOCAL => write!(fmt, "local"),
             &Transport::TCP => write!(fmt, "tcp"),
             &Transport::UDP => write!(fmt, "udp"),
+            &Transport::TLS => write!(fmt, "tls"),
         }
     }
 }
@@ -61,6 +66,7 @@ impl FromStr for Transport {
             "local" => Ok(Transport::LOCAL),
             "tcp" => Ok(Transport::TCP),
             "udp" => Ok(Transport::UDP),
+            "tls" => Ok(Transport::TLS),
             _ => Err(TransportParseError),
         }
     }
@@ -78,7 +84,7 @@ impl fmt::Display for TransportParseError {
 
 impl Error for TransportParseError {
     fn description(&self) -> &str {
-        "Transport must be one of [local,tcp,udp]"
+        "Transport must be one of [local,tcp,udp,tls]"
     }
 }
 
@@ -135,14 +141,64 @@ impl From<AddrParseError> for PathParseError {
     }
 }
 
+/// The network address part of a [SystemPath](SystemPath)
+///
+/// An address is either a concrete IP address, or a domain name that must be
+/// resolved by the dispatcher before any messages can be sent to it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Address {
+    /// An IPv4 or IPv6 address
+    Ip(IpAddr),
+    /// A domain name, such as `"worker-3.svc"`
+    Domain(String),
+}
+
+impl Address {
+    /// Returns the IP address, if this is an instance of [Address::Ip](Address::Ip)
+    pub fn ip(&self) -> Option<&IpAddr> {
+        match self {
+            Address::Ip(ip) => Some(ip),
+            Address::Domain(_) => None,
+        }
+    }
+
+    /// Returns the domain name, if this is an instance of [Address::Domain](Address::Domain)
+    pub fn domain(&self) -> Option<&str> {
+        match self {
+            Address::Ip(_) => None,
+            Address::Domain(domain) => Some(domain),
+        }
+    }
+
+    /// Returns `true` if this address must be resolved before it can be used
+    pub fn is_domain(&self) -> bool {
+        matches!(self, Address::Domain(_))
+    }
+}
+
+impl From<IpAddr> for Address {
+    fn from(ip: IpAddr) -> Self {
+        Address::Ip(ip)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Address::Ip(ip) => write!(fmt, "{}", ip),
+            Address::Domain(domain) => write!(fmt, "{}", domain),
+        }
+    }
+}
+
 /// The part of an [ActorPath](ActorPath) that refers to the [KompactSystem](KompactSystem)
 ///
-/// As a URI, a `SystemPath` looks like `"tcp://127.0.0.1:8080"`, for example.
+/// As a URI, a `SystemPath` looks like `"tcp://127.0.0.1:8080"`
+/// or `"tcp://worker-3.svc:8080"`, for example.
 #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct SystemPath {
     protocol: Transport,
-    // TODO address could be IPv4, IPv6, or a domain name (not supported yet)
-    address: IpAddr,
+    address: Address,
     port: u16,
 }
 
@@ -151,7 +207,7 @@ impl SystemPath {
     pub fn new(protocol: Transport, address: IpAddr, port: u16) -> SystemPath {
         SystemPath {
             protocol,
-            address,
+            address: Address::Ip(address),
             port,
         }
     }
@@ -160,26 +216,69 @@ impl SystemPath {
     pub fn with_socket(protocol: Transport, socket: SocketAddr) -> SystemPath {
         SystemPath {
             protocol,
-            address: socket.ip(),
+            address: Address::Ip(socket.ip()),
             port: socket.port(),
         }
     }
 
+    /// Construct a new system path from individual parts using a domain name
+    ///
+    /// The domain name will be resolved by the dispatcher when messages are sent to it.
+    pub fn with_domain<S>(protocol: Transport, domain: S, port: u16) -> SystemPath
+    where
+        S: Into<String>,
+    {
+        let domain = domain.into();
+        debug_assert!(
+            validate_domain(&domain).is_ok(),
+            "Domain is not a valid host name: {}",
+            domain
+        );
+        SystemPath {
+            protocol,
+            address: Address::Domain(domain),
+            port,
+        }
+    }
+
+    /// Construct a new system path from individual parts using an [Address](Address)
+    pub fn with_address(protocol: Transport, address: Address, port: u16) -> SystemPath {
+        SystemPath {
+            protocol,
+            address,
+            port,
+        }
+    }
+
     /// Returns a reference to the [Transport](Transport) protocol associated with with this system path
     pub fn protocol(&self) -> Transport {
         self.protocol
     }
 
-    /// Returns a reference to the IP address associated with with this system path
-    pub fn address(&self) -> &IpAddr {
+    /// Returns a reference to the address associated with with this system path
+    pub fn address(&self) -> &Address {
         &self.address
     }
 
+    /// Returns a reference to the IP address associated with with this system path
+    ///
+    /// Returns `None` if the address is a domain name.
+    pub fn ip_address(&self) -> Option<&IpAddr> {
+        self.address.ip()
+    }
+
     /// Returns the port associated with with this system path
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// Returns the socket address of this system path, if its address is an IP address
+    ///
+    /// Returns `None` if the address is a domain name that must be resolved first.
+    pub fn socket_address(&self) -> Option<SocketAddr> {
+        self.address.ip().map(|ip| SocketAddr::new(*ip, self.port))
+    }
+
     /// Create a named path starting with this system path and ending with the given string
     ///
     /// Paths created with this function will be validated to be a valid lookup path,
@@ -211,6 +310,20 @@ impl fmt::Display for SystemPath {
     }
 }
 
+impl FromStr for SystemPath {
+    type Err = PathParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split("://").collect();
+        // parts: [tcp]://[address:port]
+        if parts.len() != 2 {
+            return Err(PathParseError::Form(s.to_string()));
+        }
+        let proto: Transport = parts[0].parse()?;
+        parse_system_address(proto, parts[1])
+    }
+}
+
 /// Methods for things that contain a [SystemPath](SystemPath)
 pub trait SystemField {
     /// Returns a reference to the system path
@@ -222,10 +335,15 @@ pub trait SystemField {
     }
 
     /// Returns the address used in the system path
-    fn address(&self) -> &IpAddr {
+    fn address(&self) -> &Address {
         &self.system().address()
     }
 
+    /// Returns the IP address used in the system path, if it is not a domain name
+    fn ip_address(&self) -> Option<&IpAddr> {
+        self.system().ip_address()
+    }
+
     /// Returns the port used in the system path
     fn port(&self) -> u16 {
         self.system().port()
@@ -355,10 +473,113 @@ impl ActorPath {
             src: from,
             dst,
             msg: DispatchData::Lazy(msg),
+            trace_context: tracing::current_context(),
         };
         dispatch.dispatcher_ref().enqueue(MsgEnvelope::Typed(env))
     }
 
+    /// Send message `m` reliably to the actor designated by this path
+    ///
+    /// The returned future completes once the dispatcher of the destination system
+    /// acknowledged that it handed `m` to the actor at this path.
+    /// Until then, the local dispatcher keeps `m` and sends it again whenever a connection
+    /// to the destination is established, and periodically while it is up
+    /// (see [set_redelivery_interval](crate::prelude::NetworkConfig::set_redelivery_interval)).
+    /// The destination dispatcher drops duplicates, so that the actor receives all reliable
+    /// messages from the same sender exactly once and in the order they were sent,
+    /// as long as neither system restarts.
+    ///
+    /// If `m` is not acknowledged within the
+    /// [reliable timeout](crate::prelude::NetworkConfig::set_reliable_timeout),
+    /// the dispatcher gives up on it, together with all later messages from the same sender
+    /// to the same destination, and the returned future fails with `PromiseDropped`.
+    /// Unlike [tell](ActorPath::tell), `m` is always serialised eagerly, which fails if
+    /// it doesn't give a [size hint](Serialisable::size_hint).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kompact::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// # let system = KompactConfig::default().build().expect("system");
+    /// let path: ActorPath = "tcp://127.0.0.1:8080/ledger".parse().expect("path");
+    /// path.tell_reliable(42u64, &system)
+    ///     .expect("serialise")
+    ///     .wait_timeout(Duration::from_secs(10))
+    ///     .expect("acknowledged");
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn tell_reliable<S, B>(&self, m: B, from: &S) -> Result<KFuture<()>, SerError>
+    where
+        S: ActorPathFactory + Dispatching,
+        B: Serialisable,
+    {
+        let msg = crate::serialisation::ser_helpers::serialise_to_serialised(&m)?;
+        let mut src = from.actor_path();
+        src.set_protocol(self.protocol());
+        let (promise, future) = promise();
+        let env = DispatchEnvelope::Reliable(ReliableEnvelope {
+            src,
+            dst: self.clone(),
+            msg,
+            promise,
+        });
+        from.dispatcher_ref().enqueue(MsgEnvelope::Typed(env));
+        Ok(future)
+    }
+
+    /// Send `request` to the actor designated by this path and await its reply
+    ///
+    /// A temporary reply actor is created and registered with the dispatcher
+    /// under a unique path, which is supplied as the sender of `request`.
+    /// The first message sent back to that path is deserialised as `R` using `D`
+    /// and completes the returned future. If no reply arrives within `timeout`,
+    /// the future completes with [AskError::Timeout](AskError::Timeout) instead.
+    ///
+    /// Either way the reply actor is killed afterwards and its stale registration
+    /// is eventually removed by the dispatcher's reaper.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use kompact::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// # let system = KompactConfig::default().build().expect("system");
+    /// let path: ActorPath = "tcp://127.0.0.1:8080/ponger".parse().expect("path");
+    /// let reply = path
+    ///     .ask::<u64, u64, _>(42u64, &system, Duration::from_millis(500))
+    ///     .wait()
+    ///     .expect("reply");
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn ask<R, D, S>(
+        &self,
+        request: impl Into<Box<dyn Serialisable>>,
+        system: &S,
+        timeout: Duration,
+    ) -> KFuture<Result<R, AskError>>
+    where
+        R: Send + 'static,
+        D: Deserialiser<R> + 'static,
+        S: SystemHandle,
+    {
+        let (promise, future) = promise();
+        let reply_actor = system.create(move || AskReplyActor::<R, D>::new(promise, timeout));
+        // The dispatcher handles the registration before it sees the request,
+        // so the reply path is always known by the time a response can arrive.
+        let _registration = system.register(&reply_actor);
+        let mut reply_path = ActorPath::Unique(UniquePath::with_system(
+            system.system_path(),
+            reply_actor.id(),
+        ));
+        reply_path.set_protocol(self.protocol());
+        system.start(&reply_actor);
+        self.tell_with_sender(request, system, reply_path);
+        future
+    }
+
     /// Send message `m` to the actor designated by this path
     ///
     /// This function has the same effect as [tell](ActorPath::tell),
@@ -394,12 +615,19 @@ impl ActorPath {
         } else {
             dispatch.ctx().with_buffer(|buffer| {
                 let mut buf = buffer.get_buffer_encoder();
-                let msg =
-                    crate::serialisation::ser_helpers::serialise_msg(&from, &self, &m, &mut buf)?;
+                let trace_context = tracing::current_context();
+                let msg = crate::serialisation::ser_helpers::serialise_msg_with_trace(
+                    &from,
+                    &self,
+                    trace_context.as_ref(),
+                    &m,
+                    &mut buf,
+                )?;
                 let env = DispatchEnvelope::Msg {
                     src: from,
                     dst: self.clone(),
                     msg: DispatchData::SerialisedLease(msg),
+                    trace_context,
                 };
                 dispatch.dispatcher_ref().enqueue(MsgEnvelope::Typed(env));
                 Ok(())
@@ -437,13 +665,20 @@ impl ActorPath {
     ) -> Result<(), SerError> {
         dispatch.ctx().with_buffer(|buffer| {
             let mut buf = buffer.get_buffer_encoder();
-            let msg = crate::serialisation::ser_helpers::serialise_msg_with_preserialised(
-                &from, &self, content, &mut buf,
-            )?;
+            let trace_context = tracing::current_context();
+            let msg =
+                crate::serialisation::ser_helpers::serialise_msg_with_preserialised_with_trace(
+                    &from,
+                    &self,
+                    trace_context.as_ref(),
+                    content,
+                    &mut buf,
+                )?;
             let env = DispatchEnvelope::Msg {
                 src: from,
                 dst: self.clone(),
                 msg: DispatchData::SerialisedRef(msg),
+                trace_context,
             };
             dispatch.dispatcher_ref().enqueue(MsgEnvelope::Typed(env));
             Ok(())
@@ -454,6 +689,9 @@ impl ActorPath {
     ///
     /// This can be used for routing protocls where the final recipient is supposed to reply
     /// to the original sender, not the intermediaries.
+    ///
+    /// If there is a [current trace context](crate::tracing::current_context),
+    /// the forwarded message continues from there instead of its original context.
     pub fn forward_with_original_sender<D>(
         &self,
         mut serialised_message: NetMessage,
@@ -463,6 +701,9 @@ impl ActorPath {
         D: Dispatching,
     {
         serialised_message.receiver = self.clone();
+        if let Some(context) = tracing::current_context() {
+            serialised_message.trace_context = Some(Box::new(context));
+        }
         let env = DispatchEnvelope::ForwardedMsg {
             msg: serialised_message,
         };
@@ -470,6 +711,8 @@ impl ActorPath {
     }
 
     /// Forwards the still serialised message to this path replacing the sender with the given one
+    ///
+    /// Trace contexts are handled as in [forward_with_original_sender](ActorPath::forward_with_original_sender).
     pub fn forward_with_sender<D>(
         &self,
         mut serialised_message: NetMessage,
@@ -481,6 +724,9 @@ impl ActorPath {
     {
         serialised_message.receiver = self.clone();
         serialised_message.sender = from;
+        if let Some(context) = tracing::current_context() {
+            serialised_message.trace_context = Some(Box::new(context));
+        }
         let env = DispatchEnvelope::ForwardedMsg {
             msg: serialised_message,
         };
@@ -724,21 +970,21 @@ impl FromStr for UniquePath {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parts: Vec<&str> = s.split("://").collect();
-        // parts: [tcp]://[IP:port#id]
+        // parts: [tcp]://[address:port#id]
         if parts.len() != 2 {
             return Err(PathParseError::Form(s.to_string()));
         }
         let proto: Transport = parts[0].parse()?;
         let parts: Vec<&str> = parts[1].split(UNIQUE_PATH_SEP).collect();
-        // parts: [IP:port]#[UUID]
+        // parts: [address:port]#[UUID]
         if parts.len() != 2 {
             return Err(PathParseError::Form(s.to_string()));
         }
-        let socket = SocketAddr::from_str(parts[0])?;
+        let system = parse_system_address(proto, parts[0])?;
         let uuid =
             Uuid::from_str(parts[1]).map_err(|_parse_err| PathParseError::Form(s.to_string()))?;
 
-        Ok(UniquePath::with_socket(proto, socket, uuid))
+        Ok(UniquePath::with_system(system, uuid))
     }
 }
 
@@ -878,14 +1124,14 @@ impl FromStr for NamedPath {
         if s2.is_empty() {
             return Err(PathParseError::Form(s.to_string()));
         }
-        let socket = SocketAddr::from_str(s2[0])?;
+        let system = parse_system_address(proto, s2[0])?;
         let path: Vec<String> = if s2.len() > 1 {
             s2.split_off(1).into_iter().map(|v| v.to_string()).collect()
         } else {
             Vec::default()
         };
         validate_lookup_path(&path)?;
-        Ok(NamedPath::with_socket(proto, socket, path))
+        Ok(NamedPath::with_system(system, path))
     }
 }
 
@@ -913,6 +1159,65 @@ impl Div<char> for NamedPath {
     }
 }
 
+/// Parse the `address:port` part of a path, where `address` is either an IP address or a domain name
+fn parse_system_address(proto: Transport, s: &str) -> Result<SystemPath, PathParseError> {
+    match SocketAddr::from_str(s) {
+        Ok(socket) => Ok(SystemPath::with_socket(proto, socket)),
+        Err(addr_err) => {
+            // Not an IP address, so try `domain:port` instead
+            let (domain, port) = match s.rfind(':') {
+                Some(index) => (&s[..index], &s[index + 1..]),
+                None => return Err(PathParseError::Addr(addr_err)),
+            };
+            if validate_domain(domain).is_err() {
+                return Err(PathParseError::Addr(addr_err));
+            }
+            let port =
+                u16::from_str(port).map_err(|_parse_err| PathParseError::Form(s.to_string()))?;
+            Ok(SystemPath::with_domain(proto, domain, port))
+        }
+    }
+}
+
+/// Check that the given domain is a valid host name
+///
+/// Host names consist of non-empty labels separated by `.`,
+/// which contain only ASCII letters, digits, and `-` (but not at the start or the end).
+/// To avoid confusion with malformed IPv4 addresses,
+/// a host name must contain at least one character that is not a digit or a `.`.
+pub fn validate_domain(domain: &str) -> Result<(), PathParseError> {
+    if domain.is_empty() || domain.len() > 253 {
+        return Err(PathParseError::Form(format!(
+            "Invalid domain length: {}",
+            domain.len()
+        )));
+    }
+    for label in domain.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(PathParseError::Form(format!(
+                "Invalid domain label in: {}",
+                domain
+            )));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(PathParseError::IllegalCharacter('-'));
+        }
+        if let Some(c) = label
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '-'))
+        {
+            return Err(PathParseError::IllegalCharacter(c));
+        }
+    }
+    if domain.chars().all(|c| c.is_ascii_digit() || c == '.') {
+        return Err(PathParseError::Form(format!(
+            "Domain looks like an IP address: {}",
+            domain
+        )));
+    }
+    Ok(())
+}
+
 /// Split the `&str` into segments using [PATH_SEP](crate::constants::PATH_SEP)
 pub fn parse_path(s: &str) -> Vec<String> {
     s.split(PATH_SEP)
@@ -1010,6 +1315,14 @@ mod tests {
         assert_eq!(ap, ap2);
     }
 
+    #[test]
+    fn actor_path_tls_strings() {
+        let path = "tls://127.0.0.1:8080/test_actor";
+        let ap = ActorPath::from_str(path).expect("a proper path");
+        assert_eq!(ap.system().protocol(), Transport::TLS);
+        assert_eq!(path, &ap.to_string());
+    }
+
     #[test]
     fn actor_path_unique_strings() {
         let ref1 = ActorPath::Unique(UniquePath::new(
@@ -1039,4 +1352,32 @@ mod tests {
         assert_eq!(ref1, ref1_deser);
         assert_eq!(ref1, ref1_deser2);
     }
+
+    #[test]
+    fn actor_path_domain_strings() {
+        let path = "tcp://worker-3.svc:8080/foo";
+        let ap = ActorPath::from_str(path).expect("a proper path");
+        assert_eq!(ap.address(), &Address::Domain("worker-3.svc".to_string()));
+        assert_eq!(ap.ip_address(), None);
+        assert_eq!(ap.port(), 8080);
+        assert_eq!(ap.system().socket_address(), None);
+        assert_eq!(path, &ap.to_string());
+
+        let ref1 = ActorPath::Unique(UniquePath::with_system(
+            SystemPath::with_domain(Transport::TCP, "localhost", 8080),
+            Uuid::new_v4(),
+        ));
+        let ref1_deser: ActorPath = ref1.to_string().parse().expect("a proper path");
+        assert_eq!(ref1, ref1_deser);
+    }
+
+    #[test]
+    fn actor_path_invalid_domains() {
+        assert!(ActorPath::from_str("tcp://worker_3.svc:8080/foo").is_err());
+        assert!(ActorPath::from_str("tcp://-worker.svc:8080/foo").is_err());
+        assert!(ActorPath::from_str("tcp://worker..svc:8080/foo").is_err());
+        assert!(ActorPath::from_str("tcp://worker.svc/foo").is_err());
+        assert!(ActorPath::from_str("tcp://worker.svc:http/foo").is_err());
+        assert!(ActorPath::from_str("tcp://127.0.0:8080/foo").is_err());
+    }
 }
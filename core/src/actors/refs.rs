@@ -1,24 +1,376 @@
 use super::*;
 
-use std::{fmt, ops::Deref};
+use crate::tracing::{self, TraceContext};
+use arc_swap::ArcSwapOption;
+use std::{
+    fmt,
+    ops::Deref,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+};
 use uuid::Uuid;
 
 /// The type of actor references for [dispatcher](Dispatcher) implementations
 pub type DispatcherRef = ActorRefStrong<DispatchEnvelope>;
 
+type PriorityFunction<M> = fn(&M) -> MessagePriority;
+
+/// A message in the mailbox, together with the trace context it was sent in
+pub(crate) type TracedEnvelope<M> = (MsgEnvelope<M>, Option<TraceContext>);
+
+type Lane<M> = Arc<ConcurrentQueue<TracedEnvelope<M>>>;
+
+/// The additional lanes and settings of a mailbox that has been prioritised at some point
+///
+/// The normal priority lane is the queue's main lane.
+/// Reconfiguring the mailbox replaces these settings, but keeps the lanes.
+#[derive(Debug)]
+struct Priorities<M: MessageBounds> {
+    high: Lane<M>,
+    low: Lane<M>,
+    // `None` if the mailbox is not prioritised anymore, but the lanes may still hold messages
+    weights: Option<[usize; 3]>,
+    function: Option<PriorityFunction<M>>,
+    // remaining messages per lane in the current round, only touched by the consumer
+    credits: [AtomicUsize; 3],
+}
+impl<M: MessageBounds> Priorities<M> {
+    fn new(
+        current: Option<&Priorities<M>>,
+        weights: Option<[usize; 3]>,
+        function: Option<PriorityFunction<M>>,
+    ) -> Self {
+        let (high, low) = match current {
+            Some(current) => (current.high.clone(), current.low.clone()),
+            None => (
+                Arc::new(ConcurrentQueue::new()),
+                Arc::new(ConcurrentQueue::new()),
+            ),
+        };
+        Priorities {
+            high,
+            low,
+            weights,
+            function,
+            credits: Default::default(),
+        }
+    }
+
+    fn priority_of(
+        &self,
+        value: &MsgEnvelope<M>,
+        priority: Option<MessagePriority>,
+    ) -> MessagePriority {
+        if self.weights.is_none() {
+            return MessagePriority::Normal;
+        }
+        match (priority, value) {
+            (Some(priority), _) => priority,
+            (None, MsgEnvelope::Typed(msg)) => self.function.map(|f| f(msg)).unwrap_or_default(),
+            (None, MsgEnvelope::Net(_)) => MessagePriority::Normal,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct TypedMsgQueue<M: MessageBounds> {
-    inner: ConcurrentQueue<MsgEnvelope<M>>,
+    // the only lane, unless the mailbox is prioritised
+    queue: ConcurrentQueue<TracedEnvelope<M>>,
+    len: AtomicUsize,
+    // 0 means unbounded
+    capacity: AtomicUsize,
+    overflow_policy: AtomicU8,
+    // set once `priorities` has been created, so unprioritised queues can skip it
+    has_priorities: AtomicBool,
+    priorities: ArcSwapOption<Priorities<M>>,
 }
 impl<M: MessageBounds> TypedMsgQueue<M> {
     pub(crate) fn new() -> TypedMsgQueue<M> {
-        TypedMsgQueue {
-            inner: ConcurrentQueue::new(),
+        TypedMsgQueue::with_config(&MailboxConfig::unbounded())
+    }
+
+    pub(crate) fn with_config(config: &MailboxConfig) -> TypedMsgQueue<M> {
+        let queue = TypedMsgQueue {
+            queue: ConcurrentQueue::new(),
+            len: AtomicUsize::new(0),
+            capacity: AtomicUsize::new(0),
+            overflow_policy: AtomicU8::new(0),
+            has_priorities: AtomicBool::new(false),
+            priorities: ArcSwapOption::empty(),
+        };
+        queue.set_config(config);
+        queue
+    }
+
+    pub(crate) fn set_config(&self, config: &MailboxConfig) {
+        self.overflow_policy
+            .store(config.get_overflow_policy().as_u8(), Ordering::SeqCst);
+        self.capacity
+            .store(config.get_capacity().unwrap_or(0), Ordering::SeqCst);
+        let weights = if config.is_prioritised() {
+            let mut weights = [0; 3];
+            for priority in MessagePriority::ALL.iter() {
+                weights[priority.index()] = config
+                    .get_priority_weight(*priority)
+                    .expect("Prioritised mailboxes have weights");
+            }
+            Some(weights)
+        } else {
+            None
+        };
+        if weights.is_some() || self.has_priorities.load(Ordering::Acquire) {
+            self.update_priorities(|current| {
+                Priorities::new(current, weights, current.and_then(|p| p.function))
+            });
+        }
+    }
+
+    /// Derive the priority of typed messages without an explicit priority via `f`
+    ///
+    /// Enables the default priority weights, if the queue is not prioritised already.
+    pub(crate) fn set_priority_function(&self, f: PriorityFunction<M>) {
+        self.update_priorities(|current| {
+            let weights = current
+                .and_then(|p| p.weights)
+                .unwrap_or(MailboxConfig::DEFAULT_PRIORITY_WEIGHTS);
+            Priorities::new(current, Some(weights), Some(f))
+        });
+    }
+
+    fn update_priorities<F>(&self, f: F)
+    where
+        F: Fn(Option<&Priorities<M>>) -> Priorities<M>,
+    {
+        self.priorities
+            .rcu(|current| Some(Arc::new(f(current.as_deref()))));
+        self.has_priorities.store(true, Ordering::Release);
+    }
+
+    /// The number of messages in the queue
+    pub(crate) fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    fn push(&self, value: MsgEnvelope<M>, priority: Option<MessagePriority>) {
+        if self.has_priorities.load(Ordering::Acquire) {
+            if let Some(ref priorities) = *self.priorities.load() {
+                match priorities.priority_of(&value, priority) {
+                    MessagePriority::High => return priorities.high.push(Self::traced(value)),
+                    MessagePriority::Low => return priorities.low.push(Self::traced(value)),
+                    MessagePriority::Normal => (),
+                }
+            }
+        }
+        self.queue.push(Self::traced(value));
+    }
+
+    /// Takes the next message, according to priority if the mailbox is prioritised
+    pub(crate) fn pop(&self) -> Option<TracedEnvelope<M>> {
+        let env = if self.has_priorities.load(Ordering::Acquire) {
+            match *self.priorities.load() {
+                Some(ref priorities) => self.pop_prioritised(priorities),
+                None => self.queue.pop().ok(),
+            }
+        } else {
+            self.queue.pop().ok()
+        };
+        if env.is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+        }
+        env
+    }
+
+    /// Takes the next message according to a weighted round robin over the priority lanes
+    ///
+    /// Within each round, every lane hands out at most as many messages as its weight,
+    /// higher priorities first, which ensures that lower priorities can't be starved.
+    /// A new round starts when no lane with remaining credits has any messages.
+    fn pop_prioritised(&self, priorities: &Priorities<M>) -> Option<TracedEnvelope<M>> {
+        let lanes = [&*priorities.high, &self.queue, &*priorities.low];
+        for _round in 0..2 {
+            for (index, lane) in lanes.iter().enumerate() {
+                let credits = &priorities.credits[index];
+                if credits.load(Ordering::Relaxed) > 0 {
+                    if let Ok(env) = lane.pop() {
+                        credits.fetch_sub(1, Ordering::Relaxed);
+                        return Some(env);
+                    }
+                }
+            }
+            for (index, credits) in priorities.credits.iter().enumerate() {
+                // queues that are not prioritised anymore may still have left-overs in the other lanes
+                let weight = priorities.weights.map_or(1, |weights| weights[index]);
+                credits.store(weight, Ordering::Relaxed);
+            }
         }
+        None
+    }
+
+    /// Removes the oldest message of the lowest non-empty priority, without touching the counts
+    fn pop_oldest(&self) -> Option<TracedEnvelope<M>> {
+        if self.has_priorities.load(Ordering::Acquire) {
+            if let Some(ref priorities) = *self.priorities.load() {
+                return priorities
+                    .low
+                    .pop()
+                    .or_else(|_| self.queue.pop())
+                    .or_else(|_| priorities.high.pop())
+                    .ok();
+            }
+        }
+        self.queue.pop().ok()
     }
 
-    pub(crate) fn pop(&self) -> Option<MsgEnvelope<M>> {
-        self.inner.pop().ok()
+    /// Attaches the current trace context to local messages
+    ///
+    /// Network messages carry their own trace context.
+    fn traced(value: MsgEnvelope<M>) -> TracedEnvelope<M> {
+        match value {
+            MsgEnvelope::Typed(_) => (value, tracing::current_context()),
+            MsgEnvelope::Net(_) => (value, None),
+        }
+    }
+
+    /// Enqueues `value` and schedules `container` if necessary,
+    /// unless the mailbox is at capacity, in which case `value` is returned
+    ///
+    /// If the queue is prioritised, `value` is placed according to `priority`,
+    /// or the priority function if no priority is given.
+    pub(crate) fn try_enqueue<C>(
+        &self,
+        value: MsgEnvelope<M>,
+        priority: Option<MessagePriority>,
+        container: &C,
+    ) -> Result<(), MsgEnvelope<M>>
+    where
+        C: CoreContainer + ?Sized,
+    {
+        if !self.try_reserve() {
+            return Err(value);
+        }
+        self.push_reserved(value, priority, container);
+        Ok(())
+    }
+
+    /// Like [try_enqueue](TypedMsgQueue::try_enqueue), but for a local message
+    /// with the default priority, which is returned as is if the mailbox is at capacity
+    pub(crate) fn try_enqueue_typed<C>(&self, msg: M, container: &C) -> Result<(), M>
+    where
+        C: CoreContainer + ?Sized,
+    {
+        if !self.try_reserve() {
+            return Err(msg);
+        }
+        self.push_reserved(MsgEnvelope::Typed(msg), None, container);
+        Ok(())
+    }
+
+    /// Counts one more message towards the capacity, if the mailbox isn't full
+    fn try_reserve(&self) -> bool {
+        // the length is only a count, access to the messages is synchronised by the lanes
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        if capacity == 0 {
+            self.len.fetch_add(1, Ordering::Relaxed);
+            true
+        } else {
+            self.len
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |len| {
+                    if len < capacity {
+                        Some(len + 1)
+                    } else {
+                        None
+                    }
+                })
+                .is_ok()
+        }
+    }
+
+    /// Pushes `value`, which already has its place [reserved](TypedMsgQueue::try_reserve),
+    /// and schedules `container` if necessary
+    fn push_reserved<C>(
+        &self,
+        value: MsgEnvelope<M>,
+        priority: Option<MessagePriority>,
+        container: &C,
+    ) where
+        C: CoreContainer + ?Sized,
+    {
+        let sd = container.core().increment_work(); // must do it in this order to maintain counting guarantees
+        self.push(value, priority);
+        if let SchedulingDecision::Schedule = sd {
+            container.schedule();
+        }
+    }
+
+    /// Enqueues `value` and schedules `container` if necessary,
+    /// applying the overflow policy if the mailbox is at capacity
+    pub(crate) fn enqueue<C>(&self, value: MsgEnvelope<M>, container: &C)
+    where
+        C: CoreContainer + ?Sized,
+    {
+        self.enqueue_with_priority(value, None, container)
+    }
+
+    /// Like [enqueue](TypedMsgQueue::enqueue), but with an explicit `priority` for `value`
+    pub(crate) fn enqueue_with_priority<C>(
+        &self,
+        value: MsgEnvelope<M>,
+        priority: Option<MessagePriority>,
+        container: &C,
+    ) where
+        C: CoreContainer + ?Sized,
+    {
+        let mut value = value;
+        loop {
+            value = match self.try_enqueue(value, priority, container) {
+                Ok(()) => return,
+                Err(value) => value,
+            };
+            let logger = container.system().logger();
+            match OverflowPolicy::from_u8(self.overflow_policy.load(Ordering::SeqCst)) {
+                OverflowPolicy::DropNewest => {
+                    debug!(
+                        logger,
+                        "Mailbox of Component({}) is full. Dropping {:?}",
+                        container.id(),
+                        value
+                    );
+                    return;
+                }
+                OverflowPolicy::DropOldest => {
+                    // swap out the oldest message without touching the counts,
+                    // or try again if the mailbox has been drained in the meantime
+                    if let Some((oldest, _)) = self.pop_oldest() {
+                        self.push(value, priority);
+                        debug!(
+                            logger,
+                            "Mailbox of Component({}) is full. Dropping {:?}",
+                            container.id(),
+                            oldest
+                        );
+                        return;
+                    }
+                }
+                OverflowPolicy::RejectToDeadletter => {
+                    match value {
+                        MsgEnvelope::Net(msg) => {
+                            container
+                                .system()
+                                .deadletter_ref()
+                                .enqueue(MsgEnvelope::Net(msg));
+                        }
+                        MsgEnvelope::Typed(msg) => {
+                            info!(
+                                logger,
+                                "Dead letter for Component({}) with full mailbox: {:?}",
+                                container.id(),
+                                msg
+                            );
+                        }
+                    }
+                    return;
+                }
+            }
+        }
     }
 
     #[allow(unused)]
@@ -26,11 +378,6 @@ impl<M: MessageBounds> TypedMsgQueue<M> {
         q as Arc<dyn DynMsgQueue>
     }
 
-    #[inline(always)]
-    pub(crate) fn push(&self, value: MsgEnvelope<M>) {
-        self.inner.push(value)
-    }
-
     pub(crate) fn create_adapter<In: 'static>(
         component: Weak<dyn MsgQueueContainer<Message = M>>,
         convert: fn(In) -> M,
@@ -41,15 +388,16 @@ impl<M: MessageBounds> TypedMsgQueue<M> {
 }
 impl<M: MessageBounds> DynMsgQueue for TypedMsgQueue<M> {
     #[inline(always)]
-    fn push_net(&self, value: NetMessage) {
-        self.push(MsgEnvelope::Net(value));
+    fn enqueue_net(&self, value: NetMessage, container: &dyn CoreContainer) {
+        self.enqueue(MsgEnvelope::Net(Box::new(value)), container);
     }
 }
 
 /// A message queue handle that only deals with
 /// net messages.
 pub trait DynMsgQueue: fmt::Debug + Sync + Send {
-    fn push_net(&self, value: NetMessage);
+    /// Enqueue `value` and schedule the owning `container` if necessary
+    fn enqueue_net(&self, value: NetMessage, container: &dyn CoreContainer);
 }
 pub(crate) trait AdaptedQueueContainer<M>: fmt::Debug + Sync + Send {
     fn id(&self) -> Option<Uuid>;
@@ -83,12 +431,7 @@ where
         let out = self.convert(value);
         let msg = MsgEnvelope::Typed(out);
         if let Some(c) = self.inner.upgrade() {
-            let q = c.message_queue();
-            let sd = c.core().increment_work();
-            q.push(msg);
-            if let SchedulingDecision::Schedule = sd {
-                c.schedule();
-            }
+            c.message_queue().enqueue(msg, c.as_ref());
         } else {
             #[cfg(test)]
             println!("Dropping msg as target component is unavailable: {:?}", msg)
@@ -143,18 +486,17 @@ pub struct DynActorRef {
 impl DynActorRef {
     pub(crate) fn enqueue(&self, msg: NetMessage) -> () {
         if let Some(c) = self.component.upgrade() {
-            let q = c.dyn_message_queue();
-            let sd = c.core().increment_work();
-            q.push_net(msg);
-            if let SchedulingDecision::Schedule = sd {
-                c.schedule();
-            }
+            c.dyn_message_queue().enqueue_net(msg, c.as_ref());
         } else {
             #[cfg(test)]
             println!("Dropping msg as target component is unavailable: {:?}", msg)
         }
     }
 
+    pub(crate) fn component(&self) -> Option<Arc<dyn CoreContainer>> {
+        self.component.upgrade()
+    }
+
     /// Attempts to upgrade the contained component, returning `true` if possible.
     ///
     /// This a somewhat weaker equivalent to an `is_alive` function, in that
@@ -220,15 +562,12 @@ impl<M: MessageBounds> Clone for ActorRefStrong<M> {
 impl<M: MessageBounds> ActorRefStrong<M> {
     pub(crate) fn enqueue(&self, env: MsgEnvelope<M>) -> () {
         let c = &self.component;
-        let q = c.message_queue();
-        let sd = c.core().increment_work();
-        q.push(env);
-        if let SchedulingDecision::Schedule = sd {
-            c.schedule();
-        }
+        c.message_queue().enqueue(env, c.as_ref());
     }
 
     /// Send message `v` to the actor instance referenced by this actor reference
+    ///
+    /// If the target's mailbox is bounded and full, its [OverflowPolicy](OverflowPolicy) is applied.
     pub fn tell<I>(&self, v: I) -> ()
     where
         I: Into<M>,
@@ -238,6 +577,39 @@ impl<M: MessageBounds> ActorRefStrong<M> {
         self.enqueue(env)
     }
 
+    /// Send message `v` with the given `priority` to the actor instance referenced by this actor reference
+    ///
+    /// The priority takes precedence over any priority derived from the message itself,
+    /// but is ignored if the target's mailbox is not [prioritised](MailboxConfig::prioritised).
+    pub fn tell_with_priority<I>(&self, v: I, priority: MessagePriority) -> ()
+    where
+        I: Into<M>,
+    {
+        let msg: M = v.into();
+        let c = &self.component;
+        c.message_queue().enqueue_with_priority(
+            MsgEnvelope::Typed(msg),
+            Some(priority),
+            c.as_ref(),
+        );
+    }
+
+    /// Send message `v` to the actor instance referenced by this actor reference,
+    /// unless its mailbox is full
+    ///
+    /// Instead of applying the target's [OverflowPolicy](OverflowPolicy),
+    /// the message is handed back in [TryTellError::Full](TryTellError::Full).
+    pub fn try_tell<I>(&self, v: I) -> Result<(), TryTellError<M>>
+    where
+        I: Into<M>,
+    {
+        let msg: M = v.into();
+        let c = &self.component;
+        c.message_queue()
+            .try_enqueue_typed(msg, c.as_ref())
+            .map_err(TryTellError::Full)
+    }
+
     /// Helper to create messages that expect a response via a future instead of a message
     ///
     /// # Example
@@ -372,12 +744,7 @@ impl<M: MessageBounds> ActorRef<M> {
 
     pub(crate) fn enqueue(&self, env: MsgEnvelope<M>) -> () {
         if let Some(c) = self.component.upgrade() {
-            let q = c.message_queue();
-            let sd = c.core().increment_work();
-            q.push(env);
-            if let SchedulingDecision::Schedule = sd {
-                c.schedule();
-            }
+            c.message_queue().enqueue(env, c.as_ref());
         } else {
             #[cfg(test)]
             println!("Dropping msg as target component is unavailable: {:?}", env)
@@ -399,6 +766,8 @@ impl<M: MessageBounds> ActorRef<M> {
     }
 
     /// Send message `v` to the actor instance referenced by this actor reference
+    ///
+    /// If the target's mailbox is bounded and full, its [OverflowPolicy](OverflowPolicy) is applied.
     pub fn tell<I>(&self, v: I) -> ()
     where
         I: Into<M>,
@@ -408,6 +777,120 @@ impl<M: MessageBounds> ActorRef<M> {
         self.enqueue(env);
     }
 
+    /// Send message `v` with the given `priority` to the actor instance referenced by this actor reference
+    ///
+    /// The priority takes precedence over any priority derived from the message itself,
+    /// but is ignored if the target's mailbox is not [prioritised](MailboxConfig::prioritised).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::prelude::*;
+    ///
+    /// #[derive(ComponentDefinition)]
+    /// struct Sink {
+    ///     ctx: ComponentContext<Self>,
+    ///     received: Vec<u64>,
+    /// }
+    /// ignore_lifecycle!(Sink);
+    /// impl Actor for Sink {
+    ///     type Message = u64;
+    ///
+    ///     fn receive_local(&mut self, msg: Self::Message) -> Handled {
+    ///         self.received.push(msg);
+    ///         Handled::Ok
+    ///     }
+    ///
+    ///     fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+    ///         unimplemented!("We don't care about this.");
+    ///     }
+    /// }
+    ///
+    /// let system = KompactConfig::default().build().expect("system");
+    /// let c = system.create(|| Sink {
+    ///     ctx: ComponentContext::uninitialised(),
+    ///     received: Vec::new(),
+    /// });
+    /// c.set_mailbox(MailboxConfig::unbounded().prioritised());
+    /// let actor_ref = c.actor_ref();
+    /// actor_ref.tell(1u64);
+    /// actor_ref.tell_with_priority(2u64, MessagePriority::High);
+    /// system.start_notify(&c).wait();
+    /// // c handles 2 before 1
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn tell_with_priority<I>(&self, v: I, priority: MessagePriority) -> ()
+    where
+        I: Into<M>,
+    {
+        let msg: M = v.into();
+        let env = MsgEnvelope::Typed(msg);
+        if let Some(c) = self.component.upgrade() {
+            c.message_queue()
+                .enqueue_with_priority(env, Some(priority), c.as_ref());
+        } else {
+            #[cfg(test)]
+            println!("Dropping msg as target component is unavailable: {:?}", env)
+        }
+    }
+
+    /// Send message `v` to the actor instance referenced by this actor reference,
+    /// unless its mailbox is full
+    ///
+    /// Instead of applying the target's [OverflowPolicy](OverflowPolicy),
+    /// the message is handed back in [TryTellError::Full](TryTellError::Full),
+    /// so that the sender can apply backpressure, for example by retrying later.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::prelude::*;
+    ///
+    /// #[derive(ComponentDefinition)]
+    /// struct Sink {
+    ///     ctx: ComponentContext<Self>,
+    /// }
+    /// ignore_lifecycle!(Sink);
+    /// impl Actor for Sink {
+    ///     type Message = u64;
+    ///
+    ///     fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+    ///         Handled::Ok
+    ///     }
+    ///
+    ///     fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+    ///         unimplemented!("We don't care about this.");
+    ///     }
+    /// }
+    ///
+    /// let system = KompactConfig::default().build().expect("system");
+    /// let c = system.create(|| Sink {
+    ///     ctx: ComponentContext::uninitialised(),
+    /// });
+    /// c.set_mailbox(MailboxConfig::bounded(1, OverflowPolicy::DropNewest));
+    /// // not started, so nothing is taken out of the mailbox
+    /// let actor_ref = c.actor_ref();
+    /// assert!(actor_ref.try_tell(1u64).is_ok());
+    /// match actor_ref.try_tell(2u64) {
+    ///     Err(TryTellError::Full(msg)) => assert_eq!(2u64, msg),
+    ///     _ => panic!("Mailbox should have been full"),
+    /// }
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn try_tell<I>(&self, v: I) -> Result<(), TryTellError<M>>
+    where
+        I: Into<M>,
+    {
+        let msg: M = v.into();
+        match self.component.upgrade() {
+            Some(c) => c
+                .message_queue()
+                .try_enqueue_typed(msg, c.as_ref())
+                .map_err(TryTellError::Full),
+            None => Err(TryTellError::Unavailable(msg)),
+        }
+    }
+
     /// Helper to create messages that expect a response via a future instead of a message
     ///
     /// # Example
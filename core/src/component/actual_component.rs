@@ -1,5 +1,12 @@
 use super::*;
 
+use crate::{
+    actors::TracedEnvelope,
+    messaging::MsgEnvelope,
+    metrics::instruments::ComponentMetrics,
+    tracing::{self, ComponentTracer, TraceContext},
+};
+
 // just define these expansions, so I don't have to write it multiple times
 macro_rules! check_and_handle_blocking {
     ($self:ident, $guard:ident,$count:ident,$res:ident) => {
@@ -58,6 +65,8 @@ pub struct Component<CD: ComponentTraits> {
     supervisor: Option<ProvidedRef<SupervisionPort>>,
     logger: KompactLogger,
     recovery_function: Mutex<Box<RecoveryFunction>>,
+    metrics: Option<ComponentMetrics>,
+    tracer: Option<ComponentTracer>,
 }
 
 impl<CD: ComponentTraits> Component<CD> {
@@ -66,21 +75,26 @@ impl<CD: ComponentTraits> Component<CD> {
         definition: CD,
         supervisor: ProvidedRef<SupervisionPort>,
     ) -> Self {
+        let mailbox_config = system.mailbox_config();
         let core = ComponentCore::with::<Component<CD>>(system);
         let logger = core
             .system
             .logger()
             .new(o!("cid" => format!("{}", core.id)));
         let mutable_core = ComponentMutableCore::from(definition);
+        let metrics = Self::instrument(&core);
+        let tracer = Self::tracer(&core);
         Component {
             core,
             custom_scheduler: None,
             mutable_core: Mutex::new(mutable_core),
             ctrl_queue: ConcurrentQueue::new(),
-            msg_queue: TypedMsgQueue::new(),
+            msg_queue: TypedMsgQueue::with_config(&mailbox_config),
             supervisor: Some(supervisor),
             logger,
             recovery_function: Mutex::new(Box::new(default_recovery_function)),
+            metrics,
+            tracer,
         }
     }
 
@@ -90,21 +104,26 @@ impl<CD: ComponentTraits> Component<CD> {
         supervisor: ProvidedRef<SupervisionPort>,
         custom_scheduler: dedicated_scheduler::DedicatedThreadScheduler,
     ) -> Self {
+        let mailbox_config = system.mailbox_config();
         let core = ComponentCore::with::<Component<CD>>(system);
         let logger = core
             .system
             .logger()
             .new(o!("cid" => format!("{}", core.id)));
         let mutable_core = ComponentMutableCore::from(definition);
+        let metrics = Self::instrument(&core);
+        let tracer = Self::tracer(&core);
         Component {
             core,
             custom_scheduler: Some(custom_scheduler),
             mutable_core: Mutex::new(mutable_core),
             ctrl_queue: ConcurrentQueue::new(),
-            msg_queue: TypedMsgQueue::new(),
+            msg_queue: TypedMsgQueue::with_config(&mailbox_config),
             supervisor: Some(supervisor),
             logger,
             recovery_function: Mutex::new(Box::new(default_recovery_function)),
+            metrics,
+            tracer,
         }
     }
 
@@ -115,6 +134,8 @@ impl<CD: ComponentTraits> Component<CD> {
             .logger()
             .new(o!("cid" => format!("{}", core.id)));
         let mutable_core = ComponentMutableCore::from(definition);
+        let metrics = Self::instrument(&core);
+        let tracer = Self::tracer(&core);
         Component {
             core,
             custom_scheduler: None,
@@ -124,6 +145,8 @@ impl<CD: ComponentTraits> Component<CD> {
             supervisor: None,
             logger,
             recovery_function: Mutex::new(Box::new(default_recovery_function)),
+            metrics,
+            tracer,
         }
     }
 
@@ -138,6 +161,8 @@ impl<CD: ComponentTraits> Component<CD> {
             .logger()
             .new(o!("cid" => format!("{}", core.id)));
         let mutable_core = ComponentMutableCore::from(definition);
+        let metrics = Self::instrument(&core);
+        let tracer = Self::tracer(&core);
         Component {
             core,
             custom_scheduler: Some(custom_scheduler),
@@ -147,9 +172,23 @@ impl<CD: ComponentTraits> Component<CD> {
             supervisor: None,
             logger,
             recovery_function: Mutex::new(Box::new(default_recovery_function)),
+            metrics,
+            tracer,
         }
     }
 
+    fn instrument(core: &ComponentCore) -> Option<ComponentMetrics> {
+        core.system
+            .system_metrics()
+            .map(|metrics| metrics.component(CD::type_name(), &core.id))
+    }
+
+    fn tracer(core: &ComponentCore) -> Option<ComponentTracer> {
+        core.system.span_sink().map(|sink| {
+            ComponentTracer::new(sink.clone(), core.system.label(), CD::type_name(), &core.id)
+        })
+    }
+
     pub(crate) fn enqueue_control(&self, event: ControlEvent) -> () {
         let res = self.core.increment_work(); // must do it in this order to maintain counting guarantees
         self.ctrl_queue.push(event);
@@ -255,12 +294,83 @@ impl<CD: ComponentTraits> Component<CD> {
         *current = boxed;
     }
 
+    /// Set the capacity and overflow policy of this component's mailbox
+    ///
+    /// See [MailboxConfig](crate::prelude::MailboxConfig) for more information.
+    ///
+    /// Messages already in the mailbox are kept, even if they exceed the new capacity.
+    pub fn set_mailbox(&self, config: MailboxConfig) -> () {
+        self.msg_queue.set_config(&config);
+    }
+
+    /// Handle messages sent via `tell` according to their [Prioritised](crate::prelude::Prioritised) implementation
+    ///
+    /// If the mailbox is not [prioritised](crate::prelude::MailboxConfig::prioritised) yet,
+    /// it will be prioritised with the default weights.
+    pub fn prioritise_messages(&self) -> ()
+    where
+        CD::Message: Prioritised,
+    {
+        self.msg_queue
+            .set_priority_function(<CD::Message as Prioritised>::priority);
+    }
+
+    /// Unstashed messages go before anything in the mailbox
+    fn next_message(&self, definition: &mut CD) -> Option<TracedEnvelope<CD::Message>> {
+        let msg = definition
+            .ctx_mut()
+            .next_unstashed()
+            .map(|env| (env, None))
+            .or_else(|| self.msg_queue.pop());
+        if let (Some(_), Some(metrics)) = (&msg, &self.metrics) {
+            metrics.message_handled();
+        }
+        msg
+    }
+
+    /// Handles `env` in a new span, if it was sent in a trace
+    fn deliver(
+        &self,
+        definition: &mut CD,
+        env: MsgEnvelope<CD::Message>,
+        trace_context: Option<TraceContext>,
+    ) -> Handled {
+        let parent = match env {
+            MsgEnvelope::Typed(_) => trace_context,
+            MsgEnvelope::Net(ref msg) => msg.trace_context.as_deref().copied(),
+        };
+        match parent {
+            Some(parent) => match self.tracer {
+                Some(ref tracer) => {
+                    let span = tracer.start(parent, &env);
+                    let res = {
+                        let _guard = tracing::enter(Some(span.context()));
+                        ComponentContext::deliver(definition, env)
+                    };
+                    span.end();
+                    res
+                }
+                // still continue the trace for anyone downstream
+                None => {
+                    let _guard = tracing::enter(Some(parent));
+                    ComponentContext::deliver(definition, env)
+                }
+            },
+            None => ComponentContext::deliver(definition, env),
+        }
+    }
+
     fn inner_execute(&self) -> SchedulingDecision {
         let max_events = self.core.system.throughput();
         let max_messages = self.core.system.max_messages();
 
         match self.mutable_core.lock() {
             Ok(mut guard) => {
+                if let Some(ref metrics) = self.metrics {
+                    let timers = guard.definition.ctx_mut().timer_manager_mut();
+                    let (backlog, scheduled) = (timers.backlog(), timers.scheduled());
+                    metrics.begin_execute(self.msg_queue.len(), backlog, scheduled);
+                }
                 if guard.definition.ctx().is_blocking() {
                     return guard
                         .definition
@@ -313,6 +423,9 @@ impl<CD: ComponentTraits> Component<CD> {
                             count += 1;
                             res
                         }
+                        lifecycle::ControlEvent::Escalate(child_id) => {
+                            panic!("Child component {} escalated its fault", child_id);
+                        }
                     };
 
                     match res {
@@ -366,8 +479,8 @@ impl<CD: ComponentTraits> Component<CD> {
                 }
                 // then some messages
                 while count < max_messages {
-                    if let Some(env) = self.msg_queue.pop() {
-                        let res = guard.definition.receive(env);
+                    if let Some((env, trace_context)) = self.next_message(&mut guard.definition) {
+                        let res = self.deliver(&mut guard.definition, env, trace_context);
                         count += 1;
                         check_and_handle_blocking!(self, guard, count, res);
                     } else {
@@ -381,14 +494,18 @@ impl<CD: ComponentTraits> Component<CD> {
                     let res = guard.definition.execute(rem_events, skip);
                     guard.skip = res.skip;
                     count += res.count;
+                    if let Some(ref metrics) = self.metrics {
+                        metrics.events_handled(res.count);
+                    }
                     if res.blocking {
                         run_blocking!(self, guard, count);
                     }
 
                     // and maybe some more messages
                     while count < max_events {
-                        if let Some(env) = self.msg_queue.pop() {
-                            let res = guard.definition.receive(env);
+                        if let Some((env, trace_context)) = self.next_message(&mut guard.definition)
+                        {
+                            let res = self.deliver(&mut guard.definition, env, trace_context);
                             count += 1;
                             check_and_handle_blocking!(self, guard, count, res);
                         } else {
@@ -497,7 +614,12 @@ impl<CD: ComponentTraits> CoreContainer for Component<CD> {
         }
         let res = panic::catch_unwind(panic::AssertUnwindSafe(|| self.inner_execute()));
         match res {
-            Ok(decision) => decision, // great
+            Ok(decision) => {
+                if let Some(ref metrics) = self.metrics {
+                    metrics.end_execute(self.msg_queue.len(), &decision);
+                }
+                decision // great
+            }
             Err(e) => {
                 if let Some(error_msg) = e.downcast_ref::<&str>() {
                     error!(self.logger, "Component panicked with: {:?}", error_msg);
@@ -542,6 +664,9 @@ impl<CD: ComponentTraits> CoreContainer for Component<CD> {
     }
 
     fn schedule(&self) -> () {
+        if let Some(ref metrics) = self.metrics {
+            metrics.scheduled();
+        }
         match self.custom_scheduler {
             Some(ref scheduler) => scheduler.schedule_custom(),
             None => {
@@ -1,7 +1,17 @@
 use super::*;
 
-use crate::net::buffers::{BufferConfig, ChunkAllocator, ChunkRef};
-use std::task::Poll;
+use crate::{
+    messaging::{
+        DispatchEnvelope,
+        MsgEnvelope,
+        PathResolvable,
+        RegistrationEnvelope,
+        RegistrationResult,
+        WatchEnvelope,
+    },
+    net::buffers::{BufferConfig, ChunkAllocator, ChunkRef},
+};
+use std::{collections::VecDeque, task::Poll};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(super) enum StateTransition {
@@ -21,6 +31,16 @@ struct BlockingState {
     unblock_state: StateTransition,
 }
 
+type BehaviourFn<CD> =
+    dyn FnMut(&mut CD, MsgEnvelope<<CD as ActorRaw>::Message>) -> Handled + Send + 'static;
+
+enum Behaviour<CD: ComponentTraits> {
+    Default,
+    Custom(Box<BehaviourFn<CD>>),
+    // taken out while it is handling a message
+    Running,
+}
+
 /// The contextual object for a Kompact component
 ///
 /// Gives access compact internal features like
@@ -30,6 +50,9 @@ pub struct ComponentContext<CD: ComponentTraits> {
     buffer: RefCell<Option<EncodeBuffer>>,
     blocking_future: Option<BlockingState>,
     pub(super) non_blocking_futures: FxHashMap<Uuid, NonBlockingFuture>,
+    stash: VecDeque<MsgEnvelope<CD::Message>>,
+    unstashed: VecDeque<MsgEnvelope<CD::Message>>,
+    behaviour: Behaviour<CD>,
 }
 
 struct ComponentContextInner<CD: ComponentTraits> {
@@ -56,6 +79,9 @@ where
             buffer: RefCell::new(None),
             blocking_future: None,
             non_blocking_futures: FxHashMap::default(),
+            stash: VecDeque::new(),
+            unstashed: VecDeque::new(),
+            behaviour: Behaviour::Default,
         }
     }
 
@@ -282,6 +308,162 @@ where
         }
     }
 
+    /// Defer handling of `msg` until the next call to [unstash_all](ComponentContext::unstash_all)
+    ///
+    /// This allows a component to put aside messages it can not handle yet,
+    /// for example while it is waiting for some initialisation to complete.
+    /// Both local and network messages can be stashed,
+    /// by wrapping them into the appropriate [MsgEnvelope](crate::messaging::MsgEnvelope) variant.
+    ///
+    /// Stashed messages do not count towards the mailbox capacity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::prelude::*;
+    ///
+    /// #[derive(ComponentDefinition)]
+    /// struct Lazy {
+    ///    ctx: ComponentContext<Self>,
+    ///    ready: bool,
+    /// }
+    /// ignore_lifecycle!(Lazy);
+    /// impl Actor for Lazy {
+    ///     type Message = Option<String>;
+    ///
+    ///     fn receive_local(&mut self, msg: Self::Message) -> Handled {
+    ///         match msg {
+    ///             Some(s) if self.ready => info!(self.log(), "Got {}", s),
+    ///             Some(s) => self.ctx.stash(MsgEnvelope::Typed(Some(s))),
+    ///             None => {
+    ///                 self.ready = true;
+    ///                 self.ctx.unstash_all();
+    ///             }
+    ///         }
+    ///         Handled::Ok
+    ///     }
+    ///
+    ///     fn receive_network(&mut self, msg: NetMessage) -> Handled {
+    ///         if !self.ready {
+    ///             self.ctx.stash(MsgEnvelope::Net(Box::new(msg)));
+    ///         }
+    ///         Handled::Ok
+    ///     }
+    /// }
+    /// ```
+    pub fn stash(&mut self, msg: MsgEnvelope<CD::Message>) -> () {
+        self.stash.push_back(msg);
+    }
+
+    /// Returns the number of currently stashed messages
+    pub fn stash_size(&self) -> usize {
+        self.stash.len()
+    }
+
+    /// Hand all stashed messages back to the component, in the order they were stashed
+    ///
+    /// The messages will be handled before any other messages in the mailbox,
+    /// once the current handler returns.
+    pub fn unstash_all(&mut self) -> () {
+        if self.stash.is_empty() {
+            return;
+        }
+        // unstashed messages are counted as work, just like messages in the mailbox
+        let component = self.typed_component();
+        for _msg in self.stash.iter() {
+            if let SchedulingDecision::Schedule = component.core().increment_work() {
+                component.schedule();
+            }
+        }
+        let mut unstashed = std::mem::take(&mut self.stash);
+        unstashed.append(&mut self.unstashed);
+        self.unstashed = unstashed;
+    }
+
+    pub(super) fn next_unstashed(&mut self) -> Option<MsgEnvelope<CD::Message>> {
+        self.unstashed.pop_front()
+    }
+
+    /// Handle all future messages with `behaviour`, instead of the component's [ActorRaw](ActorRaw) implementation
+    ///
+    /// This is similar to Akka's `become`, and replaces any behaviour that was set before.
+    /// It allows components to switch between different modes of message handling,
+    /// without having to keep track of their current mode explicitly.
+    /// The behaviour can also replace itself while it is handling a message.
+    ///
+    /// Use [reset_behaviour](ComponentContext::reset_behaviour) to go back to the
+    /// component's own message handlers.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::prelude::*;
+    ///
+    /// #[derive(ComponentDefinition)]
+    /// struct Switch {
+    ///    ctx: ComponentContext<Self>,
+    /// }
+    /// ignore_lifecycle!(Switch);
+    /// impl Switch {
+    ///     fn on(&mut self, env: MsgEnvelope<bool>) -> Handled {
+    ///         if let MsgEnvelope::Typed(false) = env {
+    ///             info!(self.log(), "Switching off");
+    ///             self.ctx.reset_behaviour();
+    ///         }
+    ///         Handled::Ok
+    ///     }
+    /// }
+    /// impl Actor for Switch {
+    ///     type Message = bool;
+    ///
+    ///     fn receive_local(&mut self, msg: Self::Message) -> Handled {
+    ///         if msg {
+    ///             info!(self.log(), "Switching on");
+    ///             self.ctx.set_behaviour(Switch::on);
+    ///         }
+    ///         Handled::Ok
+    ///     }
+    ///
+    ///     fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+    ///         unimplemented!("We don't care about this.");
+    ///     }
+    /// }
+    /// ```
+    pub fn set_behaviour<F>(&mut self, behaviour: F) -> ()
+    where
+        F: FnMut(&mut CD, MsgEnvelope<CD::Message>) -> Handled + Send + 'static,
+    {
+        self.behaviour = Behaviour::Custom(Box::new(behaviour));
+    }
+
+    /// Handle all future messages with the component's [ActorRaw](ActorRaw) implementation again
+    ///
+    /// This undoes the effect of [set_behaviour](ComponentContext::set_behaviour).
+    pub fn reset_behaviour(&mut self) -> () {
+        self.behaviour = Behaviour::Default;
+    }
+
+    /// Hand `env` to the currently active behaviour of `definition`
+    pub(super) fn deliver(definition: &mut CD, env: MsgEnvelope<CD::Message>) -> Handled {
+        let ctx = definition.ctx_mut();
+        match std::mem::replace(&mut ctx.behaviour, Behaviour::Running) {
+            Behaviour::Default => {
+                ctx.behaviour = Behaviour::Default;
+                definition.receive(env)
+            }
+            Behaviour::Custom(mut behaviour) => {
+                let res = behaviour(definition, env);
+                let ctx = definition.ctx_mut();
+                // keep it, unless it was replaced while running
+                if let Behaviour::Running = ctx.behaviour {
+                    ctx.behaviour = Behaviour::Custom(behaviour);
+                }
+                res
+            }
+            Behaviour::Running => unreachable!("Behaviours must not be invoked recursively"),
+        }
+    }
+
     pub(crate) fn context_system(&self) -> ContextSystemHandle {
         ContextSystemHandle::from(self.component())
     }
@@ -326,6 +508,56 @@ where
         self.component().enqueue_control(ControlEvent::Kill);
     }
 
+    /// Watch the actor at `path` for termination
+    ///
+    /// Once the actor has been destroyed, this component receives a
+    /// [Terminated](crate::prelude::Terminated) message with `path` via
+    /// [receive_network](crate::prelude::Actor::receive_network).
+    /// The same happens if the connection to the actor's system has been lost for longer than the
+    /// [unreachable timeout](crate::prelude::NetworkConfig::set_unreachable_timeout)
+    /// of the [NetworkDispatcher](crate::prelude::NetworkDispatcher).
+    ///
+    /// Watching an actor that does not exist results in an immediate notification.
+    /// Watching the same path more than once has no additional effect.
+    pub fn watch(&self, path: ActorPath) -> () {
+        let env = WatchEnvelope::Watch {
+            watcher_id: *self.id(),
+            watcher: self.actor_ref().dyn_ref(),
+            watcher_path: self.actor_path(),
+            target: path,
+        };
+        self.dispatcher_ref().tell(DispatchEnvelope::Watch(env));
+    }
+
+    /// Stop watching the actor at `path`
+    ///
+    /// A [Terminated](crate::prelude::Terminated) message that is already on its way
+    /// may still be delivered after this call.
+    pub fn unwatch(&self, path: ActorPath) -> () {
+        let env = WatchEnvelope::Unwatch {
+            watcher_id: *self.id(),
+            target: path,
+        };
+        self.dispatcher_ref().tell(DispatchEnvelope::Watch(env));
+    }
+
+    /// Registers this component under `alias`, replacing any existing registration
+    ///
+    /// Unlike [update_alias_registration](SystemHandle::update_alias_registration),
+    /// this does not lock the component, so it can be called from its own handlers.
+    pub(crate) fn update_own_alias_registration(&self, alias: &str) -> KFuture<RegistrationResult> {
+        let (promise, future) = promise();
+        let envelope = RegistrationEnvelope::actor_with_promise(
+            &self.actor_ref(),
+            PathResolvable::Alias(alias.to_string()),
+            true,
+            promise,
+        );
+        self.dispatcher_ref()
+            .tell(DispatchEnvelope::Registration(envelope));
+        future
+    }
+
     pub(crate) fn with_buffer<R>(&self, f: impl FnOnce(&mut EncodeBuffer) -> R) -> R {
         {
             // Scoping the borrow
@@ -401,8 +633,12 @@ where
                 );
             }
 
-            let buffer =
+            let mut buffer =
                 EncodeBuffer::with_dispatcher_ref(self.dispatcher_ref(), &cfg, custom_allocator);
+            let component = self.component();
+            if let Some(metrics) = component.core().system().system_metrics() {
+                buffer.buffer_pool.set_metrics(metrics.buffer_pool("component"));
+            }
             *buffer_location = Some(buffer);
         }
     }
@@ -433,6 +669,94 @@ where
     {
         self.typed_component().set_recovery_function(f);
     }
+
+    /// Set the capacity and overflow policy of this component's mailbox
+    ///
+    /// This overrides the default from the `mailbox` section of the system config.
+    /// See [MailboxConfig](crate::prelude::MailboxConfig) for more information.
+    pub fn set_mailbox(&self, config: MailboxConfig) -> () {
+        self.typed_component().set_mailbox(config);
+    }
+
+    /// Handle messages sent via `tell` according to their [Prioritised](crate::prelude::Prioritised) implementation
+    ///
+    /// If the mailbox is not [prioritised](crate::prelude::MailboxConfig::prioritised) yet,
+    /// it will be prioritised with the default weights.
+    /// Messages sent via `tell_with_priority` keep their explicit priority.
+    pub fn prioritise_messages(&self) -> ()
+    where
+        CD::Message: Prioritised,
+    {
+        self.typed_component().prioritise_messages();
+    }
+
+    /// Create a new component as a child of this component
+    ///
+    /// The child is created like with [create](KompactSystem::create) and is not started
+    /// automatically, but its lifecycle follows the lifecycle of this component afterwards:
+    ///
+    /// - Stopping this component also stops its children,
+    ///   and starting it again restarts the children it stopped.
+    /// - Killing this component also kills its children.
+    /// - When this component faults, its children are killed.
+    ///
+    /// The futures returned by [stop_notify](KompactSystem::stop_notify) and
+    /// [kill_notify](KompactSystem::kill_notify) for this component
+    /// only complete once all of its children are stopped or destroyed as well.
+    ///
+    /// Faults of the child are [escalated](FaultContext::escalate) to this component,
+    /// unless a different recovery function is set on the child.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::prelude::*;
+    /// # use kompact::doctest_helpers::*;
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(ComponentDefinition, Actor)]
+    /// struct Parent {
+    ///     ctx: ComponentContext<Self>,
+    ///     child: Option<Arc<Component<TestComponent1>>>,
+    /// }
+    /// impl ComponentLifecycle for Parent {
+    ///     fn on_start(&mut self) -> Handled {
+    ///         if self.child.is_none() {
+    ///             let child = self.ctx.create_child(TestComponent1::new);
+    ///             self.ctx.system().start(&child);
+    ///             self.child = Some(child);
+    ///         }
+    ///         Handled::Ok
+    ///     }
+    /// }
+    ///
+    /// let system = KompactConfig::default().build().expect("system");
+    /// let parent = system.create(|| Parent {
+    ///     ctx: ComponentContext::uninitialised(),
+    ///     child: None,
+    /// });
+    /// system.start_notify(&parent).wait();
+    /// // also kills the child
+    /// system.kill_notify(parent).wait();
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn create_child<C, F>(&self, f: F) -> Arc<Component<C>>
+    where
+        F: FnOnce() -> C,
+        C: ComponentDefinition + 'static,
+    {
+        let parent = self.component();
+        let child = parent.system().create(f);
+        child.set_recovery_function(|fault| fault.escalate());
+        parent
+            .system()
+            .supervision_port()
+            .enqueue(SupervisorMsg::Adopt {
+                parent: parent.clone(),
+                child: child.clone(),
+            });
+        child
+    }
 }
 
 impl<CD> ActorRefFactory for ComponentContext<CD>
@@ -29,6 +29,16 @@ impl ComponentCore {
         LifecycleState::load(&self.state)
     }
 
+    /// Returns `true` if the component has been destroyed or has faulted
+    pub(crate) fn is_terminated(&self) -> bool {
+        lifecycle::is_destroyed(&self.state) || lifecycle::is_faulty(&self.state)
+    }
+
+    /// Returns `true` if the component is currently running
+    pub(crate) fn is_active(&self) -> bool {
+        lifecycle::is_active(&self.state)
+    }
+
     /// Returns a reference to the Kompact system this component is a part of
     pub fn system(&self) -> &KompactSystem {
         &self.system
@@ -18,6 +18,8 @@ pub enum ControlEvent {
     Kill,
     /// Ask the component to poll a non-blocking future
     Poll(Uuid),
+    /// Fault the component, because its child with the given id escalated a fault
+    Escalate(Uuid),
 }
 
 const ACTIVE: u64 = 0u64;
@@ -73,7 +73,7 @@ impl DefaultTimer {
 
 impl TimerRefFactory for DefaultTimer {
     fn timer_ref(&self) -> timer::TimerRef {
-        self.inner.timer_ref()
+        self.inner.timer_ref().into()
     }
 }
 impl TimerComponent for DefaultTimer {
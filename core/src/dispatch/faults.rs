@@ -0,0 +1,365 @@
+//! Fault injection for the [NetworkDispatcher](NetworkDispatcher)
+//!
+//! A [NetworkFaults](NetworkFaults) handle is installed into a [NetworkConfig](NetworkConfig)
+//! and consulted by the dispatcher for every message it sends to a remote system,
+//! before the message is handed to the network thread.
+//! Messages can be dropped, duplicated, or delayed (and thereby reordered),
+//! and links between systems can be partitioned and healed at runtime.
+//!
+//! Messages reflected to actors on the same system are never affected.
+
+use crate::{actors::SystemPath, messaging::SerialisedFrame, utils::SplitMix64};
+use std::{
+    collections::HashSet,
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// A cloneable handle to the faults a [NetworkDispatcher](crate::prelude::NetworkDispatcher) injects
+/// into its outgoing messages
+///
+/// Install the handle with [set_fault_injection](crate::prelude::NetworkConfig::set_fault_injection)
+/// and keep a clone around to change the faults while the system is running.
+/// All clones share the same settings.
+///
+/// Faults are decided by the sending system, so a [partition](NetworkFaults::partition) only
+/// cuts both directions of a link if both systems consult it. The easiest way to achieve that
+/// is to install the same handle into every system of a test.
+///
+/// Random decisions are drawn from a generator seeded with [with_seed](NetworkFaults::with_seed),
+/// so that they are reproducible as long as the messages are sent in the same order.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{prelude::*, faults::NetworkFaults};
+///
+/// let faults = NetworkFaults::with_seed(42);
+/// let mut net_cfg = NetworkConfig::default();
+/// net_cfg.set_fault_injection(faults.clone());
+/// let mut cfg = KompactConfig::default();
+/// cfg.system_components(DeadletterBox::new, net_cfg.build());
+/// let system = cfg.build().expect("system");
+///
+/// // lose every tenth message from now on
+/// faults.set_drop_probability(0.1);
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone)]
+pub struct NetworkFaults {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl NetworkFaults {
+    /// Create a new handle that doesn't inject any faults, yet
+    pub fn new() -> NetworkFaults {
+        NetworkFaults::with_seed(0)
+    }
+
+    /// Create a new handle that doesn't inject any faults, yet,
+    /// and draws its random decisions from `seed`
+    pub fn with_seed(seed: u64) -> NetworkFaults {
+        NetworkFaults {
+            state: Arc::new(Mutex::new(FaultState {
+                rng: SplitMix64::new(seed),
+                drop_probability: 0.0,
+                duplicate_probability: 0.0,
+                min_latency: Duration::from_millis(0),
+                max_latency: Duration::from_millis(0),
+                partitions: HashSet::new(),
+                isolated: HashSet::new(),
+                stats: FaultStats::default(),
+            })),
+        }
+    }
+
+    /// Drop each outgoing message with probability `p`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not within `[0, 1]`.
+    pub fn set_drop_probability(&self, p: f64) -> () {
+        assert!(
+            (0.0..=1.0).contains(&p),
+            "Drop probability must be within [0, 1], but was {}",
+            p
+        );
+        self.lock().drop_probability = p;
+    }
+
+    /// Send each outgoing message that isn't dropped twice, with probability `p`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not within `[0, 1]`.
+    pub fn set_duplicate_probability(&self, p: f64) -> () {
+        assert!(
+            (0.0..=1.0).contains(&p),
+            "Duplicate probability must be within [0, 1], but was {}",
+            p
+        );
+        self.lock().duplicate_probability = p;
+    }
+
+    /// Delay each outgoing message (and each duplicate) by a uniformly random time
+    /// between `min` and `max`
+    ///
+    /// Whenever `min < max` messages may overtake each other, i.e. they are reordered.
+    /// Delays are scheduled on the dispatcher's timer, so they advance in virtual time
+    /// if the system uses a [VirtualTimer](crate::timer::VirtualTimer).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min > max`.
+    pub fn set_latency(&self, min: Duration, max: Duration) -> () {
+        assert!(
+            min <= max,
+            "Minimum latency {:?} exceeds maximum latency {:?}",
+            min,
+            max
+        );
+        let mut state = self.lock();
+        state.min_latency = min;
+        state.max_latency = max;
+    }
+
+    /// Drop all messages between the systems at `a` and `b`, in both directions
+    pub fn partition(&self, a: &SystemPath, b: &SystemPath) -> () {
+        self.lock().partitions.insert(Link::new(a, b));
+    }
+
+    /// Allow messages between the systems at `a` and `b` again
+    ///
+    /// This does not affect an [isolation](NetworkFaults::isolate) of either system.
+    pub fn heal(&self, a: &SystemPath, b: &SystemPath) -> () {
+        self.lock().partitions.remove(&Link::new(a, b));
+    }
+
+    /// Drop all messages from and to the system at `system`
+    pub fn isolate(&self, system: &SystemPath) -> () {
+        self.lock().isolated.insert(system.clone());
+    }
+
+    /// Allow messages from and to the system at `system` again
+    ///
+    /// This does not affect any [partition](NetworkFaults::partition) involving `system`.
+    pub fn reconnect(&self, system: &SystemPath) -> () {
+        self.lock().isolated.remove(system);
+    }
+
+    /// Remove all partitions and isolations
+    pub fn heal_all(&self) -> () {
+        let mut state = self.lock();
+        state.partitions.clear();
+        state.isolated.clear();
+    }
+
+    /// Returns `true` if messages from `a` to `b` are currently dropped due to a partition
+    /// or an isolation
+    pub fn is_partitioned(&self, a: &SystemPath, b: &SystemPath) -> bool {
+        self.lock().is_partitioned(a, b)
+    }
+
+    /// Turn off all faults, including partitions, but keep the statistics
+    pub fn reset(&self) -> () {
+        let mut state = self.lock();
+        state.drop_probability = 0.0;
+        state.duplicate_probability = 0.0;
+        state.min_latency = Duration::from_millis(0);
+        state.max_latency = Duration::from_millis(0);
+        state.partitions.clear();
+        state.isolated.clear();
+    }
+
+    /// Returns how many faults have been injected so far
+    pub fn stats(&self) -> FaultStats {
+        self.lock().stats.clone()
+    }
+
+    /// Decides the fate of a message from `src` to `dst`
+    ///
+    /// Returns the delay for each copy of the message that should be sent,
+    /// which is empty if the message should be dropped.
+    pub(crate) fn plan(&self, src: &SystemPath, dst: &SystemPath) -> Vec<Duration> {
+        let mut state = self.lock();
+        if state.is_partitioned(src, dst) {
+            state.stats.partitioned += 1;
+            return Vec::new();
+        }
+        let drop_probability = state.drop_probability;
+        if state.chance(drop_probability) {
+            state.stats.dropped += 1;
+            return Vec::new();
+        }
+        let duplicate_probability = state.duplicate_probability;
+        let copies = if state.chance(duplicate_probability) {
+            state.stats.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        (0..copies).map(|_| state.next_latency()).collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FaultState> {
+        self.state.lock().expect("Network faults lock poisoned")
+    }
+}
+
+impl Default for NetworkFaults {
+    fn default() -> Self {
+        NetworkFaults::new()
+    }
+}
+
+impl fmt::Debug for NetworkFaults {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("NetworkFaults")
+            .field("drop_probability", &state.drop_probability)
+            .field("duplicate_probability", &state.duplicate_probability)
+            .field("min_latency", &state.min_latency)
+            .field("max_latency", &state.max_latency)
+            .field("partitions", &state.partitions.len())
+            .field("isolated", &state.isolated)
+            .finish()
+    }
+}
+
+/// Counts of the faults a [NetworkFaults](NetworkFaults) handle has injected
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FaultStats {
+    /// Messages dropped at random
+    pub dropped: u64,
+    /// Messages dropped because of a partition or isolation
+    pub partitioned: u64,
+    /// Messages sent twice
+    pub duplicated: u64,
+    /// Messages (or duplicates) sent with a non-zero delay
+    pub delayed: u64,
+}
+
+/// An undirected link between two systems
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Link(SystemPath, SystemPath);
+
+impl Link {
+    fn new(a: &SystemPath, b: &SystemPath) -> Link {
+        if a <= b {
+            Link(a.clone(), b.clone())
+        } else {
+            Link(b.clone(), a.clone())
+        }
+    }
+}
+
+struct FaultState {
+    rng: SplitMix64,
+    drop_probability: f64,
+    duplicate_probability: f64,
+    min_latency: Duration,
+    max_latency: Duration,
+    partitions: HashSet<Link>,
+    isolated: HashSet<SystemPath>,
+    stats: FaultStats,
+}
+
+impl FaultState {
+    fn is_partitioned(&self, src: &SystemPath, dst: &SystemPath) -> bool {
+        self.isolated.contains(src)
+            || self.isolated.contains(dst)
+            || self.partitions.contains(&Link::new(src, dst))
+    }
+
+    /// Returns `true` with probability `p`
+    fn chance(&mut self, p: f64) -> bool {
+        // don't advance the generator for faults that are turned off
+        p > 0.0 && self.rng.next_f64() < p
+    }
+
+    fn next_latency(&mut self) -> Duration {
+        let spread = self.max_latency - self.min_latency;
+        let latency = if spread > Duration::from_millis(0) {
+            self.min_latency + spread.mul_f64(self.rng.next_f64())
+        } else {
+            self.min_latency
+        };
+        if latency > Duration::from_millis(0) {
+            self.stats.delayed += 1;
+        }
+        latency
+    }
+}
+
+/// Turns `frame` into two frames with the same content
+pub(crate) fn duplicate_frame(frame: SerialisedFrame) -> (SerialisedFrame, SerialisedFrame) {
+    match frame {
+        SerialisedFrame::Bytes(bytes) => (
+            SerialisedFrame::Bytes(bytes.clone()),
+            SerialisedFrame::Bytes(bytes),
+        ),
+        SerialisedFrame::ChunkLease(chunk) => {
+            let chunk = chunk.into_chunk_ref();
+            (
+                SerialisedFrame::ChunkRef(chunk.clone()),
+                SerialisedFrame::ChunkRef(chunk),
+            )
+        }
+        SerialisedFrame::ChunkRef(chunk) => (
+            SerialisedFrame::ChunkRef(chunk.clone()),
+            SerialisedFrame::ChunkRef(chunk),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::Transport;
+
+    fn path(port: u16) -> SystemPath {
+        SystemPath::with_socket(Transport::TCP, ([127, 0, 0, 1], port).into())
+    }
+
+    #[test]
+    fn test_partitions_are_symmetric() {
+        let faults = NetworkFaults::new();
+        let (a, b, c) = (path(1), path(2), path(3));
+        faults.partition(&a, &b);
+        assert!(faults.is_partitioned(&a, &b));
+        assert!(faults.is_partitioned(&b, &a));
+        assert!(!faults.is_partitioned(&a, &c));
+        assert!(faults.plan(&b, &a).is_empty());
+        assert_eq!(1, faults.plan(&a, &c).len());
+        faults.heal(&b, &a);
+        assert!(!faults.is_partitioned(&a, &b));
+
+        faults.isolate(&c);
+        assert!(faults.is_partitioned(&a, &c));
+        assert!(faults.is_partitioned(&c, &b));
+        faults.reconnect(&c);
+        assert!(!faults.is_partitioned(&a, &c));
+        assert_eq!(1, faults.stats().partitioned);
+    }
+
+    #[test]
+    fn test_random_faults_are_reproducible() {
+        let run = |seed: u64| -> Vec<Vec<Duration>> {
+            let faults = NetworkFaults::with_seed(seed);
+            faults.set_drop_probability(0.3);
+            faults.set_duplicate_probability(0.3);
+            faults.set_latency(Duration::from_millis(10), Duration::from_millis(50));
+            (0..100).map(|_| faults.plan(&path(1), &path(2))).collect()
+        };
+        let plans = run(5);
+        assert_eq!(plans, run(5));
+        assert_ne!(plans, run(6));
+        assert!(plans.iter().any(|p| p.is_empty()));
+        assert!(plans.iter().any(|p| p.len() == 2));
+        for delay in plans.iter().flatten() {
+            assert!(*delay >= Duration::from_millis(10));
+            assert!(*delay <= Duration::from_millis(50));
+        }
+    }
+}
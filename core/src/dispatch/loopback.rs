@@ -0,0 +1,348 @@
+//! An in-memory network for the [NetworkDispatcher](NetworkDispatcher)
+//!
+//! Systems whose dispatchers share a [LoopbackFabric](LoopbackFabric) exchange their messages
+//! through it, instead of through sockets and a network thread.
+//! Messages are still serialised by the sender and deserialised by the receiver,
+//! so the wire format is covered, but no ports are bound and no connections are set up.
+
+use super::lookup::{ActorLookup, ActorStore, LookupResult};
+use crate::{
+    actors::DispatcherRef,
+    messaging::{DispatchEnvelope, EventEnvelope, SerialisedFrame},
+    net::{events::NetworkEvent, frames::FRAME_HEAD_LEN, ConnectionState},
+    prelude::NetworkConfig,
+    serialisation::{ser_helpers::deserialise_bytes, SerError},
+    KompactLogger,
+};
+use arc_swap::ArcSwap;
+use bytes::Buf;
+use rustc_hash::FxHashMap;
+use slog::{error, warn};
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+// Virtual ports are handed out from here on, if a system asks for port 0
+const FIRST_PORT: u16 = 1;
+
+/// An in-memory network between the [NetworkDispatchers](crate::prelude::NetworkDispatcher)
+/// of several systems in the same process
+///
+/// Every dispatcher configured with a clone of the same fabric binds a virtual address on it,
+/// which becomes its system's [SystemPath](crate::prelude::SystemPath).
+/// A configured port of `0` is replaced with the next free virtual port,
+/// so a fabric never runs out of addresses the way the operating system might.
+/// Messages to other systems on the fabric are serialised into frames exactly as for a real network,
+/// copied, and deserialised into the receiving actor's mailbox by the sending dispatcher.
+/// Messages to addresses that are not bound on the fabric are dropped.
+/// When a system leaves the fabric, the others are told that their connection to it was closed,
+/// so watched actors on it are considered terminated after the
+/// [unreachable timeout](crate::prelude::NetworkConfig::set_unreachable_timeout).
+///
+/// All other features of the dispatcher, like [fault injection](crate::faults::NetworkFaults)
+/// and death-watch, work the same as over a real network.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{loopback::LoopbackFabric, prelude::*};
+///
+/// let fabric = LoopbackFabric::new();
+/// let build = || {
+///     let mut cfg = KompactConfig::default();
+///     cfg.system_components(DeadletterBox::new, fabric.config().build());
+///     cfg.build().expect("system")
+/// };
+/// let system1 = build();
+/// let system2 = build();
+/// assert_ne!(system1.system_path(), system2.system_path());
+/// # system1.shutdown().expect("shutdown");
+/// # system2.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone, Default)]
+pub struct LoopbackFabric {
+    state: Arc<Mutex<FabricState>>,
+}
+
+impl LoopbackFabric {
+    /// Create a new fabric without any systems on it
+    pub fn new() -> LoopbackFabric {
+        LoopbackFabric::default()
+    }
+
+    /// Returns a default [NetworkConfig](NetworkConfig) that uses this fabric
+    ///
+    /// This is a shorthand for calling [set_loopback_fabric](NetworkConfig::set_loopback_fabric)
+    /// on [NetworkConfig::default](NetworkConfig::default).
+    pub fn config(&self) -> NetworkConfig {
+        let mut cfg = NetworkConfig::default();
+        cfg.set_loopback_fabric(self.clone());
+        cfg
+    }
+
+    /// Returns the addresses of all systems currently on this fabric
+    pub fn bound_addresses(&self) -> Vec<SocketAddr> {
+        self.lock().endpoints.keys().copied().collect()
+    }
+
+    /// Binds `addr` to a system that looks up its actors in `lookup`
+    ///
+    /// The system stays reachable until the returned endpoint is dropped,
+    /// and its `dispatcher` is told when other systems leave the fabric.
+    pub(crate) fn bind(
+        &self,
+        addr: SocketAddr,
+        lookup: Arc<ArcSwap<ActorStore>>,
+        dispatcher: DispatcherRef,
+    ) -> Result<LoopbackEndpoint, String> {
+        let mut state = self.lock();
+        let addr = if addr.port() == 0 {
+            let mut addr = addr;
+            loop {
+                addr.set_port(state.next_port);
+                state.next_port = state.next_port.checked_add(1).unwrap_or(FIRST_PORT);
+                if !state.endpoints.contains_key(&addr) {
+                    break addr;
+                }
+            }
+        } else if state.endpoints.contains_key(&addr) {
+            return Err(format!("{} is already bound on the loopback fabric", addr));
+        } else {
+            addr
+        };
+        state.endpoints.insert(addr, Peer { lookup, dispatcher });
+        Ok(LoopbackEndpoint {
+            fabric: self.clone(),
+            addr,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FabricState> {
+        self.state.lock().expect("Loopback fabric lock poisoned")
+    }
+}
+
+impl fmt::Debug for LoopbackFabric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoopbackFabric")
+            .field("bound_addresses", &self.bound_addresses())
+            .finish()
+    }
+}
+
+struct FabricState {
+    next_port: u16,
+    endpoints: FxHashMap<SocketAddr, Peer>,
+}
+
+struct Peer {
+    lookup: Arc<ArcSwap<ActorStore>>,
+    dispatcher: DispatcherRef,
+}
+
+impl Default for FabricState {
+    fn default() -> Self {
+        FabricState {
+            next_port: FIRST_PORT,
+            endpoints: FxHashMap::default(),
+        }
+    }
+}
+
+/// Why a frame could not be delivered over the [LoopbackFabric](LoopbackFabric)
+#[derive(Debug)]
+pub(crate) enum LoopbackError {
+    /// No system is bound at the destination address
+    Unreachable(SocketAddr),
+    /// The frame could not be deserialised into a message
+    Malformed(SerError),
+}
+
+/// A system's address on a [LoopbackFabric](LoopbackFabric), which is unbound when dropped
+pub(crate) struct LoopbackEndpoint {
+    fabric: LoopbackFabric,
+    addr: SocketAddr,
+}
+
+impl LoopbackEndpoint {
+    /// The address this endpoint is bound to
+    pub(crate) fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Deserialises `frame` and enqueues it with the actor at the system bound to `dst`
+    ///
+    /// Just like the network thread, missing actors on the receiving side are logged
+    /// and the message is dropped.
+    pub(crate) fn deliver(
+        &self,
+        dst: SocketAddr,
+        frame: SerialisedFrame,
+        log: &KompactLogger,
+    ) -> Result<(), LoopbackError> {
+        let lookup = match self.fabric.lock().endpoints.get(&dst) {
+            Some(peer) => peer.lookup.clone(),
+            None => return Err(LoopbackError::Unreachable(dst)),
+        };
+        // Copy the frame, so the receiver does not hold on to the sender's buffers
+        let bytes = match frame {
+            // Plain bytes are not framed yet, just like for the network thread
+            SerialisedFrame::Bytes(bytes) => bytes,
+            SerialisedFrame::ChunkLease(mut chunk) => {
+                chunk.advance(FRAME_HEAD_LEN as usize);
+                chunk.to_bytes()
+            }
+            SerialisedFrame::ChunkRef(mut chunk) => {
+                chunk.advance(FRAME_HEAD_LEN as usize);
+                chunk.to_bytes()
+            }
+        };
+        let envelope = deserialise_bytes(bytes).map_err(LoopbackError::Malformed)?;
+        let lease_lookup = lookup.load();
+        match lease_lookup.get_by_actor_path(&envelope.receiver) {
+            LookupResult::Ref(actor) => {
+                actor.enqueue(envelope);
+            }
+            LookupResult::Group(group) => {
+                group.route(envelope, log);
+            }
+            LookupResult::None => {
+                warn!(
+                    log,
+                    "Could not find actor reference for destination: {:?}, dropping message",
+                    envelope.receiver
+                );
+            }
+            LookupResult::Err(e) => {
+                error!(
+                    log,
+                    "An error occurred during local actor lookup for destination: {:?}, dropping message. The error was: {}",
+                    envelope.receiver,
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LoopbackEndpoint {
+    fn drop(&mut self) {
+        let peers: Vec<DispatcherRef> = {
+            let mut state = self.fabric.lock();
+            state.endpoints.remove(&self.addr);
+            state
+                .endpoints
+                .values()
+                .map(|peer| peer.dispatcher.clone())
+                .collect()
+        };
+        for dispatcher in peers {
+            dispatcher.tell(DispatchEnvelope::Event(EventEnvelope::Network(
+                NetworkEvent::Connection(self.addr, ConnectionState::Closed),
+            )));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::*,
+        testkit::{local_system, wait_until, TestProbe},
+    };
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_millis(5000);
+
+    fn loopback_system(fabric: &LoopbackFabric) -> KompactSystem {
+        let mut cfg = KompactConfig::default();
+        cfg.system_components(DeadletterBox::new, fabric.config().build());
+        cfg.build().expect("KompactSystem")
+    }
+
+    #[test]
+    fn test_bind_assigns_virtual_ports() {
+        let system = local_system();
+        let dispatcher = system.dispatcher_ref();
+        let fabric = LoopbackFabric::new();
+        let lookup = Arc::new(ArcSwap::from_pointee(ActorStore::new()));
+        let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let fixed: SocketAddr = "127.0.0.1:2".parse().unwrap();
+        let bind = |addr| fabric.bind(addr, lookup.clone(), dispatcher.clone());
+
+        let first = bind(any).expect("bind");
+        assert_eq!(1, first.addr().port());
+        let second = bind(fixed).expect("bind");
+        assert!(bind(fixed).is_err());
+        let third = bind(any).expect("bind");
+        assert_eq!(3, third.addr().port(), "Port 2 should be skipped");
+        assert_eq!(3, fabric.bound_addresses().len());
+
+        drop(second);
+        assert_eq!(2, fabric.bound_addresses().len());
+        assert!(bind(fixed).is_ok());
+        drop(first);
+        drop(third);
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_loopback_delivery() {
+        let fabric = LoopbackFabric::new();
+        let system1 = loopback_system(&fabric);
+        let system2 = loopback_system(&fabric);
+        assert_ne!(system1.system_path(), system2.system_path());
+
+        let probe1: TestProbe<String> = TestProbe::new(&system1);
+        let path1 = probe1.register();
+        let probe2: TestProbe<u64> = TestProbe::new(&system2);
+        let path2 = probe2.register();
+
+        path2.tell_with_sender(42u64, &system1, path1.clone());
+        let msg = probe2.expect_net_msg(TIMEOUT);
+        assert_eq!(path1, *msg.sender());
+        assert_eq!(42, msg.try_deserialise::<u64, u64>().expect("u64"));
+
+        path1.tell_with_sender("reply".to_string(), &system2, path2.clone());
+        let msg = probe1.expect_net_msg(TIMEOUT);
+        assert_eq!(path2, *msg.sender());
+        assert_eq!(
+            "reply",
+            msg.try_deserialise::<String, String>().expect("String")
+        );
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_shutdown_unbinds_system() {
+        let fabric = LoopbackFabric::new();
+        let system1 = loopback_system(&fabric);
+        let system2 = loopback_system(&fabric);
+        let probe: TestProbe<u64> = TestProbe::new(&system1);
+        let path = probe.register();
+        let lost_path: ActorPath = system2
+            .system_path()
+            .into_named_with_string("lost")
+            .expect("path")
+            .into();
+        assert_eq!(2, fabric.bound_addresses().len());
+
+        system2.shutdown().expect("shutdown");
+        assert!(
+            wait_until(TIMEOUT, || fabric.bound_addresses().len() == 1),
+            "System should have left the fabric"
+        );
+        // must be dropped without bothering anyone
+        lost_path.tell_with_sender(1u64, &system1, path);
+        probe.expect_no_msg(Duration::from_millis(100));
+
+        system1.shutdown().expect("shutdown");
+        assert!(fabric.bound_addresses().is_empty());
+    }
+}
@@ -1,10 +1,14 @@
 use super::*;
 
 use crate::{
-    actors::{Actor, ActorPath, Dispatcher, DynActorRef, SystemPath, Transport},
+    actors::{Actor, ActorPath, Address, Dispatcher, DynActorRef, SystemPath, Transport},
     component::{Component, ComponentContext, ExecuteResult},
 };
-use std::{net::SocketAddr, pin::Pin, sync::Arc};
+use std::{
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::Arc,
+};
 
 use crate::{
     actors::NamedPath,
@@ -23,25 +27,46 @@ use crate::{
         RegistrationPromise,
         SerialisedFrame,
     },
-    net::{buffers::*, events::NetworkEvent, ConnectionState, NetworkBridgeErr},
+    net::{
+        auth::AuthSecret,
+        buffers::*,
+        events::NetworkEvent,
+        tls::TlsConfig,
+        ConnectionState,
+        NetworkBridgeErr,
+    },
+    metrics::instruments::{NetworkMetrics, SystemMetrics},
     timer::timer_manager::Timer,
+    tracing::TraceContext,
 };
 use arc_swap::ArcSwap;
+use faults::NetworkFaults;
 use futures::{
     self,
     task::{Context, Poll},
 };
 use lookup::{ActorLookup, ActorStore, InsertResult, LookupResult};
+use loopback::{LoopbackEndpoint, LoopbackError, LoopbackFabric};
 use queue_manager::QueueManager;
-use rustc_hash::FxHashMap;
+use reliable::{ReliableDelivery, ReliableMsg, RELIABLE_ALIAS};
+use rustc_hash::{FxHashMap, FxHashSet};
 use std::{collections::VecDeque, io::ErrorKind, time::Duration};
+use watch::{DeathWatch, WatchMsg, WATCH_ALIAS};
 
+pub mod faults;
 pub mod lookup;
+pub mod loopback;
 pub mod queue_manager;
+mod reliable;
+mod watch;
 
 // Default values for network config.
 const RETRY_CONNECTIONS_INTERVAL: u64 = 5000;
 const MAX_RETRY_ATTEMPTS: u8 = 10;
+const CREDIT_WINDOW: u32 = 1024;
+const UNREACHABLE_TIMEOUT: u64 = 10000;
+const REDELIVERY_INTERVAL: u64 = 1000;
+const RELIABLE_TIMEOUT: u64 = 60000;
 
 type NetHashMap<K, V> = FxHashMap<K, V>;
 
@@ -68,6 +93,16 @@ pub struct NetworkConfig {
     tcp_nodelay: bool,
     max_connection_retry_attempts: u8,
     connection_retry_interval: u64,
+    credit_window: u32,
+    unreachable_timeout: u64,
+    redelivery_interval: u64,
+    reliable_timeout: u64,
+    tls_config: Option<TlsConfig>,
+    auth_secret: Option<AuthSecret>,
+    faults: Option<NetworkFaults>,
+    loopback: Option<LoopbackFabric>,
+    // filled in from the system by the dispatcher when it starts
+    metrics: Option<SystemMetrics>,
 }
 
 impl NetworkConfig {
@@ -82,6 +117,15 @@ impl NetworkConfig {
             tcp_nodelay: false,
             max_connection_retry_attempts: MAX_RETRY_ATTEMPTS,
             connection_retry_interval: RETRY_CONNECTIONS_INTERVAL,
+            credit_window: CREDIT_WINDOW,
+            unreachable_timeout: UNREACHABLE_TIMEOUT,
+            redelivery_interval: REDELIVERY_INTERVAL,
+            reliable_timeout: RELIABLE_TIMEOUT,
+            tls_config: None,
+            auth_secret: None,
+            faults: None,
+            loopback: None,
+            metrics: None,
         }
     }
 
@@ -97,6 +141,15 @@ impl NetworkConfig {
             tcp_nodelay: false,
             max_connection_retry_attempts: MAX_RETRY_ATTEMPTS,
             connection_retry_interval: RETRY_CONNECTIONS_INTERVAL,
+            credit_window: CREDIT_WINDOW,
+            unreachable_timeout: UNREACHABLE_TIMEOUT,
+            redelivery_interval: REDELIVERY_INTERVAL,
+            reliable_timeout: RELIABLE_TIMEOUT,
+            tls_config: None,
+            auth_secret: None,
+            faults: None,
+            loopback: None,
+            metrics: None,
         }
     }
 
@@ -116,6 +169,15 @@ impl NetworkConfig {
             tcp_nodelay: false,
             max_connection_retry_attempts: MAX_RETRY_ATTEMPTS,
             connection_retry_interval: RETRY_CONNECTIONS_INTERVAL,
+            credit_window: CREDIT_WINDOW,
+            unreachable_timeout: UNREACHABLE_TIMEOUT,
+            redelivery_interval: REDELIVERY_INTERVAL,
+            reliable_timeout: RELIABLE_TIMEOUT,
+            tls_config: None,
+            auth_secret: None,
+            faults: None,
+            loopback: None,
+            metrics: None,
         }
     }
 
@@ -186,6 +248,154 @@ impl NetworkConfig {
     pub fn get_connection_retry_interval(&self) -> u64 {
         self.connection_retry_interval
     }
+
+    /// Configures the credit window used for flow-control on TCP Network-channels.
+    ///
+    /// A remote host may at most have `window` messages in flight on a channel,
+    /// before it must wait for the receiving side to grant it more credits.
+    /// The receiving side grants at most as many credits as its `BufferPool` has free chunks.
+    /// While a channel is out of credits, the dispatcher queues further messages to its host.
+    ///
+    /// A window of `0` turns flow-control off for all channels of this system.
+    ///
+    /// Default value is 1024 messages.
+    pub fn set_credit_window(&mut self, window: u32) {
+        self.credit_window = window;
+    }
+
+    /// Returns the credit window used for flow-control on TCP Network-channels.
+    pub fn get_credit_window(&self) -> u32 {
+        self.credit_window
+    }
+
+    /// Configures how long (in ms) the connection to a remote system may be lost,
+    /// before all watched actors on that system are considered terminated.
+    ///
+    /// See [watch](crate::prelude::ComponentContext::watch).
+    ///
+    /// Default value is 10000 ms.
+    pub fn set_unreachable_timeout(&mut self, milliseconds: u64) {
+        self.unreachable_timeout = milliseconds;
+    }
+
+    /// How long (in ms) the connection to a remote system may be lost, before its watched actors are terminated.
+    pub fn get_unreachable_timeout(&self) -> u64 {
+        self.unreachable_timeout
+    }
+
+    /// Configures how often (in ms) [reliable](crate::prelude::ActorPath::tell_reliable) messages,
+    /// which have not been acknowledged yet, are sent again to connected hosts.
+    ///
+    /// Independent of this interval, they are also sent again whenever a connection is established.
+    ///
+    /// Default value is 1000 ms.
+    pub fn set_redelivery_interval(&mut self, milliseconds: u64) {
+        self.redelivery_interval = milliseconds;
+    }
+
+    /// How often (in ms) unacknowledged reliable messages are sent again to connected hosts.
+    pub fn get_redelivery_interval(&self) -> u64 {
+        self.redelivery_interval
+    }
+
+    /// Configures how long (in ms) a [reliable](crate::prelude::ActorPath::tell_reliable) message
+    /// may remain unacknowledged, before the dispatcher gives up on it.
+    ///
+    /// Since the messages of a channel are delivered in order, all other unacknowledged messages
+    /// of the same channel are given up on as well.
+    /// Channels without unacknowledged messages are forgotten after the same time,
+    /// and the deduplication state of incoming channels after twice this time.
+    ///
+    /// Default value is 60000 ms.
+    pub fn set_reliable_timeout(&mut self, milliseconds: u64) {
+        self.reliable_timeout = milliseconds;
+    }
+
+    /// How long (in ms) a reliable message may remain unacknowledged, before it is given up on.
+    pub fn get_reliable_timeout(&self) -> u64 {
+        self.reliable_timeout
+    }
+
+    /// Encrypts all Network-channels with TLS, using the certificates and keys in `tls_config`.
+    ///
+    /// This also switches the protocol of the system's [SystemPath](SystemPath) to [TLS](Transport::TLS).
+    /// The files are read when the network thread is started, which fails if they are invalid.
+    ///
+    /// Default is no encryption.
+    pub fn set_tls_config(&mut self, tls_config: TlsConfig) -> () {
+        self.transport = Transport::TLS;
+        self.tls_config = Some(tls_config);
+    }
+
+    /// Returns the [TlsConfig](net::tls::TlsConfig) if TLS is enabled.
+    pub fn get_tls_config(&self) -> Option<&TlsConfig> {
+        self.tls_config.as_ref()
+    }
+
+    /// Requires all remote hosts to prove that they know the shared `secret` when a
+    /// Network-channel is set up, and proves to them that this system knows it as well.
+    ///
+    /// Channels to hosts that fail the challenge are dropped. If this system requested the
+    /// channel, the failure is reported to the `NetworkDispatcher` as
+    /// [AuthenticationFailed](ConnectionState::AuthenticationFailed), otherwise the address
+    /// the remote host claims is ignored, as it may not be its own.
+    /// The secret itself is never sent over the network, but the rest of the traffic
+    /// is only encrypted if [TLS](NetworkConfig::set_tls_config) is enabled as well.
+    ///
+    /// Default is no authentication.
+    pub fn set_auth_secret<S>(&mut self, secret: S) -> ()
+    where
+        S: AsRef<[u8]>,
+    {
+        self.auth_secret = Some(AuthSecret::new(secret.as_ref()));
+    }
+
+    /// Returns `true` if remote hosts must authenticate with a shared secret.
+    pub fn has_auth_secret(&self) -> bool {
+        self.auth_secret.is_some()
+    }
+
+    pub(crate) fn get_auth_secret(&self) -> Option<&AuthSecret> {
+        self.auth_secret.as_ref()
+    }
+
+    /// Lets `faults` decide whether messages to remote systems are dropped, duplicated, or delayed,
+    /// and which systems are partitioned from each other.
+    ///
+    /// Keep a clone of `faults` to change them at runtime.
+    /// See [NetworkFaults](faults::NetworkFaults) for details.
+    ///
+    /// Default is no fault injection.
+    pub fn set_fault_injection(&mut self, faults: NetworkFaults) -> () {
+        self.faults = Some(faults);
+    }
+
+    /// Returns the [NetworkFaults](faults::NetworkFaults) handle, if fault injection is enabled.
+    pub fn get_fault_injection(&self) -> Option<&NetworkFaults> {
+        self.faults.as_ref()
+    }
+
+    /// Exchanges all messages with other systems through the in-memory `fabric`,
+    /// instead of binding a socket and starting a network thread.
+    ///
+    /// The configured socket address only determines the system's virtual address on the fabric.
+    /// Transport specific settings, such as [TLS](NetworkConfig::set_tls_config), are ignored.
+    /// See [LoopbackFabric](loopback::LoopbackFabric) for details.
+    ///
+    /// Default is a real network.
+    pub fn set_loopback_fabric(&mut self, fabric: LoopbackFabric) -> () {
+        self.loopback = Some(fabric);
+    }
+
+    /// Returns the [LoopbackFabric](loopback::LoopbackFabric), if the system is on one.
+    pub fn get_loopback_fabric(&self) -> Option<&LoopbackFabric> {
+        self.loopback.as_ref()
+    }
+
+    /// Returns the metrics of the system the dispatcher runs in, if it records any
+    pub(crate) fn get_metrics(&self) -> Option<&SystemMetrics> {
+        self.metrics.as_ref()
+    }
 }
 
 /// Socket defaults to `127.0.0.1:0` (i.e. a random local port) and protocol is [TCP](Transport::TCP)
@@ -199,6 +409,15 @@ impl Default for NetworkConfig {
             tcp_nodelay: false,
             max_connection_retry_attempts: MAX_RETRY_ATTEMPTS,
             connection_retry_interval: RETRY_CONNECTIONS_INTERVAL,
+            credit_window: CREDIT_WINDOW,
+            unreachable_timeout: UNREACHABLE_TIMEOUT,
+            redelivery_interval: REDELIVERY_INTERVAL,
+            reliable_timeout: RELIABLE_TIMEOUT,
+            tls_config: None,
+            auth_secret: None,
+            faults: None,
+            loopback: None,
+            metrics: None,
         }
     }
 }
@@ -210,11 +429,22 @@ impl Default for NetworkConfig {
 /// This dispatcher automatically creates channels to requested target
 /// systems on demand and maintains them while in use.
 ///
-/// The current implementation only supports [TCP](Transport::TCP) as
-/// a transport protocol.
+/// The current implementation supports [TCP](Transport::TCP), [UDP](Transport::UDP),
+/// and, if configured, [TLS](Transport::TLS) as transport protocols.
+/// For tests, it can also be configured to use an in-memory [LoopbackFabric](loopback::LoopbackFabric)
+/// instead of the network.
 ///
 /// If possible, this implementation will "reflect" messages
 /// to local actors directly back up, instead of serialising them first.
+///
+/// Paths with a [domain](Address::Domain) address are resolved asynchronously on first use
+/// and the result is cached. Whenever a connection to a resolved address fails,
+/// the domain is resolved again and queued messages follow it to its new address.
+///
+/// The dispatcher also implements remote [death-watch](crate::prelude::ComponentContext::watch),
+/// by exchanging watch requests and termination notices with the dispatchers of other systems,
+/// and [reliable delivery](crate::prelude::ActorPath::tell_reliable), by numbering, acknowledging,
+/// and redelivering messages between them.
 #[derive(ComponentDefinition)]
 pub struct NetworkDispatcher {
     ctx: ComponentContext<NetworkDispatcher>,
@@ -227,10 +457,14 @@ pub struct NetworkDispatcher {
     // Fields initialized at [Start](ControlEvent::Start) – they require ComponentContextual awareness
     /// Bridge into asynchronous networking layer
     net_bridge: Option<net::Bridge>,
+    /// Address on the in-memory network, replacing the bridge if configured
+    loopback: Option<LoopbackEndpoint>,
     /// A cached version of the bound system path
     system_path: Option<SystemPath>,
     /// Management for queuing Frames during network unavailability (conn. init. and MPSC unreadiness)
     queue_manager: QueueManager,
+    /// Connections whose channel is out of credits. Frames are queued until it is released.
+    throttled: FxHashSet<SocketAddr>,
     /// Reaper which cleans up deregistered actor references in the actor lookup table
     reaper: lookup::gc::ActorRefReaper,
     notify_ready: Option<KPromise<()>>,
@@ -238,6 +472,17 @@ pub struct NetworkDispatcher {
     /// Stores the number of retry-attempts for connections. Checked and incremented periodically by the reaper.
     retry_map: FxHashMap<SocketAddr, u8>,
     garbage_buffers: VecDeque<BufferChunk>,
+    /// Cache of resolved domain names
+    resolved_domains: NetHashMap<String, IpAddr>,
+    /// Frames waiting for their destination domain to be resolved.
+    /// An entry exists for every domain that is currently being resolved.
+    pending_resolution: NetHashMap<String, Vec<(Transport, u16, SerialisedFrame)>>,
+    /// Watched paths and their local and remote watchers
+    death_watch: DeathWatch,
+    /// Channels for reliable delivery from and to this system
+    reliable: ReliableDelivery,
+    /// Per channel metrics, if the system records any
+    metrics: Option<NetworkMetrics>,
 }
 
 impl NetworkDispatcher {
@@ -280,13 +525,20 @@ impl NetworkDispatcher {
             cfg,
             lookup,
             net_bridge: None,
+            loopback: None,
             system_path: None,
             queue_manager: QueueManager::new(),
+            throttled: Default::default(),
             reaper,
             notify_ready: Some(notify_ready),
             encode_buffer,
             garbage_buffers: VecDeque::new(),
             retry_map: Default::default(),
+            resolved_domains: Default::default(),
+            pending_resolution: Default::default(),
+            death_watch: DeathWatch::default(),
+            reliable: ReliableDelivery::default(),
+            metrics: None,
         }
     }
 
@@ -310,33 +562,69 @@ impl NetworkDispatcher {
     }
 
     fn start(&mut self) -> Result<(), net::NetworkBridgeErr> {
-        debug!(self.ctx.log(), "Starting self and network bridge");
-        let dispatcher = self
-            .actor_ref()
-            .hold()
-            .expect("Self can hardly be deallocated!");
-        let bridge_logger = self.ctx.log().new(o!("owner" => "Bridge"));
-        let network_thread_logger = self.ctx.log().new(o!("owner" => "NetworkThread"));
-        let (mut bridge, _addr) = net::Bridge::new(
-            self.lookup.clone(),
-            network_thread_logger,
-            bridge_logger,
-            self.cfg.addr,
-            dispatcher.clone(),
-            &self.cfg,
-        );
+        let component = self.ctx.component();
+        if let Some(metrics) = component.core().system().system_metrics() {
+            let pool_metrics = metrics.buffer_pool("dispatcher");
+            self.encode_buffer.buffer_pool.set_metrics(pool_metrics);
+            self.queue_manager.set_metrics(metrics.network());
+            self.metrics = Some(metrics.network());
+            self.cfg.metrics = Some(metrics.clone());
+        }
+        let bridge = match self.cfg.loopback {
+            Some(ref fabric) => {
+                debug!(self.ctx.log(), "Starting self on loopback fabric");
+                let dispatcher = self
+                    .actor_ref()
+                    .hold()
+                    .expect("Self can hardly be deallocated!");
+                let endpoint = fabric
+                    .bind(self.cfg.addr, self.lookup.clone(), dispatcher)
+                    .map_err(NetworkBridgeErr::Binding)?;
+                self.loopback = Some(endpoint);
+                None
+            }
+            None => {
+                debug!(self.ctx.log(), "Starting self and network bridge");
+                let dispatcher = self
+                    .actor_ref()
+                    .hold()
+                    .expect("Self can hardly be deallocated!");
+                let bridge_logger = self.ctx.log().new(o!("owner" => "Bridge"));
+                let network_thread_logger = self.ctx.log().new(o!("owner" => "NetworkThread"));
+                let (mut bridge, _addr) = net::Bridge::new(
+                    self.lookup.clone(),
+                    network_thread_logger,
+                    bridge_logger,
+                    self.cfg.addr,
+                    dispatcher.clone(),
+                    &self.cfg,
+                );
+                bridge.set_dispatcher(dispatcher);
+                Some(bridge)
+            }
+        };
 
         let deadletter: DynActorRef = self.ctx.system().deadletter_ref().dyn_ref();
+        let endpoint: DynActorRef = self.actor_ref().dyn_ref();
         self.lookup.rcu(|current| {
             let mut next = ActorStore::clone(&current);
             next.insert(PathResolvable::System, deadletter.clone())
                 .expect("Deadletter shouldn't error");
+            next.insert(
+                PathResolvable::Alias(WATCH_ALIAS.to_string()),
+                endpoint.clone(),
+            )
+            .expect("Watch alias shouldn't error");
+            next.insert(
+                PathResolvable::Alias(RELIABLE_ALIAS.to_string()),
+                endpoint.clone(),
+            )
+            .expect("Reliable alias shouldn't error");
             next
         });
 
-        bridge.set_dispatcher(dispatcher);
         self.schedule_retries();
-        self.net_bridge = Some(bridge);
+        self.net_bridge = bridge;
         Ok(())
     }
 
@@ -349,6 +637,8 @@ impl NetworkDispatcher {
     }
 
     fn do_stop(&mut self, _cleanup: bool) -> () {
+        // leave the fabric, so that other systems consider us unreachable
+        self.loopback.take();
         if let Some(bridge) = self.net_bridge.take() {
             if let Err(e) = bridge.stop() {
                 error!(
@@ -451,6 +741,23 @@ impl NetworkDispatcher {
                     // These are messages which we routed to a network-thread before they lost the connection.
                     self.queue_manager.enqueue_priority_frame(frame, addr);
                 }
+                NetworkEvent::Throttled(addr) => {
+                    debug!(
+                        self.ctx().log(),
+                        "Queuing frames to throttled host {}", addr
+                    );
+                    self.throttled.insert(addr);
+                }
+                NetworkEvent::Released(addr) => {
+                    debug!(self.ctx().log(), "Host {} is no longer throttled", addr);
+                    self.throttled.remove(&addr);
+                    if let Err(e) = self.drain_queue(addr) {
+                        error!(
+                            self.ctx().log(),
+                            "Error while sending queued frames to {}, \n{:?}", addr, e
+                        )
+                    }
+                }
             },
         }
     }
@@ -468,15 +775,13 @@ impl NetworkDispatcher {
                     "registering newly connected conn at {:?}", addr
                 );
                 let _ = self.retry_map.remove(&addr);
-                if self.queue_manager.has_frame(&addr) {
-                    // Drain as much as possible
-                    while let Some(frame) = self.queue_manager.pop_frame(&addr) {
-                        if let Some(bridge) = &self.net_bridge {
-                            //println!("Sending queued frame to newly established connection");
-                            bridge.route(addr, frame, net::Protocol::TCP)?;
-                        }
-                    }
+                self.cancel_unreachable_timer(addr);
+                if let Some(ref mut metrics) = self.metrics {
+                    metrics.channel(addr).connected();
                 }
+                // A new channel starts without held back frames
+                self.throttled.remove(&addr);
+                self.drain_queue(addr)?;
             }
             Closed => {
                 if self.retry_map.get(&addr).is_none() {
@@ -487,6 +792,19 @@ impl NetworkDispatcher {
                 if let Some(bridge) = &self.net_bridge {
                     bridge.ack_closed(addr)?;
                 }
+                // The host may have moved, so check the domains that pointed to it
+                self.reresolve_domains(addr.ip());
+                self.start_unreachable_timer(addr);
+            }
+            AuthenticationFailed => {
+                error!(
+                    self.ctx().log(),
+                    "authentication failed for {:?}, dropping queued messages", addr
+                );
+                // Retrying won't help, the next message to addr will try again
+                let _ = self.retry_map.remove(&addr);
+                self.queue_manager.drop_queue(&addr);
+                self.start_unreachable_timer(addr);
             }
             Error(ref err) => {
                 match err {
@@ -503,10 +821,17 @@ impl NetworkDispatcher {
                         );
                     }
                 }
+                self.reresolve_domains(addr.ip());
+                self.start_unreachable_timer(addr);
             }
             ref _other => (), // Don't care
         }
+        let connected = matches!(state, Connected(_));
         self.connections.insert(addr, state);
+        if connected {
+            // Frames lost along with the previous connection never show up as rejected
+            self.redeliver(Some(addr));
+        }
         Ok(())
     }
 
@@ -533,7 +858,9 @@ impl NetworkDispatcher {
                         "No local actor found at {:?}. Forwarding to DeadletterBox",
                         netmsg.receiver,
                     );
-                    self.ctx.deadletter_ref().enqueue(MsgEnvelope::Net(netmsg));
+                    self.ctx
+                        .deadletter_ref()
+                        .enqueue(MsgEnvelope::Net(Box::new(netmsg)));
                     Ok(())
                 }
                 LookupResult::Err(e) => {
@@ -543,7 +870,9 @@ impl NetworkDispatcher {
                         netmsg.receiver,
                         e
                     );
-                    self.ctx.deadletter_ref().enqueue(MsgEnvelope::Net(netmsg));
+                    self.ctx
+                        .deadletter_ref()
+                        .enqueue(MsgEnvelope::Net(Box::new(netmsg)));
                     Ok(())
                 }
             },
@@ -560,21 +889,249 @@ impl NetworkDispatcher {
     where
         R: Routable,
     {
-        let dst = msg.destination();
-        let protocol: Transport = dst.protocol();
-        let addr = SocketAddr::new(*dst.address(), dst.port());
+        if let Some(faults) = self.cfg.faults.clone() {
+            return self.route_remote_faulty(&faults, msg);
+        }
+        let dst = msg.destination().system().clone();
+        let serialised = {
+            let buf = &mut self.encode_buffer.get_buffer_encoder();
+            msg.into_serialised(buf)?
+        };
+        self.route_remote_frame(&dst, serialised)
+    }
+
+    /// Routes `msg` like [route_remote](NetworkDispatcher::route_remote),
+    /// after dropping, duplicating, or delaying it as decided by `faults`
+    fn route_remote_faulty<R>(
+        &mut self,
+        faults: &NetworkFaults,
+        msg: R,
+    ) -> Result<(), NetworkBridgeErr>
+    where
+        R: Routable,
+    {
+        let dst = msg.destination().system().clone();
+        let delays = faults.plan(self.system_path_ref(), &dst);
+        if delays.is_empty() {
+            trace!(
+                self.ctx.log(),
+                "Fault injection dropped a message to {}",
+                dst
+            );
+            return Ok(());
+        }
         let serialised = {
             let buf = &mut self.encode_buffer.get_buffer_encoder();
             msg.into_serialised(buf)?
         };
+        let mut frames = Vec::with_capacity(delays.len());
+        let mut frame = serialised;
+        for _ in 1..delays.len() {
+            let (copy, original) = faults::duplicate_frame(frame);
+            frames.push(copy);
+            frame = original;
+        }
+        frames.push(frame);
+        for (delay, frame) in delays.into_iter().zip(frames) {
+            if delay > Duration::from_millis(0) {
+                let dst = dst.clone();
+                self.schedule_once(delay, move |target, _id| {
+                    if let Err(e) = target.route_remote_frame(&dst, frame) {
+                        error!(target.ctx.log(), "Failed to route delayed message: {:?}", e);
+                    }
+                    Handled::Ok
+                });
+            } else {
+                self.route_remote_frame(&dst, frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Routes an already serialised frame to the system at `dst`,
+    /// resolving its domain first, if necessary
+    fn route_remote_frame(
+        &mut self,
+        dst: &SystemPath,
+        serialised: SerialisedFrame,
+    ) -> Result<(), NetworkBridgeErr> {
+        let protocol: Transport = dst.protocol();
+        let port = dst.port();
+        let target: Result<SocketAddr, String> = match dst.address() {
+            Address::Ip(ip) => Ok(SocketAddr::new(*ip, port)),
+            Address::Domain(domain) => match self.resolved_domains.get(domain) {
+                Some(ip) => Ok(SocketAddr::new(*ip, port)),
+                None => Err(domain.clone()),
+            },
+        };
+
+        match target {
+            Ok(addr) => self.route_remote_addr(protocol, addr, serialised),
+            Err(domain) => {
+                self.resolve_domain(domain.clone());
+                self.pending_resolution
+                    .entry(domain)
+                    .or_default()
+                    .push((protocol, port, serialised));
+                Ok(())
+            }
+        }
+    }
 
+    fn route_remote_addr(
+        &mut self,
+        protocol: Transport,
+        addr: SocketAddr,
+        serialised: SerialisedFrame,
+    ) -> Result<(), NetworkBridgeErr> {
+        if self.loopback.is_some() {
+            return self.route_loopback(addr, serialised);
+        }
         match protocol {
-            Transport::TCP => self.route_remote_tcp(addr, serialised),
+            Transport::TCP | Transport::TLS => self.route_remote_tcp(addr, serialised),
             Transport::UDP => self.route_remote_udp(addr, serialised),
             x => unimplemented!("Unsupported protocol: {}", x),
         }
     }
 
+    /// Starts resolving `domain` in the background, unless it is already being resolved
+    fn resolve_domain(&mut self, domain: String) {
+        if self.pending_resolution.contains_key(&domain) {
+            return;
+        }
+        self.pending_resolution.insert(domain.clone(), Vec::new());
+        debug!(self.ctx.log(), "Resolving domain {}", domain);
+        self.spawn_local(move |mut async_self| async move {
+            // The port is irrelevant here, as it is taken from the destination path
+            let res = async_std::net::ToSocketAddrs::to_socket_addrs(&(domain.as_str(), 0))
+                .await
+                .map(|addrs| addrs.map(|addr| addr.ip()).collect::<Vec<IpAddr>>());
+            async_self.on_domain_resolved(domain, res);
+            Handled::Ok
+        });
+    }
+
+    fn on_domain_resolved(&mut self, domain: String, res: std::io::Result<Vec<IpAddr>>) {
+        let frames = self.pending_resolution.remove(&domain).unwrap_or_default();
+        let ip = match res {
+            Ok(ips) => self.select_ip(&ips),
+            Err(e) => {
+                error!(self.ctx.log(), "Could not resolve domain {}: {}", domain, e);
+                None
+            }
+        };
+        let ip = match ip {
+            Some(ip) => {
+                info!(self.ctx.log(), "Resolved domain {} to {}", domain, ip);
+                if let Some(old_ip) = self.resolved_domains.insert(domain, ip) {
+                    if old_ip != ip {
+                        self.migrate_queues(old_ip, ip);
+                    }
+                }
+                ip
+            }
+            // Keep using the previous address, if there is one
+            None => match self.resolved_domains.get(&domain) {
+                Some(ip) => *ip,
+                None => {
+                    warn!(
+                        self.ctx.log(),
+                        "Dropping {} message(s) to unresolvable domain {}",
+                        frames.len(),
+                        domain
+                    );
+                    return;
+                }
+            },
+        };
+        for (protocol, port, frame) in frames {
+            if let Err(e) = self.route_remote_addr(protocol, SocketAddr::new(ip, port), frame) {
+                error!(self.ctx.log(), "Failed to route message: {:?}", e);
+            }
+        }
+    }
+
+    /// Prefers addresses of the same family as the bound address
+    fn select_ip(&self, ips: &[IpAddr]) -> Option<IpAddr> {
+        let local_v4 = self
+            .net_bridge
+            .as_ref()
+            .and_then(|bridge| *bridge.local_addr())
+            .is_none_or(|addr| addr.is_ipv4());
+        ips.iter()
+            .find(|ip| ip.is_ipv4() == local_v4)
+            .or_else(|| ips.first())
+            .copied()
+    }
+
+    /// Resolves all cached domains that currently point to `ip` again
+    fn reresolve_domains(&mut self, ip: IpAddr) {
+        let domains: Vec<String> = self
+            .resolved_domains
+            .iter()
+            .filter(|(_, resolved)| **resolved == ip)
+            .map(|(domain, _)| domain.clone())
+            .collect();
+        for domain in domains {
+            self.resolve_domain(domain);
+        }
+    }
+
+    /// Moves the frames queued for hosts at `old_ip` over to the same ports at `new_ip`
+    fn migrate_queues(&mut self, old_ip: IpAddr, new_ip: IpAddr) {
+        let stale: Vec<SocketAddr> = self
+            .retry_map
+            .keys()
+            .filter(|addr| addr.ip() == old_ip)
+            .copied()
+            .collect();
+        for old_addr in stale {
+            let new_addr = SocketAddr::new(new_ip, old_addr.port());
+            info!(
+                self.ctx.log(),
+                "Host {} moved to {}, redirecting queued messages", old_addr, new_addr
+            );
+            self.retry_map.remove(&old_addr);
+            while let Some(frame) = self.queue_manager.pop_frame(&old_addr) {
+                if let Err(e) = self.route_remote_tcp(new_addr, frame) {
+                    error!(self.ctx.log(), "Failed to route message: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Delivers `serialised` to the system at `addr` on the loopback fabric,
+    /// regardless of the protocol
+    fn route_loopback(
+        &mut self,
+        addr: SocketAddr,
+        serialised: SerialisedFrame,
+    ) -> Result<(), NetworkBridgeErr> {
+        let res = match self.loopback {
+            Some(ref endpoint) => endpoint.deliver(addr, serialised, self.ctx.log()),
+            None => unreachable!("Only called on a loopback fabric"),
+        };
+        match res {
+            Ok(()) => {
+                self.cancel_unreachable_timer(addr);
+                Ok(())
+            }
+            Err(LoopbackError::Unreachable(addr)) => {
+                debug!(
+                    self.ctx.log(),
+                    "Dropping message to {}, as no system is bound there on the loopback fabric",
+                    addr
+                );
+                self.start_unreachable_timer(addr);
+                Ok(())
+            }
+            Err(LoopbackError::Malformed(e)) => Err(NetworkBridgeErr::Other(format!(
+                "Could not deserialise loopback frame: {}",
+                e
+            ))),
+        }
+    }
+
     fn route_remote_udp(
         &mut self,
         addr: SocketAddr,
@@ -599,7 +1156,7 @@ impl NetworkDispatcher {
         let state: &mut ConnectionState =
             self.connections.entry(addr).or_insert(ConnectionState::New);
         let next: Option<ConnectionState> = match *state {
-            ConnectionState::New => {
+            ConnectionState::New | ConnectionState::AuthenticationFailed => {
                 debug!(
                     self.ctx.log(),
                     "No connection found; establishing and queuing frame"
@@ -616,6 +1173,10 @@ impl NetworkDispatcher {
                     Some(ConnectionState::Closed)
                 }
             }
+            ConnectionState::Connected(_) if self.throttled.contains(&addr) => {
+                self.queue_manager.enqueue_frame(serialised, addr);
+                None
+            }
             ConnectionState::Connected(_) => {
                 if self.queue_manager.has_frame(&addr) {
                     self.queue_manager.enqueue_frame(serialised, addr);
@@ -653,6 +1214,16 @@ impl NetworkDispatcher {
         Ok(())
     }
 
+    /// Sends all frames queued for `addr` to the network thread
+    fn drain_queue(&mut self, addr: SocketAddr) -> Result<(), NetworkBridgeErr> {
+        if let Some(bridge) = &self.net_bridge {
+            while let Some(frame) = self.queue_manager.pop_frame(&addr) {
+                bridge.route(addr, frame, net::Protocol::TCP)?;
+            }
+        }
+        Ok(())
+    }
+
     fn resolve_path(&mut self, resolvable: &PathResolvable) -> Result<ActorPath, PathParseError> {
         match resolvable {
             PathResolvable::Path(actor_path) => Ok(actor_path.clone()),
@@ -683,6 +1254,7 @@ impl NetworkDispatcher {
                 Transport::LOCAL => self.route_local(msg),
                 Transport::TCP => self.route_remote(msg),
                 Transport::UDP => self.route_remote(msg),
+                Transport::TLS => self.route_remote(msg),
             }
         }
     }
@@ -796,8 +1368,13 @@ impl Actor for NetworkDispatcher {
 
     fn receive_local(&mut self, msg: Self::Message) -> Handled {
         match msg {
-            DispatchEnvelope::Msg { src, dst, msg } => {
-                if let Err(e) = self.route((src, dst, msg)) {
+            DispatchEnvelope::Msg {
+                src,
+                dst,
+                msg,
+                trace_context,
+            } => {
+                if let Err(e) = self.route((src, dst, msg, trace_context)) {
                     error!(self.ctx.log(), "Failed to route message: {:?}", e);
                 };
             }
@@ -820,12 +1397,25 @@ impl Actor for NetworkDispatcher {
             }
             DispatchEnvelope::Event(ev) => self.on_event(ev),
             DispatchEnvelope::LockedChunk(trash) => self.garbage_buffers.push_back(trash),
+            DispatchEnvelope::Watch(watch) => self.on_watch(watch),
+            DispatchEnvelope::Reliable(reliable) => self.on_reliable(reliable),
         }
         Handled::Ok
     }
 
     fn receive_network(&mut self, msg: NetMessage) -> Handled {
-        warn!(self.ctx.log(), "Received network message: {:?}", msg,);
+        let sender = msg.sender.clone();
+        let res = match *msg.ser_id() {
+            ReliableMsg::SER_ID => msg
+                .try_deserialise::<ReliableMsg, ReliableMsg>()
+                .map(|reliable_msg| self.on_reliable_msg(sender, reliable_msg)),
+            _ => msg
+                .try_deserialise::<WatchMsg, WatchMsg>()
+                .map(|watch_msg| self.on_watch_msg(sender, watch_msg)),
+        };
+        if let Err(e) = res {
+            warn!(self.ctx.log(), "Received network message: {:?}", e);
+        }
         Handled::Ok
     }
 }
@@ -838,9 +1428,10 @@ impl Dispatcher for NetworkDispatcher {
         match self.system_path {
             Some(ref path) => path.clone(),
             None => {
-                let bound_addr = match self.net_bridge {
-                    Some(ref net_bridge) => net_bridge.local_addr().clone().expect("If net bridge is ready, port should be as well!"),
-                    None => panic!("You must wait until the socket is bound before attempting to create a system path!"),
+                let bound_addr = match (&self.net_bridge, &self.loopback) {
+                    (Some(net_bridge), _) => net_bridge.local_addr().clone().expect("If net bridge is ready, port should be as well!"),
+                    (None, Some(endpoint)) => endpoint.addr(),
+                    (None, None) => panic!("You must wait until the socket is bound before attempting to create a system path!"),
                 };
                 let sp = SystemPath::new(self.cfg.transport, bound_addr.ip(), bound_addr.port());
                 self.system_path = Some(sp.clone());
@@ -954,7 +1545,7 @@ impl Routable for NetMessage {
         Ok(self)
     }
 }
-impl Routable for (ActorPath, ActorPath, DispatchData) {
+impl Routable for (ActorPath, ActorPath, DispatchData, Option<TraceContext>) {
     fn source(&self) -> &ActorPath {
         &self.0
     }
@@ -964,18 +1555,21 @@ impl Routable for (ActorPath, ActorPath, DispatchData) {
     }
 
     fn into_serialised(self, buf: &mut BufferEncoder) -> Result<SerialisedFrame, SerError> {
-        self.2.into_serialised(self.0, self.1, buf)
+        self.2.into_serialised(self.0, self.1, self.3, buf)
     }
 
     fn into_local(self) -> Result<NetMessage, SerError> {
-        self.2.into_local(self.0, self.1)
+        self.2.into_local(self.0, self.1, self.3)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::{super::*, *};
-    use crate::prelude_test::net_test_helpers::{PingerAct, PongerAct};
+    use crate::{
+        prelude_test::net_test_helpers::{PingerAct, PongerAct},
+        testkit::{networked_system_with_faults, wait_until, TestProbe},
+    };
     use std::{thread, time::Duration};
 
     // replace ignore with panic cfg gate when https://github.com/rust-lang/rust/pull/74754 is merged
@@ -1171,19 +1765,17 @@ mod tests {
         // Kill the actor and wait for its BufferChunk to reach the NetworkDispatch and let the reaping try at least once
         system1.kill(pinger_named);
 
-        // TODO no sleeps!
-        thread::sleep(Duration::from_millis(5000));
-
         // Assertion 1: The Network_Dispatcher on system1 has >0 buffers to cleanup
-        let mut garbage_len = 0;
         let sc: &dyn SystemComponents = system1.get_system_components();
-        match sc.downcast::<CustomComponents<DeadletterBox, NetworkDispatcher>>() {
-            Some(cc) => {
-                garbage_len = cc.dispatcher.on_definition(|nd| nd.garbage_buffers.len());
-            }
-            _ => {}
-        }
-        assert_ne!(0, garbage_len);
+        let garbage_len =
+            || match sc.downcast::<CustomComponents<DeadletterBox, NetworkDispatcher>>() {
+                Some(cc) => cc.dispatcher.on_definition(|nd| nd.garbage_buffers.len()),
+                _ => 0,
+            };
+        assert!(
+            wait_until(Duration::from_millis(5000), || garbage_len() != 0),
+            "BufferChunk should have been queued for cleanup"
+        );
 
         // Start up system2b
         println!("Setting up system2b");
@@ -1196,17 +1788,11 @@ mod tests {
         system2b.start(&ponger_named);
 
         // We give the connection plenty of time to re-establish and transfer it's old queue and cleanup the BufferChunk
-        // TODO no sleeps!
-        thread::sleep(Duration::from_millis(10000));
-
         // Assertion 2: The Network_Dispatcher on system1 now has 0 buffers to cleanup.
-        match sc.downcast::<CustomComponents<DeadletterBox, NetworkDispatcher>>() {
-            Some(cc) => {
-                garbage_len = cc.dispatcher.on_definition(|nd| nd.garbage_buffers.len());
-            }
-            _ => {}
-        }
-        assert_eq!(0, garbage_len);
+        assert!(
+            wait_until(Duration::from_millis(10000), || garbage_len() == 0),
+            "BufferChunk should have been cleaned up"
+        );
 
         system1
             .shutdown()
@@ -1215,4 +1801,259 @@ mod tests {
             .shutdown()
             .expect("Kompact didn't shut down properly");
     }
+
+    const FAULT_TIMEOUT: Duration = Duration::from_millis(5000);
+
+    fn expect_u64(probe: &TestProbe<u64>) -> u64 {
+        probe
+            .expect_net_msg(FAULT_TIMEOUT)
+            .try_deserialise::<u64, u64>()
+            .expect("u64")
+    }
+
+    #[test]
+    fn fault_injection_partitions_systems() {
+        let faults = NetworkFaults::new();
+        let system1 = networked_system_with_faults(&faults);
+        let system2 = networked_system_with_faults(&faults);
+        let probe1: TestProbe<u64> = TestProbe::new(&system1);
+        let path1 = probe1.register();
+        let probe2: TestProbe<u64> = TestProbe::new(&system2);
+        let path2 = probe2.register();
+
+        path2.tell_with_sender(1u64, &system1, path1.clone());
+        assert_eq!(1, expect_u64(&probe2));
+
+        faults.partition(&system1.system_path(), &system2.system_path());
+        path2.tell_with_sender(2u64, &system1, path1.clone());
+        path1.tell_with_sender(3u64, &system2, path2.clone());
+        probe2.expect_no_msg(Duration::from_millis(200));
+        probe1.expect_no_msg(Duration::from_millis(200));
+        assert_eq!(2, faults.stats().partitioned);
+
+        faults.heal(&system2.system_path(), &system1.system_path());
+        path2.tell_with_sender(4u64, &system1, path1.clone());
+        path1.tell_with_sender(5u64, &system2, path2.clone());
+        assert_eq!(4, expect_u64(&probe2));
+        assert_eq!(5, expect_u64(&probe1));
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn fault_injection_drops_and_duplicates() {
+        let faults = NetworkFaults::new();
+        let system1 = networked_system_with_faults(&faults);
+        let system2 = networked_system_with_faults(&faults);
+        let sender: TestProbe<u64> = TestProbe::new(&system1);
+        let sender_path = sender.register();
+        let probe: TestProbe<u64> = TestProbe::new(&system2);
+        let path = probe.register();
+
+        faults.set_drop_probability(1.0);
+        for i in 0..10u64 {
+            path.tell_with_sender(i, &system1, sender_path.clone());
+        }
+        probe.expect_no_msg(Duration::from_millis(200));
+        assert_eq!(10, faults.stats().dropped);
+
+        faults.reset();
+        faults.set_duplicate_probability(1.0);
+        path.tell_with_sender(42u64, &system1, sender_path.clone());
+        assert_eq!(42, expect_u64(&probe));
+        assert_eq!(42, expect_u64(&probe));
+        probe.expect_no_msg(Duration::from_millis(200));
+        assert_eq!(1, faults.stats().duplicated);
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn fault_injection_delays_and_reorders() {
+        let faults = NetworkFaults::with_seed(3);
+        faults.set_latency(Duration::from_millis(10), Duration::from_millis(300));
+        let system1 = networked_system_with_faults(&faults);
+        let system2 = networked_system_with_faults(&faults);
+        let sender: TestProbe<u64> = TestProbe::new(&system1);
+        let sender_path = sender.register();
+        let probe: TestProbe<u64> = TestProbe::new(&system2);
+        let path = probe.register();
+
+        let sent: Vec<u64> = (0..20).collect();
+        for i in sent.iter() {
+            path.tell_with_sender(*i, &system1, sender_path.clone());
+        }
+        let received: Vec<u64> = sent.iter().map(|_| expect_u64(&probe)).collect();
+        assert_ne!(sent, received, "Messages should have been reordered");
+        let mut sorted = received.clone();
+        sorted.sort_unstable();
+        assert_eq!(sent, sorted);
+        assert_eq!(20, faults.stats().delayed);
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn reliable_delivery_redelivers_and_deduplicates() {
+        let faults = NetworkFaults::new();
+        let system1 = networked_system_with_faults(&faults);
+        let system2 = networked_system_with_faults(&faults);
+        let sender: TestProbe<u64> = TestProbe::new(&system1);
+        let sender_path = sender.register();
+        let probe: TestProbe<u64> = TestProbe::new(&system2);
+        let path = probe.register();
+        let from = sender_path.using_dispatcher(&system1);
+
+        path.tell_reliable(0u64, &from)
+            .expect("serialise")
+            .wait_timeout(FAULT_TIMEOUT)
+            .expect("ack");
+        assert_eq!(0, expect_u64(&probe));
+
+        faults.partition(&system1.system_path(), &system2.system_path());
+        let acks: Vec<KFuture<()>> = (1..5u64)
+            .map(|i| path.tell_reliable(i, &from).expect("serialise"))
+            .collect();
+        probe.expect_no_msg(Duration::from_millis(200));
+
+        // redelivered once the partition is gone, but delivered only once and in order,
+        // even though every message is now duplicated
+        faults.set_duplicate_probability(1.0);
+        faults.heal(&system1.system_path(), &system2.system_path());
+        for ack in acks {
+            ack.wait_timeout(FAULT_TIMEOUT).expect("ack");
+        }
+        for i in 1..5u64 {
+            assert_eq!(i, expect_u64(&probe));
+        }
+        probe.expect_no_msg(Duration::from_millis(200));
+        assert!(faults.stats().duplicated > 0);
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+
+    fn reliable_system(faults: &NetworkFaults, reliable_timeout: u64) -> KompactSystem {
+        let mut net_cfg = NetworkConfig::default();
+        net_cfg.set_fault_injection(faults.clone());
+        net_cfg.set_reliable_timeout(reliable_timeout);
+        let mut cfg = KompactConfig::default();
+        cfg.system_components(DeadletterBox::new, net_cfg.build());
+        cfg.build().expect("KompactSystem")
+    }
+
+    fn reliable_channels(system: &KompactSystem) -> (usize, usize, usize) {
+        let sc: &dyn SystemComponents = system.get_system_components();
+        let cc = sc
+            .downcast::<CustomComponents<DeadletterBox, NetworkDispatcher>>()
+            .expect("NetworkDispatcher");
+        cc.dispatcher.on_definition(|nd| nd.reliable.channels())
+    }
+
+    #[test]
+    fn reliable_delivery_gives_up_and_expires_channels() {
+        let faults = NetworkFaults::new();
+        let system1 = reliable_system(&faults, 500);
+        let system2 = reliable_system(&faults, 500);
+        let sender: TestProbe<u64> = TestProbe::new(&system1);
+        let sender_path = sender.register();
+        let probe: TestProbe<u64> = TestProbe::new(&system2);
+        let path = probe.register();
+        let from = sender_path.using_dispatcher(&system1);
+
+        path.tell_reliable(0u64, &from)
+            .expect("serialise")
+            .wait_timeout(FAULT_TIMEOUT)
+            .expect("ack");
+        assert_eq!(0, expect_u64(&probe));
+
+        // both sides forget the idle channel
+        assert!(
+            wait_until(FAULT_TIMEOUT, || reliable_channels(&system1) == (0, 0, 0)
+                && reliable_channels(&system2) == (0, 0, 0)),
+            "Idle reliable channels should have been expired"
+        );
+
+        // messages that can't be delivered fail their futures after the timeout
+        faults.partition(&system1.system_path(), &system2.system_path());
+        let acks: Vec<KFuture<()>> = (1..3u64)
+            .map(|i| path.tell_reliable(i, &from).expect("serialise"))
+            .collect();
+        for ack in acks {
+            match ack.wait_timeout(FAULT_TIMEOUT) {
+                Err(WaitErr::PromiseDropped(_)) => (),
+                res => panic!("Expected the promise to be dropped, got {:?}", res),
+            }
+        }
+        assert_eq!((0, 0), {
+            let (sessions, outgoing, _) = reliable_channels(&system1);
+            (sessions, outgoing)
+        });
+        probe.expect_no_msg(Duration::from_millis(200));
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+
+    fn flow_controlled_system(buffer_config: BufferConfig) -> KompactSystem {
+        let mut net_cfg = NetworkConfig::default();
+        net_cfg.set_buffer_config(buffer_config);
+        net_cfg.set_credit_window(16);
+        let mut cfg = KompactConfig::default();
+        cfg.system_components(DeadletterBox::new, net_cfg.build());
+        cfg.build().expect("KompactSystem")
+    }
+
+    fn throttled_hosts(system: &KompactSystem) -> usize {
+        let sc: &dyn SystemComponents = system.get_system_components();
+        let cc = sc
+            .downcast::<CustomComponents<DeadletterBox, NetworkDispatcher>>()
+            .expect("NetworkDispatcher");
+        cc.dispatcher.on_definition(|nd| nd.throttled.len())
+    }
+
+    #[test]
+    fn slow_receiver_throttles_sender() {
+        const MSG_COUNT: u64 = 1000;
+        let sender_system = flow_controlled_system(BufferConfig::default());
+        let mut small_buffers = BufferConfig::default();
+        small_buffers.chunk_size(1024);
+        small_buffers.initial_chunk_count(2);
+        small_buffers.max_chunk_count(8);
+        let receiver_system = flow_controlled_system(small_buffers);
+        let sender: TestProbe<u64> = TestProbe::new(&sender_system);
+        let sender_path = sender.register();
+        let from = sender_path.using_dispatcher(&sender_system);
+        let receiver: TestProbe<u64> = TestProbe::new(&receiver_system);
+        let receiver_path = receiver.register();
+
+        // Messages for the stopped receiver hold on to the chunks they were received into,
+        // until the receiving BufferPool runs dry and no more credits are granted.
+        receiver_system
+            .stop_notify(receiver.component())
+            .wait_timeout(FAULT_TIMEOUT)
+            .expect("receiver never stopped");
+        for i in 0..MSG_COUNT {
+            receiver_path.tell(i, &from);
+        }
+        assert!(
+            wait_until(FAULT_TIMEOUT, || throttled_hosts(&sender_system) == 1),
+            "The sender should have been throttled"
+        );
+
+        receiver_system.start(receiver.component());
+        for i in 0..MSG_COUNT {
+            assert_eq!(i, expect_u64(&receiver));
+        }
+        assert!(
+            wait_until(FAULT_TIMEOUT, || throttled_hosts(&sender_system) == 0),
+            "The sender should have been released"
+        );
+
+        sender_system.shutdown().expect("shutdown");
+        receiver_system.shutdown().expect("shutdown");
+    }
 }
@@ -1,4 +1,4 @@
-use crate::messaging::SerialisedFrame;
+use crate::{messaging::SerialisedFrame, metrics::instruments::NetworkMetrics};
 use std::{
     collections::{HashMap, VecDeque},
     net::SocketAddr,
@@ -11,6 +11,7 @@ use std::{
 pub struct QueueManager {
     inner: HashMap<SocketAddr, VecDeque<SerialisedFrame>>,
     priority_queue: HashMap<SocketAddr, VecDeque<SerialisedFrame>>,
+    metrics: Option<NetworkMetrics>,
 }
 
 impl QueueManager {
@@ -18,6 +19,18 @@ impl QueueManager {
         QueueManager {
             inner: HashMap::new(),
             priority_queue: HashMap::new(),
+            metrics: None,
+        }
+    }
+
+    /// Report the number of queued frames per destination to `metrics` from now on
+    pub(crate) fn set_metrics(&mut self, metrics: NetworkMetrics) {
+        self.metrics = Some(metrics);
+    }
+
+    fn record_queued(&mut self, dst: SocketAddr, change: i64) {
+        if let Some(ref mut metrics) = self.metrics {
+            metrics.channel(dst).queued_frames.add(change);
         }
     }
 
@@ -33,6 +46,7 @@ impl QueueManager {
             .entry(dst)
             .or_insert_with(VecDeque::new)
             .push_front(frame);
+        self.record_queued(dst, 1);
     }
 
     /// Appends the given frame onto the SocketAddr's queue
@@ -41,6 +55,7 @@ impl QueueManager {
             .entry(dst)
             .or_insert_with(VecDeque::new)
             .push_front(frame);
+        self.record_queued(dst, 1);
     }
 
     /// Extracts the next queue-up frame for the SocketAddr, if one exists
@@ -57,12 +72,18 @@ impl QueueManager {
                 self.inner.remove(dst);
             }
         }
+        if res.is_some() {
+            self.record_queued(*dst, -1);
+        }
         res
     }
 
     pub fn drop_queue(&mut self, addr: &SocketAddr) {
-        self.priority_queue.remove(addr);
-        self.inner.remove(addr);
+        let dropped = self.priority_queue.remove(addr).map_or(0, |q| q.len())
+            + self.inner.remove(addr).map_or(0, |q| q.len());
+        if dropped > 0 {
+            self.record_queued(*addr, -(dropped as i64));
+        }
     }
 
     /*
@@ -0,0 +1,545 @@
+//! Reliable delivery support for the [NetworkDispatcher](NetworkDispatcher)
+//!
+//! Messages sent with [tell_reliable](ActorPath::tell_reliable) are numbered per channel,
+//! i.e. per pair of source and destination path, and kept by the sending dispatcher until
+//! the dispatcher of the destination system acknowledges them. Unacknowledged messages are
+//! sent again whenever a connection to the destination is (re-)established, as well as
+//! periodically while it is up. The receiving dispatcher, which is always registered under
+//! [RELIABLE_ALIAS](RELIABLE_ALIAS), hands every message to its destination only once
+//! and in order, and acknowledges everything it delivered so far cumulatively.
+//!
+//! A channel whose oldest unacknowledged message exceeds the reliable timeout is given up on,
+//! which fails all its pending messages. Idle channels are forgotten after the same time on the
+//! sending side, and after twice that time on the receiving side. By then, the sender can not
+//! send any message of the channel again, which the receiver might have already delivered.
+
+use super::*;
+use crate::{
+    messaging::ReliableEnvelope,
+    serialisation::{
+        serialisation_ids,
+        Deserialiser,
+        SerError,
+        SerId,
+        SerIdBuf,
+        SerIdBufMut,
+        Serialisable,
+    },
+    timer::timer_manager::ScheduledTimer,
+};
+use bytes::{Buf, BufMut, Bytes};
+use std::{any::Any, collections::BTreeMap, mem, time::Instant};
+use uuid::Uuid;
+
+/// The alias under which every [NetworkDispatcher](NetworkDispatcher) receives
+/// reliable delivery messages from its peers
+pub(crate) const RELIABLE_ALIAS: &str = "$reliable";
+
+/// The reliable delivery protocol spoken between dispatchers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReliableMsg {
+    /// Message number `seq` of the channel with the given `session`
+    Deliver {
+        /// Identifies the channel, and changes whenever the sending system restarts
+        session: Uuid,
+        /// The number of the message in its channel, starting at 1
+        seq: u64,
+        /// All messages up to here have already been acknowledged
+        acked: u64,
+        /// The original sender of the message
+        src: ActorPath,
+        /// The final destination of the message
+        dst: ActorPath,
+        /// The serialisation id of the message
+        ser_id: SerId,
+        /// The serialised message
+        data: Bytes,
+    },
+    /// All messages up to and including `seq` of the channel with the given `session` were delivered
+    Ack {
+        /// Identifies the channel
+        session: Uuid,
+        /// The number of the last delivered message
+        seq: u64,
+    },
+}
+
+impl ReliableMsg {
+    const DELIVER: u8 = 1;
+    const ACK: u8 = 2;
+
+    /// The size of the tag, the session, and the sequence number, which all messages start with
+    const HEADER_LEN: usize = 1 + 16 + 8;
+}
+
+impl Serialisable for ReliableMsg {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::RELIABLE_DELIVERY
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self {
+            ReliableMsg::Deliver { src, dst, data, .. } => {
+                let paths = src.size_hint()? + dst.size_hint()?;
+                Some(ReliableMsg::HEADER_LEN + 8 + paths + mem::size_of::<SerId>() + 4 + data.len())
+            }
+            ReliableMsg::Ack { .. } => Some(ReliableMsg::HEADER_LEN),
+        }
+    }
+
+    fn serialise(&self, mut buf: &mut dyn BufMut) -> Result<(), SerError> {
+        match self {
+            ReliableMsg::Deliver {
+                session,
+                seq,
+                acked,
+                src,
+                dst,
+                ser_id,
+                data,
+            } => {
+                buf.put_u8(ReliableMsg::DELIVER);
+                buf.put_u128(session.as_u128());
+                buf.put_u64(*seq);
+                buf.put_u64(*acked);
+                src.serialise(buf)?;
+                dst.serialise(buf)?;
+                buf.put_ser_id(*ser_id);
+                buf.put_u32(data.len() as u32);
+                buf.put_slice(data);
+            }
+            ReliableMsg::Ack { session, seq } => {
+                buf.put_u8(ReliableMsg::ACK);
+                buf.put_u128(session.as_u128());
+                buf.put_u64(*seq);
+            }
+        }
+        Ok(())
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<ReliableMsg> for ReliableMsg {
+    const SER_ID: SerId = serialisation_ids::RELIABLE_DELIVERY;
+
+    fn deserialise(mut buf: &mut dyn Buf) -> Result<ReliableMsg, SerError> {
+        if buf.remaining() < ReliableMsg::HEADER_LEN {
+            return Err(SerError::InvalidData(
+                "Could not get header for ReliableMsg".into(),
+            ));
+        }
+        let tag = buf.get_u8();
+        let session = Uuid::from_u128(buf.get_u128());
+        let seq = buf.get_u64();
+        match tag {
+            ReliableMsg::DELIVER => {
+                if buf.remaining() < 8 {
+                    return Err(SerError::InvalidData(
+                        "Could not get acked sequence number for ReliableMsg".into(),
+                    ));
+                }
+                let acked = buf.get_u64();
+                let src = ActorPath::deserialise(buf)?;
+                let dst = ActorPath::deserialise(buf)?;
+                if buf.remaining() < mem::size_of::<SerId>() + 4 {
+                    return Err(SerError::InvalidData(
+                        "Could not get data header for ReliableMsg".into(),
+                    ));
+                }
+                let ser_id = buf.get_ser_id();
+                let len = buf.get_u32() as usize;
+                if buf.remaining() < len {
+                    return Err(SerError::InvalidData(format!(
+                        "ReliableMsg data of {} bytes cut short at {}",
+                        len,
+                        buf.remaining()
+                    )));
+                }
+                let mut data = vec![0u8; len];
+                buf.copy_to_slice(&mut data);
+                Ok(ReliableMsg::Deliver {
+                    session,
+                    seq,
+                    acked,
+                    src,
+                    dst,
+                    ser_id,
+                    data: Bytes::from(data),
+                })
+            }
+            ReliableMsg::ACK => Ok(ReliableMsg::Ack { session, seq }),
+            _ => Err(SerError::InvalidType(format!(
+                "Unknown ReliableMsg tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// A message that has not been acknowledged yet
+struct Unacked {
+    ser_id: SerId,
+    data: Bytes,
+    /// Dropping the promise fails the sender's future
+    promise: KPromise<()>,
+    /// When the message was sent for the first time
+    sent_at: Instant,
+}
+
+/// The sending side of a channel
+struct OutgoingChannel {
+    src: ActorPath,
+    dst: ActorPath,
+    /// The sequence number of the last acknowledged message
+    acked: u64,
+    /// The sequence number of the last sent message
+    sent: u64,
+    unacked: BTreeMap<u64, Unacked>,
+    /// When a message was last sent or acknowledged
+    last_active: Instant,
+}
+
+impl OutgoingChannel {
+    /// Whether the channel should be given up on or forgotten at `now`
+    fn is_expired(&self, now: Instant, timeout: Duration) -> bool {
+        let since = match self.unacked.values().next() {
+            Some(oldest) => oldest.sent_at,
+            None => self.last_active,
+        };
+        now.duration_since(since) >= timeout
+    }
+}
+
+/// The receiving side of a channel
+struct IncomingChannel {
+    /// The sequence number of the last delivered message
+    delivered: u64,
+    /// When a message of the channel was last received
+    last_active: Instant,
+}
+
+/// Reliable delivery state of a [NetworkDispatcher](NetworkDispatcher)
+#[derive(Default)]
+pub(crate) struct ReliableDelivery {
+    /// The session of the channel between each pair of source and destination
+    sessions: NetHashMap<(ActorPath, ActorPath), Uuid>,
+    /// The channels this system sends on
+    outgoing: NetHashMap<Uuid, OutgoingChannel>,
+    /// The channels this system receives on
+    incoming: NetHashMap<Uuid, IncomingChannel>,
+    /// Periodic redelivery, while there are unacknowledged messages
+    redelivery: Option<ScheduledTimer>,
+    /// Periodic expiry, while there are any channels
+    expiry: Option<ScheduledTimer>,
+}
+
+impl ReliableDelivery {
+    /// The number of sessions, outgoing and incoming channels
+    #[cfg(test)]
+    pub(super) fn channels(&self) -> (usize, usize, usize) {
+        (
+            self.sessions.len(),
+            self.outgoing.len(),
+            self.incoming.len(),
+        )
+    }
+}
+
+impl NetworkDispatcher {
+    pub(super) fn on_reliable(&mut self, env: ReliableEnvelope) {
+        let ReliableEnvelope {
+            src,
+            dst,
+            msg,
+            promise,
+        } = env;
+        if self.is_local(&dst) {
+            let msg = NetMessage::with_bytes(msg.ser_id, src, dst, msg.data);
+            if let Err(e) = self.route_local(msg) {
+                error!(self.ctx.log(), "Failed to route reliable message: {:?}", e);
+            }
+            let _ = promise.fulfil(());
+            return;
+        }
+        let key = (src, dst);
+        let session = *self
+            .reliable
+            .sessions
+            .entry(key.clone())
+            .or_insert_with(Uuid::new_v4);
+        let (src, dst) = key;
+        let channel = self
+            .reliable
+            .outgoing
+            .entry(session)
+            .or_insert_with(|| OutgoingChannel {
+                src,
+                dst,
+                acked: 0,
+                sent: 0,
+                unacked: BTreeMap::new(),
+                last_active: Instant::now(),
+            });
+        let now = Instant::now();
+        channel.last_active = now;
+        channel.sent += 1;
+        let seq = channel.sent;
+        let deliver = ReliableMsg::Deliver {
+            session,
+            seq,
+            acked: channel.acked,
+            src: channel.src.clone(),
+            dst: channel.dst.clone(),
+            ser_id: msg.ser_id,
+            data: msg.data.clone(),
+        };
+        channel.unacked.insert(
+            seq,
+            Unacked {
+                ser_id: msg.ser_id,
+                data: msg.data,
+                promise,
+                sent_at: now,
+            },
+        );
+        let system = channel.dst.system().clone();
+        self.send_reliable_msg(&system, deliver);
+        self.schedule_redelivery();
+        self.schedule_expiry();
+    }
+
+    pub(super) fn on_reliable_msg(&mut self, sender: ActorPath, msg: ReliableMsg) {
+        match msg {
+            ReliableMsg::Deliver {
+                session,
+                seq,
+                acked,
+                src,
+                dst,
+                ser_id,
+                data,
+            } => {
+                // A channel we haven't heard of before may have delivered to a previous incarnation
+                // of this system, but nothing beyond what the sender already saw acknowledged.
+                let now = Instant::now();
+                let channel = self
+                    .reliable
+                    .incoming
+                    .entry(session)
+                    .or_insert(IncomingChannel {
+                        delivered: acked,
+                        last_active: now,
+                    });
+                channel.last_active = now;
+                let delivered = channel.delivered;
+                if seq == delivered + 1 {
+                    channel.delivered = seq;
+                    let msg = NetMessage::with_bytes(ser_id, src, dst, data);
+                    if let Err(e) = self.route_local(msg) {
+                        error!(self.ctx.log(), "Failed to route reliable message: {:?}", e);
+                    }
+                } else if seq > delivered + 1 {
+                    // a predecessor got lost, wait for it to be redelivered
+                    trace!(
+                        self.ctx.log(),
+                        "Dropping reliable message {} of session {}, expected {}",
+                        seq,
+                        session,
+                        delivered + 1
+                    );
+                }
+                let seq = self.reliable.incoming[&session].delivered;
+                if seq > 0 {
+                    self.send_reliable_msg(sender.system(), ReliableMsg::Ack { session, seq });
+                }
+                self.schedule_expiry();
+            }
+            ReliableMsg::Ack { session, seq } => {
+                if let Some(channel) = self.reliable.outgoing.get_mut(&session) {
+                    if seq > channel.acked {
+                        channel.acked = seq;
+                        channel.last_active = Instant::now();
+                        let remaining = channel.unacked.split_off(&(seq + 1));
+                        let acked = mem::replace(&mut channel.unacked, remaining);
+                        for (_seq, unacked) in acked {
+                            // the sender may have stopped waiting
+                            let _ = unacked.promise.fulfil(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn send_reliable_msg(&mut self, system: &SystemPath, msg: ReliableMsg) {
+        let dst = ActorPath::Named(NamedPath::with_system(
+            system.clone(),
+            vec![RELIABLE_ALIAS.to_string()],
+        ));
+        let mut src = ActorPath::Named(NamedPath::with_system(
+            self.system_path(),
+            vec![RELIABLE_ALIAS.to_string()],
+        ));
+        src.set_protocol(system.protocol());
+        if let Err(e) = self.route((src, dst, DispatchData::Lazy(Box::new(msg)), None)) {
+            error!(self.ctx.log(), "Failed to route reliable message: {:?}", e);
+        }
+    }
+
+    /// Sends all unacknowledged messages again, which are destined for `addr`,
+    /// or for any host that is currently connected, if `addr` is `None`
+    pub(super) fn redeliver(&mut self, addr: Option<SocketAddr>) {
+        let mut msgs = Vec::new();
+        for (session, channel) in self.reliable.outgoing.iter() {
+            if channel.unacked.is_empty() {
+                continue;
+            }
+            let reachable = match (addr, self.watched_addr(&channel.dst)) {
+                (Some(addr), Some(dst_addr)) => addr == dst_addr,
+                (None, Some(dst_addr)) => self.is_connected(&channel.dst, dst_addr),
+                (_, None) => false, // still resolving
+            };
+            if !reachable {
+                continue;
+            }
+            for (seq, unacked) in channel.unacked.iter() {
+                let msg = ReliableMsg::Deliver {
+                    session: *session,
+                    seq: *seq,
+                    acked: channel.acked,
+                    src: channel.src.clone(),
+                    dst: channel.dst.clone(),
+                    ser_id: unacked.ser_id,
+                    data: unacked.data.clone(),
+                };
+                msgs.push((channel.dst.system().clone(), msg));
+            }
+        }
+        if !msgs.is_empty() {
+            debug!(
+                self.ctx.log(),
+                "Redelivering {} unacknowledged message(s)",
+                msgs.len()
+            );
+        }
+        for (system, msg) in msgs {
+            self.send_reliable_msg(&system, msg);
+        }
+    }
+
+    /// Whether messages to `path` at `addr` currently go out right away, instead of being queued
+    fn is_connected(&self, path: &ActorPath, addr: SocketAddr) -> bool {
+        self.loopback.is_some()
+            || path.protocol() == Transport::UDP
+            || matches!(
+                self.connections.get(&addr),
+                Some(ConnectionState::Connected(_))
+            )
+    }
+
+    fn has_unacked(&self) -> bool {
+        self.reliable
+            .outgoing
+            .values()
+            .any(|channel| !channel.unacked.is_empty())
+    }
+
+    fn schedule_redelivery(&mut self) {
+        if self.reliable.redelivery.is_some() {
+            return;
+        }
+        let interval = Duration::from_millis(self.cfg.redelivery_interval);
+        let handle = self.schedule_once(interval, move |target, _id| {
+            target.reliable.redelivery = None;
+            if target.has_unacked() {
+                target.redeliver(None);
+                target.schedule_redelivery();
+            }
+            Handled::Ok
+        });
+        self.reliable.redelivery = Some(handle);
+    }
+
+    /// Gives up on outgoing channels whose oldest message is unacknowledged for too long,
+    /// and forgets channels that have been idle for too long
+    fn expire_reliable(&mut self) {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(self.cfg.reliable_timeout);
+        let expired: Vec<Uuid> = self
+            .reliable
+            .outgoing
+            .iter()
+            .filter(|(_, channel)| channel.is_expired(now, timeout))
+            .map(|(session, _)| *session)
+            .collect();
+        for session in expired {
+            if let Some(channel) = self.reliable.outgoing.remove(&session) {
+                if !channel.unacked.is_empty() {
+                    warn!(
+                        self.ctx.log(),
+                        "Giving up on {} unacknowledged reliable message(s) from {} to {}",
+                        channel.unacked.len(),
+                        channel.src,
+                        channel.dst
+                    );
+                }
+                self.reliable.sessions.remove(&(channel.src, channel.dst));
+                // dropping the unacknowledged messages fails their promises
+            }
+        }
+        self.reliable
+            .incoming
+            .retain(|_, channel| now.duration_since(channel.last_active) < timeout * 2);
+    }
+
+    fn schedule_expiry(&mut self) {
+        if self.reliable.expiry.is_some() {
+            return;
+        }
+        let interval = Duration::from_millis((self.cfg.reliable_timeout / 2).max(1));
+        let handle = self.schedule_once(interval, move |target, _id| {
+            target.reliable.expiry = None;
+            target.expire_reliable();
+            if !target.reliable.outgoing.is_empty() || !target.reliable.incoming.is_empty() {
+                target.schedule_expiry();
+            }
+            Handled::Ok
+        });
+        self.reliable.expiry = Some(handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn reliable_msg_serequiv() {
+        let src: ActorPath = "tcp://127.0.0.1:8080/some/actor".parse().expect("path");
+        let dst: ActorPath = "tcp://127.0.0.1:8081/other/actor".parse().expect("path");
+        let session = Uuid::new_v4();
+        let msgs = vec![
+            ReliableMsg::Deliver {
+                session,
+                seq: 7,
+                acked: 5,
+                src,
+                dst,
+                ser_id: serialisation_ids::U64,
+                data: Bytes::from_static(&[1, 2, 3, 4]),
+            },
+            ReliableMsg::Ack { session, seq: 6 },
+        ];
+        for msg in msgs {
+            let mut buf = BytesMut::with_capacity(msg.size_hint().expect("size hint"));
+            msg.serialise(&mut buf).expect("serialise");
+            let mut bytes = buf.freeze();
+            let res = ReliableMsg::deserialise(&mut bytes).expect("deserialise");
+            assert_eq!(msg, res);
+        }
+    }
+}
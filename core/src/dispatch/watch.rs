@@ -0,0 +1,379 @@
+//! Death-watch support for the [NetworkDispatcher](NetworkDispatcher)
+//!
+//! Local components register their interest in an [ActorPath](ActorPath) with their
+//! own dispatcher. If the path is local, the dispatcher listens for the destruction
+//! of the component via the supervisor. Otherwise it forwards the request to the
+//! dispatcher of the remote system, which is always registered under [WATCH_ALIAS](WATCH_ALIAS),
+//! and waits for it to report back, or for the connection to remain lost for too long.
+
+use super::*;
+use crate::{
+    messaging::{Terminated, WatchEnvelope},
+    serialisation::{serialisation_ids, Deserialiser, SerError, SerId, Serialisable},
+    supervision::{ListenEvent, SupervisorMsg},
+    timer::timer_manager::ScheduledTimer,
+    utils::promise,
+};
+use bytes::{Buf, BufMut};
+use rustc_hash::FxHashSet;
+use std::{any::Any, sync::Mutex};
+use uuid::Uuid;
+
+/// The alias under which every [NetworkDispatcher](NetworkDispatcher) receives
+/// death-watch messages from its peers
+pub(crate) const WATCH_ALIAS: &str = "$watch";
+
+/// The death-watch protocol spoken between dispatchers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum WatchMsg {
+    /// The sending system wants to know when the actor at the path is gone
+    Watch(ActorPath),
+    /// The sending system is no longer interested in the actor at the path
+    Unwatch(ActorPath),
+    /// The actor at the path is gone
+    Terminated(ActorPath),
+}
+
+impl WatchMsg {
+    const WATCH: u8 = 1;
+    const UNWATCH: u8 = 2;
+    const TERMINATED: u8 = 3;
+
+    fn path(&self) -> &ActorPath {
+        match self {
+            WatchMsg::Watch(path) => path,
+            WatchMsg::Unwatch(path) => path,
+            WatchMsg::Terminated(path) => path,
+        }
+    }
+}
+
+impl Serialisable for WatchMsg {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::DEATH_WATCH
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.path().size_hint().map(|size| size + 1)
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        let tag = match self {
+            WatchMsg::Watch(_) => WatchMsg::WATCH,
+            WatchMsg::Unwatch(_) => WatchMsg::UNWATCH,
+            WatchMsg::Terminated(_) => WatchMsg::TERMINATED,
+        };
+        buf.put_u8(tag);
+        self.path().serialise(buf)
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<WatchMsg> for WatchMsg {
+    const SER_ID: SerId = serialisation_ids::DEATH_WATCH;
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<WatchMsg, SerError> {
+        if buf.remaining() < 1 {
+            return Err(SerError::InvalidData(
+                "Could not get tag for WatchMsg".into(),
+            ));
+        }
+        let tag = buf.get_u8();
+        let path = ActorPath::deserialise(buf)?;
+        match tag {
+            WatchMsg::WATCH => Ok(WatchMsg::Watch(path)),
+            WatchMsg::UNWATCH => Ok(WatchMsg::Unwatch(path)),
+            WatchMsg::TERMINATED => Ok(WatchMsg::Terminated(path)),
+            _ => Err(SerError::InvalidType(format!(
+                "Unknown WatchMsg tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// A local component that watches some path
+struct Watcher {
+    id: Uuid,
+    actor: DynActorRef,
+    path: ActorPath,
+}
+
+/// Death-watch state of a [NetworkDispatcher](NetworkDispatcher)
+#[derive(Default)]
+pub(crate) struct DeathWatch {
+    /// Local components watching each path
+    watchers: NetHashMap<ActorPath, Vec<Watcher>>,
+    /// Remote systems watching each local path
+    remote_watchers: NetHashMap<ActorPath, Vec<SystemPath>>,
+    /// Local paths with an outstanding termination listener
+    listening: FxHashSet<ActorPath>,
+    /// Timers for watched hosts that are currently not connected
+    unreachable: NetHashMap<SocketAddr, ScheduledTimer>,
+}
+
+impl NetworkDispatcher {
+    pub(super) fn on_watch(&mut self, env: WatchEnvelope) {
+        match env {
+            WatchEnvelope::Watch {
+                watcher_id,
+                watcher,
+                watcher_path,
+                target,
+            } => {
+                let first = !self.death_watch.watchers.contains_key(&target);
+                let watchers = self.death_watch.watchers.entry(target.clone()).or_default();
+                if watchers.iter().any(|w| w.id == watcher_id) {
+                    return;
+                }
+                watchers.push(Watcher {
+                    id: watcher_id,
+                    actor: watcher,
+                    path: watcher_path,
+                });
+                if first {
+                    if self.is_local(&target) {
+                        self.watch_local(target);
+                    } else {
+                        self.send_watch_msg(target.system(), WatchMsg::Watch(target.clone()));
+                        if let Some(addr) = self.watched_addr(&target) {
+                            if !matches!(
+                                self.connections.get(&addr),
+                                Some(ConnectionState::Connected(_))
+                            ) {
+                                self.start_unreachable_timer(addr);
+                            }
+                        }
+                    }
+                }
+            }
+            WatchEnvelope::Unwatch { watcher_id, target } => {
+                if let Some(watchers) = self.death_watch.watchers.get_mut(&target) {
+                    watchers.retain(|w| w.id != watcher_id);
+                    if watchers.is_empty() {
+                        self.death_watch.watchers.remove(&target);
+                        if !self.is_local(&target) {
+                            self.send_watch_msg(target.system(), WatchMsg::Unwatch(target.clone()));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub(super) fn on_watch_msg(&mut self, sender: ActorPath, msg: WatchMsg) {
+        let system = sender.system();
+        match msg {
+            WatchMsg::Watch(target) => {
+                let systems = self
+                    .death_watch
+                    .remote_watchers
+                    .entry(target.clone())
+                    .or_default();
+                if !systems.contains(system) {
+                    systems.push(system.clone());
+                }
+                self.watch_local(target);
+            }
+            WatchMsg::Unwatch(target) => {
+                if let Some(systems) = self.death_watch.remote_watchers.get_mut(&target) {
+                    systems.retain(|s| s != system);
+                    if systems.is_empty() {
+                        self.death_watch.remote_watchers.remove(&target);
+                    }
+                }
+            }
+            WatchMsg::Terminated(target) => self.notify_terminated(&target),
+        }
+    }
+
+    /// Makes sure `on_local_terminated` is called once the local actor at `target` is gone
+    fn watch_local(&mut self, target: ActorPath) {
+        if !self.death_watch.listening.insert(target.clone()) {
+            return; // already listening
+        }
+        let component = match self.lookup.load().get_by_actor_path(&target) {
+            LookupResult::Ref(actor) => actor.component(),
+            _ => None,
+        };
+        match component {
+            Some(c) => {
+                let (p, f) = promise::<()>();
+                c.system().supervision_port().enqueue(SupervisorMsg::Listen(
+                    Arc::new(Mutex::new(p)),
+                    ListenEvent::Destroyed(c.id()),
+                ));
+                // Check only after the listener is in place, since the supervisor
+                // ignores listeners for components that are already gone.
+                if c.core().is_terminated() {
+                    self.on_local_terminated(target);
+                } else {
+                    self.spawn_local(move |mut async_self| async move {
+                        // A dropped promise means the component faulted, which counts as well
+                        let _ = f.await;
+                        async_self.on_local_terminated(target);
+                        Handled::Ok
+                    });
+                }
+            }
+            None => {
+                debug!(
+                    self.ctx.log(),
+                    "Watched path {} does not refer to a live component", target
+                );
+                self.on_local_terminated(target);
+            }
+        }
+    }
+
+    fn on_local_terminated(&mut self, target: ActorPath) {
+        self.death_watch.listening.remove(&target);
+        self.notify_terminated(&target);
+        if let Some(systems) = self.death_watch.remote_watchers.remove(&target) {
+            for system in systems {
+                self.send_watch_msg(&system, WatchMsg::Terminated(target.clone()));
+            }
+        }
+    }
+
+    /// Delivers a [Terminated](Terminated) message to every local watcher of `target`
+    fn notify_terminated(&mut self, target: &ActorPath) {
+        if let Some(watchers) = self.death_watch.watchers.remove(target) {
+            debug!(
+                self.ctx.log(),
+                "Notifying {} watcher(s) that {} terminated",
+                watchers.len(),
+                target
+            );
+            for watcher in watchers {
+                let msg = NetMessage::with_box(
+                    serialisation_ids::TERMINATED,
+                    target.clone(),
+                    watcher.path,
+                    Box::new(Terminated(target.clone())),
+                );
+                watcher.actor.enqueue(msg);
+            }
+        }
+    }
+
+    fn send_watch_msg(&mut self, system: &SystemPath, msg: WatchMsg) {
+        let dst = ActorPath::Named(NamedPath::with_system(
+            system.clone(),
+            vec![WATCH_ALIAS.to_string()],
+        ));
+        let mut src = ActorPath::Named(NamedPath::with_system(
+            self.system_path(),
+            vec![WATCH_ALIAS.to_string()],
+        ));
+        src.set_protocol(system.protocol());
+        if let Err(e) = self.route((src, dst, DispatchData::Lazy(Box::new(msg)), None)) {
+            error!(self.ctx.log(), "Failed to route watch message: {:?}", e);
+        }
+    }
+
+    pub(super) fn is_local(&mut self, path: &ActorPath) -> bool {
+        path.protocol() == Transport::LOCAL || self.system_path_ref() == path.system()
+    }
+
+    /// The socket address of the system of `path`, if it is known
+    pub(super) fn watched_addr(&self, path: &ActorPath) -> Option<SocketAddr> {
+        let system = path.system();
+        match system.address() {
+            Address::Ip(ip) => Some(SocketAddr::new(*ip, system.port())),
+            Address::Domain(domain) => self
+                .resolved_domains
+                .get(domain)
+                .map(|ip| SocketAddr::new(*ip, system.port())),
+        }
+    }
+
+    fn is_watching_host(&self, addr: SocketAddr) -> bool {
+        self.death_watch
+            .watchers
+            .keys()
+            .any(|path| self.watched_addr(path) == Some(addr))
+            || self
+                .death_watch
+                .remote_watchers
+                .values()
+                .flatten()
+                .any(|system| system.socket_address() == Some(addr))
+    }
+
+    /// Starts counting down until everything watched at `addr` is considered terminated
+    pub(super) fn start_unreachable_timer(&mut self, addr: SocketAddr) {
+        if self.death_watch.unreachable.contains_key(&addr) || !self.is_watching_host(addr) {
+            return;
+        }
+        debug!(
+            self.ctx.log(),
+            "Watched host {} is unreachable, starting timeout", addr
+        );
+        let timeout = Duration::from_millis(self.cfg.unreachable_timeout);
+        let handle = self.schedule_once(timeout, move |target, _id| {
+            target.on_unreachable_timeout(addr);
+            Handled::Ok
+        });
+        self.death_watch.unreachable.insert(addr, handle);
+    }
+
+    pub(super) fn cancel_unreachable_timer(&mut self, addr: SocketAddr) {
+        if let Some(handle) = self.death_watch.unreachable.remove(&addr) {
+            debug!(self.ctx.log(), "Watched host {} is reachable again", addr);
+            self.cancel_timer(handle);
+        }
+    }
+
+    fn on_unreachable_timeout(&mut self, addr: SocketAddr) {
+        self.death_watch.unreachable.remove(&addr);
+        let lost: Vec<ActorPath> = self
+            .death_watch
+            .watchers
+            .keys()
+            .filter(|path| self.watched_addr(path) == Some(addr))
+            .cloned()
+            .collect();
+        warn!(
+            self.ctx.log(),
+            "Host {} was unreachable for {}ms, considering {} watched actor(s) terminated",
+            addr,
+            self.cfg.unreachable_timeout,
+            lost.len()
+        );
+        for path in lost.iter() {
+            self.notify_terminated(path);
+        }
+        // Nobody left at `addr` to tell about local terminations
+        self.death_watch.remote_watchers.retain(|_, systems| {
+            systems.retain(|system| system.socket_address() != Some(addr));
+            !systems.is_empty()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn watch_msg_serequiv() {
+        let path: ActorPath = "tcp://127.0.0.1:8080/some/actor".parse().expect("path");
+        let msgs = vec![
+            WatchMsg::Watch(path.clone()),
+            WatchMsg::Unwatch(path.clone()),
+            WatchMsg::Terminated(path),
+        ];
+        for msg in msgs {
+            let mut buf = BytesMut::with_capacity(msg.size_hint().expect("size hint"));
+            msg.serialise(&mut buf).expect("serialise");
+            let mut bytes = buf.freeze();
+            let res = WatchMsg::deserialise(&mut bytes).expect("deserialise");
+            assert_eq!(msg, res);
+        }
+    }
+}
@@ -0,0 +1,631 @@
+//! A heartbeat-based phi-accrual failure detector for remote systems.
+//!
+//! The [FailureDetector](FailureDetector) component periodically sends heartbeat requests
+//! to the failure detectors of all the systems it has been asked to monitor,
+//! which are always registered under [FAILURE_DETECTOR_ALIAS](FAILURE_DETECTOR_ALIAS).
+//! From the arrival times of the replies it computes a suspicion level *phi*
+//! as described by Hayashibara et al. in "The φ Accrual Failure Detector",
+//! and publishes [Suspect](Liveness::Suspect) and [Alive](Liveness::Alive)
+//! indications on its [FailureDetection](FailureDetection) port whenever that level
+//! crosses the configured threshold.
+//!
+//! Every system that wants to be monitored must run a failure detector as well,
+//! since requests are answered by the detector on the receiving side.
+//! The simplest way to do so is to run the detector as a system component via
+//! [KompactConfig::failure_detector](crate::prelude::KompactConfig::failure_detector).
+
+use super::prelude::*;
+use crate::serialisation::serialisation_ids;
+use rustc_hash::FxHashMap;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// The alias every [FailureDetector](FailureDetector) registers itself under
+pub const FAILURE_DETECTOR_ALIAS: &str = "$failure-detector";
+
+// Default values for failure detector config.
+const HEARTBEAT_INTERVAL: u64 = 1000;
+const THRESHOLD: f64 = 8.0;
+const MAX_SAMPLE_SIZE: usize = 1000;
+const MIN_STD_DEVIATION: u64 = 100;
+const ACCEPTABLE_HEARTBEAT_PAUSE: u64 = 3000;
+const FIRST_HEARTBEAT_ESTIMATE: u64 = 1000;
+
+/// Requests to a [FailureDetector](FailureDetector)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Monitoring {
+    /// Start monitoring the system at the given path
+    Monitor(SystemPath),
+    /// Stop monitoring the system at the given path
+    Unmonitor(SystemPath),
+}
+
+/// Indications published by a [FailureDetector](FailureDetector)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Liveness {
+    /// The monitored system has not responded for too long and may have failed
+    Suspect(SystemPath),
+    /// The monitored system has responded for the first time, or again after being suspected
+    Alive(SystemPath),
+}
+
+/// A port for asking a [FailureDetector](FailureDetector) to monitor remote systems
+/// and for receiving updates about their liveness
+pub struct FailureDetection;
+impl Port for FailureDetection {
+    type Indication = Liveness;
+    type Request = Monitoring;
+}
+
+/// Configuration builder for a [FailureDetector](FailureDetector)
+///
+/// All durations are given in milliseconds.
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+///
+/// let mut fd_config = FailureDetectorConfig::default();
+/// fd_config.set_heartbeat_interval(100);
+/// fd_config.set_acceptable_heartbeat_pause(500);
+///
+/// let mut cfg = KompactConfig::default();
+/// cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+/// cfg.failure_detector(fd_config);
+/// let system = cfg.build().expect("KompactSystem");
+/// let detector = system.failure_detector().expect("failure detector");
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone, Debug)]
+pub struct FailureDetectorConfig {
+    heartbeat_interval: u64,
+    threshold: f64,
+    max_sample_size: usize,
+    min_std_deviation: u64,
+    acceptable_heartbeat_pause: u64,
+    first_heartbeat_estimate: u64,
+}
+
+impl FailureDetectorConfig {
+    /// Sets how often heartbeat requests are sent to every monitored system
+    ///
+    /// Default value is 1000 ms.
+    pub fn set_heartbeat_interval(&mut self, milliseconds: u64) {
+        self.heartbeat_interval = milliseconds;
+    }
+
+    /// Returns how often heartbeat requests are sent to every monitored system
+    pub fn get_heartbeat_interval(&self) -> u64 {
+        self.heartbeat_interval
+    }
+
+    /// Sets the phi value above which a monitored system is suspected
+    ///
+    /// A threshold of 1 means roughly a 10% chance that a suspicion is a mistake,
+    /// 2 means roughly 1%, 3 means roughly 0.1%, and so on.
+    ///
+    /// Default value is 8.0.
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold;
+    }
+
+    /// Returns the phi value above which a monitored system is suspected
+    pub fn get_threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Sets how many inter-arrival times are kept per monitored system
+    ///
+    /// Default value is 1000.
+    pub fn set_max_sample_size(&mut self, max_sample_size: usize) {
+        self.max_sample_size = max_sample_size;
+    }
+
+    /// Returns how many inter-arrival times are kept per monitored system
+    pub fn get_max_sample_size(&self) -> usize {
+        self.max_sample_size
+    }
+
+    /// Sets the lower bound for the standard deviation of inter-arrival times
+    ///
+    /// This prevents very regular heartbeats from making the detector overly sensitive.
+    ///
+    /// Default value is 100 ms.
+    pub fn set_min_std_deviation(&mut self, milliseconds: u64) {
+        self.min_std_deviation = milliseconds;
+    }
+
+    /// Returns the lower bound for the standard deviation of inter-arrival times
+    pub fn get_min_std_deviation(&self) -> u64 {
+        self.min_std_deviation
+    }
+
+    /// Sets how long a heartbeat may be late without raising suspicion notably
+    ///
+    /// Default value is 3000 ms.
+    pub fn set_acceptable_heartbeat_pause(&mut self, milliseconds: u64) {
+        self.acceptable_heartbeat_pause = milliseconds;
+    }
+
+    /// Returns how long a heartbeat may be late without raising suspicion notably
+    pub fn get_acceptable_heartbeat_pause(&self) -> u64 {
+        self.acceptable_heartbeat_pause
+    }
+
+    /// Sets the expected inter-arrival time used before any heartbeats have been observed
+    ///
+    /// Default value is 1000 ms.
+    pub fn set_first_heartbeat_estimate(&mut self, milliseconds: u64) {
+        self.first_heartbeat_estimate = milliseconds;
+    }
+
+    /// Returns the expected inter-arrival time used before any heartbeats have been observed
+    pub fn get_first_heartbeat_estimate(&self) -> u64 {
+        self.first_heartbeat_estimate
+    }
+
+    /// Completes the configuration and returns a function that produces a
+    /// [FailureDetector](FailureDetector) component
+    pub fn build(self) -> impl Fn() -> FailureDetector {
+        move || FailureDetector::new(self.clone())
+    }
+}
+
+impl Default for FailureDetectorConfig {
+    fn default() -> Self {
+        FailureDetectorConfig {
+            heartbeat_interval: HEARTBEAT_INTERVAL,
+            threshold: THRESHOLD,
+            max_sample_size: MAX_SAMPLE_SIZE,
+            min_std_deviation: MIN_STD_DEVIATION,
+            acceptable_heartbeat_pause: ACCEPTABLE_HEARTBEAT_PAUSE,
+            first_heartbeat_estimate: FIRST_HEARTBEAT_ESTIMATE,
+        }
+    }
+}
+
+/// The heartbeat protocol spoken between failure detectors
+///
+/// Both messages carry the monitored system's path as known to the requester,
+/// so replies can be matched independent of how the responder names itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum HeartbeatMsg {
+    Request(SystemPath),
+    Response(SystemPath),
+}
+
+impl HeartbeatMsg {
+    const REQUEST: u8 = 1;
+    const RESPONSE: u8 = 2;
+
+    fn path(&self) -> &SystemPath {
+        match self {
+            HeartbeatMsg::Request(path) => path,
+            HeartbeatMsg::Response(path) => path,
+        }
+    }
+}
+
+impl Serialisable for HeartbeatMsg {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::HEARTBEAT
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.path().size_hint().map(|size| size + 1)
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        let tag = match self {
+            HeartbeatMsg::Request(_) => HeartbeatMsg::REQUEST,
+            HeartbeatMsg::Response(_) => HeartbeatMsg::RESPONSE,
+        };
+        buf.put_u8(tag);
+        self.path().serialise(buf)
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<HeartbeatMsg> for HeartbeatMsg {
+    const SER_ID: SerId = serialisation_ids::HEARTBEAT;
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<HeartbeatMsg, SerError> {
+        if buf.remaining() < 1 {
+            return Err(SerError::InvalidData(
+                "Could not get tag for HeartbeatMsg".into(),
+            ));
+        }
+        let tag = buf.get_u8();
+        let path = SystemPath::deserialise(buf)?;
+        match tag {
+            HeartbeatMsg::REQUEST => Ok(HeartbeatMsg::Request(path)),
+            HeartbeatMsg::RESPONSE => Ok(HeartbeatMsg::Response(path)),
+            _ => Err(SerError::InvalidType(format!(
+                "Unknown HeartbeatMsg tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// A bounded window of heartbeat inter-arrival times in milliseconds
+#[derive(Debug, Clone)]
+struct HeartbeatHistory {
+    max_sample_size: usize,
+    intervals: VecDeque<f64>,
+    sum: f64,
+    squared_sum: f64,
+}
+
+impl HeartbeatHistory {
+    fn new(max_sample_size: usize) -> Self {
+        assert!(max_sample_size > 0, "max_sample_size must be positive");
+        HeartbeatHistory {
+            max_sample_size,
+            intervals: VecDeque::with_capacity(max_sample_size),
+            sum: 0.0,
+            squared_sum: 0.0,
+        }
+    }
+
+    fn add(&mut self, interval: f64) {
+        if self.intervals.len() >= self.max_sample_size {
+            if let Some(oldest) = self.intervals.pop_front() {
+                self.sum -= oldest;
+                self.squared_sum -= oldest * oldest;
+            }
+        }
+        self.intervals.push_back(interval);
+        self.sum += interval;
+        self.squared_sum += interval * interval;
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum / (self.intervals.len() as f64)
+    }
+
+    fn variance(&self) -> f64 {
+        let mean = self.mean();
+        // guard against tiny negative values from floating point error
+        (self.squared_sum / (self.intervals.len() as f64) - mean * mean).max(0.0)
+    }
+
+    fn std_deviation(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
+
+/// Phi-accrual suspicion state for a single monitored system
+#[derive(Debug, Clone)]
+struct PhiAccrualEstimator {
+    history: HeartbeatHistory,
+    last_heartbeat: Instant,
+    min_std_deviation: f64,
+    acceptable_heartbeat_pause: f64,
+}
+
+impl PhiAccrualEstimator {
+    /// Creates an estimator that treats `now` as the time of the first heartbeat
+    ///
+    /// The history is seeded from the first heartbeat estimate, so that a system
+    /// that never responds at all is eventually suspected as well.
+    fn new(config: &FailureDetectorConfig, now: Instant) -> Self {
+        let mut history = HeartbeatHistory::new(config.max_sample_size);
+        let mean = config.first_heartbeat_estimate as f64;
+        let std_deviation = mean / 4.0;
+        history.add(mean - std_deviation);
+        history.add(mean + std_deviation);
+        PhiAccrualEstimator {
+            history,
+            last_heartbeat: now,
+            min_std_deviation: config.min_std_deviation as f64,
+            acceptable_heartbeat_pause: config.acceptable_heartbeat_pause as f64,
+        }
+    }
+
+    fn heartbeat(&mut self, now: Instant) {
+        let interval = now.saturating_duration_since(self.last_heartbeat);
+        self.history.add(millis(interval));
+        self.last_heartbeat = now;
+    }
+
+    fn phi(&self, now: Instant) -> f64 {
+        let time_diff = millis(now.saturating_duration_since(self.last_heartbeat));
+        let mean = self.history.mean() + self.acceptable_heartbeat_pause;
+        let std_deviation = self.history.std_deviation().max(self.min_std_deviation);
+        phi(time_diff, mean, std_deviation)
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Computes phi for the given time since the last heartbeat
+///
+/// Uses a logistic approximation of the cumulative normal distribution,
+/// which is cheap to compute and avoids precision problems in the tail.
+fn phi(time_diff: f64, mean: f64, std_deviation: f64) -> f64 {
+    let y = (time_diff - mean) / std_deviation;
+    let e = (-y * (1.5976 + 0.070566 * y * y)).exp();
+    if time_diff > mean {
+        -(e / (1.0 + e)).log10()
+    } else {
+        -(1.0 - 1.0 / (1.0 + e)).log10()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PeerStatus {
+    /// No heartbeat has been received yet
+    Unknown,
+    Alive,
+    Suspected,
+}
+
+#[derive(Debug)]
+struct Peer {
+    estimator: PhiAccrualEstimator,
+    status: PeerStatus,
+}
+
+/// A component that monitors remote systems using phi-accrual failure detection
+///
+/// The detector registers itself under [FAILURE_DETECTOR_ALIAS](FAILURE_DETECTOR_ALIAS)
+/// when it is started, so it requires a system with a networked dispatcher.
+/// Systems to monitor are added and removed via [Monitoring](Monitoring) requests on the
+/// [FailureDetection](FailureDetection) port, and changes in their liveness are indicated on the same port.
+///
+/// A monitored system is considered to have sent its first heartbeat at the time monitoring started.
+/// Thus systems that never respond at all are suspected as well, once the first heartbeat estimate
+/// and the acceptable pause have been exceeded sufficiently.
+#[derive(ComponentDefinition)]
+pub struct FailureDetector {
+    ctx: ComponentContext<Self>,
+    detection: ProvidedPort<FailureDetection>,
+    config: FailureDetectorConfig,
+    peers: FxHashMap<SystemPath, Peer>,
+    timer: Option<ScheduledTimer>,
+}
+
+impl FailureDetector {
+    /// Create a new failure detector with the given configuration
+    pub fn new(config: FailureDetectorConfig) -> Self {
+        FailureDetector {
+            ctx: ComponentContext::uninitialised(),
+            detection: ProvidedPort::uninitialised(),
+            config,
+            peers: FxHashMap::default(),
+            timer: None,
+        }
+    }
+
+    /// Create a new failure detector with the default configuration
+    pub fn with_default_config() -> Self {
+        FailureDetector::new(FailureDetectorConfig::default())
+    }
+
+    fn detector_path(system: SystemPath) -> ActorPath {
+        ActorPath::Named(NamedPath::with_system(
+            system,
+            vec![FAILURE_DETECTOR_ALIAS.to_string()],
+        ))
+    }
+
+    /// The path of this detector, as it should appear to the given remote system
+    fn own_path_for(&self, remote: &SystemPath) -> ActorPath {
+        let mut path = Self::detector_path(self.ctx.system().system_path());
+        path.set_protocol(remote.protocol());
+        path
+    }
+
+    fn tick(&mut self, _timeout_id: ScheduledTimer) -> Handled {
+        let systems: Vec<SystemPath> = self.peers.keys().cloned().collect();
+        for system in systems {
+            let dst = Self::detector_path(system.clone());
+            let src = self.own_path_for(&system);
+            dst.tell_with_sender(HeartbeatMsg::Request(system), self, src);
+        }
+        self.check_peers();
+        Handled::Ok
+    }
+
+    fn check_peers(&mut self) {
+        let now = Instant::now();
+        let threshold = self.config.threshold;
+        let mut suspected: Vec<SystemPath> = Vec::new();
+        for (system, peer) in self.peers.iter_mut() {
+            if peer.status != PeerStatus::Suspected {
+                let phi = peer.estimator.phi(now);
+                if phi > threshold {
+                    debug!(self.ctx.log(), "Suspecting {} with phi={}", system, phi);
+                    peer.status = PeerStatus::Suspected;
+                    suspected.push(system.clone());
+                }
+            }
+        }
+        for system in suspected {
+            self.detection.trigger(Liveness::Suspect(system));
+        }
+    }
+
+    fn on_heartbeat(&mut self, system: SystemPath) {
+        if let Some(peer) = self.peers.get_mut(&system) {
+            peer.estimator.heartbeat(Instant::now());
+            if peer.status != PeerStatus::Alive {
+                debug!(self.ctx.log(), "{} is alive", system);
+                peer.status = PeerStatus::Alive;
+                self.detection.trigger(Liveness::Alive(system));
+            }
+        } else {
+            trace!(
+                self.ctx.log(),
+                "Ignoring heartbeat from unmonitored system {}",
+                system
+            );
+        }
+    }
+}
+
+impl ComponentLifecycle for FailureDetector {
+    fn on_start(&mut self) -> Handled {
+        let registration = self
+            .ctx
+            .update_own_alias_registration(FAILURE_DETECTOR_ALIAS);
+        self.spawn_local(move |async_self| async move {
+            match registration.await {
+                Ok(Ok(_)) => (),
+                Ok(Err(e)) => error!(
+                    async_self.ctx.log(),
+                    "Could not register failure detector: {:?}", e
+                ),
+                Err(e) => error!(
+                    async_self.ctx.log(),
+                    "Failure detector registration was dropped: {:?}", e
+                ),
+            }
+            Handled::Ok
+        });
+        let interval = Duration::from_millis(self.config.heartbeat_interval);
+        let now = Instant::now();
+        // don't count the time we were stopped against the monitored systems
+        for peer in self.peers.values_mut() {
+            peer.estimator.last_heartbeat = now;
+        }
+        self.timer = Some(self.schedule_periodic(interval, interval, Self::tick));
+        Handled::Ok
+    }
+
+    fn on_stop(&mut self) -> Handled {
+        if let Some(timer) = self.timer.take() {
+            self.cancel_timer(timer);
+        }
+        Handled::Ok
+    }
+
+    fn on_kill(&mut self) -> Handled {
+        self.on_stop()
+    }
+}
+
+impl Provide<FailureDetection> for FailureDetector {
+    fn handle(&mut self, request: Monitoring) -> Handled {
+        match request {
+            Monitoring::Monitor(system) => {
+                let config = &self.config;
+                self.peers.entry(system).or_insert_with(|| Peer {
+                    estimator: PhiAccrualEstimator::new(config, Instant::now()),
+                    status: PeerStatus::Unknown,
+                });
+            }
+            Monitoring::Unmonitor(system) => {
+                self.peers.remove(&system);
+            }
+        }
+        Handled::Ok
+    }
+}
+
+impl Actor for FailureDetector {
+    type Message = Never;
+
+    fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+        unreachable!("Never type is empty")
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let sender = msg.sender.clone();
+        match msg.try_deserialise::<HeartbeatMsg, HeartbeatMsg>() {
+            Ok(HeartbeatMsg::Request(system)) => {
+                let src = self.own_path_for(sender.system());
+                sender.tell_with_sender(HeartbeatMsg::Response(system), self, src);
+            }
+            Ok(HeartbeatMsg::Response(system)) => self.on_heartbeat(system),
+            Err(e) => warn!(self.ctx.log(), "Invalid heartbeat message: {:?}", e),
+        }
+        Handled::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    fn test_config() -> FailureDetectorConfig {
+        let mut config = FailureDetectorConfig::default();
+        config.set_min_std_deviation(10);
+        config.set_acceptable_heartbeat_pause(0);
+        config.set_first_heartbeat_estimate(100);
+        config
+    }
+
+    #[test]
+    fn heartbeat_msg_serequiv() {
+        let system: SystemPath = "tcp://127.0.0.1:8080".parse().expect("system path");
+        let msgs = vec![
+            HeartbeatMsg::Request(system.clone()),
+            HeartbeatMsg::Response(system),
+        ];
+        for msg in msgs {
+            let mut buf = BytesMut::with_capacity(msg.size_hint().expect("size hint"));
+            msg.serialise(&mut buf).expect("serialise");
+            let mut bytes = buf.freeze();
+            let res = HeartbeatMsg::deserialise(&mut bytes).expect("deserialise");
+            assert_eq!(msg, res);
+        }
+    }
+
+    #[test]
+    fn heartbeat_history_is_bounded() {
+        let mut history = HeartbeatHistory::new(3);
+        for interval in &[1000.0, 10.0, 20.0, 30.0] {
+            history.add(*interval);
+        }
+        assert_eq!(3, history.intervals.len());
+        assert!((history.mean() - 20.0).abs() < 1e-9);
+        assert!((history.variance() - 200.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn phi_increases_with_time_since_last_heartbeat() {
+        let at_mean = phi(100.0, 100.0, 10.0);
+        assert!((at_mean - 0.3).abs() < 0.01, "phi at mean was {}", at_mean);
+        assert!(phi(50.0, 100.0, 10.0) < at_mean);
+        assert!(phi(150.0, 100.0, 10.0) > at_mean);
+        assert!(phi(150.0, 100.0, 10.0) < phi(200.0, 100.0, 10.0));
+        assert!(phi(1000.0, 100.0, 10.0) > THRESHOLD);
+    }
+
+    #[test]
+    fn estimator_suspects_after_missing_heartbeats() {
+        let config = test_config();
+        let start = Instant::now();
+        let mut estimator = PhiAccrualEstimator::new(&config, start);
+        let mut now = start;
+        for _ in 0..10 {
+            now += Duration::from_millis(100);
+            estimator.heartbeat(now);
+        }
+        assert!(estimator.phi(now + Duration::from_millis(100)) < 1.0);
+        assert!(estimator.phi(now + Duration::from_millis(1000)) > THRESHOLD);
+    }
+
+    #[test]
+    fn acceptable_pause_delays_suspicion() {
+        let mut config = test_config();
+        let start = Instant::now();
+        let strict = PhiAccrualEstimator::new(&config, start);
+        config.set_acceptable_heartbeat_pause(1000);
+        let lenient = PhiAccrualEstimator::new(&config, start);
+        let later = start + Duration::from_millis(500);
+        assert!(strict.phi(later) > THRESHOLD);
+        assert!(lenient.phi(later) < 1.0);
+    }
+}
@@ -108,22 +108,38 @@ mod dedicated_scheduler;
 /// Default implementations for system components
 pub mod default_components;
 mod dispatch;
+/// Heartbeat-based failure detection for remote systems
+pub mod failure_detector;
+/// Cluster membership via gossip
+pub mod membership;
+/// Metrics about components, the network, and buffers, and exporting them
+pub mod metrics;
 /// Facilities and utilities for dealing with network messages
 pub mod messaging;
 /// Default networking implementation
 pub mod net;
+/// Event-sourced components that persist their state into a journal
+pub mod persistence;
 mod ports;
+/// Distributed publish/subscribe via named topics
+pub mod pubsub;
 /// Facilities for routing messages
 pub mod routing;
 /// Kompact system runtime facilities, such as configuration and schedulers
 pub mod runtime;
 mod serialisation;
+/// Sharding of entity actors across the systems of a cluster
+pub mod sharding;
 mod supervision;
+/// Probes and helpers for testing components
+pub mod testkit;
 /// Reusable timer facility internals
 pub mod timer;
+/// Tracing messages across components and systems
+pub mod tracing;
 mod utils;
 
-pub use dispatch::lookup;
+pub use dispatch::{faults, lookup, loopback};
 
 /// A more readable placeholder for a stable Never (`!`) type.
 ///
@@ -189,21 +205,28 @@ pub mod prelude {
             ActorRef,
             ActorRefFactory,
             ActorRefStrong,
+            Address,
+            AskError,
             Dispatcher,
             DispatcherRef,
             Dispatching,
             DispatchingPath,
             DynActorRef,
+            MailboxConfig,
             MessageBounds,
+            MessagePriority,
             NamedPath,
             NetworkActor,
+            OverflowPolicy,
             PathParseError,
+            Prioritised,
             Receiver,
             Recipient,
             Request,
             SystemField,
             SystemPath,
             Transport,
+            TryTellError,
             UniquePath,
             WithRecipient,
             WithSender,
@@ -227,16 +250,34 @@ pub mod prelude {
             Require,
             RequireRef,
         },
-        net::buffers::{BufferConfig, ChunkLease, ChunkRef},
+        net::{
+            buffers::{BufferConfig, ChunkLease, ChunkRef},
+            tls::TlsConfig,
+        },
         ports::{Port, ProvidedPort, ProvidedRef, RequiredPort, RequiredRef},
         runtime::{KompactConfig, KompactSystem, SystemHandle},
-        supervision::{FaultContext, RecoveryHandler},
+        supervision::{FaultContext, RecoveryHandler, RestartStrategy, SupervisionEvent},
         Never,
     };
 
     pub use crate::{
         default_components::{CustomComponents, DeadletterBox, LocalDispatcher},
         dispatch::{NetworkConfig, NetworkDispatcher},
+        failure_detector::{
+            FailureDetection,
+            FailureDetector,
+            FailureDetectorConfig,
+            Liveness,
+            Monitoring,
+        },
+        membership::{
+            ClusterMembership,
+            MemberStatus,
+            Membership,
+            MembershipConfig,
+            MembershipEvent,
+            MembershipRequest,
+        },
         messaging::{
             DispatchEnvelope,
             MsgEnvelope,
@@ -245,8 +286,11 @@ pub mod prelude {
             RegistrationError,
             RegistrationResult,
             Serialised,
+            Terminated,
             UnpackError,
         },
+        pubsub::{PubSubConfig, PubSubMediator, PubSubRequest},
+        sharding::{ShardEnvelope, ShardRegion, ShardingConfig},
         timer::timer_manager::{CanCancelTimers, ScheduledTimer, Timer, TimerRefFactory},
     };
 
@@ -1167,6 +1211,243 @@ mod tests {
             .expect("Kompact didn't shut down properly");
     }
 
+    #[derive(ComponentDefinition)]
+    struct SupervisionListener {
+        ctx: ComponentContext<Self>,
+        exceeded: Option<KPromise<SupervisionEvent>>,
+    }
+
+    impl SupervisionListener {
+        fn new(exceeded: KPromise<SupervisionEvent>) -> Self {
+            SupervisionListener {
+                ctx: ComponentContext::uninitialised(),
+                exceeded: Some(exceeded),
+            }
+        }
+    }
+
+    ignore_lifecycle!(SupervisionListener);
+
+    impl Actor for SupervisionListener {
+        type Message = SupervisionEvent;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            if let Some(promise) = self.exceeded.take() {
+                promise.fulfil(msg).expect("fulfilled");
+            }
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    // replace ignore with panic cfg gate when https://github.com/rust-lang/rust/pull/74754 is merged
+    #[test]
+    #[ignore]
+    fn test_restart_strategy_limit() -> () {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let system = KompactConfig::default().build().expect("KompactSystem");
+
+        let (p, f) = promise::<SupervisionEvent>();
+        let listener = system.create(move || SupervisionListener::new(p));
+        system.start(&listener);
+        system.subscribe_supervision_events(listener.actor_ref());
+
+        let created = Arc::new(AtomicUsize::new(0));
+        let strategy = RestartStrategy::one_for_one()
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(100))
+            .with_limit(3, Duration::from_secs(60));
+        let factory = {
+            let created = created.clone();
+            move || {
+                created.fetch_add(1, Ordering::SeqCst);
+                CrasherComponent::new(true)
+            }
+        };
+        let cc = system.create(factory.clone());
+        cc.set_recovery_function(strategy.recovery_function(factory));
+        system.start(&cc);
+
+        let event = f
+            .wait_timeout(Duration::from_millis(5000))
+            .expect("restart limit never exceeded");
+        match event {
+            SupervisionEvent::RestartLimitExceeded {
+                component_type,
+                max_restarts,
+                ..
+            } => {
+                assert_eq!(CrasherComponent::type_name(), component_type);
+                assert_eq!(3, max_restarts);
+            }
+        }
+        // the original and 3 replacements
+        assert_eq!(4, created.load(Ordering::SeqCst));
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
+    #[derive(ComponentDefinition)]
+    struct TreeComponent {
+        ctx: ComponentContext<Self>,
+        depth: usize,
+        children: Vec<Arc<Component<TreeComponent>>>,
+    }
+
+    impl TreeComponent {
+        fn new(depth: usize) -> Self {
+            TreeComponent {
+                ctx: ComponentContext::uninitialised(),
+                depth,
+                children: Vec::new(),
+            }
+        }
+
+        fn descendants(c: &Arc<Component<TreeComponent>>) -> Vec<Arc<Component<TreeComponent>>> {
+            let children = c.on_definition(|cd| cd.children.clone());
+            let mut all = Vec::new();
+            for child in children {
+                all.extend(Self::descendants(&child));
+                all.push(child);
+            }
+            all
+        }
+    }
+
+    impl ComponentLifecycle for TreeComponent {
+        fn on_start(&mut self) -> Handled {
+            if self.depth > 0 && self.children.is_empty() {
+                for _i in 0..2 {
+                    let depth = self.depth - 1;
+                    let child = self.ctx.create_child(move || TreeComponent::new(depth));
+                    self.ctx.system().start(&child);
+                    self.children.push(child);
+                }
+            }
+            Handled::Ok
+        }
+    }
+
+    impl Actor for TreeComponent {
+        type Message = ();
+
+        fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+            info!(self.ctx.log(), "Crashing TreeComponent");
+            panic!("Test panic please ignore");
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    fn wait_until<F>(timeout: Duration, condition: F) -> bool
+    where
+        F: Fn() -> bool,
+    {
+        let deadline = time::Instant::now() + timeout;
+        while time::Instant::now() < deadline {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        condition()
+    }
+
+    #[test]
+    fn test_child_lifecycle_follows_parent() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let root = system.create(|| TreeComponent::new(2));
+        system
+            .start_notify(&root)
+            .wait_timeout(one_sec)
+            .expect("root never started");
+        // grandchildren are only created once the children have started
+        assert!(
+            wait_until(one_sec, || TreeComponent::descendants(&root).len() == 6),
+            "Descendants should have been created"
+        );
+        let descendants = TreeComponent::descendants(&root);
+        assert!(
+            wait_until(one_sec, || descendants.iter().all(|c| c.is_active())),
+            "Children should have been started"
+        );
+
+        system
+            .stop_notify(&root)
+            .wait_timeout(one_sec)
+            .expect("root never stopped");
+        assert!(
+            descendants.iter().all(|c| !c.is_active()),
+            "Children should have been stopped with the root"
+        );
+
+        system
+            .start_notify(&root)
+            .wait_timeout(one_sec)
+            .expect("root never restarted");
+        assert!(
+            wait_until(one_sec, || descendants.iter().all(|c| c.is_active())),
+            "Children should have been restarted with the root"
+        );
+
+        system
+            .kill_notify(root)
+            .wait_timeout(one_sec)
+            .expect("root never died");
+        assert!(
+            descendants.iter().all(|c| c.is_destroyed()),
+            "Children should have been killed with the root"
+        );
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
+    // replace ignore with panic cfg gate when https://github.com/rust-lang/rust/pull/74754 is merged
+    #[test]
+    #[ignore]
+    fn test_child_fault_escalates() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let root = system.create(|| TreeComponent::new(1));
+        system
+            .start_notify(&root)
+            .wait_timeout(one_sec)
+            .expect("root never started");
+        let children = TreeComponent::descendants(&root);
+        assert!(
+            wait_until(one_sec, || children.iter().all(|c| c.is_active())),
+            "Children should have been started"
+        );
+
+        children[0].actor_ref().tell(());
+
+        assert!(
+            wait_until(one_sec, || root.is_faulty()),
+            "Root should have faulted with its child"
+        );
+        assert!(children[0].is_faulty());
+        assert!(
+            wait_until(one_sec, || children[1].is_destroyed()),
+            "Sibling should have been killed with the root"
+        );
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
     #[derive(ComponentDefinition, Actor)]
     struct Stopper {
         ctx: ComponentContext<Self>,
@@ -1187,6 +1468,400 @@ mod tests {
         }
     }
 
+    #[derive(ComponentDefinition)]
+    struct MailboxSink {
+        ctx: ComponentContext<Self>,
+        received: Vec<u64>,
+    }
+
+    impl MailboxSink {
+        fn new() -> Self {
+            MailboxSink {
+                ctx: ComponentContext::uninitialised(),
+                received: Vec::new(),
+            }
+        }
+    }
+
+    ignore_lifecycle!(MailboxSink);
+
+    impl Actor for MailboxSink {
+        type Message = u64;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            self.received.push(msg);
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    fn received_with_policy(policy: OverflowPolicy) -> Vec<u64> {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        // the sink doesn't process messages before it is started, so they pile up in the mailbox
+        let sink = system.create(MailboxSink::new);
+        sink.set_mailbox(MailboxConfig::bounded(2, policy));
+        let sink_ref = sink.actor_ref();
+        for i in 1u64..=3 {
+            sink_ref.tell(i);
+        }
+        system
+            .start_notify(&sink)
+            .wait_timeout(one_sec)
+            .expect("sink never started");
+        assert!(
+            wait_until(one_sec, || sink.on_definition(|cd| cd.received.len()) == 2),
+            "Sink should have received the messages that fit into its mailbox"
+        );
+        let received = sink.on_definition(|cd| cd.received.clone());
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+        received
+    }
+
+    #[test]
+    fn test_mailbox_overflow_policies() -> () {
+        assert_eq!(vec![1, 2], received_with_policy(OverflowPolicy::DropNewest));
+        assert_eq!(vec![2, 3], received_with_policy(OverflowPolicy::DropOldest));
+        assert_eq!(
+            vec![1, 2],
+            received_with_policy(OverflowPolicy::RejectToDeadletter)
+        );
+    }
+
+    #[test]
+    fn test_mailbox_try_tell() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let sink = system.create(MailboxSink::new);
+        sink.set_mailbox(MailboxConfig::bounded(2, OverflowPolicy::DropOldest));
+        let sink_ref = sink.actor_ref();
+        sink_ref.try_tell(1u64).expect("mailbox should have space");
+        sink_ref.try_tell(2u64).expect("mailbox should have space");
+        match sink_ref.try_tell(3u64) {
+            Err(TryTellError::Full(msg)) => assert_eq!(3, msg),
+            res => panic!("Expected a full mailbox, got {:?}", res),
+        }
+
+        system
+            .start_notify(&sink)
+            .wait_timeout(one_sec)
+            .expect("sink never started");
+        assert!(
+            wait_until(one_sec, || sink.on_definition(|cd| cd.received.len()) == 2),
+            "Sink should have drained its mailbox"
+        );
+        // space was freed up by processing
+        sink_ref
+            .try_tell(3u64)
+            .expect("mailbox should have space again");
+        assert!(
+            wait_until(one_sec, || sink.on_definition(|cd| cd.received.clone())
+                == vec![1, 2, 3]),
+            "Sink should have received all messages"
+        );
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct PrioritisedMsg(MessagePriority, u64);
+
+    impl Prioritised for PrioritisedMsg {
+        fn priority(&self) -> MessagePriority {
+            self.0
+        }
+    }
+
+    #[derive(ComponentDefinition)]
+    struct PrioritySink {
+        ctx: ComponentContext<Self>,
+        received: Vec<PrioritisedMsg>,
+    }
+
+    impl PrioritySink {
+        fn new() -> Self {
+            PrioritySink {
+                ctx: ComponentContext::uninitialised(),
+                received: Vec::new(),
+            }
+        }
+    }
+
+    ignore_lifecycle!(PrioritySink);
+
+    impl Actor for PrioritySink {
+        type Message = PrioritisedMsg;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            self.received.push(msg);
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn test_mailbox_priorities() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let sink = system.create(PrioritySink::new);
+        sink.prioritise_messages();
+        let sink_ref = sink.actor_ref();
+        for i in 0..2 {
+            sink_ref.tell(PrioritisedMsg(MessagePriority::Low, i));
+        }
+        for i in 0..7 {
+            sink_ref.tell(PrioritisedMsg(MessagePriority::High, i));
+        }
+        // an explicit priority overrides the message's own
+        sink_ref.tell_with_priority(
+            PrioritisedMsg(MessagePriority::Low, 7),
+            MessagePriority::High,
+        );
+        system
+            .start_notify(&sink)
+            .wait_timeout(one_sec)
+            .expect("sink never started");
+        assert!(
+            wait_until(one_sec, || sink.on_definition(|cd| cd.received.len()) == 10),
+            "Sink should have received all messages"
+        );
+
+        // with the default weights, every 4 high priority messages are followed by a low priority one
+        let order: Vec<u64> =
+            sink.on_definition(|cd| cd.received.iter().map(|msg| msg.1).collect());
+        assert_eq!(vec![0, 1, 2, 3, 0, 4, 5, 6, 7, 1], order);
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
+    #[test]
+    fn test_mailbox_priorities_removed() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let sink = system.create(PrioritySink::new);
+        sink.set_mailbox(MailboxConfig::unbounded().prioritised());
+        let sink_ref = sink.actor_ref();
+        sink_ref.tell_with_priority(
+            PrioritisedMsg(MessagePriority::Low, 0),
+            MessagePriority::Low,
+        );
+        sink_ref.tell_with_priority(
+            PrioritisedMsg(MessagePriority::High, 1),
+            MessagePriority::High,
+        );
+        // messages already in the priority lanes must not get lost
+        sink.set_mailbox(MailboxConfig::unbounded());
+        for i in 2..5 {
+            sink_ref.tell_with_priority(
+                PrioritisedMsg(MessagePriority::High, i),
+                MessagePriority::High,
+            );
+        }
+        system
+            .start_notify(&sink)
+            .wait_timeout(one_sec)
+            .expect("sink never started");
+        assert!(
+            wait_until(one_sec, || sink.on_definition(|cd| cd.received.len()) == 5),
+            "Sink should have received all messages"
+        );
+
+        // the explicit priority is ignored once the mailbox is not prioritised anymore
+        let mut order: Vec<u64> =
+            sink.on_definition(|cd| cd.received.iter().map(|msg| msg.1).collect());
+        order.retain(|i| *i >= 2);
+        assert_eq!(vec![2, 3, 4], order);
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
+    #[derive(Debug)]
+    enum StashMsg {
+        Ready,
+        Value(u64),
+    }
+
+    #[derive(ComponentDefinition)]
+    struct StashingComponent {
+        ctx: ComponentContext<Self>,
+        ready: bool,
+        received: Vec<String>,
+    }
+
+    impl StashingComponent {
+        fn new() -> Self {
+            StashingComponent {
+                ctx: ComponentContext::uninitialised(),
+                ready: false,
+                received: Vec::new(),
+            }
+        }
+    }
+
+    ignore_lifecycle!(StashingComponent);
+
+    impl Actor for StashingComponent {
+        type Message = StashMsg;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            match msg {
+                StashMsg::Ready => {
+                    self.ready = true;
+                    self.ctx.unstash_all();
+                }
+                StashMsg::Value(v) if self.ready => self.received.push(format!("local {}", v)),
+                msg => self.ctx.stash(MsgEnvelope::Typed(msg)),
+            }
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, msg: NetMessage) -> Handled {
+            if self.ready {
+                let v = msg.try_deserialise::<u64, u64>().expect("u64");
+                self.received.push(format!("net {}", v));
+            } else {
+                self.ctx.stash(MsgEnvelope::Net(Box::new(msg)));
+            }
+            Handled::Ok
+        }
+    }
+
+    #[test]
+    fn test_stash() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let stasher = system.create(StashingComponent::new);
+        system
+            .start_notify(&stasher)
+            .wait_timeout(one_sec)
+            .expect("stasher never started");
+        let stasher_ref = stasher.actor_ref();
+        let stasher_path = system.actor_path_for(&stasher);
+        stasher_ref.tell(StashMsg::Value(1));
+        stasher_ref.dyn_ref().tell(NetMessage::with_box(
+            <u64 as Deserialiser<u64>>::SER_ID,
+            stasher_path.clone(),
+            stasher_path,
+            Box::new(2u64),
+        ));
+        stasher_ref.tell(StashMsg::Value(3));
+        assert!(
+            wait_until(one_sec, || stasher.on_definition(|cd| cd.ctx.stash_size())
+                == 3),
+            "Messages should have been stashed"
+        );
+        stasher_ref.tell(StashMsg::Ready);
+        stasher_ref.tell(StashMsg::Value(4));
+        assert!(
+            wait_until(one_sec, || stasher.on_definition(|cd| cd.received.len())
+                == 4),
+            "All messages should have been handled"
+        );
+        // unstashed messages keep their order and go before newer ones
+        assert_eq!(
+            vec!["local 1", "net 2", "local 3", "local 4"],
+            stasher.on_definition(|cd| cd.received.clone())
+        );
+        assert_eq!(0, stasher.on_definition(|cd| cd.ctx.stash_size()));
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
+    #[derive(ComponentDefinition)]
+    struct BehaviourComponent {
+        ctx: ComponentContext<Self>,
+        received: Vec<String>,
+    }
+
+    impl BehaviourComponent {
+        fn new() -> Self {
+            BehaviourComponent {
+                ctx: ComponentContext::uninitialised(),
+                received: Vec::new(),
+            }
+        }
+    }
+
+    ignore_lifecycle!(BehaviourComponent);
+
+    impl Actor for BehaviourComponent {
+        type Message = u64;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            if msg == 0 {
+                let mut seen = 0;
+                self.ctx
+                    .set_behaviour(move |cd: &mut BehaviourComponent, env| {
+                        if let MsgEnvelope::Typed(v) = env {
+                            cd.received.push(format!("custom {}", v));
+                        }
+                        seen += 1;
+                        if seen == 2 {
+                            cd.ctx.reset_behaviour();
+                        }
+                        Handled::Ok
+                    });
+            } else {
+                self.received.push(format!("default {}", msg));
+            }
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn test_behaviour_switching() -> () {
+        let system = KompactConfig::default().build().expect("KompactSystem");
+        let one_sec = Duration::from_millis(1000);
+
+        let component = system.create(BehaviourComponent::new);
+        system
+            .start_notify(&component)
+            .wait_timeout(one_sec)
+            .expect("component never started");
+        let component_ref = component.actor_ref();
+        for msg in [1u64, 0, 2, 3, 4].iter() {
+            component_ref.tell(*msg);
+        }
+        assert!(
+            wait_until(one_sec, || component.on_definition(|cd| cd.received.len())
+                == 4),
+            "All messages should have been handled"
+        );
+        assert_eq!(
+            vec!["default 1", "custom 2", "custom 3", "default 4"],
+            component.on_definition(|cd| cd.received.clone())
+        );
+
+        system
+            .shutdown()
+            .expect("Kompact didn't shut down properly");
+    }
+
     #[test]
     fn test_async_shutdown() -> () {
         let system = KompactConfig::default().build().expect("system");
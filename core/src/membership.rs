@@ -0,0 +1,889 @@
+//! Cluster membership via gossip.
+//!
+//! The [Membership](Membership) component maintains the list of systems in a cluster
+//! and publishes changes to it on its [ClusterMembership](ClusterMembership) port.
+//! A new system contacts the seed systems listed in its configuration, and from then on
+//! every member periodically exchanges its full view of the cluster with one other member.
+//!
+//! Each member owns a version number for its own entry, which only it may increase,
+//! and a heartbeat counter it increases in every gossip round.
+//! When views are merged, the entry with the higher version wins, and for equal versions
+//! the later status (in the order `Joining`, `Up`, `Left`, `Down`) wins.
+//! A member whose heartbeat has not increased for the configured timeout is marked as `Down`.
+//! Should a member that is still alive learn that it has been marked as `Down`,
+//! it increases its version and announces itself as `Up` again.
+//!
+//! # Configuration
+//!
+//! The component reads its settings from the `membership` section of the system's HOCON config:
+//!
+//! ```hocon
+//! membership {
+//!     seeds = ["tcp://127.0.0.1:45678"]
+//!     gossip_interval = 500 ms
+//!     down_timeout = 5 s
+//! }
+//! ```
+//!
+//! A system without any seeds (other than itself) forms a new cluster on its own.
+
+use super::prelude::*;
+use crate::serialisation::serialisation_ids;
+use hocon::Hocon;
+use rustc_hash::FxHashMap;
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// The alias every [Membership](Membership) component registers itself under
+pub const MEMBERSHIP_ALIAS: &str = "$membership";
+
+// Default values for membership config.
+const GOSSIP_INTERVAL: u64 = 500;
+const DOWN_TIMEOUT: u64 = 5000;
+
+/// The status of a member in the cluster
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MemberStatus {
+    /// The member has contacted the cluster, but has not yet been seen by any other member
+    Joining,
+    /// The member is part of the cluster
+    Up,
+    /// The member has left the cluster voluntarily
+    Left,
+    /// The member has not been heard from for too long
+    Down,
+}
+
+impl MemberStatus {
+    fn is_live(self) -> bool {
+        matches!(self, MemberStatus::Joining | MemberStatus::Up)
+    }
+
+    fn to_u8(self) -> u8 {
+        match self {
+            MemberStatus::Joining => 1,
+            MemberStatus::Up => 2,
+            MemberStatus::Left => 3,
+            MemberStatus::Down => 4,
+        }
+    }
+
+    fn from_u8(tag: u8) -> Result<Self, SerError> {
+        match tag {
+            1 => Ok(MemberStatus::Joining),
+            2 => Ok(MemberStatus::Up),
+            3 => Ok(MemberStatus::Left),
+            4 => Ok(MemberStatus::Down),
+            _ => Err(SerError::InvalidType(format!(
+                "Unknown MemberStatus tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// Changes in cluster membership, as indicated by a [Membership](Membership) component
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MembershipEvent {
+    /// A system has been seen for the first time
+    Joined(SystemPath),
+    /// A system has become a full member of the cluster
+    Up(SystemPath),
+    /// A system has left the cluster voluntarily
+    Left(SystemPath),
+    /// A system has not been heard from for too long and is considered failed
+    Down(SystemPath),
+}
+
+/// Requests to a [Membership](Membership) component
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MembershipRequest {
+    /// Contact the given systems in addition to the configured seeds
+    Join(Vec<SystemPath>),
+    /// Announce that this system is leaving the cluster and stop gossiping
+    Leave,
+}
+
+/// A port for observing and influencing the cluster membership of a system
+pub struct ClusterMembership;
+impl Port for ClusterMembership {
+    type Indication = MembershipEvent;
+    type Request = MembershipRequest;
+}
+
+/// Configuration for a [Membership](Membership) component
+///
+/// All durations are given in milliseconds.
+#[derive(Clone, Debug)]
+pub struct MembershipConfig {
+    seeds: Vec<SystemPath>,
+    gossip_interval: u64,
+    down_timeout: u64,
+}
+
+impl MembershipConfig {
+    /// Reads a configuration from the `membership` section of the given `config`
+    ///
+    /// Returns the same values as [MembershipConfig::default](MembershipConfig::default)
+    /// for all keys that are missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the configured seeds is not a valid [SystemPath](SystemPath).
+    pub fn from_config(config: &Hocon) -> Self {
+        let section = &config["membership"];
+        let mut membership_config = MembershipConfig::default();
+        if let Hocon::Array(seeds) = &section["seeds"] {
+            for seed in seeds {
+                let seed_str = seed
+                    .as_string()
+                    .unwrap_or_else(|| panic!("Seed {:?} is not a string", seed));
+                let path = SystemPath::from_str(&seed_str).unwrap_or_else(|e| {
+                    panic!("Seed {} is not a valid system path: {}", seed_str, e)
+                });
+                membership_config.seeds.push(path);
+            }
+        }
+        if let Some(interval) = section["gossip_interval"].as_duration() {
+            membership_config.gossip_interval = interval.as_millis() as u64;
+        }
+        if let Some(timeout) = section["down_timeout"].as_duration() {
+            membership_config.down_timeout = timeout.as_millis() as u64;
+        }
+        membership_config
+    }
+
+    /// Sets the systems contacted when joining the cluster
+    pub fn set_seeds(&mut self, seeds: Vec<SystemPath>) {
+        self.seeds = seeds;
+    }
+
+    /// Returns the systems contacted when joining the cluster
+    pub fn get_seeds(&self) -> &[SystemPath] {
+        &self.seeds
+    }
+
+    /// Sets how often a member exchanges its view with another member
+    ///
+    /// Default value is 500 ms.
+    pub fn set_gossip_interval(&mut self, milliseconds: u64) {
+        self.gossip_interval = milliseconds;
+    }
+
+    /// Returns how often a member exchanges its view with another member
+    pub fn get_gossip_interval(&self) -> u64 {
+        self.gossip_interval
+    }
+
+    /// Sets how long a member's heartbeat may stay unchanged before it is marked as `Down`
+    ///
+    /// Default value is 5000 ms.
+    pub fn set_down_timeout(&mut self, milliseconds: u64) {
+        self.down_timeout = milliseconds;
+    }
+
+    /// Returns how long a member's heartbeat may stay unchanged before it is marked as `Down`
+    pub fn get_down_timeout(&self) -> u64 {
+        self.down_timeout
+    }
+}
+
+impl Default for MembershipConfig {
+    fn default() -> Self {
+        MembershipConfig {
+            seeds: Vec::new(),
+            gossip_interval: GOSSIP_INTERVAL,
+            down_timeout: DOWN_TIMEOUT,
+        }
+    }
+}
+
+/// A single member's entry as exchanged in gossip
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct MemberState {
+    system: SystemPath,
+    status: MemberStatus,
+    version: u64,
+    heartbeat: u64,
+}
+
+impl MemberState {
+    /// Whether `self` should replace `other` when merging
+    fn supersedes(&self, other: &MemberState) -> bool {
+        (self.version, self.status) > (other.version, other.status)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.system.size_hint().map(|size| size + 1 + 8 + 8)
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        self.system.serialise(buf)?;
+        buf.put_u8(self.status.to_u8());
+        buf.put_u64(self.version);
+        buf.put_u64(self.heartbeat);
+        Ok(())
+    }
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<MemberState, SerError> {
+        let system = SystemPath::deserialise(buf)?;
+        if buf.remaining() < 1 + 8 + 8 {
+            return Err(SerError::InvalidData(
+                "Not enough data for MemberState".into(),
+            ));
+        }
+        let status = MemberStatus::from_u8(buf.get_u8())?;
+        let version = buf.get_u64();
+        let heartbeat = buf.get_u64();
+        Ok(MemberState {
+            system,
+            status,
+            version,
+            heartbeat,
+        })
+    }
+}
+
+/// The gossip protocol spoken between membership components
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum GossipMsg {
+    /// The sender's view, to be merged and answered with the receiver's view
+    Gossip(Vec<MemberState>),
+    /// The answer to a `Gossip` message, to be merged only
+    Reply(Vec<MemberState>),
+}
+
+impl GossipMsg {
+    const GOSSIP: u8 = 1;
+    const REPLY: u8 = 2;
+
+    fn members(&self) -> &[MemberState] {
+        match self {
+            GossipMsg::Gossip(members) => members,
+            GossipMsg::Reply(members) => members,
+        }
+    }
+}
+
+impl Serialisable for GossipMsg {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::MEMBERSHIP
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.members().iter().try_fold(1 + 4, |acc, member| {
+            member.size_hint().map(|size| acc + size)
+        })
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        let tag = match self {
+            GossipMsg::Gossip(_) => GossipMsg::GOSSIP,
+            GossipMsg::Reply(_) => GossipMsg::REPLY,
+        };
+        buf.put_u8(tag);
+        buf.put_u32(self.members().len() as u32);
+        for member in self.members() {
+            member.serialise(buf)?;
+        }
+        Ok(())
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<GossipMsg> for GossipMsg {
+    const SER_ID: SerId = serialisation_ids::MEMBERSHIP;
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<GossipMsg, SerError> {
+        if buf.remaining() < 1 + 4 {
+            return Err(SerError::InvalidData(
+                "Could not get header for GossipMsg".into(),
+            ));
+        }
+        let tag = buf.get_u8();
+        let len = buf.get_u32() as usize;
+        // every member takes at least a path header, its status, version and heartbeat
+        if buf.remaining() / (1 + 1 + 8 + 8) < len {
+            return Err(SerError::InvalidData(format!(
+                "GossipMsg of {} members cut short at {} bytes",
+                len,
+                buf.remaining()
+            )));
+        }
+        let mut members = Vec::with_capacity(len);
+        for _ in 0..len {
+            members.push(MemberState::deserialise(buf)?);
+        }
+        match tag {
+            GossipMsg::GOSSIP => Ok(GossipMsg::Gossip(members)),
+            GossipMsg::REPLY => Ok(GossipMsg::Reply(members)),
+            _ => Err(SerError::InvalidType(format!(
+                "Unknown GossipMsg tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// A member as tracked locally
+#[derive(Debug)]
+struct Member {
+    state: MemberState,
+    /// When the member's heartbeat last increased
+    last_seen: Instant,
+}
+
+/// The events caused by a change from `old` to `new` status of `system`
+fn status_events(
+    system: &SystemPath,
+    old: Option<MemberStatus>,
+    new: MemberStatus,
+) -> Vec<MembershipEvent> {
+    let mut events = Vec::new();
+    if old.is_none() {
+        events.push(MembershipEvent::Joined(system.clone()));
+    }
+    if old != Some(new) {
+        match new {
+            MemberStatus::Joining => (),
+            MemberStatus::Up => events.push(MembershipEvent::Up(system.clone())),
+            MemberStatus::Left => events.push(MembershipEvent::Left(system.clone())),
+            MemberStatus::Down => events.push(MembershipEvent::Down(system.clone())),
+        }
+    }
+    events
+}
+
+/// The local view of the cluster
+#[derive(Debug)]
+struct MemberTable {
+    own_system: SystemPath,
+    members: FxHashMap<SystemPath, Member>,
+}
+
+impl MemberTable {
+    fn new(own_system: SystemPath, status: MemberStatus, now: Instant) -> Self {
+        let mut members = FxHashMap::default();
+        let own = MemberState {
+            system: own_system.clone(),
+            status,
+            version: 0,
+            heartbeat: 0,
+        };
+        members.insert(
+            own_system.clone(),
+            Member {
+                state: own,
+                last_seen: now,
+            },
+        );
+        MemberTable {
+            own_system,
+            members,
+        }
+    }
+
+    fn own(&self) -> &MemberState {
+        &self.members[&self.own_system].state
+    }
+
+    fn own_mut(&mut self) -> &mut MemberState {
+        &mut self
+            .members
+            .get_mut(&self.own_system)
+            .expect("own entry")
+            .state
+    }
+
+    /// Changes the status of this system, announcing it with a new version
+    fn set_own_status(&mut self, status: MemberStatus) -> Vec<MembershipEvent> {
+        let own = self.own_mut();
+        let old = own.status;
+        own.status = status;
+        own.version += 1;
+        status_events(&self.own_system, Some(old), status)
+    }
+
+    fn snapshot(&self) -> Vec<MemberState> {
+        self.members
+            .values()
+            .map(|member| member.state.clone())
+            .collect()
+    }
+
+    /// Merges a received view into the local one and returns the resulting events
+    fn merge(&mut self, remote: Vec<MemberState>, now: Instant) -> Vec<MembershipEvent> {
+        let mut events = Vec::new();
+        for state in remote {
+            if state.system == self.own_system {
+                events.extend(self.merge_own(&state));
+                continue;
+            }
+            match self.members.get_mut(&state.system) {
+                Some(member) => {
+                    let old_status = member.state.status;
+                    if state.heartbeat > member.state.heartbeat {
+                        member.state.heartbeat = state.heartbeat;
+                        member.last_seen = now;
+                    }
+                    if state.supersedes(&member.state) {
+                        member.state.status = state.status;
+                        member.state.version = state.version;
+                        events.extend(status_events(&state.system, Some(old_status), state.status));
+                    }
+                }
+                None => {
+                    events.extend(status_events(&state.system, None, state.status));
+                    self.members.insert(
+                        state.system.clone(),
+                        Member {
+                            state,
+                            last_seen: now,
+                        },
+                    );
+                }
+            }
+        }
+        events
+    }
+
+    /// Reacts to how another member sees this system
+    fn merge_own(&mut self, seen: &MemberState) -> Vec<MembershipEvent> {
+        let own = self.own().clone();
+        match own.status {
+            // somebody has seen us, so we are part of the cluster now
+            MemberStatus::Joining => self.set_own_status(MemberStatus::Up),
+            // refute a false suspicion by announcing ourselves with a newer version
+            MemberStatus::Up
+                if seen.status == MemberStatus::Down && seen.version >= own.version =>
+            {
+                let own = self.own_mut();
+                own.version = seen.version + 1;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Marks all live members whose heartbeat has not increased within `timeout` as down
+    fn check_timeouts(&mut self, now: Instant, timeout: Duration) -> Vec<MembershipEvent> {
+        let mut events = Vec::new();
+        for (system, member) in self.members.iter_mut() {
+            if system != &self.own_system
+                && member.state.status.is_live()
+                && now.saturating_duration_since(member.last_seen) > timeout
+            {
+                let old = member.state.status;
+                member.state.status = MemberStatus::Down;
+                events.extend(status_events(system, Some(old), MemberStatus::Down));
+            }
+        }
+        events
+    }
+
+    /// All live members other than this system, in a stable order
+    fn live_peers(&self) -> Vec<SystemPath> {
+        let mut peers: Vec<SystemPath> = self
+            .members
+            .values()
+            .filter(|member| {
+                member.state.system != self.own_system && member.state.status.is_live()
+            })
+            .map(|member| member.state.system.clone())
+            .collect();
+        peers.sort();
+        peers
+    }
+}
+
+/// A component that maintains the cluster membership of its system via gossip
+///
+/// The component registers itself under [MEMBERSHIP_ALIAS](MEMBERSHIP_ALIAS)
+/// when it is started, so it requires a system with a networked dispatcher.
+/// Changes in membership, including those of its own system, are indicated on the
+/// [ClusterMembership](ClusterMembership) port.
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+///
+/// let mut cfg = KompactConfig::default();
+/// cfg.load_config_str("membership.gossip_interval = 100 ms");
+/// cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+/// let system = cfg.build().expect("KompactSystem");
+///
+/// let membership = system.create(Membership::new);
+/// system.start(&membership);
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(ComponentDefinition)]
+pub struct Membership {
+    ctx: ComponentContext<Self>,
+    membership: ProvidedPort<ClusterMembership>,
+    config: Option<MembershipConfig>,
+    seeds: Vec<SystemPath>,
+    table: Option<MemberTable>,
+    next_peer: usize,
+    timer: Option<ScheduledTimer>,
+}
+
+impl Membership {
+    /// Create a new membership component configured from the system's config
+    pub fn new() -> Self {
+        Membership {
+            ctx: ComponentContext::uninitialised(),
+            membership: ProvidedPort::uninitialised(),
+            config: None,
+            seeds: Vec::new(),
+            table: None,
+            next_peer: 0,
+            timer: None,
+        }
+    }
+
+    /// Create a new membership component with the given configuration
+    ///
+    /// The `membership` section of the system's config is ignored in this case.
+    pub fn with_config(config: MembershipConfig) -> Self {
+        let mut membership = Membership::new();
+        membership.config = Some(config);
+        membership
+    }
+
+    fn config(&self) -> &MembershipConfig {
+        self.config.as_ref().expect("config")
+    }
+
+    fn table(&mut self) -> &mut MemberTable {
+        self.table.as_mut().expect("member table")
+    }
+
+    fn membership_path(system: SystemPath) -> ActorPath {
+        ActorPath::Named(NamedPath::with_system(
+            system,
+            vec![MEMBERSHIP_ALIAS.to_string()],
+        ))
+    }
+
+    fn send(&self, system: &SystemPath, msg: GossipMsg) {
+        let dst = Self::membership_path(system.clone());
+        let mut src = Self::membership_path(self.ctx.system().system_path());
+        src.set_protocol(system.protocol());
+        dst.tell_with_sender(msg, self, src);
+    }
+
+    fn publish(&mut self, events: Vec<MembershipEvent>) {
+        for event in events {
+            debug!(self.ctx.log(), "Membership changed: {:?}", event);
+            self.membership.trigger(event);
+        }
+    }
+
+    fn gossip_round(&mut self, _timeout_id: ScheduledTimer) -> Handled {
+        let timeout = Duration::from_millis(self.config().get_down_timeout());
+        let now = Instant::now();
+        let events = {
+            let table = self.table();
+            table.own_mut().heartbeat += 1;
+            table.check_timeouts(now, timeout)
+        };
+        self.publish(events);
+        let table = self.table.as_ref().expect("member table");
+        let snapshot = table.snapshot();
+        if table.own().status == MemberStatus::Joining {
+            for seed in self.seeds.iter() {
+                self.send(seed, GossipMsg::Gossip(snapshot.clone()));
+            }
+        } else {
+            let peers = table.live_peers();
+            if !peers.is_empty() {
+                let peer = &peers[self.next_peer % peers.len()];
+                self.send(peer, GossipMsg::Gossip(snapshot));
+                self.next_peer = self.next_peer.wrapping_add(1);
+            }
+        }
+        Handled::Ok
+    }
+
+    fn leave(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            self.cancel_timer(timer);
+        }
+        let events = self.table().set_own_status(MemberStatus::Left);
+        self.publish(events);
+        let table = self.table.as_ref().expect("member table");
+        let snapshot = table.snapshot();
+        for peer in table.live_peers() {
+            self.send(&peer, GossipMsg::Gossip(snapshot.clone()));
+        }
+    }
+}
+
+impl Default for Membership {
+    fn default() -> Self {
+        Membership::new()
+    }
+}
+
+impl ComponentLifecycle for Membership {
+    fn on_start(&mut self) -> Handled {
+        if self.config.is_none() {
+            self.config = Some(MembershipConfig::from_config(self.ctx.config()));
+        }
+        if self.table.is_none() {
+            let own_system = self.ctx.system().system_path();
+            self.seeds = self
+                .config()
+                .get_seeds()
+                .iter()
+                .filter(|seed| **seed != own_system)
+                .cloned()
+                .collect();
+            let status = if self.seeds.is_empty() {
+                MemberStatus::Up
+            } else {
+                MemberStatus::Joining
+            };
+            let events = status_events(&own_system, None, status);
+            self.table = Some(MemberTable::new(own_system, status, Instant::now()));
+            self.publish(events);
+        }
+        let registration = self.ctx.update_own_alias_registration(MEMBERSHIP_ALIAS);
+        self.spawn_local(move |async_self| async move {
+            match registration.await {
+                Ok(Ok(_)) => (),
+                Ok(Err(e)) => error!(
+                    async_self.ctx.log(),
+                    "Could not register membership component: {:?}", e
+                ),
+                Err(e) => error!(
+                    async_self.ctx.log(),
+                    "Membership registration was dropped: {:?}", e
+                ),
+            }
+            Handled::Ok
+        });
+        if self
+            .table
+            .as_ref()
+            .expect("member table")
+            .own()
+            .status
+            .is_live()
+        {
+            let interval = Duration::from_millis(self.config().get_gossip_interval());
+            self.timer = Some(self.schedule_periodic(interval, interval, Self::gossip_round));
+        }
+        Handled::Ok
+    }
+
+    fn on_stop(&mut self) -> Handled {
+        if let Some(timer) = self.timer.take() {
+            self.cancel_timer(timer);
+        }
+        Handled::Ok
+    }
+
+    fn on_kill(&mut self) -> Handled {
+        self.on_stop()
+    }
+}
+
+impl Provide<ClusterMembership> for Membership {
+    fn handle(&mut self, request: MembershipRequest) -> Handled {
+        match request {
+            MembershipRequest::Join(systems) => {
+                let own_system = self.table().own_system.clone();
+                for system in systems {
+                    if system != own_system && !self.seeds.contains(&system) {
+                        self.seeds.push(system);
+                    }
+                }
+                let table = self.table();
+                if table.own().status == MemberStatus::Up && table.live_peers().is_empty() {
+                    // a lone member has to go through the seeds again to find the others
+                    let events = table.set_own_status(MemberStatus::Joining);
+                    self.publish(events);
+                }
+            }
+            MembershipRequest::Leave => {
+                if self.table().own().status.is_live() {
+                    self.leave();
+                }
+            }
+        }
+        Handled::Ok
+    }
+}
+
+impl Actor for Membership {
+    type Message = Never;
+
+    fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+        unreachable!("Never type is empty")
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let sender = msg.sender.clone();
+        let msg = match msg.try_deserialise::<GossipMsg, GossipMsg>() {
+            Ok(msg) => msg,
+            Err(e) => {
+                warn!(self.ctx.log(), "Invalid gossip message: {:?}", e);
+                return Handled::Ok;
+            }
+        };
+        if self.table.is_none() || !self.table().own().status.is_live() {
+            return Handled::Ok;
+        }
+        let now = Instant::now();
+        match msg {
+            GossipMsg::Gossip(members) => {
+                let events = self.table().merge(members, now);
+                self.publish(events);
+                let snapshot = self.table().snapshot();
+                self.send(sender.system(), GossipMsg::Reply(snapshot));
+            }
+            GossipMsg::Reply(members) => {
+                let events = self.table().merge(members, now);
+                self.publish(events);
+            }
+        }
+        Handled::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use hocon::HoconLoader;
+
+    fn system(port: u16) -> SystemPath {
+        SystemPath::new(Transport::TCP, "127.0.0.1".parse().unwrap(), port)
+    }
+
+    fn state(port: u16, status: MemberStatus, version: u64, heartbeat: u64) -> MemberState {
+        MemberState {
+            system: system(port),
+            status,
+            version,
+            heartbeat,
+        }
+    }
+
+    #[test]
+    fn gossip_msg_serequiv() {
+        let members = vec![
+            state(1, MemberStatus::Joining, 0, 0),
+            state(2, MemberStatus::Up, 3, 17),
+            state(3, MemberStatus::Down, 1, 2),
+        ];
+        let msgs = vec![
+            GossipMsg::Gossip(members.clone()),
+            GossipMsg::Reply(members),
+        ];
+        for msg in msgs {
+            let mut buf = BytesMut::with_capacity(msg.size_hint().expect("size hint"));
+            msg.serialise(&mut buf).expect("serialise");
+            let mut bytes = buf.freeze();
+            let res = GossipMsg::deserialise(&mut bytes).expect("deserialise");
+            assert_eq!(msg, res);
+        }
+    }
+
+    #[test]
+    fn gossip_msg_rejects_oversized_member_count() {
+        let mut buf = BytesMut::with_capacity(5);
+        buf.put_u8(GossipMsg::GOSSIP);
+        buf.put_u32(u32::MAX);
+        let mut bytes = buf.freeze();
+        assert!(GossipMsg::deserialise(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn config_from_hocon() {
+        let hocon = HoconLoader::new()
+            .load_str(
+                r#"membership {
+                    seeds = ["tcp://127.0.0.1:1234", "tcp://127.0.0.1:1235"]
+                    gossip_interval = 100 ms
+                }"#,
+            )
+            .unwrap()
+            .hocon()
+            .unwrap();
+        let config = MembershipConfig::from_config(&hocon);
+        assert_eq!(config.get_seeds(), &[system(1234), system(1235)][..]);
+        assert_eq!(config.get_gossip_interval(), 100);
+        assert_eq!(config.get_down_timeout(), DOWN_TIMEOUT);
+    }
+
+    #[test]
+    fn merge_emits_events_for_changes() {
+        let now = Instant::now();
+        let mut table = MemberTable::new(system(1), MemberStatus::Up, now);
+        let events = table.merge(vec![state(2, MemberStatus::Joining, 0, 0)], now);
+        assert_eq!(events, vec![MembershipEvent::Joined(system(2))]);
+        let events = table.merge(vec![state(2, MemberStatus::Up, 1, 1)], now);
+        assert_eq!(events, vec![MembershipEvent::Up(system(2))]);
+        // stale information is ignored
+        let events = table.merge(vec![state(2, MemberStatus::Joining, 0, 0)], now);
+        assert!(events.is_empty());
+        // for equal versions the later status wins
+        let events = table.merge(vec![state(2, MemberStatus::Down, 1, 1)], now);
+        assert_eq!(events, vec![MembershipEvent::Down(system(2))]);
+        let events = table.merge(vec![state(2, MemberStatus::Up, 1, 5)], now);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn joining_member_is_up_once_seen() {
+        let now = Instant::now();
+        let mut table = MemberTable::new(system(1), MemberStatus::Joining, now);
+        let events = table.merge(
+            vec![
+                state(1, MemberStatus::Joining, 0, 0),
+                state(2, MemberStatus::Up, 1, 3),
+            ],
+            now,
+        );
+        assert_eq!(
+            events,
+            vec![
+                MembershipEvent::Up(system(1)),
+                MembershipEvent::Joined(system(2)),
+                MembershipEvent::Up(system(2)),
+            ]
+        );
+        assert_eq!(table.own().version, 1);
+    }
+
+    #[test]
+    fn false_suspicion_is_refuted() {
+        let now = Instant::now();
+        let mut table = MemberTable::new(system(1), MemberStatus::Up, now);
+        let events = table.merge(vec![state(1, MemberStatus::Down, 0, 0)], now);
+        assert!(events.is_empty());
+        assert_eq!(table.own().status, MemberStatus::Up);
+        assert!(table.own().version > 0);
+    }
+
+    #[test]
+    fn silent_members_are_marked_down() {
+        let start = Instant::now();
+        let mut table = MemberTable::new(system(1), MemberStatus::Up, start);
+        table.merge(vec![state(2, MemberStatus::Up, 1, 1)], start);
+        let timeout = Duration::from_millis(100);
+        let events = table.check_timeouts(start + Duration::from_millis(50), timeout);
+        assert!(events.is_empty());
+        let events = table.check_timeouts(start + Duration::from_millis(150), timeout);
+        assert_eq!(events, vec![MembershipEvent::Down(system(2))]);
+        assert!(table.live_peers().is_empty());
+    }
+}
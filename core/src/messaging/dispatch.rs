@@ -16,11 +16,19 @@ impl DispatchData {
     ///
     /// This can fail, if the data can't be moved onto the heap, and serialisation
     /// also fails.
-    pub fn into_local(self, src: ActorPath, dst: ActorPath) -> Result<NetMessage, SerError> {
+    ///
+    /// Already serialised data carries its own trace context, so `trace_context`
+    /// is only used for lazily serialised data.
+    pub fn into_local(
+        self,
+        src: ActorPath,
+        dst: ActorPath,
+        trace_context: Option<TraceContext>,
+    ) -> Result<NetMessage, SerError> {
         match self {
             DispatchData::Lazy(ser) => {
                 let ser_id = ser.ser_id();
-                Ok(NetMessage::with_box(ser_id, src, dst, ser))
+                Ok(NetMessage::with_box(ser_id, src, dst, ser).with_trace_context(trace_context))
             }
             DispatchData::SerialisedLease(mut chunk) => {
                 // The chunk contains the full frame, deserialize_msg does not deserialize FrameHead so we advance the read_pointer first
@@ -38,15 +46,24 @@ impl DispatchData {
     }
 
     /// Try to serialise this to data to bytes for remote delivery
+    ///
+    /// As with [into_local](DispatchData::into_local), `trace_context` is only used for lazily serialised data.
     pub fn into_serialised(
         self,
         src: ActorPath,
         dst: ActorPath,
+        trace_context: Option<TraceContext>,
         buf: &mut BufferEncoder,
     ) -> Result<SerialisedFrame, SerError> {
         match self {
             DispatchData::Lazy(ser) => Ok(SerialisedFrame::ChunkLease(
-                crate::serialisation::ser_helpers::serialise_msg(&src, &dst, ser.deref(), buf)?,
+                crate::serialisation::ser_helpers::serialise_msg_with_trace(
+                    &src,
+                    &dst,
+                    trace_context.as_ref(),
+                    ser.deref(),
+                    buf,
+                )?,
             )),
             DispatchData::SerialisedLease(chunk) => Ok(SerialisedFrame::ChunkLease(chunk)),
             DispatchData::SerialisedRef(chunk) => Ok(SerialisedFrame::ChunkRef(chunk)),
@@ -65,6 +82,8 @@ pub enum DispatchEnvelope {
         dst: ActorPath,
         /// The actual data to be dispatched
         msg: DispatchData,
+        /// The trace context the message was sent in, if any
+        trace_context: Option<TraceContext>,
     },
     /// A message that may already be partially serialised
     ForwardedMsg {
@@ -77,4 +96,23 @@ pub enum DispatchEnvelope {
     Event(EventEnvelope),
     /// Killed components send their BufferChunks to the Dispatcher for safe de-allocation
     LockedChunk(BufferChunk),
+    /// A request to start or stop watching an actor for termination
+    Watch(WatchEnvelope),
+    /// A message to be delivered reliably
+    Reliable(ReliableEnvelope),
+}
+
+/// A request to the dispatcher to deliver a message reliably
+///
+/// See [tell_reliable](ActorPath::tell_reliable).
+#[derive(Debug)]
+pub struct ReliableEnvelope {
+    /// The source of the message
+    pub src: ActorPath,
+    /// The destination of the message
+    pub dst: ActorPath,
+    /// The eagerly serialised message
+    pub msg: Serialised,
+    /// Fulfilled once the destination system acknowledged the message
+    pub promise: utils::KPromise<()>,
 }
@@ -1,8 +1,9 @@
 //! Message framing (serialization and deserialization into and from byte buffers)
 
 use crate::{
-    actors::{ActorPath, NamedPath, SystemField, SystemPath, Transport, UniquePath},
+    actors::{ActorPath, Address, NamedPath, SystemField, SystemPath, Transport, UniquePath},
     serialisation::{serialisation_ids, Deserialiser, SerError, SerId, Serialisable},
+    tracing::{SpanId, TraceContext, TraceId},
 };
 use bitfields::BitField;
 use bytes::{Buf, BufMut};
@@ -32,7 +33,7 @@ pub enum PathType {
 }
 
 impl BitField for AddressType {
-    const POS: usize = 2;
+    const POS: usize = 0;
     const WIDTH: usize = 2;
 }
 
@@ -42,7 +43,7 @@ impl BitField for PathType {
 }
 
 impl BitField for Transport {
-    const POS: usize = 0;
+    const POS: usize = 2;
     const WIDTH: usize = 5;
 }
 
@@ -71,6 +72,7 @@ impl TryFrom<u8> for AddressType {
         match x {
             x if x == AddressType::IPv4 as u8 => Ok(AddressType::IPv4),
             x if x == AddressType::IPv6 as u8 => Ok(AddressType::IPv6),
+            x if x == AddressType::Domain as u8 => Ok(AddressType::Domain),
             _ => Err(SerError::InvalidType("Unsupported AddressType".into())),
         }
     }
@@ -96,6 +98,7 @@ impl TryFrom<u8> for Transport {
             x if x == Transport::LOCAL as u8 => Ok(Transport::LOCAL),
             x if x == Transport::UDP as u8 => Ok(Transport::UDP),
             x if x == Transport::TCP as u8 => Ok(Transport::TCP),
+            x if x == Transport::TLS as u8 => Ok(Transport::TLS),
             _ => Err(SerError::InvalidType(
                 "Unsupported transport protocol".into(),
             )),
@@ -112,6 +115,15 @@ impl<'a> From<&'a IpAddr> for AddressType {
     }
 }
 
+impl<'a> From<&'a Address> for AddressType {
+    fn from(addr: &'a Address) -> Self {
+        match addr {
+            Address::Ip(ip) => ip.into(),
+            Address::Domain(_) => AddressType::Domain,
+        }
+    }
+}
+
 /// The header for a [system path](SystemPath)
 #[derive(Debug)]
 pub struct SystemPathHeader {
@@ -233,6 +245,13 @@ impl TryFrom<u8> for SystemPathHeader {
 /// |                   Address (4/16/ * bytes)                  ...| Port (2 bytes) |
 /// +---------------------------------------------------------------+----------------+
 /// ```
+///
+/// The address type occupies the two least significant bits of the header,
+/// followed by the protocol, with the path type in the most significant bit.
+/// Versions without domain support stored the protocol in the lowest five bits instead,
+/// overlapping the address type, so their paths can not be read by this version and vice versa.
+///
+/// Domain addresses are length-prefixed (1 byte) followed by the ASCII bytes of the name.
 impl Serialisable for SystemPath {
     fn ser_id(&self) -> SerId {
         serialisation_ids::SYSTEM_PATH
@@ -242,8 +261,9 @@ impl Serialisable for SystemPath {
         let mut size: usize = 0;
         size += 1; // header
         size += match self.address() {
-            IpAddr::V4(_) => 4,  // IPv4 uses 4 bytes
-            IpAddr::V6(_) => 16, // IPv4 uses 16 bytes
+            Address::Ip(IpAddr::V4(_)) => 4,                 // IPv4 uses 4 bytes
+            Address::Ip(IpAddr::V6(_)) => 16,                // IPv4 uses 16 bytes
+            Address::Domain(ref domain) => 1 + domain.len(), // length prefix + name
         };
         size += 2; // port # (0-65_535)
         Some(size)
@@ -266,9 +286,16 @@ impl Serialisable for SystemPath {
 #[inline(always)]
 fn system_path_put_into_buf(path: &SystemPath, buf: &mut dyn BufMut) -> Result<(), SerError> {
     match *path.address() {
-        IpAddr::V4(ref ip) => buf.put_slice(&ip.octets()),
-        IpAddr::V6(ref ip) => buf.put_slice(&ip.octets()),
-        // TODO support named Domain
+        Address::Ip(IpAddr::V4(ref ip)) => buf.put_slice(&ip.octets()),
+        Address::Ip(IpAddr::V6(ref ip)) => buf.put_slice(&ip.octets()),
+        Address::Domain(ref domain) => {
+            let data = domain.as_bytes();
+            let domain_len: u8 = u8::try_from(data.len()).map_err(|_| {
+                SerError::InvalidData("Domain overflows designated 1 byte length.".into())
+            })?;
+            buf.put_u8(domain_len);
+            buf.put_slice(data);
+        }
     }
     buf.put_u16(path.port());
     Ok(())
@@ -278,7 +305,7 @@ fn system_path_from_buf(buf: &mut dyn Buf) -> Result<(SystemPathHeader, SystemPa
     // Deserialize system path
     let fields: u8 = buf.get_u8();
     let header = SystemPathHeader::try_from(fields)?;
-    let address: IpAddr = match header.address_type {
+    let address: Address = match header.address_type {
         AddressType::IPv4 => {
             if buf.remaining() < 4 {
                 return Err(SerError::InvalidData(
@@ -287,7 +314,7 @@ fn system_path_from_buf(buf: &mut dyn Buf) -> Result<(SystemPathHeader, SystemPa
             } else {
                 let mut ip_bytes = [0u8; 4];
                 buf.copy_to_slice(&mut ip_bytes);
-                IpAddr::from(ip_bytes)
+                Address::Ip(IpAddr::from(ip_bytes))
             }
         }
         AddressType::IPv6 => {
@@ -298,15 +325,33 @@ fn system_path_from_buf(buf: &mut dyn Buf) -> Result<(SystemPathHeader, SystemPa
             } else {
                 let mut ip_bytes = [0u8; 16];
                 buf.copy_to_slice(&mut ip_bytes);
-                IpAddr::from(ip_bytes)
+                Address::Ip(IpAddr::from(ip_bytes))
             }
         }
         AddressType::Domain => {
-            unimplemented!();
+            if buf.remaining() < 1 {
+                return Err(SerError::InvalidData(
+                    "Could not parse length of domain address".into(),
+                ));
+            }
+            let domain_len = buf.get_u8() as usize;
+            if buf.remaining() < domain_len {
+                return Err(SerError::InvalidData(format!(
+                    "Could not parse {} bytes for domain address",
+                    domain_len
+                )));
+            } else {
+                let mut domain_bytes = vec![0u8; domain_len];
+                buf.copy_to_slice(&mut domain_bytes);
+                let domain = String::from_utf8(domain_bytes).map_err(|_| {
+                    SerError::InvalidData("Domain address is not valid UTF-8".into())
+                })?;
+                Address::Domain(domain)
+            }
         }
     };
     let port = buf.get_u16();
-    let system_path = SystemPath::new(header.protocol, address, port);
+    let system_path = SystemPath::with_address(header.protocol, address, port);
     Ok((header, system_path))
 }
 
@@ -430,6 +475,102 @@ impl Deserialiser<ActorPath> for ActorPath {
     }
 }
 
+/// The number of bytes of a serialised [TraceContext](TraceContext)
+pub const TRACE_CONTEXT_LEN: usize = 24;
+
+/// # Trace Context Serialization
+/// ```text
+/// +---------------------+-------------------+
+/// | Trace id (16 bytes) | Span id (8 bytes) |
+/// +---------------------+-------------------+
+/// ```
+impl Serialisable for TraceContext {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::TRACE_CONTEXT
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(TRACE_CONTEXT_LEN)
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        buf.put_slice(self.trace_id.as_bytes());
+        buf.put_slice(self.span_id.as_bytes());
+        Ok(())
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<TraceContext> for TraceContext {
+    const SER_ID: SerId = serialisation_ids::TRACE_CONTEXT;
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<TraceContext, SerError> {
+        if buf.remaining() < TRACE_CONTEXT_LEN {
+            return Err(SerError::InvalidData(format!(
+                "Could not get {} bytes for trace context",
+                TRACE_CONTEXT_LEN
+            )));
+        }
+        let mut trace_id = [0u8; 16];
+        buf.copy_to_slice(&mut trace_id);
+        let mut span_id = [0u8; 8];
+        buf.copy_to_slice(&mut span_id);
+        Ok(TraceContext {
+            trace_id: TraceId::from_bytes(trace_id),
+            span_id: SpanId::from_bytes(span_id),
+        })
+    }
+}
+
+/// Returns the number of bytes [put_trace_context](put_trace_context) uses for `context`
+pub fn trace_context_size(context: Option<&TraceContext>) -> usize {
+    match context {
+        Some(_) => 1 + TRACE_CONTEXT_LEN,
+        None => 1,
+    }
+}
+
+/// Puts the optional trace context of a message frame into `buf`
+///
+/// The context is prefixed with a single byte that is `1` if a context follows and `0` otherwise.
+pub fn put_trace_context(
+    context: Option<&TraceContext>,
+    buf: &mut dyn BufMut,
+) -> Result<(), SerError> {
+    match context {
+        Some(context) => {
+            buf.put_u8(1);
+            context.serialise(buf)
+        }
+        None => {
+            buf.put_u8(0);
+            Ok(())
+        }
+    }
+}
+
+/// Reads the optional trace context of a message frame from `buf`
+///
+/// This expects the format from [put_trace_context](put_trace_context).
+pub fn get_trace_context(buf: &mut dyn Buf) -> Result<Option<TraceContext>, SerError> {
+    if buf.remaining() < 1 {
+        return Err(SerError::InvalidData(
+            "Could not get trace context flag".into(),
+        ));
+    }
+    match buf.get_u8() {
+        0 => Ok(None),
+        1 => TraceContext::deserialise(buf).map(Some),
+        flag => Err(SerError::InvalidData(format!(
+            "Invalid trace context flag {}",
+            flag
+        ))),
+    }
+}
+
 #[cfg(test)]
 mod serialisation_tests {
     use super::*;
@@ -514,7 +655,7 @@ mod serialisation_tests {
                 .expect("UUID ActorPath Deserialisation should succeed");
             assert_eq!(buf.len(), 0);
             let deser_sys: &SystemPath = SystemField::system(&deser_path);
-            assert_eq!(deser_sys.address(), &expected_addr);
+            assert_eq!(deser_sys.address(), &Address::Ip(expected_addr));
             match deser_path {
                 ActorPath::Unique(ref up) => {
                     assert_eq!(up.id(), unique_id);
@@ -536,7 +677,7 @@ mod serialisation_tests {
                 .expect("Named ActorPath Deserialisation should succeed");
             assert_eq!(buf.len(), 0);
             let deser_sys: &SystemPath = SystemField::system(&deser_path);
-            assert_eq!(deser_sys.address(), &expected_addr);
+            assert_eq!(deser_sys.address(), &Address::Ip(expected_addr));
             match deser_path {
                 ActorPath::Unique(_) => panic!("expected Named path, got Unique path"),
                 ActorPath::Named(ref np) => {
@@ -545,4 +686,49 @@ mod serialisation_tests {
             }
         }
     }
+
+    #[test]
+    fn domain_path_serequiv() {
+        let system_path = SystemPath::with_domain(Transport::TCP, "worker-3.svc", 8080u16);
+        let named_path = ActorPath::Named(NamedPath::with_system(
+            system_path.clone(),
+            vec!["foo".into()],
+        ));
+        {
+            let header = SystemPathHeader::from_path(&named_path);
+            assert_eq!(header.address_type, AddressType::Domain);
+            // path type in bit 7, protocol in bits 2-6, address type in bits 0-1
+            assert_eq!(header.storage[0], 0b1000_0110);
+        }
+
+        let size = Serialisable::size_hint(&named_path).expect("Paths should have size hints");
+        let mut buf = BytesMut::with_capacity(size);
+        Serialisable::serialise(&named_path, &mut buf)
+            .expect("Domain ActorPath Serialisation should succeed");
+        assert_eq!(buf.len(), size);
+        let deser_path = ActorPath::deserialise(&mut buf)
+            .expect("Domain ActorPath Deserialisation should succeed");
+        assert_eq!(buf.len(), 0);
+        assert_eq!(deser_path, named_path);
+    }
+
+    #[test]
+    fn trace_context_serequiv() {
+        let context = TraceContext::new_root();
+        for expected in [None, Some(context)].iter() {
+            let size = trace_context_size(expected.as_ref());
+            let mut buf = BytesMut::with_capacity(size);
+            put_trace_context(expected.as_ref(), &mut buf)
+                .expect("TraceContext Serialisation should succeed");
+            assert_eq!(buf.len(), size);
+            let deserialised =
+                get_trace_context(&mut buf).expect("TraceContext Deserialisation should succeed");
+            assert_eq!(buf.len(), 0);
+            assert_eq!(&deserialised, expected);
+        }
+        let mut invalid = BytesMut::new();
+        invalid.put_u8(1);
+        invalid.put_slice(&[0u8; 4]);
+        assert!(get_trace_context(&mut invalid).is_err());
+    }
 }
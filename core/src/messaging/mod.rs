@@ -16,6 +16,7 @@ use crate::{
         Serialiser,
         TryClone,
     },
+    tracing::TraceContext,
     utils,
 };
 use bytes::{Buf, Bytes};
@@ -29,6 +30,8 @@ mod serialised;
 pub use serialised::*;
 mod dispatch;
 pub use dispatch::*;
+mod watch;
+pub use watch::*;
 mod deser_macro;
 pub use deser_macro::*;
 
@@ -49,7 +52,9 @@ pub enum MsgEnvelope<M: MessageBounds> {
     /// A message of the actor's `Message` type
     Typed(M),
     /// A message from the network
-    Net(NetMessage),
+    ///
+    /// The message is boxed, so that local messages don't pay for its size.
+    Net(Box<NetMessage>),
 }
 
 /// Something that can resolved to some kind of path by the dispatcher
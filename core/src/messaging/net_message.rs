@@ -27,6 +27,10 @@ pub struct NetMessage {
     pub receiver: ActorPath,
     /// The actual data of the message
     pub data: NetData,
+    /// The trace context the message was sent in, if it is being [traced](crate::tracing)
+    ///
+    /// The context is boxed, so that untraced messages don't pay for its size.
+    pub trace_context: Option<Box<TraceContext>>,
 }
 
 /// The data part of an incoming message from the networking subsystem
@@ -80,6 +84,7 @@ impl NetMessage {
             sender,
             receiver,
             data: NetData::with(ser_id, HeapOrSer::Boxed(data)),
+            trace_context: None,
         }
     }
 
@@ -94,6 +99,7 @@ impl NetMessage {
             sender,
             receiver,
             data: NetData::with(ser_id, HeapOrSer::Serialised(data)),
+            trace_context: None,
         }
     }
 
@@ -109,6 +115,7 @@ impl NetMessage {
             sender,
             receiver,
             data: NetData::with(ser_id, HeapOrSer::ChunkLease(data)),
+            trace_context: None,
         }
     }
 
@@ -124,6 +131,7 @@ impl NetMessage {
             sender,
             receiver,
             data: NetData::with(ser_id, HeapOrSer::ChunkRef(data)),
+            trace_context: None,
         }
     }
 
@@ -132,6 +140,12 @@ impl NetMessage {
         &self.sender
     }
 
+    /// Set the trace context of this message
+    pub fn with_trace_context(mut self, trace_context: Option<TraceContext>) -> Self {
+        self.trace_context = trace_context.map(Box::new);
+        self
+    }
+
     /// Try to deserialise the data into a value of type `T` wrapped into a message
     ///
     /// This method attempts to deserialise the contents into an
@@ -180,6 +194,7 @@ impl NetMessage {
             sender,
             receiver,
             data,
+            trace_context,
         } = self;
         match data.try_deserialise::<T, D>() {
             Ok(t) => Ok(DeserialisedMessage::with(sender, receiver, t)),
@@ -188,6 +203,7 @@ impl NetMessage {
                     sender,
                     receiver,
                     data,
+                    trace_context,
                 }),
                 UnpackError::NoCast(data) => UnpackError::NoCast(data),
                 UnpackError::DeserError(e) => UnpackError::DeserError(e),
@@ -302,6 +318,7 @@ impl NetMessage {
             sender,
             receiver,
             data,
+            trace_context,
         } = self;
         data.try_deserialise_unchecked::<T, D>()
             .map_err(|e| match e {
@@ -309,6 +326,7 @@ impl NetMessage {
                     sender,
                     receiver,
                     data,
+                    trace_context,
                 }),
                 UnpackError::NoCast(data) => UnpackError::NoCast(data),
                 UnpackError::DeserError(e) => UnpackError::DeserError(e),
@@ -327,6 +345,7 @@ impl TryClone for NetMessage {
             sender: self.sender.clone(),
             receiver: self.receiver.clone(),
             data,
+            trace_context: self.trace_context.clone(),
         })
     }
 }
@@ -0,0 +1,79 @@
+use super::*;
+use crate::serialisation::serialisation_ids;
+use bytes::BufMut;
+
+/// A request to the dispatcher to start or stop watching an actor
+///
+/// See [watch](crate::prelude::ComponentContext::watch) and
+/// [unwatch](crate::prelude::ComponentContext::unwatch).
+#[derive(Debug)]
+pub enum WatchEnvelope {
+    /// Deliver a [Terminated](Terminated) message to `watcher` once `target` is gone
+    Watch {
+        /// The unique id of the watching component
+        watcher_id: Uuid,
+        /// A reference to the watching component
+        watcher: DynActorRef,
+        /// The unique path of the watching component
+        watcher_path: ActorPath,
+        /// The actor to watch
+        target: ActorPath,
+    },
+    /// Stop delivering notifications about `target` to the component with id `watcher_id`
+    Unwatch {
+        /// The unique id of the watching component
+        watcher_id: Uuid,
+        /// The actor to stop watching
+        target: ActorPath,
+    },
+}
+
+/// Notification that a watched actor is gone
+///
+/// This message is delivered via [receive_network](crate::prelude::Actor::receive_network)
+/// to every component that [watched](crate::prelude::ComponentContext::watch) the contained path,
+/// either when the actor was destroyed, or when its system has been unreachable
+/// for longer than the dispatcher's configured timeout.
+///
+/// `Terminated` is its own [Deserialiser](Deserialiser).
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+///
+/// # fn handle(msg: NetMessage) {
+/// match_deser!(msg; {
+///     terminated: Terminated [Terminated] => println!("{} is gone", terminated.0),
+///     _ => (),
+/// });
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Terminated(pub ActorPath);
+
+impl Serialisable for Terminated {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::TERMINATED
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.0.size_hint()
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        self.0.serialise(buf)
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<Terminated> for Terminated {
+    const SER_ID: SerId = serialisation_ids::TERMINATED;
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<Terminated, SerError> {
+        ActorPath::deserialise(buf).map(Terminated)
+    }
+}
@@ -0,0 +1,448 @@
+//! The metrics Kompact records about itself
+
+use super::*;
+use crate::{component::SchedulingDecision, net::buffers::BufferChunk};
+use rustc_hash::FxHashMap;
+use std::net::SocketAddr;
+use uuid::Uuid;
+
+const COMPONENT_MESSAGES: &str = "kompact_component_messages_total";
+const COMPONENT_EVENTS: &str = "kompact_component_events_total";
+const COMPONENT_MESSAGES_PER_EXECUTE: &str = "kompact_component_messages_per_execute";
+const COMPONENT_SCHEDULING_LATENCY: &str = "kompact_component_scheduling_latency_seconds";
+const COMPONENT_MAILBOX_DEPTH: &str = "kompact_component_mailbox_depth";
+const COMPONENT_TIMER_BACKLOG: &str = "kompact_component_timer_backlog";
+const COMPONENT_TIMERS: &str = "kompact_component_timers";
+
+const NETWORK_SENT_BYTES: &str = "kompact_network_sent_bytes_total";
+const NETWORK_RECEIVED_BYTES: &str = "kompact_network_received_bytes_total";
+const NETWORK_SENT_FRAMES: &str = "kompact_network_sent_frames_total";
+const NETWORK_RECEIVED_FRAMES: &str = "kompact_network_received_frames_total";
+const NETWORK_RECONNECTS: &str = "kompact_network_reconnects_total";
+const NETWORK_QUEUED_FRAMES: &str = "kompact_network_queued_frames";
+
+const BUFFER_POOL_ALLOCATED: &str = "kompact_buffer_pool_allocated_chunks";
+const BUFFER_POOL_CAPACITY: &str = "kompact_buffer_pool_capacity_chunks";
+const BUFFER_POOL_EXHAUSTED: &str = "kompact_buffer_pool_exhausted_total";
+
+/// A registry together with the label of the system recording into it
+#[derive(Clone, Debug)]
+pub(crate) struct SystemMetrics {
+    registry: MetricsRegistry,
+    system: String,
+}
+
+impl SystemMetrics {
+    pub(crate) fn new(registry: MetricsRegistry, system: String) -> SystemMetrics {
+        SystemMetrics { registry, system }
+    }
+
+    pub(crate) fn registry(&self) -> &MetricsRegistry {
+        &self.registry
+    }
+
+    pub(crate) fn component(&self, type_name: &'static str, id: &Uuid) -> ComponentMetrics {
+        let id = id.to_string();
+        let labels = [
+            ("system", self.system.as_str()),
+            ("type", type_name),
+            ("id", id.as_str()),
+        ];
+        let r = &self.registry;
+        ComponentMetrics {
+            messages: r.counter(COMPONENT_MESSAGES, "Messages handled", &labels),
+            events: r.counter(COMPONENT_EVENTS, "Port events handled", &labels),
+            messages_per_execute: r.histogram(
+                COMPONENT_MESSAGES_PER_EXECUTE,
+                "Messages handled each time the component was run",
+                COUNT_BUCKETS,
+                &labels,
+            ),
+            scheduling_latency: r.histogram(
+                COMPONENT_SCHEDULING_LATENCY,
+                "Time between scheduling the component and running it",
+                LATENCY_BUCKETS,
+                &labels,
+            ),
+            mailbox_depth: r.gauge(COMPONENT_MAILBOX_DEPTH, "Messages in the mailbox", &labels),
+            timer_backlog: r.gauge(
+                COMPONENT_TIMER_BACKLOG,
+                "Expired timers waiting for the component to run",
+                &labels,
+            ),
+            timers: r.gauge(COMPONENT_TIMERS, "Scheduled timers", &labels),
+            scheduled_at: AtomicU64::new(0),
+            messages_before_execute: AtomicU64::new(0),
+            registry: self.registry.clone(),
+            system: self.system.clone(),
+            type_name,
+            id,
+        }
+    }
+
+    pub(crate) fn network(&self) -> NetworkMetrics {
+        NetworkMetrics {
+            metrics: self.clone(),
+            channels: FxHashMap::default(),
+        }
+    }
+
+    pub(crate) fn buffer_pool(&self, pool: &str) -> BufferPoolMetrics {
+        let labels = [("system", self.system.as_str()), ("pool", pool)];
+        let r = &self.registry;
+        BufferPoolMetrics {
+            allocated: r.gauge(
+                BUFFER_POOL_ALLOCATED,
+                "Chunks allocated by buffer pools",
+                &labels,
+            ),
+            capacity: r.gauge(
+                BUFFER_POOL_CAPACITY,
+                "Chunks buffer pools may allocate at most",
+                &labels,
+            ),
+            exhausted: r.counter(
+                BUFFER_POOL_EXHAUSTED,
+                "Times a buffer pool had no chunk to hand out",
+                &labels,
+            ),
+        }
+    }
+}
+
+/// The metrics of a single component, which are removed from the registry with the component
+pub(crate) struct ComponentMetrics {
+    messages: Counter,
+    events: Counter,
+    messages_per_execute: Histogram,
+    scheduling_latency: Histogram,
+    mailbox_depth: Gauge,
+    timer_backlog: Gauge,
+    timers: Gauge,
+    // registry time in nanoseconds plus one when the component was scheduled, 0 if it wasn't
+    scheduled_at: AtomicU64,
+    messages_before_execute: AtomicU64,
+    registry: MetricsRegistry,
+    system: String,
+    type_name: &'static str,
+    id: String,
+}
+
+impl ComponentMetrics {
+    /// The component was handed to a scheduler
+    pub(crate) fn scheduled(&self) -> () {
+        let now = self.registry.elapsed().as_nanos() as u64;
+        self.scheduled_at.store(now + 1, Ordering::Relaxed);
+    }
+
+    /// The component starts running with the given backlog
+    pub(crate) fn begin_execute(&self, mailbox: usize, timer_backlog: usize, timers: usize) -> () {
+        let scheduled_at = self.scheduled_at.swap(0, Ordering::Relaxed);
+        if scheduled_at > 0 {
+            let now = self.registry.elapsed().as_nanos() as u64;
+            let waited = now.saturating_sub(scheduled_at - 1);
+            self.scheduling_latency
+                .observe_duration(Duration::from_nanos(waited));
+        }
+        self.mailbox_depth.set(mailbox as i64);
+        self.timer_backlog.set(timer_backlog as i64);
+        self.timers.set(timers as i64);
+        self.messages_before_execute
+            .store(self.messages.get(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn message_handled(&self) -> () {
+        self.messages.inc();
+    }
+
+    pub(crate) fn events_handled(&self, count: usize) -> () {
+        self.events.inc_by(count as u64);
+    }
+
+    /// The component stopped running and will be run again if `decision` says so
+    pub(crate) fn end_execute(&self, mailbox: usize, decision: &SchedulingDecision) -> () {
+        let before = self.messages_before_execute.load(Ordering::Relaxed);
+        let handled = self.messages.get().saturating_sub(before);
+        self.messages_per_execute.observe(handled as f64);
+        self.mailbox_depth.set(mailbox as i64);
+        if let SchedulingDecision::Schedule = decision {
+            // the scheduler will reschedule the component without asking it
+            self.scheduled();
+        }
+    }
+}
+
+impl Drop for ComponentMetrics {
+    fn drop(&mut self) {
+        let labels = [
+            ("system", self.system.as_str()),
+            ("type", self.type_name),
+            ("id", self.id.as_str()),
+        ];
+        for name in [
+            COMPONENT_MESSAGES,
+            COMPONENT_EVENTS,
+            COMPONENT_MESSAGES_PER_EXECUTE,
+            COMPONENT_SCHEDULING_LATENCY,
+            COMPONENT_MAILBOX_DEPTH,
+            COMPONENT_TIMER_BACKLOG,
+            COMPONENT_TIMERS,
+        ]
+        .iter()
+        {
+            self.registry.remove(name, &labels);
+        }
+    }
+}
+
+/// The metrics of all channels to remote systems, looked up by their address
+pub(crate) struct NetworkMetrics {
+    metrics: SystemMetrics,
+    channels: FxHashMap<SocketAddr, ChannelMetrics>,
+}
+
+impl NetworkMetrics {
+    pub(crate) fn channel(&mut self, addr: SocketAddr) -> &mut ChannelMetrics {
+        let metrics = &self.metrics;
+        self.channels.entry(addr).or_insert_with(|| {
+            let remote = addr.to_string();
+            let labels = [
+                ("system", metrics.system.as_str()),
+                ("remote", remote.as_str()),
+            ];
+            let r = &metrics.registry;
+            ChannelMetrics {
+                sent_bytes: r.counter(NETWORK_SENT_BYTES, "Bytes sent", &labels),
+                received_bytes: r.counter(NETWORK_RECEIVED_BYTES, "Bytes received", &labels),
+                sent_frames: r.counter(NETWORK_SENT_FRAMES, "Message frames sent", &labels),
+                received_frames: r.counter(
+                    NETWORK_RECEIVED_FRAMES,
+                    "Message frames received",
+                    &labels,
+                ),
+                reconnects: r.counter(
+                    NETWORK_RECONNECTS,
+                    "Connections established after the first one",
+                    &labels,
+                ),
+                queued_frames: r.gauge(
+                    NETWORK_QUEUED_FRAMES,
+                    "Frames queued while waiting for a connection",
+                    &labels,
+                ),
+                was_connected: false,
+            }
+        })
+    }
+}
+
+impl fmt::Debug for NetworkMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetworkMetrics")
+            .field("system", &self.metrics.system)
+            .finish()
+    }
+}
+
+/// The metrics of the channel to a single remote system
+pub(crate) struct ChannelMetrics {
+    pub(crate) sent_bytes: Counter,
+    pub(crate) received_bytes: Counter,
+    pub(crate) sent_frames: Counter,
+    pub(crate) received_frames: Counter,
+    pub(crate) queued_frames: Gauge,
+    reconnects: Counter,
+    was_connected: bool,
+}
+
+impl ChannelMetrics {
+    /// A connection to the remote system was established
+    pub(crate) fn connected(&mut self) -> () {
+        if self.was_connected {
+            self.reconnects.inc();
+        } else {
+            self.was_connected = true;
+        }
+    }
+}
+
+/// The metrics of a [BufferPool](crate::net::buffers::BufferPool),
+/// which pools with the same name share
+#[derive(Debug)]
+pub(crate) struct BufferPoolMetrics {
+    allocated: Gauge,
+    capacity: Gauge,
+    exhausted: Counter,
+}
+
+impl BufferPoolMetrics {
+    /// A pool with `allocated` chunks that may grow up to `capacity` chunks starts reporting
+    pub(crate) fn attach(&self, allocated: usize, capacity: usize) -> () {
+        self.allocated.add(allocated as i64);
+        self.capacity.add(capacity as i64);
+    }
+
+    /// A pool with `allocated` chunks that may have grown up to `capacity` chunks was dropped
+    pub(crate) fn detach(&self, allocated: usize, capacity: usize) -> () {
+        self.allocated.sub(allocated as i64);
+        self.capacity.sub(capacity as i64);
+    }
+
+    /// A pool tried to hand out a chunk, and got `chunk`
+    pub(crate) fn handed_out(&self, chunk: &Option<BufferChunk>) -> () {
+        if chunk.is_none() {
+            self.exhausted.inc();
+        }
+    }
+
+    /// A pool allocated a new chunk
+    pub(crate) fn allocated(&self) -> () {
+        self.allocated.inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::*,
+        testkit::{wait_until, TestProbe},
+    };
+
+    const TIMEOUT: Duration = Duration::from_millis(5000);
+
+    fn instrumented_system(registry: &MetricsRegistry, networked: bool) -> KompactSystem {
+        let mut cfg = KompactConfig::default();
+        cfg.metrics(registry.clone());
+        if networked {
+            cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+        }
+        cfg.build().expect("KompactSystem")
+    }
+
+    // The values of the metric `name` whose labels include all of `labels`
+    fn find_all(
+        registry: &MetricsRegistry,
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> Vec<MetricValue> {
+        registry
+            .snapshot()
+            .into_iter()
+            .filter(|family| family.name == name)
+            .flat_map(|family| family.samples)
+            .filter(|sample| {
+                labels
+                    .iter()
+                    .all(|(k, v)| sample.labels.iter().any(|(lk, lv)| lk == k && lv == v))
+            })
+            .map(|sample| sample.value)
+            .collect()
+    }
+
+    fn find(
+        registry: &MetricsRegistry,
+        name: &str,
+        labels: &[(&str, &str)],
+    ) -> Option<MetricValue> {
+        find_all(registry, name, labels).into_iter().next()
+    }
+
+    // The sum of all counters `name` whose labels include all of `labels`
+    fn counter(registry: &MetricsRegistry, name: &str, labels: &[(&str, &str)]) -> u64 {
+        find_all(registry, name, labels)
+            .into_iter()
+            .map(|value| match value {
+                MetricValue::Counter(v) => v,
+                other => panic!("{} is not a counter: {:?}", name, other),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_component_metrics() {
+        let registry = MetricsRegistry::new();
+        let system = instrumented_system(&registry, false);
+        let probe: TestProbe<u64> = TestProbe::new(&system);
+        let id = probe.component().id().to_string();
+        let labels = [("id", id.as_str())];
+
+        let probe_ref = probe.component().actor_ref();
+        for i in 0..3u64 {
+            probe_ref.tell(i);
+        }
+        for i in 0..3u64 {
+            assert_eq!(i, probe.expect_msg(TIMEOUT));
+        }
+        assert!(wait_until(TIMEOUT, || {
+            counter(&registry, COMPONENT_MESSAGES, &labels) == 3
+        }));
+        match find(&registry, COMPONENT_MESSAGES_PER_EXECUTE, &labels) {
+            Some(MetricValue::Histogram(h)) => {
+                assert!(h.count > 0);
+                assert!((h.sum - 3.0).abs() < f64::EPSILON);
+            }
+            other => panic!("Unexpected messages per execute: {:?}", other),
+        }
+        match find(&registry, COMPONENT_SCHEDULING_LATENCY, &labels) {
+            Some(MetricValue::Histogram(h)) => assert!(h.count > 0),
+            other => panic!("Unexpected scheduling latency: {:?}", other),
+        }
+        assert_eq!(
+            Some(MetricValue::Gauge(0)),
+            find(&registry, COMPONENT_MAILBOX_DEPTH, &labels)
+        );
+
+        system
+            .kill_notify(probe.component().clone())
+            .wait_timeout(TIMEOUT)
+            .expect("probe killed");
+        drop(probe_ref);
+        drop(probe);
+        assert!(
+            wait_until(TIMEOUT, || find(&registry, COMPONENT_MESSAGES, &labels)
+                .is_none()),
+            "Metrics of a deallocated component should be removed"
+        );
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_network_metrics() {
+        let registry = MetricsRegistry::new();
+        let system1 = instrumented_system(&registry, true);
+        let system2 = instrumented_system(&registry, true);
+        let probe1: TestProbe<u64> = TestProbe::new(&system1);
+        let path1 = probe1.register();
+        let probe2: TestProbe<u64> = TestProbe::new(&system2);
+        let path2 = probe2.register();
+
+        path2.tell_with_sender(42u64, &system1, path1);
+        probe2.expect_net_msg(TIMEOUT);
+
+        let label1 = system1.label().to_string();
+        let label2 = system2.label().to_string();
+        let remote2 = system2
+            .system_path()
+            .socket_address()
+            .expect("socket address")
+            .to_string();
+        let sent = [("system", label1.as_str()), ("remote", remote2.as_str())];
+        assert!(wait_until(TIMEOUT, || {
+            counter(&registry, NETWORK_SENT_FRAMES, &sent) >= 1
+                && counter(&registry, NETWORK_SENT_BYTES, &sent) > 0
+        }));
+        let received = [("system", label2.as_str())];
+        assert!(counter(&registry, NETWORK_RECEIVED_FRAMES, &received) >= 1);
+        assert!(counter(&registry, NETWORK_RECEIVED_BYTES, &received) > 0);
+
+        let pool = [("system", label1.as_str()), ("pool", "network")];
+        match find(&registry, BUFFER_POOL_ALLOCATED, &pool) {
+            Some(MetricValue::Gauge(allocated)) => assert!(allocated > 0),
+            other => panic!("Unexpected allocated chunks: {:?}", other),
+        }
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+}
@@ -0,0 +1,550 @@
+//! Counters, gauges, and histograms describing a running Kompact system.
+//!
+//! Metrics are recorded into a [MetricsRegistry](MetricsRegistry), which is given to a system
+//! via [KompactConfig::metrics](crate::prelude::KompactConfig::metrics).
+//! With a registry in place, the system instruments itself:
+//!
+//! - every component records its mailbox depth, the messages and events it handles,
+//!   the number of messages per scheduling, how long it waited to be run once scheduled,
+//!   and the number of its timers, labelled with its `type` and `id`,
+//! - the [NetworkDispatcher](crate::prelude::NetworkDispatcher) records the bytes and frames
+//!   sent and received over each TCP channel, reconnections, and the number of frames queued
+//!   for each remote system, labelled with the `remote` address,
+//! - and all buffer pools record how many of their chunks are allocated and how often they ran dry.
+//!
+//! All metrics also carry the `system` label of the system they describe,
+//! so one registry can be shared by several systems.
+//! Applications can register their own metrics in the same registry.
+//!
+//! Registries can be read via [snapshot](MetricsRegistry::snapshot) and handed to any
+//! [MetricsExporter](MetricsExporter). The [prometheus](prometheus) module provides
+//! an exporter for the Prometheus text format, as well as a small HTTP endpoint to scrape it from.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+        Mutex,
+        MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+pub(crate) mod instruments;
+pub mod prometheus;
+
+/// Histogram buckets for durations in seconds, from 10µs to 1s
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0,
+];
+
+/// Histogram buckets for counts, in powers of two from 1 to 1024
+pub const COUNT_BUCKETS: &[f64] = &[
+    1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0, 512.0, 1024.0,
+];
+
+/// A registry of named metrics, shared by everything that records into or exports from it
+///
+/// Metrics are identified by their name and labels.
+/// Asking for the same name and labels twice returns handles to the same underlying metric,
+/// so it doesn't matter who creates a metric first.
+///
+/// Cloning a registry is cheap and produces another handle to the same metrics.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{metrics::MetricsRegistry, prelude::*};
+///
+/// let registry = MetricsRegistry::new();
+/// let mut conf = KompactConfig::default();
+/// conf.metrics(registry.clone());
+/// let system = conf.build().expect("system");
+///
+/// let requests = registry.counter("requests_total", "Requests served", &[("route", "/")]);
+/// requests.inc();
+/// assert_eq!(1, registry.counter("requests_total", "", &[("route", "/")]).get());
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone)]
+pub struct MetricsRegistry {
+    inner: Arc<RegistryInner>,
+}
+
+struct RegistryInner {
+    epoch: Instant,
+    families: Mutex<BTreeMap<String, Family>>,
+}
+
+struct Family {
+    help: String,
+    kind: MetricKind,
+    series: BTreeMap<Labels, Series>,
+}
+
+type Labels = Vec<(String, String)>;
+
+enum Series {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl MetricsRegistry {
+    /// Create a new registry without any metrics
+    pub fn new() -> MetricsRegistry {
+        MetricsRegistry {
+            inner: Arc::new(RegistryInner {
+                epoch: Instant::now(),
+                families: Mutex::new(BTreeMap::new()),
+            }),
+        }
+    }
+
+    /// Returns the counter called `name` with the given `labels`, creating it if necessary
+    ///
+    /// The `help` text is only used when the first metric called `name` is created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric called `name` already exists with a different kind.
+    pub fn counter(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Counter {
+        let series = self.get_or_create(name, help, MetricKind::Counter, labels, || {
+            Series::Counter(Counter::default())
+        });
+        match series {
+            Series::Counter(counter) => counter,
+            _ => unreachable!("Kind was checked on creation"),
+        }
+    }
+
+    /// Returns the gauge called `name` with the given `labels`, creating it if necessary
+    ///
+    /// The `help` text is only used when the first metric called `name` is created.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric called `name` already exists with a different kind.
+    pub fn gauge(&self, name: &str, help: &str, labels: &[(&str, &str)]) -> Gauge {
+        let series = self.get_or_create(name, help, MetricKind::Gauge, labels, || {
+            Series::Gauge(Gauge::default())
+        });
+        match series {
+            Series::Gauge(gauge) => gauge,
+            _ => unreachable!("Kind was checked on creation"),
+        }
+    }
+
+    /// Returns the histogram called `name` with the given `labels`, creating it if necessary
+    ///
+    /// The `help` text and the upper bounds of the `buckets` are only used
+    /// when the metric is created. The bounds must be sorted in increasing order;
+    /// a final `+Inf` bucket is always added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a metric called `name` already exists with a different kind.
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        buckets: &[f64],
+        labels: &[(&str, &str)],
+    ) -> Histogram {
+        let series = self.get_or_create(name, help, MetricKind::Histogram, labels, || {
+            Series::Histogram(Histogram::with_buckets(buckets))
+        });
+        match series {
+            Series::Histogram(histogram) => histogram,
+            _ => unreachable!("Kind was checked on creation"),
+        }
+    }
+
+    /// Removes the metric called `name` with the given `labels`
+    ///
+    /// Existing handles to the metric can still be used, but their values are not exported anymore.
+    ///
+    /// Returns `true` if the metric existed.
+    pub fn remove(&self, name: &str, labels: &[(&str, &str)]) -> bool {
+        let mut families = self.lock();
+        match families.get_mut(name) {
+            Some(family) => {
+                let removed = family.series.remove(&owned_labels(labels)).is_some();
+                if family.series.is_empty() {
+                    families.remove(name);
+                }
+                removed
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the current values of all metrics, ordered by name and labels
+    pub fn snapshot(&self) -> Vec<MetricFamily> {
+        let families = self.lock();
+        families
+            .iter()
+            .map(|(name, family)| MetricFamily {
+                name: name.clone(),
+                help: family.help.clone(),
+                kind: family.kind,
+                samples: family
+                    .series
+                    .iter()
+                    .map(|(labels, series)| Sample {
+                        labels: labels.clone(),
+                        value: series.value(),
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Hands a [snapshot](MetricsRegistry::snapshot) of all metrics to `exporter`
+    pub fn export(&self, exporter: &mut dyn MetricsExporter) -> io::Result<()> {
+        exporter.export(&self.snapshot())
+    }
+
+    /// Time since the registry was created, used as a cheap shared clock by instruments
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.inner.epoch.elapsed()
+    }
+
+    fn get_or_create<F>(
+        &self,
+        name: &str,
+        help: &str,
+        kind: MetricKind,
+        labels: &[(&str, &str)],
+        create: F,
+    ) -> Series
+    where
+        F: FnOnce() -> Series,
+    {
+        let mut families = self.lock();
+        let family = families.entry(name.to_string()).or_insert_with(|| Family {
+            help: help.to_string(),
+            kind,
+            series: BTreeMap::new(),
+        });
+        assert_eq!(
+            kind, family.kind,
+            "Metric {} already exists as a {:?}",
+            name, family.kind
+        );
+        let series = family
+            .series
+            .entry(owned_labels(labels))
+            .or_insert_with(create);
+        series.handle()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<String, Family>> {
+        self.inner
+            .families
+            .lock()
+            .expect("Metrics registry lock poisoned")
+    }
+}
+
+impl Default for MetricsRegistry {
+    fn default() -> Self {
+        MetricsRegistry::new()
+    }
+}
+
+impl fmt::Debug for MetricsRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let families = self.lock();
+        f.debug_struct("MetricsRegistry")
+            .field("metrics", &families.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn owned_labels(labels: &[(&str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+impl Series {
+    // another handle to the same metric, to be used outside the registry lock
+    fn handle(&self) -> Series {
+        match self {
+            Series::Counter(c) => Series::Counter(c.clone()),
+            Series::Gauge(g) => Series::Gauge(g.clone()),
+            Series::Histogram(h) => Series::Histogram(h.clone()),
+        }
+    }
+
+    fn value(&self) -> MetricValue {
+        match self {
+            Series::Counter(c) => MetricValue::Counter(c.get()),
+            Series::Gauge(g) => MetricValue::Gauge(g.get()),
+            Series::Histogram(h) => MetricValue::Histogram(h.snapshot()),
+        }
+    }
+}
+
+/// A monotonically increasing count
+#[derive(Clone, Debug, Default)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    /// Increase the count by one
+    pub fn inc(&self) -> () {
+        self.inc_by(1);
+    }
+
+    /// Increase the count by `n`
+    pub fn inc_by(&self, n: u64) -> () {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// The current count
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down
+#[derive(Clone, Debug, Default)]
+pub struct Gauge(Arc<AtomicI64>);
+
+impl Gauge {
+    /// Set the value to `v`
+    pub fn set(&self, v: i64) -> () {
+        self.0.store(v, Ordering::Relaxed);
+    }
+
+    /// Increase the value by `n`
+    pub fn add(&self, n: i64) -> () {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Decrease the value by `n`
+    pub fn sub(&self, n: i64) -> () {
+        self.0.fetch_sub(n, Ordering::Relaxed);
+    }
+
+    /// Increase the value by one
+    pub fn inc(&self) -> () {
+        self.add(1);
+    }
+
+    /// Decrease the value by one
+    pub fn dec(&self) -> () {
+        self.sub(1);
+    }
+
+    /// The current value
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A distribution of observed values, counted into buckets with fixed upper bounds
+#[derive(Clone, Debug)]
+pub struct Histogram(Arc<HistogramCore>);
+
+#[derive(Debug)]
+struct HistogramCore {
+    bounds: Vec<f64>,
+    // one more than bounds, for +Inf
+    counts: Vec<AtomicU64>,
+    // the bits of an f64
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn with_buckets(bounds: &[f64]) -> Histogram {
+        debug_assert!(
+            bounds.windows(2).all(|w| w[0] < w[1]),
+            "Histogram buckets must be sorted in increasing order"
+        );
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Histogram(Arc::new(HistogramCore {
+            bounds: bounds.to_vec(),
+            counts,
+            sum: AtomicU64::new(0f64.to_bits()),
+        }))
+    }
+
+    /// Record `value`
+    pub fn observe(&self, value: f64) -> () {
+        let core = &self.0;
+        let index = core
+            .bounds
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(core.bounds.len());
+        core.counts[index].fetch_add(1, Ordering::Relaxed);
+        let mut current = core.sum.load(Ordering::Relaxed);
+        loop {
+            let next = (f64::from_bits(current) + value).to_bits();
+            match core.sum.compare_exchange_weak(
+                current,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Record `duration` in seconds
+    pub fn observe_duration(&self, duration: Duration) -> () {
+        self.observe(duration.as_secs_f64());
+    }
+
+    /// The current state of the histogram
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let core = &self.0;
+        let mut cumulative = 0u64;
+        let mut buckets = Vec::with_capacity(core.counts.len());
+        for (index, count) in core.counts.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let bound = core.bounds.get(index).copied().unwrap_or(f64::INFINITY);
+            buckets.push((bound, cumulative));
+        }
+        HistogramSnapshot {
+            buckets,
+            sum: f64::from_bits(core.sum.load(Ordering::Relaxed)),
+            count: cumulative,
+        }
+    }
+}
+
+/// The state of a [Histogram](Histogram) at some point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct HistogramSnapshot {
+    /// The upper bound of each bucket and the number of values less than or equal to it,
+    /// ending with the `+Inf` bucket
+    pub buckets: Vec<(f64, u64)>,
+    /// The sum of all observed values
+    pub sum: f64,
+    /// The number of observed values
+    pub count: u64,
+}
+
+/// The kind of a metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    /// A [Counter](Counter)
+    Counter,
+    /// A [Gauge](Gauge)
+    Gauge,
+    /// A [Histogram](Histogram)
+    Histogram,
+}
+
+/// All metrics with the same name, as seen by a [MetricsExporter](MetricsExporter)
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricFamily {
+    /// The name shared by all metrics in the family
+    pub name: String,
+    /// A description of what is measured
+    pub help: String,
+    /// The kind shared by all metrics in the family
+    pub kind: MetricKind,
+    /// The value for each combination of labels
+    pub samples: Vec<Sample>,
+}
+
+/// The value of a metric with a particular set of labels
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    /// The label names and values, in the order they were given on creation
+    pub labels: Vec<(String, String)>,
+    /// The current value
+    pub value: MetricValue,
+}
+
+/// The value of a single metric
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetricValue {
+    /// The count of a [Counter](Counter)
+    Counter(u64),
+    /// The value of a [Gauge](Gauge)
+    Gauge(i64),
+    /// The distribution recorded by a [Histogram](Histogram)
+    Histogram(HistogramSnapshot),
+}
+
+/// Something that ships metrics elsewhere, like a file, a socket, or a monitoring service
+///
+/// Exporters are handed snapshots via [MetricsRegistry::export](MetricsRegistry::export).
+/// How often that happens is up to the application; pull-based systems
+/// can also just take a snapshot on every request, as the
+/// [PrometheusEndpoint](prometheus::PrometheusEndpoint) does.
+pub trait MetricsExporter {
+    /// Export the current values of all `metrics`
+    fn export(&mut self, metrics: &[MetricFamily]) -> io::Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_shares_metrics() {
+        let registry = MetricsRegistry::new();
+        let a = registry.counter("hits", "Number of hits", &[("page", "a")]);
+        let b = registry.counter("hits", "ignored", &[("page", "b")]);
+        a.inc();
+        a.inc_by(2);
+        b.inc();
+        assert_eq!(3, registry.counter("hits", "", &[("page", "a")]).get());
+
+        let gauge = registry.gauge("depth", "Queue depth", &[]);
+        gauge.set(5);
+        gauge.dec();
+
+        let snapshot = registry.snapshot();
+        assert_eq!(2, snapshot.len());
+        assert_eq!("depth", snapshot[0].name);
+        assert_eq!(MetricValue::Gauge(4), snapshot[0].samples[0].value);
+        let hits = &snapshot[1];
+        assert_eq!("Number of hits", hits.help);
+        assert_eq!(MetricKind::Counter, hits.kind);
+        let values: Vec<MetricValue> = hits.samples.iter().map(|s| s.value.clone()).collect();
+        assert_eq!(vec![MetricValue::Counter(3), MetricValue::Counter(1)], values);
+
+        assert!(registry.remove("hits", &[("page", "a")]));
+        assert!(!registry.remove("hits", &[("page", "a")]));
+        assert!(registry.remove("hits", &[("page", "b")]));
+        assert_eq!(1, registry.snapshot().len());
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let registry = MetricsRegistry::new();
+        let histogram = registry.histogram("sizes", "Sizes", &[1.0, 10.0], &[]);
+        histogram.observe(0.5);
+        histogram.observe(1.0);
+        histogram.observe(5.0);
+        histogram.observe(100.0);
+        let snapshot = histogram.snapshot();
+        assert_eq!(
+            vec![(1.0, 2), (10.0, 3), (f64::INFINITY, 4)],
+            snapshot.buckets
+        );
+        assert_eq!(4, snapshot.count);
+        assert!((snapshot.sum - 106.5).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn test_kind_mismatch_panics() {
+        let registry = MetricsRegistry::new();
+        registry.counter("thing", "", &[]);
+        registry.gauge("thing", "", &[]);
+    }
+}
@@ -0,0 +1,364 @@
+//! Exporting metrics in the [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/)
+//!
+//! The [PrometheusExporter](PrometheusExporter) writes snapshots to any [Write](std::io::Write),
+//! while the [PrometheusEndpoint](PrometheusEndpoint) serves a registry over HTTP
+//! for a Prometheus server to scrape.
+
+use super::*;
+use std::{
+    io::{Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::atomic::AtomicBool,
+    thread,
+};
+
+/// The path the [PrometheusEndpoint](PrometheusEndpoint) serves metrics at
+pub const METRICS_PATH: &str = "/metrics";
+/// The content type of the Prometheus text format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// Requests with longer headers are rejected, we only care about the request line anyway
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A [MetricsExporter](MetricsExporter) writing metrics in the Prometheus text format
+///
+/// # Example
+///
+/// ```
+/// use kompact::metrics::{prometheus::PrometheusExporter, MetricsRegistry};
+///
+/// let registry = MetricsRegistry::new();
+/// registry.gauge("temperature", "Current temperature", &[("room", "kitchen")]).set(21);
+///
+/// let mut exporter = PrometheusExporter::new(Vec::new());
+/// registry.export(&mut exporter).expect("export");
+/// let text = String::from_utf8(exporter.into_inner()).expect("utf8");
+/// assert!(text.contains("temperature{room=\"kitchen\"} 21\n"));
+/// ```
+#[derive(Debug)]
+pub struct PrometheusExporter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PrometheusExporter<W> {
+    /// Create an exporter writing to `writer`
+    pub fn new(writer: W) -> PrometheusExporter<W> {
+        PrometheusExporter { writer }
+    }
+
+    /// Returns the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> MetricsExporter for PrometheusExporter<W> {
+    fn export(&mut self, metrics: &[MetricFamily]) -> io::Result<()> {
+        self.writer.write_all(render(metrics).as_bytes())?;
+        self.writer.flush()
+    }
+}
+
+/// Render `metrics` in the Prometheus text format
+pub fn render(metrics: &[MetricFamily]) -> String {
+    let mut out = String::new();
+    for family in metrics {
+        let kind = match family.kind {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        };
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            family.name,
+            escape_help(&family.help),
+            family.name,
+            kind
+        ));
+        for sample in &family.samples {
+            match sample.value {
+                MetricValue::Counter(v) => {
+                    write_sample(&mut out, &family.name, &sample.labels, None, &v.to_string())
+                }
+                MetricValue::Gauge(v) => {
+                    write_sample(&mut out, &family.name, &sample.labels, None, &v.to_string())
+                }
+                MetricValue::Histogram(ref histogram) => {
+                    let bucket_name = format!("{}_bucket", family.name);
+                    for (bound, count) in &histogram.buckets {
+                        let le = format_float(*bound);
+                        let le_label = Some(("le", le.as_str()));
+                        let count = count.to_string();
+                        write_sample(&mut out, &bucket_name, &sample.labels, le_label, &count);
+                    }
+                    let sum_name = format!("{}_sum", family.name);
+                    let sum = format_float(histogram.sum);
+                    write_sample(&mut out, &sum_name, &sample.labels, None, &sum);
+                    let count_name = format!("{}_count", family.name);
+                    let count = histogram.count.to_string();
+                    write_sample(&mut out, &count_name, &sample.labels, None, &count);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    extra: Option<(&str, &str)>,
+    value: &str,
+) -> () {
+    out.push_str(name);
+    let extra = extra.iter().map(|(k, v)| (*k, *v));
+    let mut all = labels
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .chain(extra)
+        .peekable();
+    if all.peek().is_some() {
+        let rendered: Vec<String> = all
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v)))
+            .collect();
+        out.push('{');
+        out.push_str(&rendered.join(","));
+        out.push('}');
+    }
+    out.push(' ');
+    out.push_str(value);
+    out.push('\n');
+}
+
+fn format_float(v: f64) -> String {
+    if v == f64::INFINITY {
+        "+Inf".to_string()
+    } else if v == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// A minimal HTTP server exposing a [MetricsRegistry](MetricsRegistry) for Prometheus to scrape
+///
+/// Every `GET` request for [METRICS_PATH](METRICS_PATH) is answered with a fresh
+/// [snapshot](MetricsRegistry::snapshot) of the registry, rendered in the text format.
+/// Requests are handled one at a time on a dedicated thread, which is stopped
+/// when the endpoint is [shut down](PrometheusEndpoint::shutdown) or dropped.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{
+///     metrics::{prometheus::PrometheusEndpoint, MetricsRegistry},
+///     prelude::*,
+/// };
+///
+/// let registry = MetricsRegistry::new();
+/// let mut conf = KompactConfig::default();
+/// conf.metrics(registry.clone());
+/// let system = conf.build().expect("system");
+///
+/// let endpoint = PrometheusEndpoint::bind("127.0.0.1:0", registry).expect("endpoint");
+/// println!("Scrape me at http://{}/metrics", endpoint.local_addr());
+/// # endpoint.shutdown().expect("endpoint shutdown");
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Debug)]
+pub struct PrometheusEndpoint {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    handle: Option<thread::JoinHandle<()>>,
+}
+
+impl PrometheusEndpoint {
+    /// Start serving `registry` at `addr`
+    ///
+    /// Use port `0` to let the operating system pick a free port,
+    /// and [local_addr](PrometheusEndpoint::local_addr) to find out which one it picked.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        registry: MetricsRegistry,
+    ) -> io::Result<PrometheusEndpoint> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = stopped.clone();
+        let handle = thread::Builder::new()
+            .name(format!("prometheus-endpoint-{}", addr))
+            .spawn(move || serve(listener, registry, thread_stopped))?;
+        Ok(PrometheusEndpoint {
+            addr,
+            stopped,
+            handle: Some(handle),
+        })
+    }
+
+    /// The address the endpoint is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop serving and wait for the server thread to exit
+    pub fn shutdown(mut self) -> Result<(), String> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> Result<(), String> {
+        if let Some(handle) = self.handle.take() {
+            self.stopped.store(true, Ordering::SeqCst);
+            // wake up the blocking accept
+            let mut wake_addr = self.addr;
+            if wake_addr.ip().is_unspecified() {
+                let loopback = match wake_addr {
+                    SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+                };
+                wake_addr.set_ip(loopback);
+            }
+            let _ = TcpStream::connect(wake_addr);
+            handle
+                .join()
+                .map_err(|_| "Prometheus endpoint thread panicked".to_string())?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PrometheusEndpoint {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn serve(listener: TcpListener, registry: MetricsRegistry, stopped: Arc<AtomicBool>) -> () {
+    for stream in listener.incoming() {
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(stream) = stream {
+            // a misbehaving client must not take down the endpoint
+            let _ = handle_request(stream, &registry);
+        }
+    }
+}
+
+fn handle_request(mut stream: TcpStream, registry: &MetricsRegistry) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return respond(&mut stream, "431 Request Header Fields Too Large", "");
+        }
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let target = request_line.next().unwrap_or("");
+    let path = target.split('?').next().unwrap_or("");
+    if method != "GET" {
+        respond(&mut stream, "405 Method Not Allowed", "")
+    } else if path != METRICS_PATH {
+        respond(&mut stream, "404 Not Found", "")
+    } else {
+        let body = render(&registry.snapshot());
+        respond(&mut stream, "200 OK", &body)
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        CONTENT_TYPE,
+        body.len()
+    );
+    stream.write_all(header.as_bytes())?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Both)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).expect("connect");
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).expect("write");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).expect("read");
+        response
+    }
+
+    #[test]
+    fn test_render_text_format() {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter("requests_total", "Requests\nserved", &[("path", "/a\"b")])
+            .inc_by(3);
+        registry.gauge("up", "Is it up", &[]).set(1);
+        let latency = registry.histogram("latency_seconds", "Latency", &[0.1, 1.0], &[("op", "x")]);
+        latency.observe(0.05);
+        latency.observe(2.0);
+
+        let expected = "\
+# HELP latency_seconds Latency
+# TYPE latency_seconds histogram
+latency_seconds_bucket{op=\"x\",le=\"0.1\"} 1
+latency_seconds_bucket{op=\"x\",le=\"1\"} 1
+latency_seconds_bucket{op=\"x\",le=\"+Inf\"} 2
+latency_seconds_sum{op=\"x\"} 2.05
+latency_seconds_count{op=\"x\"} 2
+# HELP requests_total Requests\\nserved
+# TYPE requests_total counter
+requests_total{path=\"/a\\\"b\"} 3
+# HELP up Is it up
+# TYPE up gauge
+up 1
+";
+        assert_eq!(expected, render(&registry.snapshot()));
+    }
+
+    #[test]
+    fn test_endpoint_serves_metrics() {
+        let registry = MetricsRegistry::new();
+        let hits = registry.counter("hits_total", "Hits", &[]);
+        let endpoint = PrometheusEndpoint::bind("127.0.0.1:0", registry).expect("bind");
+        let addr = endpoint.local_addr();
+
+        hits.inc();
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.ends_with("hits_total 1\n"), "{}", response);
+
+        hits.inc();
+        assert!(get(addr, "/metrics?x=y").ends_with("hits_total 2\n"));
+        assert!(get(addr, "/other").starts_with("HTTP/1.1 404"));
+
+        endpoint.shutdown().expect("shutdown");
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
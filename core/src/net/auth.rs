@@ -0,0 +1,127 @@
+//! Mutual authentication of peers during the channel handshake
+//!
+//! When a shared secret is configured with
+//! [set_auth_secret](crate::prelude::NetworkConfig::set_auth_secret),
+//! both ends of a channel must prove that they know the secret before the channel is used:
+//!
+//! 1. The accepting side sends a random challenge with its `Hello`.
+//! 2. The requesting side answers in its `Start` with an HMAC over that challenge,
+//!    and sends a challenge of its own.
+//! 3. The accepting side verifies the proof and answers in its `Ack`
+//!    with an HMAC over the requester's challenge, which the requester verifies.
+//!
+//! The secret itself is never sent over the network.
+use ring::{
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{fmt, sync::Arc};
+use uuid::Uuid;
+
+/// Number of bytes in an authentication proof
+pub const PROOF_LEN: usize = 32;
+/// An authentication proof as sent in the handshake frames
+pub type Proof = [u8; PROOF_LEN];
+
+const START_CONTEXT: &[u8] = b"kompact-start";
+const ACK_CONTEXT: &[u8] = b"kompact-ack";
+
+/// A secret shared by all systems that should be able to talk to each other
+#[derive(Clone)]
+pub(crate) struct AuthSecret {
+    key: Arc<hmac::Key>,
+}
+
+impl AuthSecret {
+    pub(crate) fn new(secret: &[u8]) -> Self {
+        AuthSecret {
+            key: Arc::new(hmac::Key::new(hmac::HMAC_SHA256, secret)),
+        }
+    }
+
+    /// Creates a fresh random challenge
+    pub(crate) fn challenge(&self) -> u128 {
+        let mut bytes = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut bytes)
+            .expect("Could not generate a random challenge");
+        u128::from_be_bytes(bytes)
+    }
+
+    /// The proof the requesting side sends in its `Start`
+    pub(crate) fn start_proof(&self, challenge: u128, id: Uuid) -> Proof {
+        self.sign(START_CONTEXT, challenge, id)
+    }
+
+    /// The proof the accepting side sends in its `Ack`
+    pub(crate) fn ack_proof(&self, challenge: u128, id: Uuid) -> Proof {
+        self.sign(ACK_CONTEXT, challenge, id)
+    }
+
+    /// Returns `true` if `proof` is a valid `Start` proof for `challenge` and `id`
+    pub(crate) fn verify_start(&self, challenge: u128, id: Uuid, proof: &Proof) -> bool {
+        self.verify(START_CONTEXT, challenge, id, proof)
+    }
+
+    /// Returns `true` if `proof` is a valid `Ack` proof for `challenge` and `id`
+    pub(crate) fn verify_ack(&self, challenge: u128, id: Uuid, proof: &Proof) -> bool {
+        self.verify(ACK_CONTEXT, challenge, id, proof)
+    }
+
+    fn message(context: &[u8], challenge: u128, id: Uuid) -> Vec<u8> {
+        let mut msg = Vec::with_capacity(context.len() + 32);
+        msg.extend_from_slice(context);
+        msg.extend_from_slice(&challenge.to_be_bytes());
+        msg.extend_from_slice(id.as_bytes());
+        msg
+    }
+
+    fn sign(&self, context: &[u8], challenge: u128, id: Uuid) -> Proof {
+        let tag = hmac::sign(&self.key, &Self::message(context, challenge, id));
+        let mut proof = [0u8; PROOF_LEN];
+        proof.copy_from_slice(tag.as_ref());
+        proof
+    }
+
+    fn verify(&self, context: &[u8], challenge: u128, id: Uuid, proof: &Proof) -> bool {
+        hmac::verify(&self.key, &Self::message(context, challenge, id), proof).is_ok()
+    }
+}
+
+impl fmt::Debug for AuthSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuthSecret(<hidden>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proofs_verify_with_same_secret() {
+        let secret = AuthSecret::new(b"top secret");
+        let challenge = secret.challenge();
+        let id = Uuid::new_v4();
+        let proof = secret.start_proof(challenge, id);
+        assert!(secret.verify_start(challenge, id, &proof));
+        // a start proof must not be usable as an ack proof
+        assert!(!secret.verify_ack(challenge, id, &proof));
+        let ack = secret.ack_proof(challenge, id);
+        assert!(secret.verify_ack(challenge, id, &ack));
+    }
+
+    #[test]
+    fn proofs_fail_with_other_secret_or_challenge() {
+        let secret = AuthSecret::new(b"top secret");
+        let other = AuthSecret::new(b"not the secret");
+        let challenge = secret.challenge();
+        let id = Uuid::new_v4();
+        let proof = other.start_proof(challenge, id);
+        assert!(!secret.verify_start(challenge, id, &proof));
+        let proof = secret.start_proof(challenge.wrapping_add(1), id);
+        assert!(!secret.verify_start(challenge, id, &proof));
+        let proof = secret.start_proof(challenge, Uuid::new_v4());
+        assert!(!secret.verify_start(challenge, id, &proof));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::net::buffers::{BufferChunk, BufferConfig, ChunkAllocator, DefaultAllocator};
+use crate::{
+    metrics::instruments::BufferPoolMetrics,
+    net::buffers::{BufferChunk, BufferConfig, ChunkAllocator, DefaultAllocator},
+};
 use std::{
     collections::{vec_deque::Drain, VecDeque},
     sync::Arc,
@@ -10,6 +13,7 @@ pub(crate) struct BufferPool {
     pool_size: usize,
     chunk_allocator: Arc<dyn ChunkAllocator>,
     max_pool_size: usize,
+    metrics: Option<BufferPoolMetrics>,
 }
 
 impl BufferPool {
@@ -37,6 +41,7 @@ impl BufferPool {
             pool_size: config.initial_chunk_count,
             chunk_allocator,
             max_pool_size: config.max_chunk_count,
+            metrics: None,
         }
     }
 
@@ -44,8 +49,21 @@ impl BufferPool {
         BufferChunk::from_chunk(self.chunk_allocator.get_chunk())
     }
 
+    /// Report the size of this pool to `metrics` from now on
+    pub(crate) fn set_metrics(&mut self, metrics: BufferPoolMetrics) -> () {
+        if let Some(old) = self.metrics.take() {
+            old.detach(self.pool_size, self.max_pool_size);
+        }
+        metrics.attach(self.pool_size, self.max_pool_size);
+        self.metrics = Some(metrics);
+    }
+
     pub fn get_buffer(&mut self) -> Option<BufferChunk> {
-        self.try_reclaim()
+        let buffer = self.try_reclaim();
+        if let Some(ref metrics) = self.metrics {
+            metrics.handed_out(&buffer);
+        }
+        buffer
     }
 
     pub fn return_buffer(&mut self, mut buffer: BufferChunk) -> () {
@@ -78,6 +96,9 @@ impl BufferPool {
             return None;
         };
         self.pool_size += 1;
+        if let Some(ref metrics) = self.metrics {
+            metrics.allocated();
+        }
         Some(self.new_buffer())
     }
 
@@ -87,6 +108,18 @@ impl BufferPool {
         (self.pool_size, self.pool.len())
     }
 
+    /// Counts the number of chunks the pool could hand out right now,
+    /// i.e. the free returned chunks and the chunks that may still be allocated
+    pub(crate) fn count_free_chunks(&mut self) -> usize {
+        let mut cnt = self.max_pool_size.saturating_sub(self.pool_size);
+        for buffer in &mut self.pool {
+            if buffer.free() {
+                cnt += 1;
+            }
+        }
+        cnt
+    }
+
     /// Counts the number of locked chunks currently in the pool
     #[allow(dead_code)]
     pub(crate) fn count_locked_chunks(&mut self) -> usize {
@@ -100,6 +133,14 @@ impl BufferPool {
     }
 }
 
+impl Drop for BufferPool {
+    fn drop(&mut self) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.detach(self.pool_size, self.max_pool_size);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -162,4 +203,26 @@ mod tests {
         assert_eq!(pool.pool_size, 4);
         assert_eq!(pool.count_locked_chunks(), 0);
     }
+
+    #[test]
+    fn buffer_pool_free_chunks() {
+        let mut cfg = BufferConfig::default();
+        cfg.initial_chunk_count(2);
+        cfg.max_chunk_count(3);
+        cfg.chunk_size(128);
+
+        let mut pool = BufferPool::with_config(&cfg, &None);
+        assert_eq!(pool.count_free_chunks(), 3);
+        let buf1 = pool.get_buffer().unwrap();
+        let _buf2 = pool.get_buffer().unwrap();
+        assert_eq!(pool.count_free_chunks(), 1);
+        let lock = buf1.get_lock();
+        pool.return_buffer(buf1);
+        assert_eq!(pool.count_free_chunks(), 1);
+        drop(lock);
+        assert_eq!(pool.count_free_chunks(), 2);
+        let _buf3 = pool.get_buffer().unwrap();
+        let _buf4 = pool.get_buffer().unwrap();
+        assert_eq!(pool.count_free_chunks(), 0);
+    }
 }
@@ -165,6 +165,13 @@ impl DecodeBuffer {
                             Err(FramingError::InvalidFrame)
                         }
                     }
+                    FrameType::CreditUpdate => {
+                        if let Ok(data) = CreditUpdate::decode_from(chunk_lease) {
+                            Ok(data)
+                        } else {
+                            Err(FramingError::InvalidFrame)
+                        }
+                    }
                     FrameType::Hello => {
                         if let Ok(hello) = Hello::decode_from(chunk_lease) {
                             Ok(hello)
@@ -338,4 +345,96 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn decode_buffer_credit_frames() {
+        let mut cfg = BufferConfig::default();
+        cfg.chunk_size(128);
+        let mut pool = BufferPool::with_config(&cfg, &None);
+        let mut decode_buffer = DecodeBuffer::new(pool.get_buffer().unwrap(), &cfg);
+
+        let mut bytes = BytesMut::with_capacity(128);
+        Frame::StreamRequest(StreamRequest::new(42))
+            .encode_into(&mut bytes)
+            .unwrap();
+        Frame::CreditUpdate(CreditUpdate::new(1337))
+            .encode_into(&mut bytes)
+            .unwrap();
+        let len = bytes.len();
+        decode_buffer
+            .get_writeable()
+            .unwrap()
+            .put_slice(bytes.as_ref());
+        decode_buffer.advance_writeable(len);
+
+        match decode_buffer.get_frame() {
+            Ok(Frame::StreamRequest(request)) => assert_eq!(request.credit_capacity, 42),
+            other => panic!("Expected StreamRequest, got {:?}", other),
+        }
+        match decode_buffer.get_frame() {
+            Ok(Frame::CreditUpdate(update)) => assert_eq!(update.credit, 1337),
+            other => panic!("Expected CreditUpdate, got {:?}", other),
+        }
+        assert!(matches!(
+            decode_buffer.get_frame(),
+            Err(FramingError::NoData)
+        ));
+    }
+
+    #[test]
+    fn decode_buffer_handshake_frames_with_auth() {
+        let mut cfg = BufferConfig::default();
+        cfg.chunk_size(512);
+        let mut pool = BufferPool::with_config(&cfg, &None);
+        let mut decode_buffer = DecodeBuffer::new(pool.get_buffer().unwrap(), &cfg);
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let id = uuid::Uuid::new_v4();
+        let proof = [7u8; crate::net::auth::PROOF_LEN];
+
+        let mut bytes = BytesMut::with_capacity(512);
+        Frame::Hello(Hello::new(addr))
+            .encode_into(&mut bytes)
+            .unwrap();
+        Frame::Hello(Hello::with_challenge(addr, 42))
+            .encode_into(&mut bytes)
+            .unwrap();
+        Frame::Start(Start::with_auth(addr, id, proof, 43))
+            .encode_into(&mut bytes)
+            .unwrap();
+        Frame::Ack(Ack {
+            offset: 0,
+            proof: Some(proof),
+        })
+        .encode_into(&mut bytes)
+        .unwrap();
+        let len = bytes.len();
+        decode_buffer
+            .get_writeable()
+            .unwrap()
+            .put_slice(bytes.as_ref());
+        decode_buffer.advance_writeable(len);
+
+        match decode_buffer.get_frame() {
+            Ok(Frame::Hello(hello)) => {
+                assert_eq!(hello.addr, addr);
+                assert_eq!(hello.challenge, None);
+            }
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+        match decode_buffer.get_frame() {
+            Ok(Frame::Hello(hello)) => assert_eq!(hello.challenge, Some(42)),
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+        match decode_buffer.get_frame() {
+            Ok(Frame::Start(start)) => {
+                assert_eq!(start.id, id);
+                assert_eq!(start.auth, Some((proof, 43)));
+            }
+            other => panic!("Expected Start, got {:?}", other),
+        }
+        match decode_buffer.get_frame() {
+            Ok(Frame::Ack(ack)) => assert_eq!(ack.proof, Some(proof)),
+            other => panic!("Expected Ack, got {:?}", other),
+        }
+    }
 }
@@ -6,7 +6,10 @@ use bytes::{Buf, BufMut};
 //use bytes::IntoBuf;
 use std::{self, fmt::Debug};
 
-use crate::net::buffers::ChunkLease;
+use crate::net::{
+    auth::{Proof, PROOF_LEN},
+    buffers::ChunkLease,
+};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use uuid::Uuid;
 
@@ -150,6 +153,8 @@ pub struct Data {
 pub struct Hello {
     /// The Cannonical Address of the host saying Hello
     pub addr: SocketAddr,
+    /// Challenge the remote host must answer if peer authentication is enabled
+    pub challenge: Option<u128>,
 }
 
 /// Hello, used to initiate network channels
@@ -159,6 +164,9 @@ pub struct Start {
     pub addr: SocketAddr,
     /// "Channel ID", used as a tie-breaker in mutual connection requests
     pub id: Uuid,
+    /// Answer to the challenge in the Hello and a challenge for the remote host,
+    /// if peer authentication is enabled
+    pub auth: Option<(Proof, u128)>,
 }
 
 /// Hello, used to initiate network channels
@@ -166,6 +174,8 @@ pub struct Start {
 pub struct Ack {
     /// Ack where we're ready to start receiving from.
     pub offset: u128,
+    /// Answer to the challenge in the Start, if peer authentication is enabled
+    pub proof: Option<Proof>,
 }
 
 /// Byte-mappings for frame types
@@ -267,16 +277,34 @@ impl FrameHead {
 }
 
 impl StreamRequest {
-    #[allow(dead_code)]
-    pub(crate) fn new(credit_capacity: u32) -> Self {
+    /// Create a new request for `credit_capacity` credits
+    pub fn new(credit_capacity: u32) -> Self {
         StreamRequest { credit_capacity }
     }
 }
 
+impl CreditUpdate {
+    /// Create a new credit update giving `credit` credits
+    pub fn new(credit: u32) -> Self {
+        CreditUpdate { credit }
+    }
+}
+
 impl Hello {
     /// Create a new hello message
     pub fn new(addr: SocketAddr) -> Self {
-        Hello { addr }
+        Hello {
+            addr,
+            challenge: None,
+        }
+    }
+
+    /// Create a new hello message with an authentication challenge
+    pub fn with_challenge(addr: SocketAddr, challenge: u128) -> Self {
+        Hello {
+            addr,
+            challenge: Some(challenge),
+        }
     }
 
     /// Get the address sent in the Hello message
@@ -288,7 +316,21 @@ impl Hello {
 impl Start {
     /// Create a new hello message
     pub fn new(addr: SocketAddr, id: Uuid) -> Self {
-        Start { addr, id }
+        Start {
+            addr,
+            id,
+            auth: None,
+        }
+    }
+
+    /// Create a new start message answering an authentication challenge with `proof`
+    /// and posing a new `challenge`
+    pub fn with_auth(addr: SocketAddr, id: Uuid, proof: Proof, challenge: u128) -> Self {
+        Start {
+            addr,
+            id,
+            auth: Some((proof, challenge)),
+        }
     }
 
     /// Get the address sent in the Start message
@@ -345,10 +387,9 @@ impl FrameExt for Data {
 
 impl FrameExt for StreamRequest {
     fn decode_from(mut src: ChunkLease) -> Result<Frame, FramingError> {
-        if src.remaining() < 8 {
+        if src.remaining() < 4 {
             return Err(FramingError::InvalidFrame);
         }
-        //let stream_id: StreamId = src.get_u32_be().into();
         let credit = src.get_u32();
         let stream_req = StreamRequest {
             credit_capacity: credit,
@@ -358,35 +399,38 @@ impl FrameExt for StreamRequest {
 
     fn encode_into<B: BufMut>(&mut self, dst: &mut B) -> Result<(), FramingError> {
         assert!(dst.remaining_mut() >= self.encoded_len());
-        //dst.put_u32_be(self.stream_id.into());
         dst.put_u32(self.credit_capacity);
         Ok(())
     }
 
     fn encoded_len(&self) -> usize {
-        4 //stream_id + credit_capacity
+        4 // credit_capacity
     }
 }
 
 impl FrameExt for Hello {
     fn decode_from(mut src: ChunkLease) -> Result<Frame, FramingError> {
-        match src.get_u8() {
+        let addr = match src.get_u8() {
             4 => {
                 let ip = Ipv4Addr::from(src.get_u32());
                 let port = src.get_u16();
-                let addr = SocketAddr::new(IpAddr::V4(ip), port);
-                Ok(Frame::Hello(Hello::new(addr)))
+                SocketAddr::new(IpAddr::V4(ip), port)
             }
             6 => {
                 let ip = Ipv6Addr::from(src.get_u128());
                 let port = src.get_u16();
-                let addr = SocketAddr::new(IpAddr::V6(ip), port);
-                Ok(Frame::Hello(Hello::new(addr)))
+                SocketAddr::new(IpAddr::V6(ip), port)
             }
             _ => {
                 panic!("Faulty Hello Message!");
             }
-        }
+        };
+        let challenge = if src.remaining() >= 16 {
+            Some(src.get_u128())
+        } else {
+            None
+        };
+        Ok(Frame::Hello(Hello { addr, challenge }))
     }
 
     fn encode_into<B: BufMut>(&mut self, dst: &mut B) -> Result<(), FramingError> {
@@ -395,50 +439,61 @@ impl FrameExt for Hello {
                 dst.put_u8(4); // version
                 dst.put_slice(&v4.ip().octets()); // ip
                 dst.put_u16(v4.port()); // port
-                Ok(())
             }
             SocketAddr::V6(v6) => {
                 dst.put_u8(6); // version
                 dst.put_slice(&v6.ip().octets()); // ip
                 dst.put_u16(v6.port()); // port
-                Ok(())
             }
         }
+        if let Some(challenge) = self.challenge {
+            dst.put_u128(challenge);
+        }
+        Ok(())
     }
 
     fn encoded_len(&self) -> usize {
-        match self.addr {
+        let addr_len = match self.addr {
             SocketAddr::V4(_v4) => {
                 1 + 4 + 2 // version + ip + port
             }
             SocketAddr::V6(_v6) => {
                 1 + 16 + 2 // version + ip + port
             }
+        };
+        match self.challenge {
+            Some(_) => addr_len + 16, // challenge
+            None => addr_len,
         }
     }
 }
 
 impl FrameExt for Start {
     fn decode_from(mut src: ChunkLease) -> Result<Frame, FramingError> {
-        match src.get_u8() {
+        let addr = match src.get_u8() {
             4 => {
                 let ip = Ipv4Addr::from(src.get_u32());
                 let port = src.get_u16();
-                let addr = SocketAddr::new(IpAddr::V4(ip), port);
-                let uuid = Uuid::from_u128(src.get_u128());
-                Ok(Frame::Start(Start::new(addr, uuid)))
+                SocketAddr::new(IpAddr::V4(ip), port)
             }
             6 => {
                 let ip = Ipv6Addr::from(src.get_u128());
                 let port = src.get_u16();
-                let addr = SocketAddr::new(IpAddr::V6(ip), port);
-                let uuid = Uuid::from_u128(src.get_u128());
-                Ok(Frame::Start(Start::new(addr, uuid)))
+                SocketAddr::new(IpAddr::V6(ip), port)
             }
             _ => {
                 panic!("Faulty Hello Message!");
             }
-        }
+        };
+        let id = Uuid::from_u128(src.get_u128());
+        let auth = if src.remaining() >= PROOF_LEN + 16 {
+            let mut proof = [0u8; PROOF_LEN];
+            src.copy_to_slice(&mut proof);
+            Some((proof, src.get_u128()))
+        } else {
+            None
+        };
+        Ok(Frame::Start(Start { addr, id, auth }))
     }
 
     fn encode_into<B: BufMut>(&mut self, dst: &mut B) -> Result<(), FramingError> {
@@ -448,57 +503,82 @@ impl FrameExt for Start {
                 dst.put_slice(&v4.ip().octets()); // ip
                 dst.put_u16(v4.port()); // port
                 dst.put_u128(self.id.as_u128()); //id
-                Ok(())
             }
             SocketAddr::V6(v6) => {
                 dst.put_u8(6); // version
                 dst.put_slice(&v6.ip().octets()); // ip
                 dst.put_u16(v6.port()); // port
                 dst.put_u128(self.id.as_u128()); //id
-                Ok(())
             }
         }
+        if let Some((proof, challenge)) = self.auth {
+            dst.put_slice(&proof);
+            dst.put_u128(challenge);
+        }
+        Ok(())
     }
 
     fn encoded_len(&self) -> usize {
-        match self.addr {
+        let base_len = match self.addr {
             SocketAddr::V4(_v4) => {
                 1 + 4 + 2 + 16 // version + ip + port + uuid
             }
             SocketAddr::V6(_v6) => {
                 1 + 16 + 2 + 16 // version + ip + port + uuid
             }
+        };
+        match self.auth {
+            Some(_) => base_len + PROOF_LEN + 16, // proof + challenge
+            None => base_len,
         }
     }
 }
 
 impl FrameExt for Ack {
     fn decode_from(mut src: ChunkLease) -> Result<Frame, FramingError> {
-        Ok(Frame::Ack(Ack {
-            offset: src.get_u128(),
-        }))
+        let offset = src.get_u128();
+        let proof = if src.remaining() >= PROOF_LEN {
+            let mut proof = [0u8; PROOF_LEN];
+            src.copy_to_slice(&mut proof);
+            Some(proof)
+        } else {
+            None
+        };
+        Ok(Frame::Ack(Ack { offset, proof }))
     }
 
     fn encode_into<B: BufMut>(&mut self, dst: &mut B) -> Result<(), FramingError> {
         dst.put_u128(self.offset);
+        if let Some(proof) = self.proof {
+            dst.put_slice(&proof);
+        }
         Ok(())
     }
 
     fn encoded_len(&self) -> usize {
-        16
+        match self.proof {
+            Some(_) => 16 + PROOF_LEN, // offset + proof
+            None => 16,
+        }
     }
 }
 
 impl FrameExt for CreditUpdate {
-    fn decode_from(_src: ChunkLease) -> Result<Frame, FramingError> {
-        unimplemented!()
+    fn decode_from(mut src: ChunkLease) -> Result<Frame, FramingError> {
+        if src.remaining() < 4 {
+            return Err(FramingError::InvalidFrame);
+        }
+        let credit = src.get_u32();
+        Ok(Frame::CreditUpdate(CreditUpdate { credit }))
     }
 
-    fn encode_into<B: BufMut>(&mut self, _dst: &mut B) -> Result<(), FramingError> {
-        unimplemented!()
+    fn encode_into<B: BufMut>(&mut self, dst: &mut B) -> Result<(), FramingError> {
+        assert!(dst.remaining_mut() >= self.encoded_len());
+        dst.put_u32(self.credit);
+        Ok(())
     }
 
     fn encoded_len(&self) -> usize {
-        4 // stream_id + credit
+        4 // credit
     }
 }
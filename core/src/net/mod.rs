@@ -15,11 +15,13 @@ use bytes::{Buf, BufMut, BytesMut};
 use crossbeam_channel::{unbounded as channel, RecvError, SendError, Sender};
 use mio::{Interest, Waker};
 
+pub mod auth;
 #[allow(missing_docs)]
 pub mod buffers;
 pub mod frames;
 pub(crate) mod network_channel;
 pub(crate) mod network_thread;
+pub mod tls;
 pub(crate) mod udp_state;
 
 /// The state of a connection
@@ -33,6 +35,9 @@ pub enum ConnectionState {
     Connected(SocketAddr),
     /// Already closed
     Closed,
+    /// The remote host could not prove that it knows the shared secret,
+    /// or refused to prove it to us
+    AuthenticationFailed,
     /// Threw an error
     Error(std::io::Error),
 }
@@ -46,6 +51,8 @@ impl From<Transport> for Protocol {
         match t {
             Transport::TCP => Protocol::TCP,
             Transport::UDP => Protocol::UDP,
+            // TLS sessions are layered on top of the TCP channels by the network thread
+            Transport::TLS => Protocol::TCP,
             _ => unimplemented!("Unsupported Protocol"),
         }
     }
@@ -69,6 +76,10 @@ pub mod events {
         Data(Frame),
         /// The NetworkThread lost connection to the remote host and rejects the frame
         RejectedFrame(SocketAddr, SerialisedFrame),
+        /// The remote host ran out of credits for the channel, so frames are held back
+        Throttled(SocketAddr),
+        /// The remote host gave enough credits to send all held back frames
+        Released(SocketAddr),
     }
 
     /// BridgeEvents emitted to the network `Bridge`
@@ -295,7 +306,7 @@ impl Bridge {
         Ok(())
     }
 
-    /// Attempts to establish a TCP connection to the provided `addr`.
+    /// Attempts to establish a TCP (or TLS) connection to the provided `addr`.
     ///
     /// # Side effects
     /// When the connection is successul:
@@ -306,7 +317,7 @@ impl Bridge {
     /// If the provided protocol is not supported
     pub fn connect(&self, proto: Transport, addr: SocketAddr) -> Result<(), NetworkBridgeErr> {
         match proto {
-            Transport::TCP => {
+            Transport::TCP | Transport::TLS => {
                 self.network_input_queue
                     .send(events::DispatchEvent::Connect(addr))?;
                 self.waker.wake()?;
@@ -383,6 +394,10 @@ pub(crate) fn broken_pipe(err: &io::Error) -> bool {
     err.kind() == io::ErrorKind::BrokenPipe
 }
 
+pub(crate) fn connection_aborted(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::ConnectionAborted
+}
+
 /// A module with helper functions for testing network configurations/implementations
 pub mod net_test_helpers {
     use crate::prelude::*;
@@ -395,18 +410,23 @@ pub mod net_test_helpers {
     /// The number of Ping-Pong messages, used in assertions and Pingers/BigPingers
     pub const PING_COUNT: u64 = 10;
 
+    /// A ping carrying a sequence number, answered by [PongerAct](PongerAct)
     #[derive(Debug, Clone)]
-    struct PingMsg {
-        i: u64,
+    pub struct PingMsg {
+        /// The sequence number of this ping
+        pub i: u64,
     }
 
+    /// The reply to a [PingMsg](PingMsg), echoing its sequence number
     #[derive(Debug, Clone)]
-    struct PongMsg {
-        i: u64,
+    pub struct PongMsg {
+        /// The sequence number of the ping this answers
+        pub i: u64,
     }
 
+    /// Serialiser for [PingMsg](PingMsg) and [PongMsg](PongMsg)
     #[derive(Debug, Clone)]
-    struct PingPongSer;
+    pub struct PingPongSer;
 
     impl PingPongSer {
         const SID: SerId = 42;
@@ -706,6 +726,119 @@ pub mod net_test_helpers {
         }
     }
 
+    /// An actor which watches `target` and records every [Terminated](Terminated) it receives
+    #[derive(ComponentDefinition)]
+    pub struct WatcherAct {
+        ctx: ComponentContext<Self>,
+        target: ActorPath,
+        /// The paths this actor has been notified about
+        pub terminated: Vec<ActorPath>,
+    }
+    impl WatcherAct {
+        /// Creates a new `WatcherAct` that starts watching `target` when started
+        pub fn new(target: ActorPath) -> Self {
+            WatcherAct {
+                ctx: ComponentContext::uninitialised(),
+                target,
+                terminated: Vec::new(),
+            }
+        }
+    }
+    impl ComponentLifecycle for WatcherAct {
+        fn on_start(&mut self) -> Handled {
+            self.ctx.watch(self.target.clone());
+            Handled::Ok
+        }
+    }
+    impl Actor for WatcherAct {
+        type Message = Never;
+
+        fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+            unimplemented!();
+        }
+
+        fn receive_network(&mut self, msg: NetMessage) -> Handled {
+            match_deser! {msg; {
+                terminated: Terminated [Terminated] => {
+                    debug!(self.ctx.log(), "Got notified that {} terminated", terminated.0);
+                    self.terminated.push(terminated.0);
+                },
+                !Err(e) => error!(self.ctx.log(), "Error deserialising Terminated: {:?}", e),
+            }}
+            Handled::Ok
+        }
+    }
+
+    /// A component which asks a failure detector to monitor `target`
+    /// and records every [Liveness](Liveness) indication it receives
+    #[derive(ComponentDefinition, Actor)]
+    pub struct LivenessRecorder {
+        ctx: ComponentContext<Self>,
+        /// The port connected to the failure detector
+        pub detection: RequiredPort<FailureDetection>,
+        target: SystemPath,
+        /// The indications this component has received
+        pub events: Vec<Liveness>,
+    }
+    impl LivenessRecorder {
+        /// Creates a new `LivenessRecorder` that starts monitoring `target` when started
+        pub fn new(target: SystemPath) -> Self {
+            LivenessRecorder {
+                ctx: ComponentContext::uninitialised(),
+                detection: RequiredPort::uninitialised(),
+                target,
+                events: Vec::new(),
+            }
+        }
+    }
+    impl ComponentLifecycle for LivenessRecorder {
+        fn on_start(&mut self) -> Handled {
+            self.detection
+                .trigger(Monitoring::Monitor(self.target.clone()));
+            Handled::Ok
+        }
+    }
+    impl Require<FailureDetection> for LivenessRecorder {
+        fn handle(&mut self, event: Liveness) -> Handled {
+            debug!(self.ctx.log(), "Got liveness update {:?}", event);
+            self.events.push(event);
+            Handled::Ok
+        }
+    }
+
+    /// A component which records every [MembershipEvent](MembershipEvent) it receives
+    #[derive(ComponentDefinition, Actor)]
+    pub struct MembershipRecorder {
+        ctx: ComponentContext<Self>,
+        /// The port connected to the membership component
+        pub membership: RequiredPort<ClusterMembership>,
+        /// The events this component has received
+        pub events: Vec<MembershipEvent>,
+    }
+    impl MembershipRecorder {
+        /// Creates a new `MembershipRecorder`
+        pub fn new() -> Self {
+            MembershipRecorder {
+                ctx: ComponentContext::uninitialised(),
+                membership: RequiredPort::uninitialised(),
+                events: Vec::new(),
+            }
+        }
+    }
+    impl Default for MembershipRecorder {
+        fn default() -> Self {
+            MembershipRecorder::new()
+        }
+    }
+    ignore_lifecycle!(MembershipRecorder);
+    impl Require<ClusterMembership> for MembershipRecorder {
+        fn handle(&mut self, event: MembershipEvent) -> Handled {
+            debug!(self.ctx.log(), "Got membership event {:?}", event);
+            self.events.push(event);
+            Handled::Ok
+        }
+    }
+
     #[derive(Clone)]
     struct BigPingMsg {
         i: u64,
@@ -2,12 +2,24 @@ use super::*;
 use crate::{
     messaging::SerialisedFrame,
     net::{
+        auth::AuthSecret,
         buffers::{BufferChunk, DecodeBuffer},
-        frames::{Ack, Frame, FramingError, Hello, Start, FRAME_HEAD_LEN},
+        tls::flush_tls,
+        frames::{
+            Ack,
+            CreditUpdate,
+            Frame,
+            FramingError,
+            Hello,
+            Start,
+            StreamRequest,
+            FRAME_HEAD_LEN,
+        },
     },
 };
 use bytes::{Buf, BytesMut};
 use mio::{net::TcpStream, Token};
+use rustls::Connection;
 use network_thread::*;
 use std::{
     cmp::Ordering,
@@ -34,9 +46,137 @@ pub(crate) enum ChannelState {
     Closed(SocketAddr, Uuid),
 }
 
+/// Credit book-keeping for the credit-based flow-control of a [TcpChannel](TcpChannel)
+///
+/// Every data frame sent on a channel consumes one credit from the sender.
+/// Credits are given out by the receiver with [CreditUpdate](Frame::CreditUpdate) frames,
+/// and may be asked for by the sender with [StreamRequest](Frame::StreamRequest) frames.
+///
+/// A `window` of `0` disables flow-control on the local side, i.e. data is sent without
+/// waiting for credits, and [StreamRequest](Frame::StreamRequest) frames from the remote
+/// are answered immediately with the requested amount.
+#[derive(Debug)]
+pub(crate) struct ChannelCredits {
+    /// The maximum number of credits the remote host may hold at any time
+    window: u32,
+    /// Credits given to us by the remote host that haven't been used yet
+    send_credits: u32,
+    /// Credits given to the remote host which it hasn't used yet
+    granted_credits: u32,
+    /// `true` if we've asked the remote for credits and haven't received any since
+    requested: bool,
+    /// `true` if the remote has asked for credits and we haven't granted any since
+    remote_waiting: bool,
+}
+
+impl ChannelCredits {
+    pub(crate) fn new(window: u32) -> Self {
+        ChannelCredits {
+            window,
+            send_credits: 0,
+            granted_credits: 0,
+            requested: false,
+            remote_waiting: false,
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.window > 0
+    }
+
+    /// Consumes a credit for sending a data frame, if one is available.
+    ///
+    /// Returns `false` if the frame must be held back until more credits arrive.
+    pub(crate) fn try_consume(&mut self) -> bool {
+        if !self.enabled() {
+            true
+        } else if self.send_credits > 0 {
+            self.send_credits -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the number of credits to request from the remote,
+    /// or `None` if there is already an outstanding request.
+    pub(crate) fn request(&mut self) -> Option<u32> {
+        if self.requested {
+            None
+        } else {
+            self.requested = true;
+            Some(self.window)
+        }
+    }
+
+    /// Adds the `credit` given by the remote host
+    pub(crate) fn receive_credits(&mut self, credit: u32) -> () {
+        self.send_credits = self.send_credits.saturating_add(credit);
+        self.requested = false;
+    }
+
+    /// Must be called for every data frame received from the remote host
+    pub(crate) fn data_received(&mut self) -> () {
+        self.granted_credits = self.granted_credits.saturating_sub(1);
+    }
+
+    /// Handles a request for `credit_capacity` credits from the remote host.
+    ///
+    /// Returns the number of credits to give immediately, if flow-control is disabled locally.
+    /// Otherwise the request is answered by the next [grant](ChannelCredits::grant).
+    pub(crate) fn remote_requested(&mut self, credit_capacity: u32) -> Option<u32> {
+        if self.enabled() {
+            self.remote_waiting = true;
+            None
+        } else {
+            Some(credit_capacity)
+        }
+    }
+
+    /// Returns `true` if the remote host should be given more credits
+    ///
+    /// That is the case when less than half the window is outstanding,
+    /// or when the remote has explicitly asked for credits.
+    pub(crate) fn wants_grant(&self) -> bool {
+        self.enabled()
+            && self.granted_credits < self.window
+            && (self.remote_waiting || self.granted_credits <= self.window / 2)
+    }
+
+    /// Fills up the window of the remote host, but by no more than `free_chunks` credits,
+    /// i.e. the number of chunks left to receive into.
+    ///
+    /// Returns the number of newly granted credits, if any.
+    pub(crate) fn grant(&mut self, free_chunks: usize) -> Option<u32> {
+        if !self.wants_grant() || free_chunks == 0 {
+            return None;
+        }
+        let missing = self.window - self.granted_credits;
+        let credit = if free_chunks < missing as usize {
+            free_chunks as u32
+        } else {
+            missing
+        };
+        self.granted_credits += credit;
+        self.remote_waiting = false;
+        Some(credit)
+    }
+}
+
 pub(crate) struct TcpChannel {
     stream: TcpStream,
+    /// The TLS session wrapping the `stream`, if the channel is encrypted
+    tls: Option<Connection>,
     outbound_queue: VecDeque<SerialisedFrame>,
+    /// Data frames waiting for credits before they can be moved to the `outbound_queue`
+    held_back: VecDeque<SerialisedFrame>,
+    credits: ChannelCredits,
+    /// The shared secret the remote host must prove knowledge of, if authentication is enabled
+    auth: Option<AuthSecret>,
+    /// The challenge we sent to the remote host, awaiting its proof
+    challenge: Option<u128>,
+    /// The challenge the remote host sent us, to be answered in our Ack
+    remote_challenge: Option<u128>,
     pub token: Token,
     input_buffer: DecodeBuffer,
     pub state: ChannelState,
@@ -53,11 +193,18 @@ impl TcpChannel {
         state: ChannelState,
         own_addr: SocketAddr,
         network_config: &NetworkConfig,
+        tls: Option<Connection>,
     ) -> Self {
         let input_buffer = DecodeBuffer::new(buffer_chunk, network_config.get_buffer_config());
         TcpChannel {
             stream,
+            tls,
             outbound_queue: VecDeque::new(),
+            held_back: VecDeque::new(),
+            credits: ChannelCredits::new(network_config.get_credit_window()),
+            auth: network_config.get_auth_secret().cloned(),
+            challenge: None,
+            remote_challenge: None,
             token,
             input_buffer,
             state,
@@ -112,19 +259,68 @@ impl TcpChannel {
     pub fn initialise(&mut self, addr: &SocketAddr) -> () {
         if let ChannelState::Initialising = self.state {
             // We must send enqueue Hello and await reply
-            let hello = Frame::Hello(Hello::new(*addr));
-            self.send_frame(hello);
+            let hello = match self.auth {
+                Some(ref auth) => {
+                    let challenge = auth.challenge();
+                    self.challenge = Some(challenge);
+                    Hello::with_challenge(*addr, challenge)
+                }
+                None => Hello::new(*addr),
+            };
+            self.send_frame(Frame::Hello(hello));
         }
     }
 
     /// Must be called when a Hello frame is received on the channel.
-    pub fn handle_hello(&mut self, hello: Hello) -> () {
+    ///
+    /// Returns `false` if authentication is enabled but the remote host didn't challenge us,
+    /// in which case the channel must be dropped.
+    pub fn handle_hello(&mut self, hello: Hello) -> bool {
         if let ChannelState::Requested(_, id) = self.state {
             // Has now received Hello(addr), must send Start(addr, uuid) and await ack
-            let start = Frame::Start(Start::new(self.own_addr, id));
-            self.send_frame(start);
+            let start = match (&self.auth, hello.challenge) {
+                (Some(auth), Some(remote_challenge)) => {
+                    let challenge = auth.challenge();
+                    self.challenge = Some(challenge);
+                    let proof = auth.start_proof(remote_challenge, id);
+                    Start::with_auth(self.own_addr, id, proof, challenge)
+                }
+                (Some(_), None) => return false,
+                (None, _) => Start::new(self.own_addr, id),
+            };
+            self.send_frame(Frame::Start(start));
             self.state = ChannelState::Initialised(hello.addr, id);
         }
+        true
+    }
+
+    /// Must be called when a Start frame is received on the channel, before it is handled.
+    ///
+    /// Returns `false` if the remote host failed to answer our challenge.
+    pub fn authenticate_start(&mut self, start: &Start) -> bool {
+        match (&self.auth, self.challenge, start.auth) {
+            (None, _, _) => true,
+            (Some(auth), Some(challenge), Some((ref proof, remote_challenge)))
+                if auth.verify_start(challenge, start.id, proof) =>
+            {
+                self.remote_challenge = Some(remote_challenge);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Must be called when an Ack frame is received on the channel, before it is handled.
+    ///
+    /// Returns `false` if the remote host failed to answer our challenge.
+    pub fn authenticate_ack(&self, ack: &Ack) -> bool {
+        match (&self.auth, self.challenge, &self.state, ack.proof) {
+            (None, _, _, _) => true,
+            (Some(auth), Some(challenge), ChannelState::Initialised(_, id), Some(ref proof)) => {
+                auth.verify_ack(challenge, *id, proof)
+            }
+            _ => false,
+        }
     }
 
     /// Must be called when we Ack the channel. This means that the sender can start using the channel
@@ -132,7 +328,11 @@ impl TcpChannel {
     pub fn handle_start(&mut self, addr: &SocketAddr, id: Uuid) -> () {
         if let ChannelState::Initialising = self.state {
             // Method called because we received Start and want to send Ack.
-            let ack = Frame::Ack(Ack { offset: 0 }); // we don't use offsets yet.
+            let proof = match (&self.auth, self.remote_challenge) {
+                (Some(auth), Some(remote_challenge)) => Some(auth.ack_proof(remote_challenge, id)),
+                _ => None,
+            };
+            let ack = Frame::Ack(Ack { offset: 0, proof }); // we don't use offsets yet.
             self.stream
                 .set_nodelay(self.nodelay)
                 .expect("set nodelay failed");
@@ -157,6 +357,58 @@ impl TcpChannel {
         }
     }
 
+    /// Must be called when a StreamRequest frame is received on the channel.
+    pub fn handle_stream_request(&mut self, request: StreamRequest) -> () {
+        if let Some(credit) = self.credits.remote_requested(request.credit_capacity) {
+            self.send_frame(Frame::CreditUpdate(CreditUpdate::new(credit)));
+        }
+    }
+
+    /// Must be called when a CreditUpdate frame is received on the channel.
+    ///
+    /// Moves as many held back frames to the outbound queue as the new credits allow.
+    /// Returns `true` if this released the last held back frame.
+    pub fn handle_credit_update(&mut self, update: CreditUpdate) -> bool {
+        let was_holding_back = !self.held_back.is_empty();
+        self.credits.receive_credits(update.credit);
+        while !self.held_back.is_empty() && self.credits.try_consume() {
+            if let Some(frame) = self.held_back.pop_front() {
+                self.outbound_queue.push_back(frame);
+            }
+        }
+        if !self.held_back.is_empty() {
+            self.request_credits();
+        }
+        let _ = self.try_drain();
+        was_holding_back && self.held_back.is_empty()
+    }
+
+    /// Returns `true` if the remote host should be given more credits.
+    pub fn wants_credit_grant(&self) -> bool {
+        self.connected() && self.credits.wants_grant()
+    }
+
+    /// Gives the remote host new credits, if there are `free_chunks` left to receive into.
+    ///
+    /// Returns `false` if credits should have been given, but couldn't be.
+    pub fn grant_credits(&mut self, free_chunks: usize) -> bool {
+        if !self.wants_credit_grant() {
+            return true;
+        }
+        if let Some(credit) = self.credits.grant(free_chunks) {
+            self.send_frame(Frame::CreditUpdate(CreditUpdate::new(credit)));
+            true
+        } else {
+            false
+        }
+    }
+
+    fn request_credits(&mut self) -> () {
+        if let Some(credit_capacity) = self.credits.request() {
+            self.send_frame(Frame::StreamRequest(StreamRequest::new(credit_capacity)));
+        }
+    }
+
     pub fn swap_buffer(&mut self, new_buffer: &mut BufferChunk) -> () {
         self.input_buffer.swap_buffer(new_buffer);
     }
@@ -166,11 +418,17 @@ impl TcpChannel {
         while let Some(frame) = self.outbound_queue.pop_front() {
             ret.push(frame);
         }
+        while let Some(frame) = self.held_back.pop_front() {
+            ret.push(frame);
+        }
         ret
     }
 
     /// This tries to read from the Tcp buffer into the DecodeBuffer, nothing else.
     pub fn receive(&mut self) -> io::Result<usize> {
+        if self.tls.is_some() {
+            return self.receive_tls();
+        }
         let mut read_bytes = 0;
         let mut sum_read_bytes = 0;
         let mut interrupts = 0;
@@ -210,6 +468,55 @@ impl TcpChannel {
         }
     }
 
+    /// Reads TLS records from the Tcp buffer and decrypts them into the DecodeBuffer.
+    ///
+    /// Also drives the TLS handshake, answering the remote if necessary.
+    fn receive_tls(&mut self) -> io::Result<usize> {
+        let tls = self.tls.as_mut().expect("receive_tls on unencrypted channel");
+        let mut interrupts = 0;
+        loop {
+            match tls.read_tls(&mut self.stream) {
+                Ok(0) => break,
+                Ok(_) => {
+                    if let Err(e) = tls.process_new_packets() {
+                        // Try to tell the remote why we are giving up on it
+                        let _ = flush_tls(tls, &mut self.stream);
+                        return Err(Error::new(ErrorKind::ConnectionAborted, e));
+                    }
+                }
+                Err(err) if would_block(&err) => break,
+                Err(err) if interrupted(&err) => {
+                    interrupts += 1;
+                    if interrupts >= network_thread::MAX_INTERRUPTS {
+                        return Err(err);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        flush_tls(tls, &mut self.stream)?;
+        let mut sum_read_bytes = 0;
+        loop {
+            if let Some(buf) = self.input_buffer.get_writeable() {
+                match tls.reader().read(buf) {
+                    Ok(0) => return Ok(sum_read_bytes),
+                    Ok(n) => {
+                        self.input_buffer.advance_writeable(n);
+                        sum_read_bytes += n;
+                    }
+                    Err(err) if would_block(&err) => return Ok(sum_read_bytes),
+                    // The remote closed the stream without saying goodbye, treat it like a Tcp EOF
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                        return Ok(sum_read_bytes)
+                    }
+                    Err(err) => return Err(err),
+                }
+            } else {
+                return Err(Error::new(ErrorKind::InvalidData, "No space in Buffer"));
+            }
+        }
+    }
+
     pub fn graceful_shutdown(&mut self) -> () {
         let mut bye = Frame::Bye();
         let mut bye_bytes = BytesMut::with_capacity(128);
@@ -230,6 +537,10 @@ impl TcpChannel {
     /// Returns `true` if this channel was not in [ChannelState::Connected](ChannelState::Connected)
     /// `true` means that it can safely be dropped.
     pub fn shutdown(&mut self) -> bool {
+        if let Some(tls) = self.tls.as_mut() {
+            tls.send_close_notify();
+            let _ = flush_tls(tls, &mut self.stream);
+        }
         let _ = self.stream.shutdown(Both); // Discard errors while closing channels for now...
         match self.state {
             ChannelState::Connected(addr, id) => {
@@ -245,6 +556,9 @@ impl TcpChannel {
         match self.input_buffer.get_frame() {
             Ok(frame) => {
                 self.messages += 1;
+                if let Frame::Data(_) = frame {
+                    self.credits.data_received();
+                }
                 Ok(frame)
             }
             Err(e) => Err(e),
@@ -253,8 +567,18 @@ impl TcpChannel {
 
     /// Enqueues the frame for sending on the channel.
     /// Enquing to a non-connected channel is disallowed.
-    pub fn enqueue_serialised(&mut self, serialized: SerialisedFrame) -> () {
-        self.outbound_queue.push_back(serialized);
+    ///
+    /// If the channel is out of credits the frame is held back until the remote gives more.
+    /// Returns `true` if this is the first frame to be held back.
+    pub fn enqueue_serialised(&mut self, serialized: SerialisedFrame) -> bool {
+        if self.held_back.is_empty() && self.credits.try_consume() {
+            self.outbound_queue.push_back(serialized);
+            false
+        } else {
+            self.held_back.push_back(serialized);
+            self.request_credits();
+            self.held_back.len() == 1
+        }
     }
 
     /// Tries to drain the outbound buffer into
@@ -310,15 +634,33 @@ impl TcpChannel {
                 }
             }
         }
+        if let Some(tls) = self.tls.as_mut() {
+            // Make sure handshake messages go out, even when there is nothing to send
+            flush_tls(tls, &mut self.stream)?;
+        }
         Ok(sent_bytes)
     }
 
     /// No direct writing allowed, Must use other interface.
     fn write_serialized(&mut self, serialized: &SerialisedFrame) -> io::Result<usize> {
-        match serialized {
-            SerialisedFrame::ChunkLease(chunk) => self.stream.write(chunk.bytes()),
-            SerialisedFrame::Bytes(bytes) => self.stream.write(bytes.bytes()),
-            SerialisedFrame::ChunkRef(chunkref) => self.stream.write(chunkref.bytes()),
+        let bytes = match serialized {
+            SerialisedFrame::ChunkLease(chunk) => chunk.bytes(),
+            SerialisedFrame::Bytes(bytes) => bytes.bytes(),
+            SerialisedFrame::ChunkRef(chunkref) => chunkref.bytes(),
+        };
+        if let Some(tls) = self.tls.as_mut() {
+            // Make room in the TLS session before we give it more plaintext
+            flush_tls(tls, &mut self.stream)?;
+            let n = tls.writer().write(bytes)?;
+            flush_tls(tls, &mut self.stream)?;
+            if n == 0 {
+                // The session buffers are full, wait for the next writable event
+                Err(Error::from(ErrorKind::WouldBlock))
+            } else {
+                Ok(n)
+            }
+        } else {
+            self.stream.write(bytes)
         }
     }
 
@@ -332,9 +674,12 @@ impl std::fmt::Debug for TcpChannel {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("TcpChannel")
             .field("State", &self.state)
+            .field("Encrypted", &self.tls.is_some())
             .field("Messages", &self.messages)
             .field("Decode Buffer", &self.input_buffer)
             .field("Outbound Queue", &self.outbound_queue.len())
+            .field("Held Back", &self.held_back.len())
+            .field("Credits", &self.credits)
             .finish()
     }
 }
@@ -408,3 +753,73 @@ impl Ord for SocketWrapper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credits_hold_back_until_granted() {
+        let mut sender = ChannelCredits::new(4);
+        let mut receiver = ChannelCredits::new(4);
+        assert!(!sender.try_consume());
+        assert_eq!(sender.request(), Some(4));
+        assert_eq!(sender.request(), None, "Only one outstanding request");
+
+        let credit = receiver.grant(4).expect("initial grant");
+        assert_eq!(credit, 4);
+        sender.receive_credits(credit);
+        for _ in 0..4 {
+            assert!(sender.try_consume());
+            receiver.data_received();
+        }
+        assert!(!sender.try_consume());
+        assert_eq!(sender.request(), Some(4));
+        assert_eq!(receiver.grant(4), Some(4));
+    }
+
+    #[test]
+    fn credits_granted_at_half_window() {
+        let mut receiver = ChannelCredits::new(8);
+        assert_eq!(receiver.grant(8), Some(8));
+        assert_eq!(receiver.grant(8), None);
+        for _ in 0..3 {
+            receiver.data_received();
+        }
+        assert!(!receiver.wants_grant());
+        receiver.data_received();
+        assert!(receiver.wants_grant());
+        assert_eq!(receiver.grant(8), Some(4));
+    }
+
+    #[test]
+    fn credits_scaled_to_free_chunks() {
+        let mut receiver = ChannelCredits::new(8);
+        assert_eq!(receiver.grant(1), Some(1));
+        assert!(receiver.wants_grant());
+        assert_eq!(receiver.grant(2), Some(2));
+        assert_eq!(receiver.grant(100), Some(5));
+        assert!(!receiver.wants_grant());
+    }
+
+    #[test]
+    fn credits_withheld_without_free_chunks() {
+        let mut receiver = ChannelCredits::new(8);
+        assert_eq!(receiver.grant(0), None);
+        assert!(receiver.wants_grant());
+        assert_eq!(receiver.remote_requested(8), None);
+        assert_eq!(receiver.grant(8), Some(8));
+        assert!(!receiver.wants_grant());
+    }
+
+    #[test]
+    fn credits_disabled() {
+        let mut credits = ChannelCredits::new(0);
+        for _ in 0..100 {
+            assert!(credits.try_consume());
+        }
+        assert!(!credits.wants_grant());
+        assert_eq!(credits.grant(1), None);
+        assert_eq!(credits.remote_requested(16), Some(16));
+    }
+}
@@ -2,9 +2,11 @@ use super::*;
 use crate::{
     dispatch::NetworkConfig,
     messaging::{DispatchEnvelope, EventEnvelope},
+    metrics::instruments::{NetworkMetrics, SystemMetrics},
     net::{
         buffers::BufferPool,
         network_channel::{ChannelState, TcpChannel},
+        tls::TlsContext,
         udp_state::UdpState,
         ConnectionState,
     },
@@ -41,6 +43,8 @@ pub const MAX_INTERRUPTS: i32 = 9;
 // We do retries when we fail to bind a socket listener during boot-up:
 const MAX_BIND_RETRIES: usize = 5;
 const BIND_RETRY_INTERVAL: u64 = 1000;
+// How often we check for free buffers when credits are being withheld from a channel
+const CREDIT_GRANT_RETRY_INTERVAL: u64 = 10;
 
 /// Thread structure responsible for driving the Network IO
 pub struct NetworkThread {
@@ -65,6 +69,12 @@ pub struct NetworkThread {
     stopped: bool,
     shutdown_promise: Option<KPromise<()>>,
     network_config: NetworkConfig,
+    /// `true` if some channel is waiting for credits which couldn't be granted
+    credits_withheld: bool,
+    /// Used to set up TLS sessions for all channels, if encryption is enabled
+    tls: Option<TlsContext>,
+    /// Per channel metrics, if the system records any
+    metrics: Option<NetworkMetrics>,
 }
 
 /// Return values for IO Operations on the [NetworkChannel](net::network_channel::NetworkChannel) abstraction
@@ -75,6 +85,7 @@ pub(super) enum IOReturn {
     None,
     Start(SocketAddr, Uuid),
     Ack,
+    AuthenticationFailed,
 }
 
 impl NetworkThread {
@@ -96,6 +107,14 @@ impl NetworkThread {
             log,
             "NetworkThread starting, trying to bind listener to address {}", &addr
         );
+        let tls = network_config.get_tls_config().map(|tls_config| {
+            tls_config.load().unwrap_or_else(|e| {
+                panic!(
+                    "NetworkThread failed to load TLS configuration: {}, config {:?}",
+                    e, tls_config
+                )
+            })
+        });
         match bind_with_retries(&addr, MAX_BIND_RETRIES, &log) {
             Ok(mut tcp_listener) => {
                 let actual_addr = tcp_listener.local_addr().expect("could not get real addr");
@@ -127,6 +146,11 @@ impl NetworkThread {
                     &network_config.get_buffer_config(),
                     &network_config.get_custom_allocator(),
                 );
+                let metrics = network_config.get_metrics();
+                if let Some(metrics) = metrics {
+                    buffer_pool.set_metrics(metrics.buffer_pool("network"));
+                }
+                let metrics = metrics.map(SystemMetrics::network);
 
                 let udp_buffer = buffer_pool
                     .get_buffer()
@@ -157,6 +181,9 @@ impl NetworkThread {
                         shutdown_promise: Some(shutdown_promise),
                         dispatcher_ref,
                         network_config,
+                        credits_withheld: false,
+                        tls,
+                        metrics,
                     },
                     waker,
                 )
@@ -175,8 +202,13 @@ impl NetworkThread {
         let mut events = Events::with_capacity(MAX_POLL_EVENTS);
         debug!(self.log, "Entering main EventLoop");
         loop {
+            let timeout = if self.credits_withheld {
+                Some(Duration::from_millis(CREDIT_GRANT_RETRY_INTERVAL))
+            } else {
+                None
+            };
             self.poll
-                .poll(&mut events, None)
+                .poll(&mut events, timeout)
                 .expect("Error when calling Poll");
 
             for event in events.iter() {
@@ -197,6 +229,9 @@ impl NetworkThread {
                     return;
                 };
             }
+            if self.credits_withheld {
+                self.grant_withheld_credits();
+            }
         }
     }
 
@@ -314,12 +349,17 @@ impl NetworkThread {
                         IOReturn::Start(remote_addr, id) => {
                             self.handle_start(event.token(), remote_addr, id);
                         }
+                        IOReturn::AuthenticationFailed => {
+                            self.reject_channel(addr);
+                            return Ok(());
+                        }
                         IOReturn::Close => {
                             // Remove and deregister
                             close_channel = true;
                         }
                         _ => (),
                     }
+                    self.grant_credits(&addr);
                     if close_channel {
                         self.close_channel(addr);
                         // Tell the dispatcher that we've closed the connection
@@ -428,6 +468,9 @@ impl NetworkThread {
                     "Sending ack for {}, {}", &remote_addr, &channel.token.0
                 );
                 channel.handle_start(&remote_addr, id);
+                if !channel.grant_credits(self.buffer_pool.count_free_chunks()) {
+                    self.credits_withheld = true;
+                }
                 channel.token = token;
                 self.token_map.insert(token, remote_addr);
                 if let Err(e) = self.poll.registry().reregister(
@@ -458,6 +501,9 @@ impl NetworkThread {
         if let Some(channel) = self.channel_map.get_mut(addr) {
             debug!(self.log, "Handling ack for {}", addr);
             channel.handle_ack();
+            if !channel.grant_credits(self.buffer_pool.count_free_chunks()) {
+                self.credits_withheld = true;
+            }
             self.dispatcher_ref
                 .tell(DispatchEnvelope::Event(EventEnvelope::Network(
                     NetworkEvent::Connection(*addr, ConnectionState::Connected(*addr)),
@@ -465,6 +511,31 @@ impl NetworkThread {
         }
     }
 
+    /// Gives the channel to `addr` more credits, if it needs them and the `BufferPool` allows it
+    fn grant_credits(&mut self, addr: &SocketAddr) -> () {
+        if let Some(channel) = self.channel_map.get_mut(addr) {
+            if channel.wants_credit_grant()
+                && !channel.grant_credits(self.buffer_pool.count_free_chunks())
+            {
+                trace!(self.log, "Withholding credits from {}", addr);
+                self.credits_withheld = true;
+            }
+        }
+    }
+
+    /// Retries granting credits to all channels which were denied credits due to a full `BufferPool`
+    fn grant_withheld_credits(&mut self) -> () {
+        let mut withheld = false;
+        for channel in self.channel_map.values_mut() {
+            if channel.wants_credit_grant()
+                && !channel.grant_credits(self.buffer_pool.count_free_chunks())
+            {
+                withheld = true;
+            }
+        }
+        self.credits_withheld = withheld;
+    }
+
     fn try_write(&mut self, addr: &SocketAddr) -> IOReturn {
         if let Some(channel) = self.channel_map.get_mut(&addr) {
             match channel.try_drain() {
@@ -473,6 +544,9 @@ impl NetworkThread {
                 }
                 Ok(n) => {
                     self.sent_bytes += n as u64;
+                    if let Some(ref mut metrics) = self.metrics {
+                        metrics.channel(*addr).sent_bytes.inc_by(n as u64);
+                    }
                 }
                 Err(e) => {
                     error!(
@@ -491,6 +565,9 @@ impl NetworkThread {
             match channel.receive() {
                 Ok(n) => {
                     self.received_bytes += n as u64;
+                    if let Some(ref mut metrics) = self.metrics {
+                        metrics.channel(*addr).received_bytes.inc_by(n as u64);
+                    }
                 }
                 Err(ref err) if no_buffer_space(err) => {
                     debug!(self.log, "no_buffer_space for channel {:?}", channel);
@@ -499,6 +576,15 @@ impl NetworkThread {
                 Err(err) if interrupted(&err) || would_block(&err) => {
                     // Just retry later
                 }
+                Err(err) if connection_aborted(&err) => {
+                    warn!(
+                        self.log,
+                        "Failed to establish a secure channel to peer {}, shutting down the channel: {}",
+                        &addr,
+                        &err
+                    );
+                    ret = IOReturn::Close
+                }
                 Err(err) if connection_reset(&err) || broken_pipe(&err) => {
                     debug!(
                         self.log,
@@ -532,6 +618,29 @@ impl NetworkThread {
                         use dispatch::lookup::{ActorLookup, LookupResult};
                         use serialisation::ser_helpers::deserialise_chunk_lease;
 
+                        match channel.state {
+                            ChannelState::Connected(_, _) => (),
+                            ChannelState::Closed(_, _) => {
+                                // Was authenticated, but its messages are no longer wanted
+                                debug!(
+                                    self.log,
+                                    "Dropping Data frame from {} on a closed channel", &addr
+                                );
+                                continue;
+                            }
+                            _ => {
+                                warn!(
+                                    self.log,
+                                    "Rejecting channel from {}, it sent data before connecting",
+                                    &addr
+                                );
+                                return IOReturn::AuthenticationFailed;
+                            }
+                        }
+                        if let Some(ref mut metrics) = self.metrics {
+                            metrics.channel(*addr).received_frames.inc();
+                        }
+
                         // Forward the data frame to the correct actor
                         let lease_lookup = self.lookup.load();
                         let buf = fr.payload();
@@ -559,13 +668,47 @@ impl NetworkThread {
                     Ok(Frame::Hello(hello)) => {
                         // Channel handles hello internally. We can continue decoding.
                         debug!(self.log, "Handling Hello({}) from {}", &hello.addr, &addr);
-                        channel.handle_hello(hello);
+                        if !channel.handle_hello(hello) {
+                            warn!(
+                                self.log,
+                                "Rejecting channel to {}, it did not send an authentication challenge",
+                                &addr
+                            );
+                            return IOReturn::AuthenticationFailed;
+                        }
+                    }
+                    Ok(Frame::StreamRequest(request)) => {
+                        channel.handle_stream_request(request);
+                    }
+                    Ok(Frame::CreditUpdate(update)) => {
+                        if channel.handle_credit_update(update) {
+                            self.dispatcher_ref.tell(DispatchEnvelope::Event(
+                                EventEnvelope::Network(NetworkEvent::Released(*addr)),
+                            ));
+                        }
                     }
                     Ok(Frame::Start(start)) => {
+                        if !channel.authenticate_start(&start) {
+                            warn!(
+                                self.log,
+                                "Rejecting channel from {} claiming to be {}, it failed authentication",
+                                &addr,
+                                &start.addr
+                            );
+                            // The claimed address is unverified, so only the socket is blamed
+                            return IOReturn::AuthenticationFailed;
+                        }
                         // Channel handles hello internally. NetworkThread decides in next state transition
                         return IOReturn::Start(start.addr, start.id);
                     }
-                    Ok(Frame::Ack(_)) => {
+                    Ok(Frame::Ack(ack)) => {
+                        if !channel.authenticate_ack(&ack) {
+                            warn!(
+                                self.log,
+                                "Rejecting channel to {}, it failed authentication", &addr
+                            );
+                            return IOReturn::AuthenticationFailed;
+                        }
                         // We need to handle Acks immediately outside of the loop, then continue the loop
                         ret = IOReturn::Ack;
                         break;
@@ -587,12 +730,6 @@ impl NetworkThread {
                     Err(e) => {
                         error!(self.log, "Unhandled error {:?} from {:?}", &e, &addr);
                     }
-                    Ok(other_frame) => error!(
-                        self.log,
-                        "Received unexpected frame type {:?} from {:?}",
-                        other_frame.frame_type(),
-                        channel
-                    ),
                 }
             }
         }
@@ -673,6 +810,22 @@ impl NetworkThread {
         addr: &SocketAddr,
         state: ChannelState,
     ) -> io::Result<()> {
+        let tls = match self.tls {
+            Some(ref tls) => {
+                let session = match state {
+                    ChannelState::Requested(_, _) => tls.client_session(addr),
+                    _ => tls.server_session(),
+                };
+                match session {
+                    Ok(session) => Some(session),
+                    Err(e) => {
+                        error!(self.log, "Failed to set up TLS for {}: {}", addr, e);
+                        return Ok(());
+                    }
+                }
+            }
+            None => None,
+        };
         if let Some(buffer) = self.buffer_pool.get_buffer() {
             self.token_map.insert(self.token, *addr);
             let mut channel = TcpChannel::new(
@@ -682,6 +835,7 @@ impl NetworkThread {
                 state,
                 self.addr,
                 &self.network_config,
+                tls,
             );
             debug!(self.log, "Saying Hello to {}", addr);
             // Whatever error is thrown here will be re-triggered and handled later.
@@ -711,7 +865,14 @@ impl NetworkThread {
                     if let Some(channel) = self.channel_map.get_mut(&addr) {
                         // The stream is already set-up, buffer the package and wait for writable event
                         if channel.connected() {
-                            channel.enqueue_serialised(frame);
+                            if channel.enqueue_serialised(frame) {
+                                self.dispatcher_ref.tell(DispatchEnvelope::Event(
+                                    EventEnvelope::Network(NetworkEvent::Throttled(addr)),
+                                ));
+                            }
+                            if let Some(ref mut metrics) = self.metrics {
+                                metrics.channel(addr).sent_frames.inc();
+                            }
                         } else {
                             debug!(self.log, "Dispatch trying to route to non connected channel {:?}, rejecting the message", channel);
                             self.dispatcher_ref.tell(DispatchEnvelope::Event(
@@ -787,6 +948,29 @@ impl NetworkThread {
         }
     }
 
+    /// Drops the channel registered at `addr` immediately, as the host at the other end failed to authenticate.
+    ///
+    /// Unlike [close_channel](NetworkThread::close_channel) this doesn't wait for a ClosedAck,
+    /// as the channel was never connected and the dispatcher should not retry it.
+    /// Only channels the dispatcher requested are reported to it, since the canonical address
+    /// claimed on an incoming channel has not been verified.
+    fn reject_channel(&mut self, addr: SocketAddr) -> () {
+        if let Some(mut channel) = self.channel_map.remove(&addr) {
+            self.token_map.remove(&channel.token);
+            let _ = self.poll.registry().deregister(channel.stream_mut());
+            channel.shutdown();
+            let requested = !matches!(channel.state, ChannelState::Initialising);
+            let buffer = channel.destroy();
+            self.buffer_pool.return_buffer(buffer);
+            if requested {
+                self.dispatcher_ref
+                    .tell(DispatchEnvelope::Event(EventEnvelope::Network(
+                        NetworkEvent::Connection(addr, ConnectionState::AuthenticationFailed),
+                    )));
+            }
+        }
+    }
+
     fn handle_closed_ack(&mut self, addr: SocketAddr) -> () {
         if let Some(channel) = self.channel_map.remove(&addr) {
             match channel.state {
@@ -0,0 +1,313 @@
+//! TLS encryption for TCP network-channels
+//!
+//! When a [TlsConfig](TlsConfig) is set on the [NetworkConfig](crate::prelude::NetworkConfig),
+//! every channel the network thread opens or accepts is wrapped in a TLS session.
+//! The TLS handshake is driven by the network thread's event loop, interleaved
+//! with the channel's own Hello/Start/Ack handshake.
+use rustls::{
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig,
+    ClientConnection,
+    Connection,
+    RootCertStore,
+    ServerConfig,
+    ServerConnection,
+};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io,
+    io::BufReader,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Configuration for TLS-encrypted network-channels
+///
+/// All files are expected to be PEM-encoded.
+///
+/// If no root certificate file is given, the system's own certificate chain
+/// is used as trust root, i.e. the system will talk to all peers that use the same
+/// (possibly self-signed) certificate.
+///
+/// # Example
+///
+/// ```no_run
+/// use kompact::prelude::*;
+///
+/// let mut net_config = NetworkConfig::default();
+/// net_config.set_tls_config(TlsConfig::new("certs/node.pem", "certs/node.key"));
+/// let mut conf = KompactConfig::default();
+/// conf.system_components(DeadletterBox::new, net_config.build());
+/// let system = conf.build().expect("system");
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsConfig {
+    cert_chain_file: PathBuf,
+    private_key_file: PathBuf,
+    root_cert_file: Option<PathBuf>,
+}
+
+impl TlsConfig {
+    /// Create a new TLS config from a certificate chain file and a private key file
+    pub fn new<C, K>(cert_chain_file: C, private_key_file: K) -> Self
+    where
+        C: Into<PathBuf>,
+        K: Into<PathBuf>,
+    {
+        TlsConfig {
+            cert_chain_file: cert_chain_file.into(),
+            private_key_file: private_key_file.into(),
+            root_cert_file: None,
+        }
+    }
+
+    /// Sets the file with the root certificates used to verify remote systems
+    ///
+    /// Default is to trust the system's own certificate chain.
+    pub fn set_root_cert_file<P>(&mut self, root_cert_file: P) -> ()
+    where
+        P: Into<PathBuf>,
+    {
+        self.root_cert_file = Some(root_cert_file.into());
+    }
+
+    /// Returns the path to the certificate chain file
+    pub fn get_cert_chain_file(&self) -> &Path {
+        &self.cert_chain_file
+    }
+
+    /// Returns the path to the private key file
+    pub fn get_private_key_file(&self) -> &Path {
+        &self.private_key_file
+    }
+
+    /// Returns the path to the root certificate file, if any
+    pub fn get_root_cert_file(&self) -> Option<&Path> {
+        self.root_cert_file.as_deref()
+    }
+
+    /// Reads all the files and produces the client and server configurations
+    pub(crate) fn load(&self) -> Result<TlsContext, TlsError> {
+        let cert_chain = read_certs(&self.cert_chain_file)?;
+        let private_key = read_private_key(&self.private_key_file)?;
+        let roots = match self.root_cert_file {
+            Some(ref path) => read_certs(path)?,
+            None => cert_chain.clone(),
+        };
+        let mut root_store = RootCertStore::empty();
+        for cert in roots {
+            root_store.add(cert)?;
+        }
+        let provider = Arc::new(ring::default_provider());
+        let client = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(root_store)
+            .with_no_client_auth();
+        let server = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, private_key)?;
+        Ok(TlsContext {
+            client: Arc::new(client),
+            server: Arc::new(server),
+        })
+    }
+}
+
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        Err(TlsError::NoCertificates(path.to_path_buf()))
+    } else {
+        Ok(certs)
+    }
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| TlsError::NoPrivateKey(path.to_path_buf()))
+}
+
+/// Loaded TLS configurations for both sides of a channel
+#[derive(Clone)]
+pub(crate) struct TlsContext {
+    client: Arc<ClientConfig>,
+    server: Arc<ServerConfig>,
+}
+
+impl TlsContext {
+    /// Creates a TLS session for a channel we requested to the host at `addr`
+    pub(crate) fn client_session(&self, addr: &SocketAddr) -> Result<Connection, TlsError> {
+        let server_name = ServerName::from(addr.ip());
+        let session = ClientConnection::new(self.client.clone(), server_name)?;
+        Ok(Connection::Client(session))
+    }
+
+    /// Creates a TLS session for a channel we accepted
+    pub(crate) fn server_session(&self) -> Result<Connection, TlsError> {
+        let session = ServerConnection::new(self.server.clone())?;
+        Ok(Connection::Server(session))
+    }
+}
+
+impl fmt::Debug for TlsContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TlsContext(<client>, <server>)")
+    }
+}
+
+/// Errors that can occur while setting up TLS
+#[derive(Debug)]
+pub enum TlsError {
+    /// A file could not be read
+    Io(io::Error),
+    /// The file did not contain any PEM-encoded certificates
+    NoCertificates(PathBuf),
+    /// The file did not contain a PEM-encoded private key
+    NoPrivateKey(PathBuf),
+    /// The TLS library rejected the configuration
+    Rustls(rustls::Error),
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Io(e) => write!(fmt, "Could not read TLS file: {}", e),
+            TlsError::NoCertificates(p) => write!(fmt, "No certificates in {}", p.display()),
+            TlsError::NoPrivateKey(p) => write!(fmt, "No private key in {}", p.display()),
+            TlsError::Rustls(e) => write!(fmt, "Invalid TLS configuration: {}", e),
+        }
+    }
+}
+
+impl Error for TlsError {}
+
+impl From<io::Error> for TlsError {
+    fn from(e: io::Error) -> Self {
+        TlsError::Io(e)
+    }
+}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Rustls(e)
+    }
+}
+
+/// Writes as much buffered TLS data as the `stream` will take without blocking
+pub(crate) fn flush_tls<W: io::Write>(session: &mut Connection, stream: &mut W) -> io::Result<()> {
+    let mut interrupts = 0;
+    while session.wants_write() {
+        match session.write_tls(stream) {
+            Ok(0) => return Ok(()),
+            Ok(_) => (),
+            Err(err) if super::would_block(&err) => return Ok(()),
+            Err(err) if super::interrupted(&err) => {
+                interrupts += 1;
+                if interrupts >= super::network_thread::MAX_INTERRUPTS {
+                    return Err(err);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tls_test_helpers {
+    use super::*;
+    use std::io::Write;
+
+    /// Writes a fresh self-signed certificate for `localhost` and `127.0.0.1` into `dir`
+    pub(crate) fn self_signed_config(dir: &Path) -> TlsConfig {
+        let certified = rcgen::generate_simple_self_signed(vec![
+            "localhost".to_string(),
+            "127.0.0.1".to_string(),
+        ])
+        .expect("certificate");
+        let cert_file = dir.join("cert.pem");
+        let key_file = dir.join("key.pem");
+        File::create(&cert_file)
+            .and_then(|mut f| f.write_all(certified.cert.pem().as_bytes()))
+            .expect("write cert");
+        File::create(&key_file)
+            .and_then(|mut f| f.write_all(certified.key_pair.serialize_pem().as_bytes()))
+            .expect("write key");
+        TlsConfig::new(cert_file, key_file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tls_test_helpers::self_signed_config, *};
+    use std::io::{Read, Write};
+
+    fn transfer(from: &mut Connection, to: &mut Connection) {
+        let mut buf: Vec<u8> = Vec::new();
+        while from.wants_write() {
+            from.write_tls(&mut buf).expect("write_tls");
+        }
+        let mut slice = buf.as_slice();
+        while !slice.is_empty() {
+            to.read_tls(&mut slice).expect("read_tls");
+        }
+        to.process_new_packets().expect("process_new_packets");
+    }
+
+    #[test]
+    fn tls_handshake_with_self_signed_cert() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let config = self_signed_config(dir.path());
+        let context = config.load().expect("TLS config should load");
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut client = context.client_session(&addr).expect("client");
+        let mut server = context.server_session().expect("server");
+
+        // Like the Hello frame, data may be written before the handshake is done
+        client.writer().write_all(b"hello").unwrap();
+        while client.is_handshaking() || server.is_handshaking() {
+            transfer(&mut client, &mut server);
+            transfer(&mut server, &mut client);
+        }
+        transfer(&mut client, &mut server);
+        let mut received = [0u8; 5];
+        server.reader().read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello");
+    }
+
+    #[test]
+    fn tls_rejects_unknown_cert() {
+        let dir1 = tempfile::tempdir().expect("tempdir");
+        let dir2 = tempfile::tempdir().expect("tempdir");
+        let context1 = self_signed_config(dir1.path()).load().expect("load");
+        let context2 = self_signed_config(dir2.path()).load().expect("load");
+        let addr: SocketAddr = "127.0.0.1:1234".parse().unwrap();
+        let mut client = context1.client_session(&addr).expect("client");
+        let mut server = context2.server_session().expect("server");
+
+        transfer(&mut client, &mut server);
+        let mut buf: Vec<u8> = Vec::new();
+        while server.wants_write() {
+            server.write_tls(&mut buf).expect("write_tls");
+        }
+        let mut slice = buf.as_slice();
+        while !slice.is_empty() {
+            client.read_tls(&mut slice).expect("read_tls");
+        }
+        assert!(client.process_new_packets().is_err());
+    }
+
+    #[test]
+    fn tls_config_missing_files() {
+        let config = TlsConfig::new("/does/not/exist.pem", "/does/not/exist.key");
+        assert!(matches!(config.load(), Err(TlsError::Io(_))));
+    }
+}
@@ -0,0 +1,409 @@
+//! Journals that store the events of persistent components
+//!
+//! A [Journal](Journal) keeps a separate, gap-free sequence of events for every persistence id.
+//! Events are stored in their serialised form, so the journal never needs to know their types.
+//!
+//! Two implementations are provided:
+//!
+//! - [InMemoryJournal](InMemoryJournal) keeps events in memory. It survives component restarts, but not process restarts, which makes it a good fit for tests.
+//! - [FileJournal](FileJournal) appends events to a single local file, and survives process restarts.
+
+use super::*;
+use crate::serialisation::{SerIdBuf, SerIdBufMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A single event, as stored in a [Journal](Journal)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PersistedEvent {
+    /// The position of the event in its persistence id's sequence, starting at 1
+    pub sequence_nr: u64,
+    /// The serialisation id of the event
+    pub ser_id: SerId,
+    /// The serialised event
+    pub data: Bytes,
+}
+
+/// Storage for the events of [persistent components](super::PersistentComponent)
+///
+/// Journals are shared between all components of a system, and thus must be thread-safe.
+/// Calls happen directly on the component's thread, so they should not block for too long.
+pub trait Journal: Send + Sync {
+    /// Appends `event` to the events of `persistence_id`
+    ///
+    /// The event's sequence number must follow the highest stored one directly,
+    /// otherwise this fails with [SequenceMismatch](PersistenceError::SequenceMismatch).
+    /// This prevents two incarnations of the same component from interleaving their events.
+    fn append(&self, persistence_id: &str, event: PersistedEvent) -> Result<(), PersistenceError>;
+
+    /// Returns all events of `persistence_id` with a sequence number of at least `from_sequence_nr`, in order
+    fn replay(
+        &self,
+        persistence_id: &str,
+        from_sequence_nr: u64,
+    ) -> Result<Vec<PersistedEvent>, PersistenceError>;
+
+    /// Returns the highest sequence number stored for `persistence_id`, or 0 if there are no events
+    fn highest_sequence_nr(&self, persistence_id: &str) -> Result<u64, PersistenceError>;
+}
+
+fn check_sequence_nr(
+    persistence_id: &str,
+    highest: u64,
+    event: &PersistedEvent,
+) -> Result<(), PersistenceError> {
+    if event.sequence_nr == highest + 1 {
+        Ok(())
+    } else {
+        Err(PersistenceError::SequenceMismatch {
+            persistence_id: persistence_id.to_string(),
+            expected: highest + 1,
+            actual: event.sequence_nr,
+        })
+    }
+}
+
+/// A [Journal](Journal) that keeps all events in memory
+///
+/// Clones share the same events, so a clone can be kept around to inspect
+/// what components have persisted.
+#[derive(Clone, Default)]
+pub struct InMemoryJournal {
+    events: Arc<Mutex<HashMap<String, Vec<PersistedEvent>>>>,
+}
+
+impl InMemoryJournal {
+    /// Creates a new, empty journal
+    pub fn new() -> Self {
+        InMemoryJournal::default()
+    }
+}
+
+impl Journal for InMemoryJournal {
+    fn append(&self, persistence_id: &str, event: PersistedEvent) -> Result<(), PersistenceError> {
+        let mut events = lock(&self.events);
+        let stored = events.entry(persistence_id.to_string()).or_default();
+        check_sequence_nr(persistence_id, stored.len() as u64, &event)?;
+        stored.push(event);
+        Ok(())
+    }
+
+    fn replay(
+        &self,
+        persistence_id: &str,
+        from_sequence_nr: u64,
+    ) -> Result<Vec<PersistedEvent>, PersistenceError> {
+        let events = lock(&self.events);
+        let replayed = events
+            .get(persistence_id)
+            .map(|stored| {
+                stored
+                    .iter()
+                    .filter(|e| e.sequence_nr >= from_sequence_nr)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(replayed)
+    }
+
+    fn highest_sequence_nr(&self, persistence_id: &str) -> Result<u64, PersistenceError> {
+        let events = lock(&self.events);
+        Ok(events
+            .get(persistence_id)
+            .map(|stored| stored.len() as u64)
+            .unwrap_or(0))
+    }
+}
+
+/// A [Journal](Journal) that appends events to a local file
+///
+/// All persistence ids share the same file, and records are never modified once written.
+/// Each record is written as:
+///
+/// ```text
+/// +-------------------+----------------+-----------------+------------------+------------------+----------------+
+/// | record length: 32 | id length: 16  | persistence id  | sequence nr: 64  | ser id: SerId    | event data     |
+/// +-------------------+----------------+-----------------+------------------+------------------+----------------+
+/// ```
+///
+/// On [open](FileJournal::open) the journal scans the file to index the records of each persistence id.
+/// A record that is cut short at the end of the file, as left behind by a crash during an append,
+/// is discarded and the file truncated to the last complete record.
+///
+/// Appends are handed to the operating system immediately, but are only forced to disk
+/// if [sync_writes](FileJournal::sync_writes) is enabled.
+pub struct FileJournal {
+    path: PathBuf,
+    sync: bool,
+    state: Mutex<FileState>,
+}
+
+struct FileState {
+    file: File,
+    len: u64,
+    index: HashMap<String, Vec<u64>>,
+}
+
+const RECORD_LEN_SIZE: usize = 4;
+
+impl FileJournal {
+    /// Opens the journal at `path`, creating the file if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        let mut index: HashMap<String, Vec<u64>> = HashMap::new();
+        let mut offset = 0usize;
+        while let Some(record) = contents.get(offset..).and_then(complete_record) {
+            let mut buf = record;
+            let persistence_id = read_persistence_id(&mut buf)?;
+            index.entry(persistence_id).or_default().push(offset as u64);
+            offset += RECORD_LEN_SIZE + record.len();
+        }
+        if offset < contents.len() {
+            file.set_len(offset as u64)?;
+        }
+        Ok(FileJournal {
+            path,
+            sync: false,
+            state: Mutex::new(FileState {
+                file,
+                len: offset as u64,
+                index,
+            }),
+        })
+    }
+
+    /// Force every append to disk before returning
+    ///
+    /// This makes appends durable against operating system crashes, at the cost of much slower appends.
+    pub fn sync_writes(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// The path of the file this journal writes to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Returns the body of the record at the start of `contents`, if it is complete
+fn complete_record(mut contents: &[u8]) -> Option<&[u8]> {
+    if contents.len() < RECORD_LEN_SIZE {
+        return None;
+    }
+    let len = contents.get_u32() as usize;
+    contents.get(..len)
+}
+
+fn read_persistence_id(buf: &mut &[u8]) -> Result<String, PersistenceError> {
+    if buf.remaining() < 2 {
+        return Err(PersistenceError::Corrupted(
+            "missing persistence id".to_string(),
+        ));
+    }
+    let len = buf.get_u16() as usize;
+    if buf.remaining() < len {
+        return Err(PersistenceError::Corrupted(
+            "persistence id cut short".to_string(),
+        ));
+    }
+    let id = String::from_utf8(buf[..len].to_vec())
+        .map_err(|e| PersistenceError::Corrupted(format!("invalid persistence id: {}", e)))?;
+    buf.advance(len);
+    Ok(id)
+}
+
+fn decode_event(mut buf: &[u8]) -> Result<PersistedEvent, PersistenceError> {
+    let _persistence_id = read_persistence_id(&mut buf)?;
+    let header = 8 + mem::size_of::<SerId>();
+    if buf.remaining() < header {
+        return Err(PersistenceError::Corrupted(
+            "event header cut short".to_string(),
+        ));
+    }
+    let sequence_nr = buf.get_u64();
+    let ser_id = buf.get_ser_id();
+    Ok(PersistedEvent {
+        sequence_nr,
+        ser_id,
+        data: Bytes::copy_from_slice(buf),
+    })
+}
+
+impl Journal for FileJournal {
+    fn append(&self, persistence_id: &str, event: PersistedEvent) -> Result<(), PersistenceError> {
+        let id_len = persistence_id.len();
+        if id_len > u16::MAX as usize {
+            return Err(PersistenceError::InvalidPersistenceId(
+                persistence_id.to_string(),
+            ));
+        }
+        let mut state = lock(&self.state);
+        let highest = state.index.get(persistence_id).map_or(0, Vec::len) as u64;
+        check_sequence_nr(persistence_id, highest, &event)?;
+        let body_len = 2 + id_len + 8 + mem::size_of::<SerId>() + event.data.len();
+        let mut record = BytesMut::with_capacity(RECORD_LEN_SIZE + body_len);
+        record.put_u32(body_len as u32);
+        record.put_u16(id_len as u16);
+        record.put_slice(persistence_id.as_bytes());
+        record.put_u64(event.sequence_nr);
+        record.put_ser_id(event.ser_id);
+        record.put_slice(&event.data);
+        let offset = state.len;
+        if let Err(e) = state.file.write_all(&record) {
+            // don't leave a partial record behind for the next append to follow
+            let _ = state.file.set_len(offset);
+            return Err(e.into());
+        }
+        if self.sync {
+            state.file.sync_data()?;
+        }
+        state.len += record.len() as u64;
+        state
+            .index
+            .entry(persistence_id.to_string())
+            .or_default()
+            .push(offset);
+        Ok(())
+    }
+
+    fn replay(
+        &self,
+        persistence_id: &str,
+        from_sequence_nr: u64,
+    ) -> Result<Vec<PersistedEvent>, PersistenceError> {
+        let state = lock(&self.state);
+        let offsets = match state.index.get(persistence_id) {
+            Some(offsets) => offsets,
+            None => return Ok(Vec::new()),
+        };
+        // sequence numbers are gap-free, so the n-th record holds sequence number n
+        let skip = from_sequence_nr.saturating_sub(1) as usize;
+        let mut reader = &state.file;
+        let mut events = Vec::with_capacity(offsets.len().saturating_sub(skip));
+        for offset in offsets.iter().skip(skip) {
+            reader.seek(SeekFrom::Start(*offset))?;
+            let mut len = [0u8; RECORD_LEN_SIZE];
+            reader.read_exact(&mut len)?;
+            let mut record = vec![0u8; u32::from_be_bytes(len) as usize];
+            reader.read_exact(&mut record)?;
+            events.push(decode_event(&record)?);
+        }
+        Ok(events)
+    }
+
+    fn highest_sequence_nr(&self, persistence_id: &str) -> Result<u64, PersistenceError> {
+        let state = lock(&self.state);
+        Ok(state.index.get(persistence_id).map_or(0, Vec::len) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(sequence_nr: u64, data: &'static [u8]) -> PersistedEvent {
+        PersistedEvent {
+            sequence_nr,
+            ser_id: 42,
+            data: Bytes::from_static(data),
+        }
+    }
+
+    fn check_journal(journal: &dyn Journal) {
+        journal.append("a", event(1, b"a1")).expect("append");
+        journal.append("b", event(1, b"b1")).expect("append");
+        journal.append("a", event(2, b"a2")).expect("append");
+        match journal.append("a", event(2, b"a2 again")) {
+            Err(PersistenceError::SequenceMismatch {
+                expected, actual, ..
+            }) => {
+                assert_eq!(3, expected);
+                assert_eq!(2, actual);
+            }
+            res => panic!("Unexpected append result: {:?}", res),
+        }
+        assert_eq!(2, journal.highest_sequence_nr("a").expect("highest"));
+        assert_eq!(1, journal.highest_sequence_nr("b").expect("highest"));
+        assert_eq!(0, journal.highest_sequence_nr("c").expect("highest"));
+        assert_eq!(
+            vec![event(1, b"a1"), event(2, b"a2")],
+            journal.replay("a", 0).expect("replay")
+        );
+        assert_eq!(
+            vec![event(2, b"a2")],
+            journal.replay("a", 2).expect("replay")
+        );
+        assert!(journal.replay("a", 3).expect("replay").is_empty());
+        assert!(journal.replay("c", 1).expect("replay").is_empty());
+    }
+
+    #[test]
+    fn test_in_memory_journal() {
+        let journal = InMemoryJournal::new();
+        check_journal(&journal);
+        let shared = journal.clone();
+        assert_eq!(2, shared.highest_sequence_nr("a").expect("highest"));
+    }
+
+    #[test]
+    fn test_file_journal() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("journal");
+        {
+            let journal = FileJournal::open(&path).expect("journal");
+            check_journal(&journal);
+        }
+        let journal = FileJournal::open(&path).expect("reopened journal");
+        assert_eq!(
+            vec![event(1, b"a1"), event(2, b"a2")],
+            journal.replay("a", 1).expect("replay")
+        );
+        journal.append("b", event(2, b"b2")).expect("append");
+        assert_eq!(
+            vec![event(1, b"b1"), event(2, b"b2")],
+            journal.replay("b", 1).expect("replay")
+        );
+    }
+
+    #[test]
+    fn test_file_journal_truncates_partial_record() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let path = dir.path().join("journal");
+        {
+            let journal = FileJournal::open(&path).expect("journal");
+            journal.append("a", event(1, b"a1")).expect("append");
+            journal.append("a", event(2, b"a2")).expect("append");
+        }
+        let full_len = std::fs::metadata(&path).expect("metadata").len();
+        let file = OpenOptions::new().write(true).open(&path).expect("file");
+        file.set_len(full_len - 1).expect("truncate");
+        drop(file);
+
+        let journal = FileJournal::open(&path).expect("reopened journal");
+        assert_eq!(1, journal.highest_sequence_nr("a").expect("highest"));
+        journal.append("a", event(2, b"a2")).expect("append");
+        drop(journal);
+        assert_eq!(full_len, std::fs::metadata(&path).expect("metadata").len());
+        let journal = FileJournal::open(&path).expect("reopened journal");
+        assert_eq!(
+            vec![event(1, b"a1"), event(2, b"a2")],
+            journal.replay("a", 1).expect("replay")
+        );
+    }
+}
@@ -0,0 +1,869 @@
+//! Event-sourced components that survive restarts.
+//!
+//! A [PersistentComponent](PersistentComponent) never changes its state directly. Instead, it
+//! [persists](PersistentComponent::persist) *events* describing each change into a [Journal](journal::Journal),
+//! and only then [applies](PersistentComponent::apply_event) them to its state.
+//! When a new instance of the component starts, for example after a fault was handled via
+//! [restart_default](crate::prelude::FaultContext::restart_default), it
+//! [recovers](PersistentComponent::recover) by replaying all journaled events
+//! on top of its initial state, before it handles any new messages.
+//!
+//! Events are stored using their [Serialisable](crate::prelude::Serialisable) implementation
+//! and read back with their [Deserialiser](crate::prelude::Deserialiser).
+//!
+//! The journal is either given to each component explicitly, via [Persistence::with_journal](Persistence::with_journal),
+//! or shared by all components of a system, via [KompactConfig::journal](crate::prelude::KompactConfig::journal).
+//!
+//! Components with long event histories can additionally implement [SnapshotComponent](SnapshotComponent),
+//! to save [snapshots](snapshot) of their state from time to time.
+//! Recovery then starts from the latest snapshot and only replays the events after it.
+//!
+//! # Example
+//!
+//! ```
+//! use kompact::{
+//!     persistence::{
+//!         journal::{InMemoryJournal, Journal},
+//!         *,
+//!     },
+//!     prelude::*,
+//! };
+//! use std::sync::Arc;
+//!
+//! #[derive(Debug)]
+//! struct Added(u64);
+//! impl Serialisable for Added {
+//!     fn ser_id(&self) -> SerId {
+//!         42
+//!     }
+//!     fn size_hint(&self) -> Option<usize> {
+//!         Some(8)
+//!     }
+//!     fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+//!         buf.put_u64(self.0);
+//!         Ok(())
+//!     }
+//!     fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+//!         Ok(self)
+//!     }
+//! }
+//! impl Deserialiser<Added> for Added {
+//!     const SER_ID: SerId = 42;
+//!     fn deserialise(buf: &mut dyn Buf) -> Result<Added, SerError> {
+//!         Ok(Added(buf.get_u64()))
+//!     }
+//! }
+//!
+//! #[derive(ComponentDefinition, Actor)]
+//! struct Counter {
+//!     ctx: ComponentContext<Self>,
+//!     persistence: Persistence,
+//!     total: u64,
+//! }
+//! impl Default for Counter {
+//!     fn default() -> Self {
+//!         Counter {
+//!             ctx: ComponentContext::uninitialised(),
+//!             persistence: Persistence::new(),
+//!             total: 0,
+//!         }
+//!     }
+//! }
+//! impl PersistentComponent for Counter {
+//!     type Event = Added;
+//!     fn persistence_id(&self) -> String {
+//!         "counter".to_string()
+//!     }
+//!     fn persistence(&mut self) -> &mut Persistence {
+//!         &mut self.persistence
+//!     }
+//!     fn apply_event(&mut self, event: Added) -> () {
+//!         self.total += event.0;
+//!     }
+//! }
+//! impl ComponentLifecycle for Counter {
+//!     fn on_start(&mut self) -> Handled {
+//!         self.recover().expect("recovery");
+//!         if self.total < 5 {
+//!             self.persist(Added(5)).expect("persist");
+//!         }
+//!         Handled::Ok
+//!     }
+//! }
+//!
+//! let journal = InMemoryJournal::new();
+//! let mut conf = KompactConfig::default();
+//! conf.journal(Arc::new(journal.clone()));
+//! let system = conf.build().expect("system");
+//! for _ in 0..2 {
+//!     let counter = system.create(Counter::default);
+//!     system.start_notify(&counter).wait_timeout(std::time::Duration::from_millis(1000)).expect("started");
+//!     counter.on_definition(|c| assert_eq!(5, c.total));
+//!     system.kill_notify(counter).wait_timeout(std::time::Duration::from_millis(1000)).expect("killed");
+//! }
+//! assert_eq!(1, journal.highest_sequence_nr("counter").expect("journal"));
+//! # system.shutdown().expect("shutdown");
+//! ```
+
+use crate::prelude::*;
+use bytes::BytesMut;
+use std::{
+    error::Error,
+    fmt, io,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+pub mod journal;
+pub mod snapshot;
+
+use journal::{Journal, PersistedEvent};
+use snapshot::{PersistedSnapshot, SnapshotRetention, SnapshotStore};
+
+/// The persistence state of a [PersistentComponent](PersistentComponent)
+///
+/// Keeps track of the journal the component uses and of how far it has gotten in its event sequence.
+#[derive(Default)]
+pub struct Persistence {
+    journal: Option<Arc<dyn Journal>>,
+    snapshot_store: Option<Arc<dyn SnapshotStore>>,
+    retention: Option<SnapshotRetention>,
+    sequence_nr: u64,
+    recovered: bool,
+}
+
+impl Persistence {
+    /// Creates the state for a component using the system's [journal](crate::prelude::KompactConfig::journal)
+    pub fn new() -> Self {
+        Persistence::default()
+    }
+
+    /// Creates the state for a component using `journal`, instead of the system's
+    pub fn with_journal(journal: Arc<dyn Journal>) -> Self {
+        Persistence {
+            journal: Some(journal),
+            ..Persistence::default()
+        }
+    }
+
+    /// Use `store` for snapshots, instead of the system's [snapshot store](crate::prelude::KompactConfig::snapshot_store)
+    pub fn snapshot_store(mut self, store: Arc<dyn SnapshotStore>) -> Self {
+        self.snapshot_store = Some(store);
+        self
+    }
+
+    /// Use `retention` for snapshots, instead of the one [configured](SnapshotRetention::from_config) for the system
+    pub fn snapshot_retention(mut self, retention: SnapshotRetention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// The sequence number of the last event the component persisted or recovered, or 0 if there was none
+    pub fn sequence_nr(&self) -> u64 {
+        self.sequence_nr
+    }
+
+    /// Whether the component has [recovered](PersistentComponent::recover) yet
+    pub fn is_recovered(&self) -> bool {
+        self.recovered
+    }
+}
+
+/// A component whose state is made up of the events it has persisted
+///
+/// See the [module docs](crate::persistence) for an example.
+pub trait PersistentComponent: ComponentDefinition + Sized {
+    /// The type of events describing changes to the component's state
+    ///
+    /// Only a single [Deserialiser](Deserialiser) is used during recovery,
+    /// so different kinds of events should be variants of a single type.
+    type Event: Serialisable + Deserialiser<Self::Event>;
+
+    /// The id the component's events are stored under
+    ///
+    /// This must be the same for every incarnation of the component, but unique among
+    /// all components sharing a journal.
+    fn persistence_id(&self) -> String;
+
+    /// Gives access to the component's [Persistence](Persistence) state
+    fn persistence(&mut self) -> &mut Persistence;
+
+    /// Updates the component's state with `event`
+    ///
+    /// This is called both for newly persisted events and during recovery,
+    /// so it should not have any side effects beyond changing the state.
+    fn apply_event(&mut self, event: Self::Event) -> ();
+
+    /// Replays all journaled events via [apply_event](PersistentComponent::apply_event)
+    ///
+    /// This should be called from [on_start](ComponentLifecycle::on_start), so that the state
+    /// is complete before the component handles any messages.
+    /// Components that save snapshots should call [recover_with_snapshot](SnapshotComponent::recover_with_snapshot) instead.
+    /// Events are replayed after the current [sequence number](Persistence::sequence_nr), so calling this again
+    /// only replays what other writers may have added in the meantime.
+    ///
+    /// Returns the number of replayed events.
+    fn recover(&mut self) -> Result<usize, PersistenceError> {
+        let journal = resolve_journal(self)?;
+        let persistence_id = self.persistence_id();
+        let from = self.persistence().sequence_nr + 1;
+        let events = journal.replay(&persistence_id, from)?;
+        let count = events.len();
+        for PersistedEvent {
+            sequence_nr,
+            ser_id,
+            mut data,
+        } in events
+        {
+            if ser_id != <Self::Event as Deserialiser<Self::Event>>::SER_ID {
+                return Err(PersistenceError::Serialisation(SerError::InvalidType(
+                    format!(
+                        "Event {} of {} has ser_id={}, but the deserialiser expects {}",
+                        sequence_nr,
+                        persistence_id,
+                        ser_id,
+                        <Self::Event as Deserialiser<Self::Event>>::SER_ID
+                    ),
+                )));
+            }
+            let event = <Self::Event as Deserialiser<Self::Event>>::deserialise(&mut data)?;
+            self.apply_event(event);
+            self.persistence().sequence_nr = sequence_nr;
+        }
+        let persistence = self.persistence();
+        persistence.recovered = true;
+        let sequence_nr = persistence.sequence_nr;
+        debug!(
+            self.ctx().log(),
+            "Recovered {} events of {}, now at sequence number {}.",
+            count,
+            persistence_id,
+            sequence_nr
+        );
+        Ok(count)
+    }
+
+    /// Writes `event` to the journal, and then applies it to the component's state
+    ///
+    /// The write happens synchronously on the component's thread.
+    /// If it fails, the event is not applied.
+    ///
+    /// Fails with [NotRecovered](PersistenceError::NotRecovered) if [recover](PersistentComponent::recover)
+    /// hasn't been called yet, since the event would otherwise be applied to an incomplete state.
+    fn persist(&mut self, event: Self::Event) -> Result<(), PersistenceError> {
+        if !self.persistence().recovered {
+            return Err(PersistenceError::NotRecovered(self.persistence_id()));
+        }
+        let journal = resolve_journal(self)?;
+        let serialised = event.serialised()?;
+        let sequence_nr = self.persistence().sequence_nr + 1;
+        let persisted = PersistedEvent {
+            sequence_nr,
+            ser_id: serialised.ser_id,
+            data: serialised.data,
+        };
+        journal.append(&self.persistence_id(), persisted)?;
+        self.persistence().sequence_nr = sequence_nr;
+        self.apply_event(event);
+        Ok(())
+    }
+}
+
+/// A [PersistentComponent](PersistentComponent) that can save snapshots of its state
+///
+/// Snapshots are written with the component's [snapshot_serialiser](SnapshotComponent::snapshot_serialiser)
+/// into a [SnapshotStore](snapshot::SnapshotStore), which is either given to the component via
+/// [Persistence::snapshot_store](Persistence::snapshot_store), or shared by the system via
+/// [KompactConfig::snapshot_store](crate::prelude::KompactConfig::snapshot_store).
+pub trait SnapshotComponent: PersistentComponent {
+    /// The type of snapshots of the component's state
+    type Snapshot;
+
+    /// The serialiser used to store and load snapshots
+    type SnapshotSerialiser: Serialiser<Self::Snapshot> + Deserialiser<Self::Snapshot>;
+
+    /// Returns the serialiser used to store snapshots
+    fn snapshot_serialiser(&self) -> &Self::SnapshotSerialiser;
+
+    /// Replaces the component's state with `snapshot`
+    ///
+    /// This is called during [recover_with_snapshot](SnapshotComponent::recover_with_snapshot),
+    /// before any events are replayed.
+    fn on_recover_snapshot(&mut self, snapshot: Self::Snapshot) -> ();
+
+    /// Saves `snapshot` as the component's state after the last persisted event
+    ///
+    /// `snapshot` must reflect all events applied so far, since recovery will only replay the events after it.
+    /// Afterwards, older snapshots are deleted according to the [SnapshotRetention](snapshot::SnapshotRetention).
+    ///
+    /// Like [persist](PersistentComponent::persist), this fails with [NotRecovered](PersistenceError::NotRecovered)
+    /// if the component hasn't recovered yet.
+    fn save_snapshot(&mut self, snapshot: Self::Snapshot) -> Result<(), PersistenceError> {
+        if !self.persistence().recovered {
+            return Err(PersistenceError::NotRecovered(self.persistence_id()));
+        }
+        let store = resolve_snapshot_store(self)?;
+        let sequence_nr = self.persistence().sequence_nr;
+        let serialiser = self.snapshot_serialiser();
+        let mut data = BytesMut::with_capacity(serialiser.size_hint().unwrap_or(0));
+        serialiser.serialise(&snapshot, &mut data)?;
+        let persisted = PersistedSnapshot {
+            sequence_nr,
+            ser_id: serialiser.ser_id(),
+            data: data.freeze(),
+        };
+        let persistence_id = self.persistence_id();
+        store.save(&persistence_id, persisted)?;
+        let retention = match self.persistence().retention {
+            Some(retention) => retention,
+            None => {
+                let retention = SnapshotRetention::from_config(self.ctx().config());
+                self.persistence().retention = Some(retention);
+                retention
+            }
+        };
+        let deleted = store.retain_latest(&persistence_id, retention.keep())?;
+        debug!(
+            self.ctx().log(),
+            "Saved snapshot of {} and deleted {} older ones.", persistence_id, deleted
+        );
+        Ok(())
+    }
+
+    /// Restores the latest snapshot, if any, and then [recovers](PersistentComponent::recover)
+    /// the events after it
+    ///
+    /// Like [recover](PersistentComponent::recover), this should be called from [on_start](ComponentLifecycle::on_start).
+    /// A snapshot is only restored if the component hasn't recovered any events yet.
+    ///
+    /// Returns the number of replayed events.
+    fn recover_with_snapshot(&mut self) -> Result<usize, PersistenceError> {
+        if self.persistence().sequence_nr == 0 {
+            let store = resolve_snapshot_store(self)?;
+            let persistence_id = self.persistence_id();
+            if let Some(snapshot) = store.load_latest(&persistence_id)? {
+                let expected = <Self::SnapshotSerialiser as Deserialiser<Self::Snapshot>>::SER_ID;
+                if snapshot.ser_id != expected {
+                    return Err(PersistenceError::Serialisation(SerError::InvalidType(
+                        format!(
+                            "Snapshot {} of {} has ser_id={}, but the deserialiser expects {}",
+                            snapshot.sequence_nr, persistence_id, snapshot.ser_id, expected
+                        ),
+                    )));
+                }
+                let mut data = snapshot.data;
+                let state =
+                    <Self::SnapshotSerialiser as Deserialiser<Self::Snapshot>>::deserialise(
+                        &mut data,
+                    )?;
+                self.on_recover_snapshot(state);
+                self.persistence().sequence_nr = snapshot.sequence_nr;
+                debug!(
+                    self.ctx().log(),
+                    "Restored snapshot {} of {}.", snapshot.sequence_nr, persistence_id
+                );
+            }
+        }
+        self.recover()
+    }
+}
+
+/// Returns the component's journal, falling back to the system's if it doesn't have its own
+fn resolve_journal<C: PersistentComponent>(
+    component: &mut C,
+) -> Result<Arc<dyn Journal>, PersistenceError> {
+    if let Some(ref journal) = component.persistence().journal {
+        return Ok(journal.clone());
+    }
+    let journal = component
+        .ctx()
+        .typed_component()
+        .system()
+        .journal()
+        .cloned()
+        .ok_or_else(|| PersistenceError::NoJournal(component.persistence_id()))?;
+    component.persistence().journal = Some(journal.clone());
+    Ok(journal)
+}
+
+/// Returns the component's snapshot store, falling back to the system's if it doesn't have its own
+fn resolve_snapshot_store<C: PersistentComponent>(
+    component: &mut C,
+) -> Result<Arc<dyn SnapshotStore>, PersistenceError> {
+    if let Some(ref store) = component.persistence().snapshot_store {
+        return Ok(store.clone());
+    }
+    let store = component
+        .ctx()
+        .typed_component()
+        .system()
+        .snapshot_store()
+        .cloned()
+        .ok_or_else(|| PersistenceError::NoSnapshotStore(component.persistence_id()))?;
+    component.persistence().snapshot_store = Some(store.clone());
+    Ok(store)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // stores either complete an operation or leave their state untouched, so poisoning can be ignored
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Errors that can occur while persisting or recovering events and snapshots
+#[derive(Debug)]
+pub enum PersistenceError {
+    /// Neither the component nor its system has a journal
+    NoJournal(String),
+    /// Neither the component nor its system has a snapshot store
+    NoSnapshotStore(String),
+    /// The component tried to persist an event before recovering
+    NotRecovered(String),
+    /// An event did not directly follow the last stored event of its persistence id
+    SequenceMismatch {
+        /// The persistence id the event was appended to
+        persistence_id: String,
+        /// The sequence number the journal expected next
+        expected: u64,
+        /// The sequence number of the event
+        actual: u64,
+    },
+    /// The persistence id can't be stored in the journal
+    InvalidPersistenceId(String),
+    /// An event or snapshot could not be serialised or deserialised
+    Serialisation(SerError),
+    /// The journal or snapshot store could not be read or written
+    Io(io::Error),
+    /// The contents of the journal or snapshot store are not as expected
+    Corrupted(String),
+}
+
+impl fmt::Display for PersistenceError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PersistenceError::NoJournal(id) => write!(fmt, "No journal available for {}", id),
+            PersistenceError::NoSnapshotStore(id) => {
+                write!(fmt, "No snapshot store available for {}", id)
+            }
+            PersistenceError::NotRecovered(id) => {
+                write!(fmt, "Can't persist events of {} before recovering", id)
+            }
+            PersistenceError::SequenceMismatch {
+                persistence_id,
+                expected,
+                actual,
+            } => write!(
+                fmt,
+                "Expected sequence number {} for {}, but got {}",
+                expected, persistence_id, actual
+            ),
+            PersistenceError::InvalidPersistenceId(id) => {
+                write!(fmt, "Invalid persistence id: {}", id)
+            }
+            PersistenceError::Serialisation(e) => write!(fmt, "Invalid event: {}", e),
+            PersistenceError::Io(e) => write!(fmt, "Persistence I/O failed: {}", e),
+            PersistenceError::Corrupted(reason) => write!(fmt, "Corrupted storage: {}", reason),
+        }
+    }
+}
+
+impl Error for PersistenceError {}
+
+impl From<io::Error> for PersistenceError {
+    fn from(e: io::Error) -> Self {
+        PersistenceError::Io(e)
+    }
+}
+
+impl From<SerError> for PersistenceError {
+    fn from(e: SerError) -> Self {
+        PersistenceError::Serialisation(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        journal::InMemoryJournal,
+        snapshot::{InMemorySnapshotStore, SnapshotRetention},
+        *,
+    };
+    use crate::{testkit::wait_until, utils::promise};
+    use once_cell::sync::Lazy;
+    use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+    const TIMEOUT: Duration = Duration::from_millis(3000);
+
+    #[derive(Debug)]
+    enum CounterEvent {
+        Added(u64),
+        Reset,
+    }
+
+    impl Serialisable for CounterEvent {
+        fn ser_id(&self) -> SerId {
+            Self::SER_ID
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(9)
+        }
+
+        fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+            match self {
+                CounterEvent::Added(n) => {
+                    buf.put_u8(0);
+                    buf.put_u64(*n);
+                }
+                CounterEvent::Reset => buf.put_u8(1),
+            }
+            Ok(())
+        }
+
+        fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+            Ok(self)
+        }
+    }
+
+    impl Deserialiser<CounterEvent> for CounterEvent {
+        const SER_ID: SerId = 4242;
+
+        fn deserialise(buf: &mut dyn Buf) -> Result<CounterEvent, SerError> {
+            match buf.get_u8() {
+                0 => Ok(CounterEvent::Added(buf.get_u64())),
+                1 => Ok(CounterEvent::Reset),
+                tag => Err(SerError::InvalidData(format!("Unknown tag {}", tag))),
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct TotalSer;
+
+    impl Serialiser<u64> for TotalSer {
+        fn ser_id(&self) -> SerId {
+            4243
+        }
+
+        fn size_hint(&self) -> Option<usize> {
+            Some(8)
+        }
+
+        fn serialise(&self, v: &u64, buf: &mut dyn BufMut) -> Result<(), SerError> {
+            buf.put_u64(*v);
+            Ok(())
+        }
+    }
+
+    impl Deserialiser<u64> for TotalSer {
+        const SER_ID: SerId = 4243;
+
+        fn deserialise(buf: &mut dyn Buf) -> Result<u64, SerError> {
+            Ok(buf.get_u64())
+        }
+    }
+
+    #[derive(Debug)]
+    enum CounterMsg {
+        Add(u64),
+        Reset,
+        SaveSnapshot,
+        Get(KPromise<u64>),
+        Crash,
+    }
+
+    // the latest incarnation of each counter, by persistence id
+    static COUNTERS: Lazy<Mutex<HashMap<String, ActorRef<CounterMsg>>>> =
+        Lazy::new(|| Mutex::new(HashMap::new()));
+
+    const RESTARTING_ID: &str = "restarting-counter";
+
+    #[derive(ComponentDefinition)]
+    struct Counter {
+        ctx: ComponentContext<Self>,
+        persistence: Persistence,
+        id: &'static str,
+        snapshots: bool,
+        total: u64,
+        restored: Option<u64>,
+        replayed: usize,
+    }
+
+    impl Counter {
+        fn with_id(id: &'static str) -> Self {
+            Counter {
+                ctx: ComponentContext::uninitialised(),
+                persistence: Persistence::new(),
+                id,
+                snapshots: false,
+                total: 0,
+                restored: None,
+                replayed: 0,
+            }
+        }
+
+        fn with_snapshots(id: &'static str) -> Self {
+            Counter {
+                snapshots: true,
+                ..Counter::with_id(id)
+            }
+        }
+    }
+
+    impl Default for Counter {
+        fn default() -> Self {
+            Counter::with_id(RESTARTING_ID)
+        }
+    }
+
+    impl PersistentComponent for Counter {
+        type Event = CounterEvent;
+
+        fn persistence_id(&self) -> String {
+            self.id.to_string()
+        }
+
+        fn persistence(&mut self) -> &mut Persistence {
+            &mut self.persistence
+        }
+
+        fn apply_event(&mut self, event: CounterEvent) -> () {
+            match event {
+                CounterEvent::Added(n) => self.total += n,
+                CounterEvent::Reset => self.total = 0,
+            }
+        }
+    }
+
+    impl SnapshotComponent for Counter {
+        type Snapshot = u64;
+        type SnapshotSerialiser = TotalSer;
+
+        fn snapshot_serialiser(&self) -> &TotalSer {
+            &TotalSer
+        }
+
+        fn on_recover_snapshot(&mut self, snapshot: u64) -> () {
+            self.total = snapshot;
+            self.restored = Some(snapshot);
+        }
+    }
+
+    impl ComponentLifecycle for Counter {
+        fn on_start(&mut self) -> Handled {
+            self.replayed = if self.snapshots {
+                self.recover_with_snapshot()
+            } else {
+                self.recover()
+            }
+            .expect("recovery");
+            COUNTERS
+                .lock()
+                .unwrap()
+                .insert(self.id.to_string(), self.actor_ref());
+            self.ctx
+                .set_recovery_function(|fault| fault.restart_default::<Counter>());
+            Handled::Ok
+        }
+    }
+
+    impl Actor for Counter {
+        type Message = CounterMsg;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            match msg {
+                CounterMsg::Add(n) => self.persist(CounterEvent::Added(n)).expect("persist"),
+                CounterMsg::Reset => self.persist(CounterEvent::Reset).expect("persist"),
+                CounterMsg::SaveSnapshot => self.save_snapshot(self.total).expect("snapshot"),
+                CounterMsg::Get(promise) => promise.fulfil(self.total).expect("fulfil"),
+                CounterMsg::Crash => panic!("Test crash"),
+            }
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!("No networking here");
+        }
+    }
+
+    fn get_total(counter: &ActorRef<CounterMsg>) -> u64 {
+        let (p, f) = promise();
+        counter.tell(CounterMsg::Get(p));
+        f.wait_timeout(TIMEOUT).expect("total")
+    }
+
+    #[test]
+    fn test_persist_and_recover() {
+        let journal = InMemoryJournal::new();
+        let mut conf = KompactConfig::default();
+        conf.journal(Arc::new(journal.clone()));
+        let system = conf.build().expect("system");
+
+        let counter = system.create(|| Counter::with_id("counter"));
+        system
+            .start_notify(&counter)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        let counter_ref = counter.actor_ref();
+        counter_ref.tell(CounterMsg::Add(3));
+        counter_ref.tell(CounterMsg::Reset);
+        counter_ref.tell(CounterMsg::Add(4));
+        counter_ref.tell(CounterMsg::Add(5));
+        assert_eq!(9, get_total(&counter_ref));
+        assert_eq!(4, journal.highest_sequence_nr("counter").expect("journal"));
+        system
+            .kill_notify(counter)
+            .wait_timeout(TIMEOUT)
+            .expect("killed");
+
+        let counter = system.create(|| Counter::with_id("counter"));
+        system
+            .start_notify(&counter)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        counter.on_definition(|c| {
+            assert_eq!(9, c.total);
+            assert_eq!(4, c.persistence.sequence_nr());
+        });
+        counter.actor_ref().tell(CounterMsg::Add(1));
+        assert_eq!(10, get_total(&counter.actor_ref()));
+        assert_eq!(5, journal.highest_sequence_nr("counter").expect("journal"));
+
+        // the component's own journal takes precedence over the system's
+        let own_journal = InMemoryJournal::new();
+        let shadow = system.create(|| Counter {
+            persistence: Persistence::with_journal(Arc::new(own_journal.clone())),
+            ..Counter::with_id("counter")
+        });
+        system
+            .start_notify(&shadow)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        shadow.actor_ref().tell(CounterMsg::Add(2));
+        assert_eq!(2, get_total(&shadow.actor_ref()));
+        assert_eq!(
+            1,
+            own_journal.highest_sequence_nr("counter").expect("journal")
+        );
+        assert_eq!(5, journal.highest_sequence_nr("counter").expect("journal"));
+
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_recover_after_restart() {
+        let mut conf = KompactConfig::default();
+        conf.journal(Arc::new(InMemoryJournal::new()));
+        let system = conf.build().expect("system");
+
+        let counter = system.create(Counter::default);
+        system
+            .start_notify(&counter)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        let counter_ref = counter.actor_ref();
+        counter_ref.tell(CounterMsg::Add(7));
+        assert_eq!(7, get_total(&counter_ref));
+        drop(counter);
+        counter_ref.tell(CounterMsg::Crash);
+
+        let mut restarted = None;
+        assert!(wait_until(TIMEOUT, || {
+            let current = COUNTERS.lock().unwrap().get(RESTARTING_ID).cloned();
+            restarted = current.filter(|r| r != &counter_ref);
+            restarted.is_some()
+        }));
+        let restarted = restarted.unwrap();
+        assert_eq!(7, get_total(&restarted));
+        restarted.tell(CounterMsg::Add(1));
+        assert_eq!(8, get_total(&restarted));
+
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_recover_with_snapshot() {
+        let journal = InMemoryJournal::new();
+        let store = InMemorySnapshotStore::new();
+        let mut conf = KompactConfig::default();
+        conf.journal(Arc::new(journal.clone()))
+            .snapshot_store(Arc::new(store.clone()))
+            .load_config_str("persistence.snapshots.keep = 2");
+        let system = conf.build().expect("system");
+
+        let counter = system.create(|| Counter::with_snapshots("snapshot-counter"));
+        system
+            .start_notify(&counter)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        let counter_ref = counter.actor_ref();
+        for i in 1..=3 {
+            counter_ref.tell(CounterMsg::Add(i));
+            counter_ref.tell(CounterMsg::SaveSnapshot);
+        }
+        counter_ref.tell(CounterMsg::Add(10));
+        counter_ref.tell(CounterMsg::Add(20));
+        assert_eq!(36, get_total(&counter_ref));
+        // only the latest two snapshots are kept
+        assert_eq!(vec![2, 3], store.sequence_nrs("snapshot-counter"));
+        system
+            .kill_notify(counter)
+            .wait_timeout(TIMEOUT)
+            .expect("killed");
+
+        let counter = system.create(|| Counter::with_snapshots("snapshot-counter"));
+        system
+            .start_notify(&counter)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        counter.on_definition(|c| {
+            assert_eq!(Some(6), c.restored);
+            assert_eq!(2, c.replayed);
+            assert_eq!(36, c.total);
+            assert_eq!(5, c.persistence.sequence_nr());
+        });
+
+        // a component's own settings take precedence over the system's
+        let own_store = InMemorySnapshotStore::new();
+        let own = system.create(|| Counter {
+            persistence: Persistence::new()
+                .snapshot_store(Arc::new(own_store.clone()))
+                .snapshot_retention(SnapshotRetention::keep_latest(1)),
+            ..Counter::with_snapshots("own-counter")
+        });
+        system
+            .start_notify(&own)
+            .wait_timeout(TIMEOUT)
+            .expect("started");
+        for i in 1..=3 {
+            own.actor_ref().tell(CounterMsg::Add(i));
+            own.actor_ref().tell(CounterMsg::SaveSnapshot);
+        }
+        assert_eq!(6, get_total(&own.actor_ref()));
+        assert_eq!(vec![3], own_store.sequence_nrs("own-counter"));
+        assert!(store.sequence_nrs("own-counter").is_empty());
+
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_persist_requires_recovery_and_journal() {
+        let system = KompactConfig::default().build().expect("system");
+        let counter = system.create(Counter::default);
+        counter.on_definition(|c| {
+            match c.persist(CounterEvent::Added(1)) {
+                Err(PersistenceError::NotRecovered(_)) => (),
+                res => panic!("Unexpected persist result: {:?}", res),
+            }
+            match c.recover() {
+                Err(PersistenceError::NoJournal(_)) => (),
+                res => panic!("Unexpected recover result: {:?}", res),
+            }
+            match c.recover_with_snapshot() {
+                Err(PersistenceError::NoSnapshotStore(_)) => (),
+                res => panic!("Unexpected recover result: {:?}", res),
+            }
+        });
+        system.shutdown().expect("shutdown");
+    }
+}
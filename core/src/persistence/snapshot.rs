@@ -0,0 +1,413 @@
+//! Snapshot stores that keep the state of persistent components
+//!
+//! A snapshot captures a [SnapshotComponent](super::SnapshotComponent)'s state after a particular event,
+//! so that recovery only needs to replay the events after it.
+//! Like events, snapshots are stored in their serialised form.
+//!
+//! Two implementations are provided:
+//!
+//! - [FileSnapshotStore](FileSnapshotStore) keeps every snapshot in its own file within a directory.
+//! - [InMemorySnapshotStore](InMemorySnapshotStore) keeps snapshots in memory, which makes it a good fit for tests.
+//!
+//! How many snapshots are kept for each persistence id is decided by the [SnapshotRetention](SnapshotRetention).
+
+use super::*;
+use crate::serialisation::{SerIdBuf, SerIdBufMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use hocon::Hocon;
+use std::{
+    collections::HashMap,
+    fmt::Write as FmtWrite,
+    fs,
+    io::Write,
+    mem,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// A single snapshot, as stored in a [SnapshotStore](SnapshotStore)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PersistedSnapshot {
+    /// The sequence number of the last event included in the snapshot
+    pub sequence_nr: u64,
+    /// The serialisation id of the snapshot
+    pub ser_id: SerId,
+    /// The serialised snapshot
+    pub data: Bytes,
+}
+
+/// Storage for the snapshots of [snapshot components](super::SnapshotComponent)
+///
+/// Snapshot stores are shared between all components of a system, and thus must be thread-safe.
+/// Calls happen directly on the component's thread, so they should not block for too long.
+pub trait SnapshotStore: Send + Sync {
+    /// Stores `snapshot` for `persistence_id`, replacing any snapshot with the same sequence number
+    fn save(
+        &self,
+        persistence_id: &str,
+        snapshot: PersistedSnapshot,
+    ) -> Result<(), PersistenceError>;
+
+    /// Returns the snapshot with the highest sequence number for `persistence_id`, if there is any
+    fn load_latest(
+        &self,
+        persistence_id: &str,
+    ) -> Result<Option<PersistedSnapshot>, PersistenceError>;
+
+    /// Deletes all but the `keep` snapshots with the highest sequence numbers for `persistence_id`
+    ///
+    /// Returns the number of deleted snapshots.
+    fn retain_latest(&self, persistence_id: &str, keep: usize) -> Result<usize, PersistenceError>;
+}
+
+/// How many snapshots to keep for each persistence id
+///
+/// Older snapshots are deleted whenever a component [saves](super::SnapshotComponent::save_snapshot) a new one.
+/// Keeping more than one leaves older snapshots around for manual recovery,
+/// in case the latest one turns out to be unusable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotRetention {
+    keep: usize,
+}
+
+impl SnapshotRetention {
+    /// The number of snapshots kept, unless configured otherwise
+    pub const DEFAULT_KEEP: usize = 2;
+
+    /// Keep the latest `keep` snapshots of each persistence id
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keep` is 0, since that would delete every snapshot right after saving it.
+    pub fn keep_latest(keep: usize) -> Self {
+        assert!(keep > 0, "At least one snapshot must be kept");
+        SnapshotRetention { keep }
+    }
+
+    /// Reads the retention from the `persistence.snapshots` section of `config`
+    ///
+    /// For example:
+    ///
+    /// ```text
+    /// persistence.snapshots {
+    ///     keep = 3
+    /// }
+    /// ```
+    ///
+    /// Falls back to [DEFAULT_KEEP](SnapshotRetention::DEFAULT_KEEP) if `keep` is not set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `keep` is not positive.
+    pub fn from_config(config: &Hocon) -> Self {
+        match config["persistence"]["snapshots"]["keep"].as_i64() {
+            Some(keep) => {
+                assert!(keep > 0, "Invalid number of snapshots to keep: {}", keep);
+                SnapshotRetention::keep_latest(keep as usize)
+            }
+            None => SnapshotRetention::default(),
+        }
+    }
+
+    /// The number of snapshots kept for each persistence id
+    pub fn keep(&self) -> usize {
+        self.keep
+    }
+}
+
+impl Default for SnapshotRetention {
+    fn default() -> Self {
+        SnapshotRetention::keep_latest(SnapshotRetention::DEFAULT_KEEP)
+    }
+}
+
+/// A [SnapshotStore](SnapshotStore) that keeps all snapshots in memory
+///
+/// Clones share the same snapshots, so a clone can be kept around to inspect
+/// what components have saved.
+#[derive(Clone, Default)]
+pub struct InMemorySnapshotStore {
+    snapshots: Arc<Mutex<HashMap<String, Vec<PersistedSnapshot>>>>,
+}
+
+impl InMemorySnapshotStore {
+    /// Creates a new, empty store
+    pub fn new() -> Self {
+        InMemorySnapshotStore::default()
+    }
+
+    /// Returns the sequence numbers of all snapshots stored for `persistence_id`, in ascending order
+    pub fn sequence_nrs(&self, persistence_id: &str) -> Vec<u64> {
+        let snapshots = lock(&self.snapshots);
+        snapshots
+            .get(persistence_id)
+            .map(|stored| stored.iter().map(|s| s.sequence_nr).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl SnapshotStore for InMemorySnapshotStore {
+    fn save(
+        &self,
+        persistence_id: &str,
+        snapshot: PersistedSnapshot,
+    ) -> Result<(), PersistenceError> {
+        let mut snapshots = lock(&self.snapshots);
+        let stored = snapshots.entry(persistence_id.to_string()).or_default();
+        match stored.binary_search_by_key(&snapshot.sequence_nr, |s| s.sequence_nr) {
+            Ok(index) => stored[index] = snapshot,
+            Err(index) => stored.insert(index, snapshot),
+        }
+        Ok(())
+    }
+
+    fn load_latest(
+        &self,
+        persistence_id: &str,
+    ) -> Result<Option<PersistedSnapshot>, PersistenceError> {
+        let snapshots = lock(&self.snapshots);
+        Ok(snapshots
+            .get(persistence_id)
+            .and_then(|stored| stored.last().cloned()))
+    }
+
+    fn retain_latest(&self, persistence_id: &str, keep: usize) -> Result<usize, PersistenceError> {
+        let mut snapshots = lock(&self.snapshots);
+        let deleted = match snapshots.get_mut(persistence_id) {
+            Some(stored) if stored.len() > keep => {
+                let deleted = stored.len() - keep;
+                stored.drain(..deleted);
+                deleted
+            }
+            _ => 0,
+        };
+        Ok(deleted)
+    }
+}
+
+/// A [SnapshotStore](SnapshotStore) that keeps each snapshot in its own file within a directory
+///
+/// Files are named after the persistence id and the sequence number of the snapshot,
+/// with all characters of the id other than ASCII letters, digits, and `_` escaped as `%XX`.
+/// Each file contains:
+///
+/// ```text
+/// +------------------+------------------+----------------+
+/// | sequence nr: 64  | ser id: SerId    | snapshot data  |
+/// +------------------+------------------+----------------+
+/// ```
+///
+/// Snapshots are written to a temporary file first, which is forced to disk and then renamed,
+/// so a crash while saving never leaves a partial snapshot behind.
+#[derive(Clone, Debug)]
+pub struct FileSnapshotStore {
+    dir: PathBuf,
+}
+
+const SNAPSHOT_EXTENSION: &str = ".snapshot";
+const TEMP_EXTENSION: &str = ".tmp";
+
+impl FileSnapshotStore {
+    /// Opens the store in `dir`, creating the directory if it doesn't exist yet
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, PersistenceError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        Ok(FileSnapshotStore { dir })
+    }
+
+    /// The directory this store writes to
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn file_prefix(persistence_id: &str) -> String {
+        let mut prefix = String::with_capacity(persistence_id.len() + 1);
+        for byte in persistence_id.bytes() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' {
+                prefix.push(byte as char);
+            } else {
+                write!(prefix, "%{:02X}", byte).expect("writing to a string");
+            }
+        }
+        prefix.push('-');
+        prefix
+    }
+
+    fn snapshot_path(&self, persistence_id: &str, sequence_nr: u64) -> PathBuf {
+        self.dir.join(format!(
+            "{}{:020}{}",
+            Self::file_prefix(persistence_id),
+            sequence_nr,
+            SNAPSHOT_EXTENSION
+        ))
+    }
+
+    /// Returns the sequence numbers of all snapshots stored for `persistence_id`, in ascending order
+    pub fn sequence_nrs(&self, persistence_id: &str) -> Result<Vec<u64>, PersistenceError> {
+        let prefix = Self::file_prefix(persistence_id);
+        let mut sequence_nrs = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let sequence_nr = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(prefix.as_str()))
+                .and_then(|rest| rest.strip_suffix(SNAPSHOT_EXTENSION))
+                .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|digits| digits.parse::<u64>().ok());
+            if let Some(sequence_nr) = sequence_nr {
+                sequence_nrs.push(sequence_nr);
+            }
+        }
+        sequence_nrs.sort_unstable();
+        Ok(sequence_nrs)
+    }
+}
+
+impl SnapshotStore for FileSnapshotStore {
+    fn save(
+        &self,
+        persistence_id: &str,
+        snapshot: PersistedSnapshot,
+    ) -> Result<(), PersistenceError> {
+        let path = self.snapshot_path(persistence_id, snapshot.sequence_nr);
+        let mut temp_name = path.clone().into_os_string();
+        temp_name.push(TEMP_EXTENSION);
+        let temp_path = PathBuf::from(temp_name);
+        let mut contents =
+            BytesMut::with_capacity(8 + mem::size_of::<SerId>() + snapshot.data.len());
+        contents.put_u64(snapshot.sequence_nr);
+        contents.put_ser_id(snapshot.ser_id);
+        contents.put_slice(&snapshot.data);
+        let written = fs::File::create(&temp_path).and_then(|mut file| {
+            file.write_all(&contents)?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| fs::rename(&temp_path, &path)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    fn load_latest(
+        &self,
+        persistence_id: &str,
+    ) -> Result<Option<PersistedSnapshot>, PersistenceError> {
+        let sequence_nr = match self.sequence_nrs(persistence_id)?.last() {
+            Some(sequence_nr) => *sequence_nr,
+            None => return Ok(None),
+        };
+        let contents = fs::read(self.snapshot_path(persistence_id, sequence_nr))?;
+        let mut buf = contents.as_slice();
+        if buf.remaining() < 8 + mem::size_of::<SerId>() {
+            return Err(PersistenceError::Corrupted(format!(
+                "snapshot {} of {} cut short",
+                sequence_nr, persistence_id
+            )));
+        }
+        let stored_sequence_nr = buf.get_u64();
+        if stored_sequence_nr != sequence_nr {
+            return Err(PersistenceError::Corrupted(format!(
+                "snapshot {} of {} claims sequence number {}",
+                sequence_nr, persistence_id, stored_sequence_nr
+            )));
+        }
+        let ser_id = buf.get_ser_id();
+        Ok(Some(PersistedSnapshot {
+            sequence_nr,
+            ser_id,
+            data: Bytes::copy_from_slice(buf),
+        }))
+    }
+
+    fn retain_latest(&self, persistence_id: &str, keep: usize) -> Result<usize, PersistenceError> {
+        let sequence_nrs = self.sequence_nrs(persistence_id)?;
+        let deleted = sequence_nrs.len().saturating_sub(keep);
+        for sequence_nr in &sequence_nrs[..deleted] {
+            fs::remove_file(self.snapshot_path(persistence_id, *sequence_nr))?;
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hocon::HoconLoader;
+
+    fn snapshot(sequence_nr: u64, data: &'static [u8]) -> PersistedSnapshot {
+        PersistedSnapshot {
+            sequence_nr,
+            ser_id: 42,
+            data: Bytes::from_static(data),
+        }
+    }
+
+    fn check_store(store: &dyn SnapshotStore) {
+        assert_eq!(None, store.load_latest("a").expect("load"));
+        store.save("a", snapshot(5, b"a5")).expect("save");
+        store.save("a", snapshot(12, b"a12")).expect("save");
+        store.save("a", snapshot(3, b"a3")).expect("save");
+        store.save("a-1", snapshot(20, b"a-1 20")).expect("save");
+        assert_eq!(
+            Some(snapshot(12, b"a12")),
+            store.load_latest("a").expect("load")
+        );
+        store.save("a", snapshot(12, b"a12 again")).expect("save");
+        assert_eq!(
+            Some(snapshot(12, b"a12 again")),
+            store.load_latest("a").expect("load")
+        );
+        assert_eq!(1, store.retain_latest("a", 2).expect("retain"));
+        assert_eq!(0, store.retain_latest("a", 2).expect("retain"));
+        assert_eq!(0, store.retain_latest("b", 2).expect("retain"));
+        assert_eq!(
+            Some(snapshot(20, b"a-1 20")),
+            store.load_latest("a-1").expect("load")
+        );
+    }
+
+    #[test]
+    fn test_in_memory_store() {
+        let store = InMemorySnapshotStore::new();
+        check_store(&store);
+        assert_eq!(vec![5, 12], store.sequence_nrs("a"));
+    }
+
+    #[test]
+    fn test_file_store() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let store = FileSnapshotStore::open(dir.path().join("snapshots")).expect("store");
+        check_store(&store);
+        assert_eq!(vec![5, 12], store.sequence_nrs("a").expect("list"));
+        store.save("a/../b", snapshot(1, b"odd")).expect("save");
+
+        let reopened = FileSnapshotStore::open(store.dir()).expect("store");
+        assert_eq!(
+            Some(snapshot(12, b"a12 again")),
+            reopened.load_latest("a").expect("load")
+        );
+        assert_eq!(
+            Some(snapshot(1, b"odd")),
+            reopened.load_latest("a/../b").expect("load")
+        );
+        assert_eq!(None, reopened.load_latest("b").expect("load"));
+        let files = fs::read_dir(reopened.dir()).expect("list").count();
+        assert_eq!(4, files);
+    }
+
+    #[test]
+    fn test_retention_from_config() {
+        let empty = HoconLoader::new().hocon().expect("config");
+        assert_eq!(
+            SnapshotRetention::DEFAULT_KEEP,
+            SnapshotRetention::from_config(&empty).keep()
+        );
+        let config = HoconLoader::new()
+            .load_str("persistence.snapshots { keep = 5 }")
+            .expect("config")
+            .hocon()
+            .expect("config");
+        assert_eq!(5, SnapshotRetention::from_config(&config).keep());
+    }
+}
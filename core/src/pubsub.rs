@@ -0,0 +1,774 @@
+//! Distributed publish/subscribe via named topics.
+//!
+//! Every system in a cluster runs a [PubSubMediator](PubSubMediator). Local actors subscribe to
+//! topics with their system's mediator, and publish messages to topics through it as well.
+//! A published message is delivered to all local subscribers of its topic, and sent once to
+//! the mediator of every other system with subscribers for it, which delivers it to those.
+//!
+//! To know which systems have subscribers for which topics, mediators exchange the set of
+//! topics they have local subscribers for. Each mediator learns about the other systems
+//! in the cluster from a [Membership](crate::membership::Membership) component, which it is
+//! connected to via the [ClusterMembership](ClusterMembership) port. It sends its topics to
+//! every member that comes `Up`, to all members whenever the set changes, and to all members
+//! periodically, in case an update got lost. Each announcement carries the sender's incarnation
+//! and a version, so that stale announcements can be told apart from current ones.
+//! The topics of a member that is `Down` or has `Left` are forgotten, as are local
+//! subscribers once they terminate.
+//!
+//! # Configuration
+//!
+//! The mediator reads its settings from the `pubsub` section of the system's HOCON config:
+//!
+//! ```hocon
+//! pubsub {
+//!     gossip_interval = 1 s
+//! }
+//! ```
+
+use super::prelude::*;
+use crate::serialisation::serialisation_ids;
+use bytes::Bytes;
+use hocon::Hocon;
+use rustc_hash::{FxHashMap, FxHashSet};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The alias every [PubSubMediator](PubSubMediator) registers itself under
+pub const PUBSUB_ALIAS: &str = "$pubsub";
+
+// Default values for pub/sub config.
+const GOSSIP_INTERVAL: u64 = 1000;
+
+/// Configuration for a [PubSubMediator](PubSubMediator)
+///
+/// All durations are given in milliseconds.
+#[derive(Clone, Debug)]
+pub struct PubSubConfig {
+    gossip_interval: u64,
+}
+
+impl PubSubConfig {
+    /// Reads a configuration from the `pubsub` section of the given `config`
+    ///
+    /// Returns the same values as [PubSubConfig::default](PubSubConfig::default)
+    /// for all keys that are missing.
+    pub fn from_config(config: &Hocon) -> Self {
+        let section = &config["pubsub"];
+        let mut pubsub_config = PubSubConfig::default();
+        if let Some(interval) = section["gossip_interval"].as_duration() {
+            pubsub_config.gossip_interval = interval.as_millis() as u64;
+        }
+        pubsub_config
+    }
+
+    /// Sets how often a mediator sends its topics to all other members
+    ///
+    /// Default value is 1000 ms.
+    pub fn set_gossip_interval(&mut self, milliseconds: u64) {
+        self.gossip_interval = milliseconds;
+    }
+
+    /// Returns how often a mediator sends its topics to all other members
+    pub fn get_gossip_interval(&self) -> u64 {
+        self.gossip_interval
+    }
+}
+
+impl Default for PubSubConfig {
+    fn default() -> Self {
+        PubSubConfig {
+            gossip_interval: GOSSIP_INTERVAL,
+        }
+    }
+}
+
+/// Requests to a [PubSubMediator](PubSubMediator)
+#[derive(Debug)]
+pub enum PubSubRequest {
+    /// Deliver all messages published to `topic` to `subscriber`
+    Subscribe {
+        /// The name of the topic
+        topic: String,
+        /// The path messages to the topic are delivered to
+        subscriber: ActorPath,
+    },
+    /// Stop delivering messages published to `topic` to `subscriber`
+    Unsubscribe {
+        /// The name of the topic
+        topic: String,
+        /// The path that was subscribed
+        subscriber: ActorPath,
+    },
+    /// Deliver `msg` to all subscribers of `topic` in the cluster
+    Publish {
+        /// The name of the topic
+        topic: String,
+        /// The sender of the message, as seen by the subscribers
+        publisher: ActorPath,
+        /// The serialised message
+        msg: Serialised,
+    },
+}
+
+impl PubSubRequest {
+    /// A request to deliver all messages published to `topic` to `subscriber`
+    pub fn subscribe<T>(topic: T, subscriber: ActorPath) -> Self
+    where
+        T: Into<String>,
+    {
+        PubSubRequest::Subscribe {
+            topic: topic.into(),
+            subscriber,
+        }
+    }
+
+    /// A request to stop delivering messages published to `topic` to `subscriber`
+    pub fn unsubscribe<T>(topic: T, subscriber: ActorPath) -> Self
+    where
+        T: Into<String>,
+    {
+        PubSubRequest::Unsubscribe {
+            topic: topic.into(),
+            subscriber,
+        }
+    }
+
+    /// A request to deliver `msg` from `publisher` to all subscribers of `topic`
+    ///
+    /// Since the message may be delivered to many subscribers, it is serialised right away,
+    /// which fails if it doesn't give a [size hint](Serialisable::size_hint).
+    pub fn publish<T, M>(topic: T, publisher: ActorPath, msg: &M) -> Result<Self, SerError>
+    where
+        T: Into<String>,
+        M: Serialisable,
+    {
+        let msg = crate::serialisation::ser_helpers::serialise_to_serialised(msg)?;
+        Ok(PubSubRequest::Publish {
+            topic: topic.into(),
+            publisher,
+            msg,
+        })
+    }
+}
+
+fn string_size(s: &str) -> usize {
+    4 + s.len()
+}
+
+fn put_string(buf: &mut dyn BufMut, s: &str) {
+    buf.put_u32(s.len() as u32);
+    buf.put_slice(s.as_bytes());
+}
+
+fn get_string(buf: &mut dyn Buf) -> Result<String, SerError> {
+    if buf.remaining() < 4 {
+        return Err(SerError::InvalidData("Could not get string length".into()));
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err(SerError::InvalidData(format!(
+            "String of {} bytes cut short at {}",
+            len,
+            buf.remaining()
+        )));
+    }
+    let mut bytes = vec![0u8; len];
+    buf.copy_to_slice(&mut bytes);
+    String::from_utf8(bytes).map_err(|e| SerError::InvalidData(format!("Invalid string: {}", e)))
+}
+
+/// The protocol spoken between mediators
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum MediatorMsg {
+    /// All topics the sender has local subscribers for
+    Topics {
+        /// Changes whenever the sender's mediator is restarted
+        incarnation: Uuid,
+        /// Increased by the sender whenever its topics change
+        version: u64,
+        topics: Vec<String>,
+    },
+    /// A message to deliver to the receiver's local subscribers of `topic`
+    Publish {
+        topic: String,
+        publisher: ActorPath,
+        ser_id: SerId,
+        data: Bytes,
+    },
+}
+
+impl MediatorMsg {
+    const TOPICS: u8 = 1;
+    const PUBLISH: u8 = 2;
+}
+
+impl Serialisable for MediatorMsg {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::PUBSUB
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self {
+            MediatorMsg::Topics { topics, .. } => Some(
+                topics
+                    .iter()
+                    .fold(1 + 16 + 8 + 4, |acc, topic| acc + string_size(topic)),
+            ),
+            MediatorMsg::Publish {
+                topic,
+                publisher,
+                data,
+                ..
+            } => publisher.size_hint().map(|size| {
+                1 + string_size(topic) + size + std::mem::size_of::<SerId>() + 4 + data.len()
+            }),
+        }
+    }
+
+    fn serialise(&self, mut buf: &mut dyn BufMut) -> Result<(), SerError> {
+        match self {
+            MediatorMsg::Topics {
+                incarnation,
+                version,
+                topics,
+            } => {
+                buf.put_u8(MediatorMsg::TOPICS);
+                buf.put_u128(incarnation.as_u128());
+                buf.put_u64(*version);
+                buf.put_u32(topics.len() as u32);
+                for topic in topics {
+                    put_string(buf, topic);
+                }
+            }
+            MediatorMsg::Publish {
+                topic,
+                publisher,
+                ser_id,
+                data,
+            } => {
+                buf.put_u8(MediatorMsg::PUBLISH);
+                put_string(buf, topic);
+                publisher.serialise(buf)?;
+                buf.put_ser_id(*ser_id);
+                buf.put_u32(data.len() as u32);
+                buf.put_slice(data);
+            }
+        }
+        Ok(())
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<MediatorMsg> for MediatorMsg {
+    const SER_ID: SerId = serialisation_ids::PUBSUB;
+
+    fn deserialise(mut buf: &mut dyn Buf) -> Result<MediatorMsg, SerError> {
+        if buf.remaining() < 1 {
+            return Err(SerError::InvalidData(
+                "Could not get tag for MediatorMsg".into(),
+            ));
+        }
+        match buf.get_u8() {
+            MediatorMsg::TOPICS => {
+                if buf.remaining() < 16 + 8 + 4 {
+                    return Err(SerError::InvalidData(
+                        "Could not get header for MediatorMsg::Topics".into(),
+                    ));
+                }
+                let incarnation = Uuid::from_u128(buf.get_u128());
+                let version = buf.get_u64();
+                let len = buf.get_u32() as usize;
+                // every topic takes at least its length prefix
+                if buf.remaining() / 4 < len {
+                    return Err(SerError::InvalidData(format!(
+                        "MediatorMsg::Topics of {} topics cut short at {} bytes",
+                        len,
+                        buf.remaining()
+                    )));
+                }
+                let mut topics = Vec::with_capacity(len);
+                for _ in 0..len {
+                    topics.push(get_string(buf)?);
+                }
+                Ok(MediatorMsg::Topics {
+                    incarnation,
+                    version,
+                    topics,
+                })
+            }
+            MediatorMsg::PUBLISH => {
+                let topic = get_string(buf)?;
+                let publisher = ActorPath::deserialise(buf)?;
+                if buf.remaining() < std::mem::size_of::<SerId>() + 4 {
+                    return Err(SerError::InvalidData(
+                        "Could not get data header for MediatorMsg::Publish".into(),
+                    ));
+                }
+                let ser_id = buf.get_ser_id();
+                let len = buf.get_u32() as usize;
+                if buf.remaining() < len {
+                    return Err(SerError::InvalidData(format!(
+                        "MediatorMsg data of {} bytes cut short at {}",
+                        len,
+                        buf.remaining()
+                    )));
+                }
+                let mut data = vec![0u8; len];
+                buf.copy_to_slice(&mut data);
+                Ok(MediatorMsg::Publish {
+                    topic,
+                    publisher,
+                    ser_id,
+                    data: Bytes::from(data),
+                })
+            }
+            tag => Err(SerError::InvalidType(format!(
+                "Unknown MediatorMsg tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// The topics another member has subscribers for, as last announced
+#[derive(Debug)]
+struct RemoteTopics {
+    incarnation: Uuid,
+    version: u64,
+    topics: FxHashSet<String>,
+}
+
+/// A component that connects publishers and subscribers of topics across a cluster
+///
+/// The mediator registers itself under [PUBSUB_ALIAS](PUBSUB_ALIAS) when it is started,
+/// so it requires a system with a networked dispatcher. Its
+/// [ClusterMembership](ClusterMembership) port must be connected to a
+/// [Membership](crate::membership::Membership) component for it to reach other systems.
+/// Without one, messages are only delivered to local subscribers.
+///
+/// Subscribers receive published messages via [receive_network](Actor::receive_network),
+/// with the path of the publisher as their sender.
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+///
+/// let mut cfg = KompactConfig::default();
+/// cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+/// let system = cfg.build().expect("KompactSystem");
+///
+/// let membership = system.create(Membership::new);
+/// let mediator = system.create(PubSubMediator::new);
+/// biconnect_components::<ClusterMembership, _, _>(&membership, &mediator).expect("connection");
+/// system.start(&membership);
+/// system.start(&mediator);
+///
+/// # let subscriber = system.actor_path();
+/// mediator.actor_ref().tell(PubSubRequest::subscribe("news", subscriber.clone()));
+/// let publish = PubSubRequest::publish("news", subscriber, &42u64).expect("serialise");
+/// mediator.actor_ref().tell(publish);
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(ComponentDefinition)]
+pub struct PubSubMediator {
+    ctx: ComponentContext<Self>,
+    membership: RequiredPort<ClusterMembership>,
+    config: Option<PubSubConfig>,
+    incarnation: Uuid,
+    version: u64,
+    /// Local subscribers of each topic
+    subscribers: FxHashMap<String, Vec<ActorPath>>,
+    /// All live members other than this system
+    peers: FxHashSet<SystemPath>,
+    /// The topics of members that have announced any
+    remote: FxHashMap<SystemPath, RemoteTopics>,
+    timer: Option<ScheduledTimer>,
+}
+
+impl PubSubMediator {
+    /// Create a new mediator configured from the system's config
+    pub fn new() -> Self {
+        PubSubMediator {
+            ctx: ComponentContext::uninitialised(),
+            membership: RequiredPort::uninitialised(),
+            config: None,
+            incarnation: Uuid::new_v4(),
+            version: 0,
+            subscribers: FxHashMap::default(),
+            peers: FxHashSet::default(),
+            remote: FxHashMap::default(),
+            timer: None,
+        }
+    }
+
+    /// Create a new mediator with the given configuration
+    ///
+    /// The `pubsub` section of the system's config is ignored in this case.
+    pub fn with_config(config: PubSubConfig) -> Self {
+        let mut mediator = PubSubMediator::new();
+        mediator.config = Some(config);
+        mediator
+    }
+
+    fn config(&self) -> &PubSubConfig {
+        self.config.as_ref().expect("config")
+    }
+
+    fn mediator_path(system: SystemPath) -> ActorPath {
+        ActorPath::Named(NamedPath::with_system(
+            system,
+            vec![PUBSUB_ALIAS.to_string()],
+        ))
+    }
+
+    fn send(&self, system: &SystemPath, msg: MediatorMsg) {
+        let dst = Self::mediator_path(system.clone());
+        let mut src = Self::mediator_path(self.ctx.system().system_path());
+        src.set_protocol(system.protocol());
+        dst.tell_with_sender(msg, self, src);
+    }
+
+    fn topics_msg(&self) -> MediatorMsg {
+        MediatorMsg::Topics {
+            incarnation: self.incarnation,
+            version: self.version,
+            topics: self.subscribers.keys().cloned().collect(),
+        }
+    }
+
+    fn announce(&mut self, _timeout_id: ScheduledTimer) -> Handled {
+        let msg = self.topics_msg();
+        for peer in self.peers.iter() {
+            self.send(peer, msg.clone());
+        }
+        Handled::Ok
+    }
+
+    fn topics_changed(&mut self) {
+        self.version += 1;
+        let msg = self.topics_msg();
+        for peer in self.peers.iter() {
+            self.send(peer, msg.clone());
+        }
+    }
+
+    fn subscribe(&mut self, topic: String, subscriber: ActorPath) {
+        let new_topic = !self.subscribers.contains_key(&topic);
+        let subscribers = self.subscribers.entry(topic).or_default();
+        if !subscribers.contains(&subscriber) {
+            subscribers.push(subscriber.clone());
+            // watching the same path more than once has no additional effect
+            self.ctx.watch(subscriber);
+        }
+        if new_topic {
+            self.topics_changed();
+        }
+    }
+
+    fn unsubscribe(&mut self, topic: &str, subscriber: &ActorPath) {
+        let emptied = match self.subscribers.get_mut(topic) {
+            Some(subscribers) => {
+                subscribers.retain(|s| s != subscriber);
+                subscribers.is_empty()
+            }
+            None => return,
+        };
+        if emptied {
+            self.subscribers.remove(topic);
+            self.topics_changed();
+        }
+        if !self.subscribers.values().any(|s| s.contains(subscriber)) {
+            self.ctx.unwatch(subscriber.clone());
+        }
+    }
+
+    /// Removes `subscriber` from all topics
+    fn remove_subscriber(&mut self, subscriber: &ActorPath) {
+        let before = self.subscribers.len();
+        self.subscribers.retain(|_topic, subscribers| {
+            subscribers.retain(|s| s != subscriber);
+            !subscribers.is_empty()
+        });
+        if self.subscribers.len() != before {
+            self.topics_changed();
+        }
+    }
+
+    fn publish(&mut self, topic: String, publisher: ActorPath, ser_id: SerId, data: Bytes) {
+        let mut remote_systems = 0;
+        for (system, remote) in self.remote.iter() {
+            if remote.topics.contains(&topic) {
+                let msg = MediatorMsg::Publish {
+                    topic: topic.clone(),
+                    publisher: publisher.clone(),
+                    ser_id,
+                    data: data.clone(),
+                };
+                self.send(system, msg);
+                remote_systems += 1;
+            }
+        }
+        let local_subscribers = self.deliver_local(&topic, &publisher, ser_id, &data);
+        if local_subscribers == 0 && remote_systems == 0 {
+            debug!(
+                self.ctx.log(),
+                "Dropping message published to topic {} without subscribers", topic
+            );
+        }
+    }
+
+    /// Delivers a message to the local subscribers of `topic` and returns how many there were
+    fn deliver_local(
+        &self,
+        topic: &str,
+        publisher: &ActorPath,
+        ser_id: SerId,
+        data: &Bytes,
+    ) -> usize {
+        match self.subscribers.get(topic) {
+            Some(subscribers) => {
+                for subscriber in subscribers {
+                    let msg = NetMessage::with_bytes(
+                        ser_id,
+                        publisher.clone(),
+                        subscriber.clone(),
+                        data.clone(),
+                    );
+                    subscriber.forward_with_original_sender(msg, self);
+                }
+                subscribers.len()
+            }
+            None => 0,
+        }
+    }
+
+    fn on_topics(
+        &mut self,
+        system: SystemPath,
+        incarnation: Uuid,
+        version: u64,
+        topics: Vec<String>,
+    ) {
+        if !self.peers.contains(&system) {
+            // not (or no longer) a live member, it will announce itself again once it is up
+            return;
+        }
+        let current = matches!(
+            self.remote.get(&system),
+            Some(remote) if remote.incarnation == incarnation && remote.version >= version
+        );
+        if !current {
+            debug!(
+                self.ctx.log(),
+                "{} has subscribers for {} topic(s)",
+                system,
+                topics.len()
+            );
+            self.remote.insert(
+                system,
+                RemoteTopics {
+                    incarnation,
+                    version,
+                    topics: topics.into_iter().collect(),
+                },
+            );
+        }
+    }
+}
+
+impl Default for PubSubMediator {
+    fn default() -> Self {
+        PubSubMediator::new()
+    }
+}
+
+impl ComponentLifecycle for PubSubMediator {
+    fn on_start(&mut self) -> Handled {
+        if self.config.is_none() {
+            self.config = Some(PubSubConfig::from_config(self.ctx.config()));
+        }
+        let registration = self.ctx.update_own_alias_registration(PUBSUB_ALIAS);
+        self.spawn_local(move |async_self| async move {
+            match registration.await {
+                Ok(Ok(_)) => (),
+                Ok(Err(e)) => error!(
+                    async_self.ctx.log(),
+                    "Could not register pub/sub mediator: {:?}", e
+                ),
+                Err(e) => error!(
+                    async_self.ctx.log(),
+                    "Pub/sub mediator registration was dropped: {:?}", e
+                ),
+            }
+            Handled::Ok
+        });
+        let interval = Duration::from_millis(self.config().get_gossip_interval());
+        self.timer = Some(self.schedule_periodic(interval, interval, Self::announce));
+        Handled::Ok
+    }
+
+    fn on_stop(&mut self) -> Handled {
+        if let Some(timer) = self.timer.take() {
+            self.cancel_timer(timer);
+        }
+        Handled::Ok
+    }
+
+    fn on_kill(&mut self) -> Handled {
+        self.on_stop()
+    }
+}
+
+impl Require<ClusterMembership> for PubSubMediator {
+    fn handle(&mut self, event: MembershipEvent) -> Handled {
+        let own_system = self.ctx.system().system_path();
+        match event {
+            MembershipEvent::Up(system) if system != own_system => {
+                let msg = self.topics_msg();
+                self.send(&system, msg);
+                self.peers.insert(system);
+            }
+            MembershipEvent::Left(system) | MembershipEvent::Down(system) => {
+                self.peers.remove(&system);
+                if self.remote.remove(&system).is_some() {
+                    debug!(
+                        self.ctx.log(),
+                        "Forgetting the topics of {}, which is gone", system
+                    );
+                }
+            }
+            _ => (),
+        }
+        Handled::Ok
+    }
+}
+
+impl Actor for PubSubMediator {
+    type Message = PubSubRequest;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        match msg {
+            PubSubRequest::Subscribe { topic, subscriber } => self.subscribe(topic, subscriber),
+            PubSubRequest::Unsubscribe { topic, subscriber } => {
+                self.unsubscribe(&topic, &subscriber)
+            }
+            PubSubRequest::Publish {
+                topic,
+                publisher,
+                msg,
+            } => self.publish(topic, publisher, msg.ser_id, msg.data),
+        }
+        Handled::Ok
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let sender = msg.sender.clone();
+        match_deser!(msg; {
+            terminated: Terminated [Terminated] => self.remove_subscriber(&terminated.0),
+            mediator_msg: MediatorMsg [MediatorMsg] => match mediator_msg {
+                MediatorMsg::Topics {
+                    incarnation,
+                    version,
+                    topics,
+                } => self.on_topics(sender.system().clone(), incarnation, version, topics),
+                MediatorMsg::Publish {
+                    topic,
+                    publisher,
+                    ser_id,
+                    data,
+                } => {
+                    self.deliver_local(&topic, &publisher, ser_id, &data);
+                }
+            },
+            !Err(e) => warn!(self.ctx.log(), "Invalid pub/sub message: {:?}", e),
+            _ => warn!(self.ctx.log(), "Unexpected message from {}", sender),
+        });
+        Handled::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use hocon::HoconLoader;
+
+    #[test]
+    fn mediator_msg_serequiv() {
+        let publisher: ActorPath = "tcp://127.0.0.1:8080/some/actor".parse().expect("path");
+        let msgs = vec![
+            MediatorMsg::Topics {
+                incarnation: Uuid::new_v4(),
+                version: 3,
+                topics: vec!["news".to_string(), "weather".to_string()],
+            },
+            MediatorMsg::Publish {
+                topic: "news".to_string(),
+                publisher,
+                ser_id: serialisation_ids::U64,
+                data: Bytes::from_static(&[1, 2, 3, 4]),
+            },
+        ];
+        for msg in msgs {
+            let mut buf = BytesMut::with_capacity(msg.size_hint().expect("size hint"));
+            msg.serialise(&mut buf).expect("serialise");
+            let mut bytes = buf.freeze();
+            let res = MediatorMsg::deserialise(&mut bytes).expect("deserialise");
+            assert_eq!(msg, res);
+        }
+    }
+
+    #[test]
+    fn mediator_msg_rejects_oversized_topic_count() {
+        let mut buf = BytesMut::with_capacity(1 + 16 + 8 + 4);
+        buf.put_u8(MediatorMsg::TOPICS);
+        buf.put_u128(Uuid::new_v4().as_u128());
+        buf.put_u64(1);
+        buf.put_u32(u32::MAX);
+        let mut bytes = buf.freeze();
+        assert!(MediatorMsg::deserialise(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn topics_are_versioned_and_pruned() {
+        let system = KompactConfig::default().build().expect("system");
+        let mediator = system.create(PubSubMediator::new);
+        let peer = SystemPath::new(Transport::TCP, "127.0.0.1".parse().unwrap(), 1234);
+        let incarnation = Uuid::new_v4();
+        let news = || vec!["news".to_string()];
+        mediator.on_definition(|m| {
+            m.on_topics(peer.clone(), incarnation, 1, news());
+            assert!(m.remote.is_empty(), "Only live members should be tracked");
+            let _ = m.handle(MembershipEvent::Up(peer.clone()));
+            m.on_topics(peer.clone(), incarnation, 2, news());
+            // stale announcement
+            m.on_topics(peer.clone(), incarnation, 1, Vec::new());
+            assert!(m.remote[&peer].topics.contains("news"));
+            // restarted mediator
+            m.on_topics(peer.clone(), Uuid::new_v4(), 1, Vec::new());
+            assert!(m.remote[&peer].topics.is_empty());
+            let _ = m.handle(MembershipEvent::Down(peer.clone()));
+            assert!(m.remote.is_empty());
+            assert!(m.peers.is_empty());
+        });
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn config_from_hocon() {
+        let hocon = HoconLoader::new()
+            .load_str("pubsub.gossip_interval = 200 ms")
+            .unwrap()
+            .hocon()
+            .unwrap();
+        let config = PubSubConfig::from_config(&hocon);
+        assert_eq!(config.get_gossip_interval(), 200);
+        let hocon = HoconLoader::new().hocon().unwrap();
+        let config = PubSubConfig::from_config(&hocon);
+        assert_eq!(config.get_gossip_interval(), GOSSIP_INTERVAL);
+    }
+}
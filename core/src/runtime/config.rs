@@ -1,8 +1,14 @@
 use super::*;
 
-use crate::messaging::DispatchEnvelope;
+use crate::{
+    failure_detector::FailureDetectorConfig,
+    messaging::DispatchEnvelope,
+    metrics::MetricsRegistry,
+    persistence::{journal::Journal, snapshot::SnapshotStore},
+    tracing::SpanSink,
+};
 use executors::*;
-use std::{fmt, path::PathBuf, rc::Rc};
+use std::{fmt, path::PathBuf, rc::Rc, sync::Arc};
 
 #[derive(Debug, Clone)]
 pub(crate) enum ConfigSource {
@@ -38,6 +44,11 @@ pub struct KompactConfig {
     pub(crate) sc_builder: Rc<SCBuilder>,
     pub(crate) root_logger: Option<KompactLogger>,
     pub(crate) config_sources: Vec<ConfigSource>,
+    pub(crate) metrics: Option<MetricsRegistry>,
+    pub(crate) span_sink: Option<Arc<dyn SpanSink>>,
+    pub(crate) journal: Option<Arc<dyn Journal>>,
+    pub(crate) snapshot_store: Option<Arc<dyn SnapshotStore>>,
+    pub(crate) failure_detector: Option<FailureDetectorConfig>,
 }
 
 impl fmt::Debug for KompactConfig {
@@ -53,7 +64,12 @@ impl fmt::Debug for KompactConfig {
             scheduler_builder=<function>,
             sc_builder=<function>,
             root_logger={:?},
-            config_sources={:?}
+            config_sources={:?},
+            metrics={:?},
+            span_sink={},
+            journal={},
+            snapshot_store={},
+            failure_detector={:?}
         }}",
             self.label,
             self.throughput,
@@ -61,6 +77,23 @@ impl fmt::Debug for KompactConfig {
             self.threads,
             self.root_logger,
             self.config_sources,
+            self.metrics,
+            if self.span_sink.is_some() {
+                "<sink>"
+            } else {
+                "None"
+            },
+            if self.journal.is_some() {
+                "<journal>"
+            } else {
+                "None"
+            },
+            if self.snapshot_store.is_some() {
+                "<store>"
+            } else {
+                "None"
+            },
+            self.failure_detector,
         )
     }
 }
@@ -87,6 +120,11 @@ impl KompactConfig {
             }),
             root_logger: None,
             config_sources: Vec::new(),
+            metrics: None,
+            span_sink: None,
+            journal: None,
+            snapshot_store: None,
+            failure_detector: None,
         }
     }
 
@@ -306,6 +344,67 @@ impl KompactConfig {
         self
     }
 
+    /// Record metrics about the system into `registry`
+    ///
+    /// Without a registry, the system does not record any metrics.
+    /// See the [metrics](crate::metrics) module for what is recorded.
+    pub fn metrics(&mut self, registry: MetricsRegistry) -> &mut Self {
+        self.metrics = Some(registry);
+        self
+    }
+
+    /// Record the spans of traced messages into `sink`
+    ///
+    /// Without a sink, the system still propagates trace contexts, but does not record any spans.
+    /// See the [tracing](crate::tracing) module for details.
+    pub fn tracing(&mut self, sink: Arc<dyn SpanSink>) -> &mut Self {
+        self.span_sink = Some(sink);
+        self
+    }
+
+    /// Use `journal` for all [persistent components](crate::persistence::PersistentComponent) in the system
+    ///
+    /// Components that bring their own journal via [Persistence::with_journal](crate::persistence::Persistence::with_journal)
+    /// use that one instead.
+    pub fn journal(&mut self, journal: Arc<dyn Journal>) -> &mut Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Use `store` for the snapshots of all [snapshot components](crate::persistence::SnapshotComponent) in the system
+    ///
+    /// Components that bring their own store via [Persistence::snapshot_store](crate::persistence::Persistence::snapshot_store)
+    /// use that one instead.
+    /// How many snapshots are kept is configured in the `persistence.snapshots` section
+    /// of the HOCON config, as described in [SnapshotRetention](crate::persistence::snapshot::SnapshotRetention::from_config).
+    pub fn snapshot_store(&mut self, store: Arc<dyn SnapshotStore>) -> &mut Self {
+        self.snapshot_store = Some(store);
+        self
+    }
+
+    /// Run a [FailureDetector](crate::failure_detector::FailureDetector) with the given `config` as a system component
+    ///
+    /// The detector is started and stopped together with the system's dispatcher,
+    /// and can be accessed via [`system.failure_detector()`](KompactSystem::failure_detector)
+    /// in order to connect components to its [FailureDetection](crate::failure_detector::FailureDetection) port.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::prelude::*;
+    ///
+    /// let mut cfg = KompactConfig::default();
+    /// cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+    /// cfg.failure_detector(FailureDetectorConfig::default());
+    /// let system = cfg.build().expect("KompactSystem");
+    /// assert!(system.failure_detector().is_some());
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn failure_detector(&mut self, config: FailureDetectorConfig) -> &mut Self {
+        self.failure_detector = Some(config);
+        self
+    }
+
     /// Load a HOCON config from a file at `path`
     ///
     /// This method can be called multiple times, and the resulting configurations will be merged.
@@ -393,6 +492,11 @@ impl Default for KompactConfig {
             }),
             root_logger: None,
             config_sources: Vec::new(),
+            metrics: None,
+            span_sink: None,
+            journal: None,
+            snapshot_store: None,
+            failure_detector: None,
         }
     }
 }
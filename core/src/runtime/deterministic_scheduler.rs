@@ -0,0 +1,311 @@
+use super::*;
+use crate::utils::SplitMix64;
+use futures::{
+    future::BoxFuture,
+    task::{waker_ref, ArcWake, Context},
+};
+use std::{
+    sync::{Condvar, Mutex, Weak},
+    thread,
+};
+
+/// A [Scheduler](runtime::Scheduler) that runs all components on a single thread in a reproducible order
+///
+/// Whenever more than one component (or future) is ready to run, the next one is picked
+/// pseudo-randomly, based on the `seed` the scheduler was created with.
+/// Thus, given the same seed and the same inputs, a system on this scheduler will always
+/// interleave its messages and events in exactly the same way, which makes
+/// race conditions reproducible. Use different seeds to explore different interleavings.
+///
+/// Inputs from outside the system, e.g. from the test thread, are only reproducible
+/// if they arrive while the system is idle. So always call [run_until_idle](DeterministicScheduler::run_until_idle)
+/// before interacting with the system again. Each such call also resets the random order
+/// to a state derived from the seed and the number of preceding calls, so that nondeterminism
+/// from an earlier phase, such as the system's startup, does not leak into later ones. For timers to be reproducible as well,
+/// pair this scheduler with a [VirtualTimer](crate::timer::VirtualTimer),
+/// for example via the [Simulation](crate::testkit::Simulation) in the testkit.
+///
+/// The number of [threads](KompactConfig::threads) configured for the system is ignored.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{prelude::*, runtime::DeterministicScheduler};
+///
+/// let scheduler = DeterministicScheduler::with_seed(42);
+/// let mut cfg = KompactConfig::default();
+/// cfg.scheduler({
+///     let scheduler = scheduler.clone();
+///     move |_| Box::new(scheduler.clone())
+/// });
+/// let system = cfg.build().expect("system");
+/// scheduler.run_until_idle();
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone)]
+pub struct DeterministicScheduler {
+    seed: u64,
+    shared: Arc<Shared>,
+    worker: Arc<Mutex<Option<thread::JoinHandle<()>>>>,
+    worker_id: thread::ThreadId,
+}
+
+impl DeterministicScheduler {
+    /// Create a new deterministic scheduler, picking components in an order derived from `seed`
+    pub fn with_seed(seed: u64) -> DeterministicScheduler {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                ready: Vec::new(),
+                busy: false,
+                stopped: false,
+                epoch: 0,
+                rng: SplitMix64::for_epoch(seed, 0),
+            }),
+            work_available: Condvar::new(),
+            idle: Condvar::new(),
+        });
+        let worker_shared = shared.clone();
+        let handle = thread::Builder::new()
+            .name(format!("deterministic-scheduler-{}", seed))
+            .spawn(move || worker_shared.run())
+            .expect("Could not start deterministic scheduler thread");
+        let worker_id = handle.thread().id();
+        DeterministicScheduler {
+            seed,
+            shared,
+            worker: Arc::new(Mutex::new(Some(handle))),
+            worker_id,
+        }
+    }
+
+    /// The seed this scheduler was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns `true` if no component or future is currently running or waiting to run
+    pub fn is_idle(&self) -> bool {
+        let state = self.shared.lock();
+        state.is_idle()
+    }
+
+    /// Block the current thread until no component or future is running or waiting to run anymore
+    ///
+    /// Afterwards, the random order is reset as described [above](DeterministicScheduler).
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from within a component running on this scheduler,
+    /// as it would otherwise wait for itself forever.
+    pub fn run_until_idle(&self) -> () {
+        assert_ne!(
+            thread::current().id(),
+            self.worker_id,
+            "run_until_idle must not be called from within the scheduler!"
+        );
+        let mut state = self.shared.lock();
+        while !(state.is_idle() || state.stopped) {
+            state = self
+                .shared
+                .idle
+                .wait(state)
+                .expect("Deterministic scheduler lock poisoned");
+        }
+        state.epoch += 1;
+        state.rng = SplitMix64::for_epoch(self.seed, state.epoch);
+    }
+
+    fn stop(&self) -> () {
+        let remaining = {
+            let mut state = self.shared.lock();
+            state.stopped = true;
+            self.shared.work_available.notify_all();
+            self.shared.idle.notify_all();
+            std::mem::take(&mut state.ready)
+        };
+        // break reference cycles from components to their system and back to this scheduler,
+        // but outside the lock, in case dropping them schedules anything
+        drop(remaining);
+    }
+}
+
+impl Scheduler for DeterministicScheduler {
+    fn schedule(&self, c: Arc<dyn CoreContainer>) -> () {
+        self.shared.push(Task::Component(c));
+    }
+
+    fn shutdown_async(&self) -> () {
+        self.stop();
+    }
+
+    fn shutdown(&self) -> Result<(), String> {
+        self.stop();
+        if thread::current().id() == self.worker_id {
+            // can't join ourselves, the worker will exit after the current task anyway
+            return Ok(());
+        }
+        let handle = self
+            .worker
+            .lock()
+            .map_err(|_| "Deterministic scheduler lock poisoned".to_string())?
+            .take();
+        match handle {
+            Some(handle) => handle
+                .join()
+                .map_err(|_| "Deterministic scheduler thread panicked".to_string()),
+            None => Ok(()), // already shut down
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Scheduler> {
+        Box::new(self.clone())
+    }
+
+    fn poison(&self) -> () {
+        self.stop();
+    }
+
+    fn spawn(&self, future: BoxFuture<'static, ()>) -> () {
+        let task = Arc::new(FutureTask {
+            future: Mutex::new(Some(future)),
+            shared: Arc::downgrade(&self.shared),
+        });
+        self.shared.push(Task::Future(task));
+    }
+}
+
+enum Task {
+    Component(Arc<dyn CoreContainer>),
+    Future(Arc<FutureTask>),
+}
+
+struct State {
+    ready: Vec<Task>,
+    busy: bool,
+    stopped: bool,
+    epoch: u64,
+    rng: SplitMix64,
+}
+
+impl State {
+    fn is_idle(&self) -> bool {
+        !self.busy && self.ready.is_empty()
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    work_available: Condvar,
+    idle: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("Deterministic scheduler lock poisoned")
+    }
+
+    fn push(&self, task: Task) -> () {
+        let mut state = self.lock();
+        if !state.stopped {
+            state.ready.push(task);
+            self.work_available.notify_one();
+        }
+    }
+
+    fn next_task(&self) -> Option<Task> {
+        let mut state = self.lock();
+        state.busy = false;
+        loop {
+            if state.stopped {
+                self.idle.notify_all();
+                return None;
+            }
+            if !state.ready.is_empty() {
+                let len = state.ready.len();
+                let index = state.rng.next_below(len);
+                // `swap_remove` keeps the order of the remaining tasks a function of the seed
+                let task = state.ready.swap_remove(index);
+                state.busy = true;
+                return Some(task);
+            }
+            self.idle.notify_all();
+            state = self
+                .work_available
+                .wait(state)
+                .expect("Deterministic scheduler lock poisoned");
+        }
+    }
+
+    fn run(&self) -> () {
+        while let Some(task) = self.next_task() {
+            match task {
+                Task::Component(c) => self.execute(c),
+                Task::Future(f) => f.poll(),
+            }
+        }
+    }
+
+    fn execute(&self, c: Arc<dyn CoreContainer>) -> () {
+        loop {
+            match c.execute() {
+                SchedulingDecision::Schedule => {
+                    self.push(Task::Component(c));
+                    return;
+                }
+                SchedulingDecision::Resume => (), // run again right away
+                _ => return,
+            }
+        }
+    }
+}
+
+struct FutureTask {
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+    shared: Weak<Shared>,
+}
+
+impl FutureTask {
+    fn poll(self: Arc<Self>) -> () {
+        let mut guard = self.future.lock().expect("Future lock poisoned");
+        // duplicate wake-ups may find the future already completed
+        if let Some(mut future) = guard.take() {
+            let waker = waker_ref(&self);
+            let mut cx = Context::from_waker(&waker);
+            if future.as_mut().poll(&mut cx).is_pending() {
+                *guard = Some(future);
+            }
+        }
+    }
+}
+
+impl ArcWake for FutureTask {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        if let Some(shared) = arc_self.shared.upgrade() {
+            shared.push(Task::Future(arc_self.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_runs_futures() {
+        let scheduler = DeterministicScheduler::with_seed(1);
+        let (promise, future) = utils::promise();
+        scheduler.spawn(Box::pin(async move {
+            promise.fulfil(5u8).expect("fulfil");
+        }));
+        scheduler.run_until_idle();
+        assert_eq!(
+            5u8,
+            future
+                .wait_timeout(std::time::Duration::from_millis(100))
+                .expect("result")
+        );
+        scheduler.shutdown().expect("shutdown");
+    }
+}
@@ -11,11 +11,13 @@ use std::{
 };
 
 mod config;
+mod deterministic_scheduler;
 mod lifecycle;
 mod scheduler;
 mod system;
 
 pub use config::*;
+pub use deterministic_scheduler::*;
 pub use scheduler::*;
 pub use system::*;
 
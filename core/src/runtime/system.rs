@@ -3,6 +3,7 @@ use super::*;
 #[cfg(all(nightly, feature = "type_erasure"))]
 use crate::utils::erased::CreateErased;
 use crate::{
+    failure_detector::FailureDetector,
     messaging::{
         DispatchEnvelope,
         MsgEnvelope,
@@ -11,9 +12,18 @@ use crate::{
         RegistrationError,
         RegistrationResult,
     },
+    metrics::{instruments::SystemMetrics, MetricsRegistry},
+    persistence::{journal::Journal, snapshot::SnapshotStore},
     routing::groups::StorePolicy,
-    supervision::{ComponentSupervisor, ListenEvent, SupervisionPort, SupervisorMsg},
+    supervision::{
+        ComponentSupervisor,
+        ListenEvent,
+        SupervisionEvent,
+        SupervisionPort,
+        SupervisorMsg,
+    },
     timer::timer_manager::{CanCancelTimers, TimerRefFactory},
+    tracing::{self, ActiveSpan, SpanSink},
 };
 use hocon::{Hocon, HoconLoader};
 use oncemutex::{OnceMutex, OnceMutexGuard};
@@ -78,9 +88,10 @@ impl KompactSystem {
     pub(crate) fn try_new(conf: KompactConfig) -> Result<Self, KompactError> {
         let scheduler = (*conf.scheduler_builder)(conf.threads);
         let sc_builder = conf.sc_builder.clone();
+        let fd_config = conf.failure_detector.clone();
 
         let config = Self::load_config(&conf)?;
-        let runtime = Arc::new(KompactRuntime::new(conf));
+        let runtime = Arc::new(KompactRuntime::new(conf, &config));
         let sys = KompactSystem {
             inner: runtime,
             config: Arc::new(config),
@@ -90,7 +101,8 @@ impl KompactSystem {
         let (disp_prom, disp_f) = utils::promise();
         let system_components = (*sc_builder)(&sys, dead_prom, disp_prom);
         let supervisor = sys.create_unsupervised(ComponentSupervisor::new);
-        let ic = InternalComponents::new(supervisor, system_components);
+        let failure_detector = fd_config.map(|config| sys.create_unsupervised(config.build()));
+        let ic = InternalComponents::new(supervisor, system_components, failure_detector);
         sys.inner.set_internal_components(ic);
         sys.inner.start_internal_components(&sys);
         let timeout = std::time::Duration::from_millis(50);
@@ -129,6 +141,90 @@ impl KompactSystem {
         self.scheduler.schedule(c);
     }
 
+    /// The label of this system, as set via [KompactConfig::label](KompactConfig::label)
+    pub fn label(&self) -> &str {
+        &self.inner.label
+    }
+
+    /// Get a reference to the registry this system records its metrics into, if any
+    ///
+    /// See [KompactConfig::metrics](KompactConfig::metrics).
+    pub fn metrics(&self) -> Option<&MetricsRegistry> {
+        self.inner.metrics.as_ref().map(SystemMetrics::registry)
+    }
+
+    pub(crate) fn system_metrics(&self) -> Option<&SystemMetrics> {
+        self.inner.metrics.as_ref()
+    }
+
+    /// Get a reference to the sink this system records its spans into, if any
+    ///
+    /// See [KompactConfig::tracing](KompactConfig::tracing).
+    pub fn span_sink(&self) -> Option<&Arc<dyn SpanSink>> {
+        self.inner.span_sink.as_ref()
+    }
+
+    /// Get a reference to the journal shared by this system's persistent components, if any
+    ///
+    /// See [KompactConfig::journal](KompactConfig::journal).
+    pub fn journal(&self) -> Option<&Arc<dyn Journal>> {
+        self.inner.journal.as_ref()
+    }
+
+    /// Get a reference to the snapshot store shared by this system's snapshot components, if any
+    ///
+    /// See [KompactConfig::snapshot_store](KompactConfig::snapshot_store).
+    pub fn snapshot_store(&self) -> Option<&Arc<dyn SnapshotStore>> {
+        self.inner.snapshot_store.as_ref()
+    }
+
+    /// Get the failure detector that runs as part of this system's components, if any
+    ///
+    /// See [KompactConfig::failure_detector](KompactConfig::failure_detector).
+    pub fn failure_detector(&self) -> Option<&Arc<Component<FailureDetector>>> {
+        self.inner
+            .get_internal_components()
+            .failure_detector
+            .as_ref()
+    }
+
+    /// Run `f` in a new span called `name`
+    ///
+    /// The span is a child of the [current span](crate::tracing::current_context), if any,
+    /// or else the root of a new trace.
+    /// Messages sent from within `f` carry the span's context,
+    /// so that their handling shows up as part of the same trace.
+    /// The span itself is recorded into the system's [span sink](KompactConfig::tracing), if it has one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use kompact::{prelude::*, tracing};
+    ///
+    /// # let system = KompactConfig::default().build().expect("system");
+    /// let context = system.in_span("request", || tracing::current_context());
+    /// assert!(context.is_some());
+    /// # system.shutdown().expect("shutdown");
+    /// ```
+    pub fn in_span<R>(&self, name: &str, f: impl FnOnce() -> R) -> R {
+        match self.inner.span_sink {
+            Some(ref sink) => {
+                let attributes = vec![("kompact.system".to_string(), self.label().to_string())];
+                let span = ActiveSpan::start_root(sink.as_ref(), name, attributes);
+                let res = tracing::with_context(span.context(), f);
+                span.end();
+                res
+            }
+            None => {
+                let context = match tracing::current_context() {
+                    Some(parent) => parent.child(),
+                    None => tracing::TraceContext::new_root(),
+                };
+                tracing::with_context(context, f)
+            }
+        }
+    }
+
     /// Get a reference to the system-wide Kompact logger
     ///
     /// # Example
@@ -818,6 +914,16 @@ impl KompactSystem {
         f
     }
 
+    /// Subscribe `subscriber` to all [supervision events](SupervisionEvent) of this system
+    ///
+    /// The supervisor publishes an event, for example, when a component
+    /// exceeds the restart limit of its [RestartStrategy](crate::prelude::RestartStrategy).
+    pub fn subscribe_supervision_events(&self, subscriber: ActorRef<SupervisionEvent>) -> () {
+        self.inner.assert_active();
+        self.supervision_port()
+            .enqueue(SupervisorMsg::Subscribe(subscriber));
+    }
+
     /// Trigger an indication `event` on a shared `port`
     ///
     /// This can be used to send events to component without connecting a channel.
@@ -857,6 +963,14 @@ impl KompactSystem {
         self.inner.max_messages
     }
 
+    /// Return the mailbox config new components start out with
+    ///
+    /// This is read once from the `mailbox` section of the system's config,
+    /// as described in [MailboxConfig](crate::prelude::MailboxConfig).
+    pub fn mailbox_config(&self) -> MailboxConfig {
+        self.inner.mailbox
+    }
+
     /// Wait for the Kompact system to be terminated
     ///
     /// Suspends this thread until the system is terminated
@@ -1039,6 +1153,121 @@ impl TimerRefFactory for KompactSystem {
     }
 }
 
+impl SystemHandle for KompactSystem {
+    fn create<C, F>(&self, f: F) -> Arc<Component<C>>
+    where
+        F: FnOnce() -> C,
+        C: ComponentDefinition + 'static,
+    {
+        KompactSystem::create(self, f)
+    }
+
+    #[cfg(all(nightly, feature = "type_erasure"))]
+    fn create_erased<M: MessageBounds>(
+        &self,
+        a: Box<dyn CreateErased<M>>,
+    ) -> Arc<dyn AbstractComponent<Message = M>> {
+        KompactSystem::create_erased(self, a)
+    }
+
+    fn register(&self, c: &Arc<impl AbstractComponent + ?Sized>) -> KFuture<RegistrationResult> {
+        KompactSystem::register(self, c)
+    }
+
+    fn create_and_register<C, F>(&self, f: F) -> (Arc<Component<C>>, KFuture<RegistrationResult>)
+    where
+        F: FnOnce() -> C,
+        C: ComponentDefinition + 'static,
+    {
+        KompactSystem::create_and_register(self, f)
+    }
+
+    fn register_by_alias<A>(
+        &self,
+        c: &Arc<impl AbstractComponent + ?Sized>,
+        alias: A,
+    ) -> KFuture<RegistrationResult>
+    where
+        A: Into<String>,
+    {
+        KompactSystem::register_by_alias(self, c, alias)
+    }
+
+    fn update_alias_registration<A>(
+        &self,
+        c: &Arc<impl AbstractComponent + ?Sized>,
+        alias: A,
+    ) -> KFuture<RegistrationResult>
+    where
+        A: Into<String>,
+    {
+        KompactSystem::update_alias_registration(self, c, alias)
+    }
+
+    fn set_routing_policy<P>(
+        &self,
+        policy: P,
+        path: &str,
+        update: bool,
+    ) -> KFuture<RegistrationResult>
+    where
+        P: Into<StorePolicy>,
+    {
+        KompactSystem::set_routing_policy(self, policy, path, update)
+    }
+
+    fn start(&self, c: &Arc<impl AbstractComponent + ?Sized>) -> () {
+        KompactSystem::start(self, c)
+    }
+
+    fn start_notify(&self, c: &Arc<impl AbstractComponent + ?Sized>) -> KFuture<()> {
+        KompactSystem::start_notify(self, c)
+    }
+
+    fn stop(&self, c: &Arc<impl AbstractComponent + ?Sized>) -> () {
+        KompactSystem::stop(self, c)
+    }
+
+    fn stop_notify(&self, c: &Arc<impl AbstractComponent + ?Sized>) -> KFuture<()> {
+        KompactSystem::stop_notify(self, c)
+    }
+
+    fn kill(&self, c: Arc<impl AbstractComponent + ?Sized>) -> () {
+        KompactSystem::kill(self, c)
+    }
+
+    fn kill_notify(&self, c: Arc<impl AbstractComponent + ?Sized>) -> KFuture<()> {
+        KompactSystem::kill_notify(self, c)
+    }
+
+    fn throughput(&self) -> usize {
+        KompactSystem::throughput(self)
+    }
+
+    fn max_messages(&self) -> usize {
+        KompactSystem::max_messages(self)
+    }
+
+    fn shutdown_async(&self) -> () {
+        KompactSystem::shutdown_async(self)
+    }
+
+    fn system_path(&self) -> SystemPath {
+        KompactSystem::system_path(self)
+    }
+
+    fn deadletter_ref(&self) -> ActorRef<Never> {
+        self.actor_ref()
+    }
+
+    fn spawn<R: Send + 'static>(
+        &self,
+        future: impl futures::Future<Output = R> + 'static + Send,
+    ) -> JoinHandle<R> {
+        KompactSystem::spawn(self, future)
+    }
+}
+
 /// A limited version of a [KompactSystem](KompactSystem)
 ///
 /// This is meant for use from within components, where blocking APIs
@@ -1558,24 +1787,30 @@ struct InternalComponents {
     supervisor: Arc<Component<ComponentSupervisor>>,
     supervision_port: ProvidedRef<SupervisionPort>,
     system_components: Box<dyn SystemComponents>,
+    failure_detector: Option<Arc<Component<FailureDetector>>>,
 }
 
 impl InternalComponents {
     fn new(
         supervisor: Arc<Component<ComponentSupervisor>>,
         system_components: Box<dyn SystemComponents>,
+        failure_detector: Option<Arc<Component<FailureDetector>>>,
     ) -> InternalComponents {
         let supervision_port = supervisor.on_definition(|s| s.supervision.share());
         InternalComponents {
             supervisor,
             supervision_port,
             system_components,
+            failure_detector,
         }
     }
 
     fn start(&self, system: &KompactSystem) -> () {
         self.system_components.start(system);
         system.start(&self.supervisor);
+        if let Some(ref detector) = self.failure_detector {
+            system.start(detector);
+        }
     }
 
     fn deadletter_ref(&self) -> ActorRef<Never> {
@@ -1599,6 +1834,10 @@ impl InternalComponents {
         self.supervision_port
             .enqueue(SupervisorMsg::Shutdown(Arc::new(Mutex::new(p))));
         f.wait();
+        if let Some(ref detector) = self.failure_detector {
+            system.kill(detector.clone());
+            detector.wait_ended();
+        }
         self.system_components.stop(system);
     }
 
@@ -1611,26 +1850,40 @@ struct KompactRuntime {
     label: String,
     throughput: usize,
     max_messages: usize,
+    mailbox: MailboxConfig,
     timer: Box<dyn TimerComponent>,
     internal_components: OnceMutex<Option<InternalComponents>>,
     logger: KompactLogger,
+    metrics: Option<SystemMetrics>,
+    span_sink: Option<Arc<dyn SpanSink>>,
+    journal: Option<Arc<dyn Journal>>,
+    snapshot_store: Option<Arc<dyn SnapshotStore>>,
     state: AtomicUsize,
 }
 
 impl KompactRuntime {
-    fn new(conf: KompactConfig) -> Self {
+    fn new(conf: KompactConfig, config: &Hocon) -> Self {
         let mm = conf.max_messages();
         let logger = match conf.root_logger {
             Some(log) => log.new(o!("system" => conf.label.clone())),
             None => default_logger().new(o!("system" => conf.label.clone())),
         };
+        let label = conf.label.clone();
+        let metrics = conf
+            .metrics
+            .map(|registry| SystemMetrics::new(registry, label));
         KompactRuntime {
             label: conf.label,
             throughput: conf.throughput,
             max_messages: mm,
+            mailbox: MailboxConfig::from_config(config),
             timer: (conf.timer_builder)(),
             internal_components: OnceMutex::new(None),
             logger,
+            metrics,
+            span_sink: conf.span_sink,
+            journal: conf.journal,
+            snapshot_store: conf.snapshot_store,
             state: lifecycle::initial_state(),
         }
     }
@@ -1773,6 +2026,9 @@ impl KompactRuntime {
             None => panic!("KompactRuntime was not initialised at shutdown!"),
         }
         let res = self.timer.shutdown();
+        if let Some(ref sink) = self.span_sink {
+            sink.flush();
+        }
         lifecycle::set_destroyed(self.state());
         res
     }
@@ -36,6 +36,30 @@ pub mod serialisation_ids {
     /// Id for a `()` (unit type) serialiser.
     pub const UNIT: SerId = 8;
 
+    /// Id for a [Terminated](crate::prelude::Terminated) notification.
+    pub const TERMINATED: SerId = 9;
+
+    /// Id for the death-watch protocol between dispatchers.
+    pub const DEATH_WATCH: SerId = 10;
+
+    /// Id for the heartbeat protocol between failure detectors.
+    pub const HEARTBEAT: SerId = 11;
+
+    /// Id for the gossip protocol between membership components.
+    pub const MEMBERSHIP: SerId = 12;
+
+    /// Id for a [TraceContext](crate::tracing::TraceContext).
+    pub const TRACE_CONTEXT: SerId = 13;
+
+    /// Id for the reliable delivery protocol between dispatchers.
+    pub const RELIABLE_DELIVERY: SerId = 14;
+
+    /// Id for the protocol between pub/sub mediators.
+    pub const PUBSUB: SerId = 15;
+
+    /// Id for the protocol between shard regions.
+    pub const SHARDING: SerId = 16;
+
     /// Id for the Serde serialiser
     pub const SERDE: SerId = 19;
 
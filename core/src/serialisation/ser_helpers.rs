@@ -4,14 +4,21 @@
 //! and can be used for custom network implementations.
 use crate::{
     actors::ActorPath,
-    messaging::{HeapOrSer, NetData, NetMessage, Serialised},
+    messaging::{
+        framing::{get_trace_context, put_trace_context, trace_context_size},
+        HeapOrSer,
+        NetData,
+        NetMessage,
+        Serialised,
+    },
     net::{
         buffers::{BufferEncoder, ChunkLease, ChunkRef},
         frames::{FrameHead, FrameType, FRAME_HEAD_LEN},
     },
     serialisation::*,
+    tracing::TraceContext,
 };
-use bytes::{buf::BufMut, BytesMut};
+use bytes::{buf::BufMut, Bytes, BytesMut};
 
 /// Creates a new [NetMessage](NetMessage) from the provided fields
 ///
@@ -93,19 +100,41 @@ where
 ///         path        `[u8; 16]` if [unique path](ActorPath::Unique), else a length-prefixed UTF-8 encoded string
 ///     destination     ActorPath
 ///         (see above for specific format)
+///     trace_flag      u8, 1 if a trace context follows, 0 otherwise
+///     trace_context   `[u8; 24]` trace id and span id, only if `trace_flag` is 1
 ///     ser_id          u64
 ///     message         raw bytes
+///
+/// The message is sent without a trace context,
+/// use [serialise_msg_with_trace](serialise_msg_with_trace) to attach one.
 pub fn serialise_msg<B>(
     src: &ActorPath,
     dst: &ActorPath,
     msg: &B,
     buf: &mut BufferEncoder,
 ) -> Result<ChunkLease, SerError>
+where
+    B: Serialisable + ?Sized,
+{
+    serialise_msg_with_trace(src, dst, None, msg, buf)
+}
+
+/// Serialises the provided actor paths, trace context and message
+///
+/// This is the same as [serialise_msg](serialise_msg), but puts `trace_context`
+/// into the frame, so that the receiver continues the trace.
+pub fn serialise_msg_with_trace<B>(
+    src: &ActorPath,
+    dst: &ActorPath,
+    trace_context: Option<&TraceContext>,
+    msg: &B,
+    buf: &mut BufferEncoder,
+) -> Result<ChunkLease, SerError>
 where
     B: Serialisable + ?Sized,
 {
     // Check size hint and try to reserve space to avoid chaining
-    let mut reserve_size = 0;
+    let mut reserve_size = trace_context_size(trace_context);
     if let Some(hint) = msg.size_hint() {
         reserve_size += hint;
     }
@@ -122,6 +151,7 @@ where
 
     src.serialise(buf)?; // src
     dst.serialise(buf)?; // dst
+    put_trace_context(trace_context, buf)?; // trace context
     buf.put_ser_id(msg.ser_id()); // ser_id
     Serialisable::serialise(msg, buf)?; // data
     match buf.get_chunk_lease() {
@@ -160,16 +190,35 @@ where
 
 /// Serialises message- and frame-headers and appends the `content`
 /// [ChunkRef](net::buffers::ChunkRef) returning a complete network-message as a `ChunkRef`.
+///
+/// The message is sent without a trace context,
+/// use [serialise_msg_with_preserialised_with_trace](serialise_msg_with_preserialised_with_trace)
+/// to attach one.
 pub fn serialise_msg_with_preserialised(
     src: &ActorPath,
     dst: &ActorPath,
     content: ChunkRef,
     buf: &mut BufferEncoder,
+) -> Result<ChunkRef, SerError> {
+    serialise_msg_with_preserialised_with_trace(src, dst, None, content, buf)
+}
+
+/// Serialises message- and frame-headers including `trace_context` and appends the `content`
+///
+/// This is the same as [serialise_msg_with_preserialised](serialise_msg_with_preserialised),
+/// but puts `trace_context` into the frame, so that the receiver continues the trace.
+pub fn serialise_msg_with_preserialised_with_trace(
+    src: &ActorPath,
+    dst: &ActorPath,
+    trace_context: Option<&TraceContext>,
+    content: ChunkRef,
+    buf: &mut BufferEncoder,
 ) -> Result<ChunkRef, SerError> {
     // Reserve space for the header:
     buf.pad(FRAME_HEAD_LEN as usize);
     src.serialise(buf)?; // src
     dst.serialise(buf)?; // dst
+    put_trace_context(trace_context, buf)?; // trace context
     if let Some(mut header) = buf.get_chunk_lease() {
         let len = header.capacity() + content.capacity() - FRAME_HEAD_LEN as usize;
         header.insert_head(FrameHead::new(FrameType::Data, len));
@@ -188,6 +237,7 @@ pub fn embed_msg(msg: NetMessage, buf: &mut BufferEncoder) -> Result<ChunkLease,
 
     msg.sender.serialise(buf)?; // src
     msg.receiver.serialise(buf)?; // dst
+    put_trace_context(msg.trace_context.as_deref(), buf)?; // trace context
     let NetData { ser_id, data } = msg.data;
     buf.put_ser_id(ser_id); // ser_id
     match data {
@@ -230,9 +280,11 @@ pub fn deserialise_chunk_lease(mut buffer: ChunkLease) -> Result<NetMessage, Ser
 
     let src = ActorPath::deserialise(&mut buffer)?;
     let dst = ActorPath::deserialise(&mut buffer)?;
+    let trace_context = get_trace_context(&mut buffer)?;
     let ser_id = buffer.get_ser_id();
 
-    let envelope = NetMessage::with_chunk_ref(ser_id, src, dst, buffer.into_chunk_ref());
+    let envelope = NetMessage::with_chunk_ref(ser_id, src, dst, buffer.into_chunk_ref())
+        .with_trace_context(trace_context);
 
     Ok(envelope)
 }
@@ -248,9 +300,26 @@ pub fn deserialise_chunk_ref(mut buffer: ChunkRef) -> Result<NetMessage, SerErro
 
     let src = ActorPath::deserialise(&mut buffer)?;
     let dst = ActorPath::deserialise(&mut buffer)?;
+    let trace_context = get_trace_context(&mut buffer)?;
+    let ser_id = buffer.get_ser_id();
+
+    let envelope =
+        NetMessage::with_chunk_ref(ser_id, src, dst, buffer).with_trace_context(trace_context);
+
+    Ok(envelope)
+}
+
+/// Extracts a [NetMessage](NetMessage) from the provided bytes
+///
+/// This expects the format from [serialise_msg](serialise_msg), without the frame head.
+pub fn deserialise_bytes(mut buffer: Bytes) -> Result<NetMessage, SerError> {
+    let src = ActorPath::deserialise(&mut buffer)?;
+    let dst = ActorPath::deserialise(&mut buffer)?;
+    let trace_context = get_trace_context(&mut buffer)?;
     let ser_id = buffer.get_ser_id();
 
-    let envelope = NetMessage::with_chunk_ref(ser_id, src, dst, buffer);
+    let envelope =
+        NetMessage::with_bytes(ser_id, src, dst, buffer).with_trace_context(trace_context);
 
     Ok(envelope)
 }
@@ -0,0 +1,1010 @@
+//! Sharding of entity actors across the systems of a cluster.
+//!
+//! Entities are actors identified by a string id, such as accounts or devices, of which there
+//! are too many to place by hand. Instead, every system in a cluster runs a
+//! [ShardRegion](ShardRegion) for each type of entity, and all messages for an entity are sent
+//! to the local region with a [ShardEnvelope](ShardEnvelope). The region maps the entity id to
+//! one of a fixed number of *shards*, and routes the message to the region of the system that
+//! owns this shard, which creates the entity on its first message and delivers the message to it.
+//!
+//! Which system owns which shard is decided by a shard coordinator. The region on the lowest
+//! member that is `Up` acts as coordinator, according to the events of a
+//! [Membership](crate::membership::Membership) component that each region is connected to via
+//! the [ClusterMembership](ClusterMembership) port. All other regions register with it, and it
+//! sends an allocation of shards to every registered region whenever it changes.
+//! Allocations are rebalanced whenever a region registers or its member is `Down` or has `Left`,
+//! moving as few shards as possible such that no region owns more than one shard more than any
+//! other. When a shard moves away from a system, its region kills all entities of that shard.
+//! The entities are recreated on their new system with the next message they receive,
+//! so their state is lost unless they are [persistent](crate::persistence).
+//!
+//! Should the coordinator fail, the next lowest member takes over, starting from the last
+//! allocation it received. Messages that reach a region before it received any allocation are
+//! kept until it does.
+//!
+//! # Configuration
+//!
+//! Regions read their settings from the `sharding` section of the system's HOCON config:
+//!
+//! ```hocon
+//! sharding {
+//!     number_of_shards = 100
+//!     retry_interval = 1 s
+//! }
+//! ```
+//!
+//! The number of shards used by all regions of one type is decided by the coordinator,
+//! but it should nevertheless be the same on every system.
+
+use super::prelude::*;
+use crate::serialisation::serialisation_ids;
+use bytes::Bytes;
+use hocon::Hocon;
+use rustc_hash::FxHashMap;
+use std::{collections::BTreeSet, sync::Arc, time::Duration};
+
+/// The alias prefix every [ShardRegion](ShardRegion) registers itself under
+///
+/// A region registers as `$sharding/<type name>`.
+pub const SHARDING_ALIAS: &str = "$sharding";
+
+// Default values for sharding config.
+const NUMBER_OF_SHARDS: u32 = 100;
+const RETRY_INTERVAL: u64 = 1000;
+
+/// How often a message is passed on between regions that disagree about the owner of its shard
+const MAX_FORWARDS: u8 = 3;
+
+/// Configuration for a [ShardRegion](ShardRegion)
+///
+/// All durations are given in milliseconds.
+#[derive(Clone, Debug)]
+pub struct ShardingConfig {
+    number_of_shards: u32,
+    retry_interval: u64,
+}
+
+impl ShardingConfig {
+    /// Reads a configuration from the `sharding` section of the given `config`
+    ///
+    /// Returns the same values as [ShardingConfig::default](ShardingConfig::default)
+    /// for all keys that are missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of shards is not positive.
+    pub fn from_config(config: &Hocon) -> Self {
+        let section = &config["sharding"];
+        let mut sharding_config = ShardingConfig::default();
+        if let Some(shards) = section["number_of_shards"].as_i64() {
+            assert!(shards > 0, "Invalid number of shards: {}", shards);
+            sharding_config.number_of_shards = shards as u32;
+        }
+        if let Some(interval) = section["retry_interval"].as_duration() {
+            sharding_config.retry_interval = interval.as_millis() as u64;
+        }
+        sharding_config
+    }
+
+    /// Sets the number of shards entities are divided into
+    ///
+    /// This should be considerably larger than the number of systems in the cluster,
+    /// so that shards can be spread evenly.
+    ///
+    /// Default value is 100.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is 0.
+    pub fn set_number_of_shards(&mut self, shards: u32) {
+        assert!(shards > 0, "Invalid number of shards: {}", shards);
+        self.number_of_shards = shards;
+    }
+
+    /// Returns the number of shards entities are divided into
+    pub fn get_number_of_shards(&self) -> u32 {
+        self.number_of_shards
+    }
+
+    /// Sets how often a region registers with the coordinator until it is allocated shards,
+    /// and how often the coordinator resends its allocation to all regions
+    ///
+    /// Default value is 1000 ms.
+    pub fn set_retry_interval(&mut self, milliseconds: u64) {
+        self.retry_interval = milliseconds;
+    }
+
+    /// Returns how often registrations and allocations are resent
+    pub fn get_retry_interval(&self) -> u64 {
+        self.retry_interval
+    }
+}
+
+impl Default for ShardingConfig {
+    fn default() -> Self {
+        ShardingConfig {
+            number_of_shards: NUMBER_OF_SHARDS,
+            retry_interval: RETRY_INTERVAL,
+        }
+    }
+}
+
+/// A message for the entity with the id `entity_id`, to be routed by a [ShardRegion](ShardRegion)
+#[derive(Debug)]
+pub struct ShardEnvelope {
+    /// The id of the entity
+    pub entity_id: String,
+    /// The sender of the message, as seen by the entity
+    pub sender: ActorPath,
+    /// The serialised message
+    pub msg: Serialised,
+}
+
+impl ShardEnvelope {
+    /// An envelope for delivering `msg` from `sender` to the entity with the id `entity_id`
+    ///
+    /// Since the entity may live on another system, the message is serialised right away,
+    /// which fails if it doesn't give a [size hint](Serialisable::size_hint).
+    pub fn new<I, M>(entity_id: I, sender: ActorPath, msg: &M) -> Result<Self, SerError>
+    where
+        I: Into<String>,
+        M: Serialisable,
+    {
+        let msg = crate::serialisation::ser_helpers::serialise_to_serialised(msg)?;
+        Ok(ShardEnvelope {
+            entity_id: entity_id.into(),
+            sender,
+            msg,
+        })
+    }
+}
+
+/// Maps `entity_id` to one of `number_of_shards` shards
+fn shard_of(entity_id: &str, number_of_shards: usize) -> usize {
+    // FNV-1a, which unlike the std hashers is guaranteed to give the same result on every system
+    let hash = entity_id
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    (hash % number_of_shards as u64) as usize
+}
+
+/// An assignment of every shard to the region of one system
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Allocation {
+    /// Increased by the coordinator with every change
+    version: u64,
+    /// All regions registered with the coordinator, in order
+    regions: Vec<SystemPath>,
+    /// The index into `regions` of the owner of each shard
+    owners: Vec<u32>,
+}
+
+impl Allocation {
+    fn shard_owner(&self, shard: usize) -> Option<&SystemPath> {
+        self.owners
+            .get(shard)
+            .and_then(|region| self.regions.get(*region as usize))
+    }
+
+    fn owner(&self, entity_id: &str) -> Option<&SystemPath> {
+        if self.owners.is_empty() {
+            None
+        } else {
+            self.shard_owner(shard_of(entity_id, self.owners.len()))
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.regions
+            .iter()
+            .try_fold(8 + 4 + 4 + 4 * self.owners.len(), |acc, region| {
+                region.size_hint().map(|size| acc + size)
+            })
+    }
+
+    fn serialise(&self, buf: &mut dyn BufMut) -> Result<(), SerError> {
+        buf.put_u64(self.version);
+        buf.put_u32(self.regions.len() as u32);
+        for region in self.regions.iter() {
+            region.serialise(buf)?;
+        }
+        buf.put_u32(self.owners.len() as u32);
+        for owner in self.owners.iter() {
+            buf.put_u32(*owner);
+        }
+        Ok(())
+    }
+
+    fn deserialise(buf: &mut dyn Buf) -> Result<Allocation, SerError> {
+        if buf.remaining() < 8 + 4 {
+            return Err(SerError::InvalidData(
+                "Could not get header for Allocation".into(),
+            ));
+        }
+        let version = buf.get_u64();
+        let len = buf.get_u32() as usize;
+        // every region takes at least a path header, an address byte and a port
+        if buf.remaining() / (1 + 1 + 2) < len {
+            return Err(SerError::InvalidData(format!(
+                "Allocation of {} regions cut short at {} bytes",
+                len,
+                buf.remaining()
+            )));
+        }
+        let mut regions = Vec::with_capacity(len);
+        for _ in 0..len {
+            regions.push(SystemPath::deserialise(buf)?);
+        }
+        if buf.remaining() < 4 {
+            return Err(SerError::InvalidData(
+                "Could not get number of shards for Allocation".into(),
+            ));
+        }
+        let len = buf.get_u32() as usize;
+        if buf.remaining() < 4 * len {
+            return Err(SerError::InvalidData(format!(
+                "Allocation of {} shards cut short at {} bytes",
+                len,
+                buf.remaining()
+            )));
+        }
+        let owners = (0..len).map(|_| buf.get_u32()).collect();
+        Ok(Allocation {
+            version,
+            regions,
+            owners,
+        })
+    }
+}
+
+/// A message for an entity, as passed between regions
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct EntityMsg {
+    entity_id: String,
+    sender: ActorPath,
+    ser_id: SerId,
+    data: Bytes,
+    /// How often the message has been sent to another region
+    forwards: u8,
+}
+
+/// The protocol spoken between regions
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RegionMsg {
+    /// Asks the receiving coordinator to allocate shards to the sender's region
+    Register,
+    /// The coordinator's current allocation
+    Allocation(Allocation),
+    /// A message to route to its entity
+    Deliver(EntityMsg),
+}
+
+impl RegionMsg {
+    const REGISTER: u8 = 1;
+    const ALLOCATION: u8 = 2;
+    const DELIVER: u8 = 3;
+}
+
+impl Serialisable for RegionMsg {
+    fn ser_id(&self) -> SerId {
+        serialisation_ids::SHARDING
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        match self {
+            RegionMsg::Register => Some(1),
+            RegionMsg::Allocation(allocation) => allocation.size_hint().map(|size| 1 + size),
+            RegionMsg::Deliver(msg) => msg.sender.size_hint().map(|size| {
+                1 + msg.entity_id.len()
+                    + 8
+                    + size
+                    + std::mem::size_of::<SerId>()
+                    + 4
+                    + msg.data.len()
+                    + 1
+            }),
+        }
+    }
+
+    fn serialise(&self, mut buf: &mut dyn BufMut) -> Result<(), SerError> {
+        match self {
+            RegionMsg::Register => buf.put_u8(RegionMsg::REGISTER),
+            RegionMsg::Allocation(allocation) => {
+                buf.put_u8(RegionMsg::ALLOCATION);
+                allocation.serialise(buf)?;
+            }
+            RegionMsg::Deliver(msg) => {
+                buf.put_u8(RegionMsg::DELIVER);
+                msg.entity_id.serialise(buf)?;
+                msg.sender.serialise(buf)?;
+                buf.put_ser_id(msg.ser_id);
+                buf.put_u32(msg.data.len() as u32);
+                buf.put_slice(&msg.data);
+                buf.put_u8(msg.forwards);
+            }
+        }
+        Ok(())
+    }
+
+    fn local(self: Box<Self>) -> Result<Box<dyn Any + Send>, Box<dyn Serialisable>> {
+        Ok(self)
+    }
+}
+
+impl Deserialiser<RegionMsg> for RegionMsg {
+    const SER_ID: SerId = serialisation_ids::SHARDING;
+
+    fn deserialise(mut buf: &mut dyn Buf) -> Result<RegionMsg, SerError> {
+        if buf.remaining() < 1 {
+            return Err(SerError::InvalidData(
+                "Could not get tag for RegionMsg".into(),
+            ));
+        }
+        match buf.get_u8() {
+            RegionMsg::REGISTER => Ok(RegionMsg::Register),
+            RegionMsg::ALLOCATION => Allocation::deserialise(buf).map(RegionMsg::Allocation),
+            RegionMsg::DELIVER => {
+                let entity_id = String::deserialise(buf)?;
+                let sender = ActorPath::deserialise(buf)?;
+                if buf.remaining() < std::mem::size_of::<SerId>() + 4 {
+                    return Err(SerError::InvalidData(
+                        "Could not get data header for RegionMsg::Deliver".into(),
+                    ));
+                }
+                let ser_id = buf.get_ser_id();
+                let len = buf.get_u32() as usize;
+                if buf.remaining() < len + 1 {
+                    return Err(SerError::InvalidData(format!(
+                        "RegionMsg data of {} bytes cut short at {}",
+                        len,
+                        buf.remaining()
+                    )));
+                }
+                let mut data = vec![0u8; len];
+                buf.copy_to_slice(&mut data);
+                let forwards = buf.get_u8();
+                Ok(RegionMsg::Deliver(EntityMsg {
+                    entity_id,
+                    sender,
+                    ser_id,
+                    data: Bytes::from(data),
+                    forwards,
+                }))
+            }
+            tag => Err(SerError::InvalidType(format!(
+                "Unknown RegionMsg tag {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// The state of the region that acts as coordinator
+#[derive(Debug)]
+struct ShardCoordinator {
+    /// All regions that registered and whose members are still up, including the coordinator's
+    regions: BTreeSet<SystemPath>,
+}
+
+impl ShardCoordinator {
+    fn new(regions: BTreeSet<SystemPath>) -> Self {
+        ShardCoordinator { regions }
+    }
+
+    /// Assigns all shards to the registered regions
+    ///
+    /// Shards keep the owner they have in `previous` if that is still registered,
+    /// except for as many as have to move to even out the number of shards per region.
+    fn allocate(&self, previous: Option<&Allocation>, number_of_shards: u32) -> Allocation {
+        let regions: Vec<SystemPath> = self.regions.iter().cloned().collect();
+        let mut owners: Vec<Option<usize>> = (0..number_of_shards as usize)
+            .map(|shard| {
+                previous
+                    .and_then(|previous| previous.shard_owner(shard))
+                    .and_then(|owner| regions.iter().position(|region| region == owner))
+            })
+            .collect();
+        let mut counts = vec![0usize; regions.len()];
+        for owner in owners.iter().flatten() {
+            counts[*owner] += 1;
+        }
+        let least_loaded = |counts: &[usize]| {
+            (0..counts.len())
+                .min_by_key(|region| counts[*region])
+                .expect("coordinator region")
+        };
+        for owner in owners.iter_mut().filter(|owner| owner.is_none()) {
+            let region = least_loaded(&counts);
+            *owner = Some(region);
+            counts[region] += 1;
+        }
+        loop {
+            let most = (0..counts.len())
+                .max_by_key(|region| counts[*region])
+                .expect("coordinator region");
+            let least = least_loaded(&counts);
+            if counts[most] <= counts[least] + 1 {
+                break;
+            }
+            let shard = owners
+                .iter()
+                .rposition(|owner| *owner == Some(most))
+                .expect("shard of most loaded region");
+            owners[shard] = Some(least);
+            counts[most] -= 1;
+            counts[least] += 1;
+        }
+        Allocation {
+            version: previous.map_or(1, |previous| previous.version + 1),
+            regions,
+            owners: owners
+                .into_iter()
+                .map(|owner| owner.expect("allocated shard") as u32)
+                .collect(),
+        }
+    }
+}
+
+/// A component that routes messages to entities of one type, wherever in the cluster they live
+///
+/// Every system hosting entities of a type runs a region for it, which is created with the
+/// name of the type and a function that creates the entity for a given id. The region
+/// registers itself under `$sharding/<type name>` (see [SHARDING_ALIAS](SHARDING_ALIAS))
+/// when it is started, so it requires a system with a networked dispatcher. Its
+/// [ClusterMembership](ClusterMembership) port must be connected to a
+/// [Membership](crate::membership::Membership) component, since no shards are allocated
+/// before this system is `Up`.
+///
+/// Entities receive their messages via [receive_network](Actor::receive_network),
+/// with the path of the original sender as their sender. They are ordinary components of the
+/// system, which the region only kills when their shard moves elsewhere.
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+///
+/// #[derive(ComponentDefinition)]
+/// struct Account {
+///     ctx: ComponentContext<Self>,
+///     id: String,
+///     balance: u64,
+/// }
+/// ignore_lifecycle!(Account);
+/// impl Actor for Account {
+///     type Message = Never;
+///
+///     fn receive_local(&mut self, _msg: Never) -> Handled {
+///         unreachable!();
+///     }
+///
+///     fn receive_network(&mut self, msg: NetMessage) -> Handled {
+///         if let Ok(amount) = msg.try_deserialise::<u64, u64>() {
+///             self.balance += amount;
+///         }
+///         Handled::Ok
+///     }
+/// }
+///
+/// let mut cfg = KompactConfig::default();
+/// cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+/// let system = cfg.build().expect("KompactSystem");
+///
+/// let membership = system.create(Membership::new);
+/// let region = system.create(|| {
+///     ShardRegion::new("account", |id: &str| Account {
+///         ctx: ComponentContext::uninitialised(),
+///         id: id.to_string(),
+///         balance: 0,
+///     })
+/// });
+/// biconnect_components::<ClusterMembership, _, _>(&membership, &region).expect("connection");
+/// system.start(&region);
+/// system.start(&membership);
+///
+/// # let sender = system.actor_path();
+/// let deposit = ShardEnvelope::new("alice", sender, &100u64).expect("serialise");
+/// region.actor_ref().tell(deposit);
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(ComponentDefinition)]
+pub struct ShardRegion<C: ComponentDefinition + 'static> {
+    ctx: ComponentContext<Self>,
+    membership: RequiredPort<ClusterMembership>,
+    type_name: String,
+    config: Option<ShardingConfig>,
+    factory: Box<dyn Fn(&str) -> C + Send>,
+    /// All members that are up, including this system once it is
+    members: BTreeSet<SystemPath>,
+    /// The lowest member, which acts as coordinator
+    coordinator: Option<SystemPath>,
+    /// Only present while this system is the coordinator
+    shard_coordinator: Option<ShardCoordinator>,
+    /// The allocation in use, and the coordinator it came from
+    allocation: Option<(SystemPath, Allocation)>,
+    entities: FxHashMap<String, Arc<Component<C>>>,
+    /// Messages that arrived before any allocation
+    pending: Vec<EntityMsg>,
+    timer: Option<ScheduledTimer>,
+}
+
+impl<C: ComponentDefinition + 'static> ShardRegion<C> {
+    /// Create a new region for entities of type `type_name`, configured from the system's config
+    ///
+    /// The `factory` is called with the id of an entity whenever it is created.
+    pub fn new<T, F>(type_name: T, factory: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(&str) -> C + Send + 'static,
+    {
+        ShardRegion {
+            ctx: ComponentContext::uninitialised(),
+            membership: RequiredPort::uninitialised(),
+            type_name: type_name.into(),
+            config: None,
+            factory: Box::new(factory),
+            members: BTreeSet::new(),
+            coordinator: None,
+            shard_coordinator: None,
+            allocation: None,
+            entities: FxHashMap::default(),
+            pending: Vec::new(),
+            timer: None,
+        }
+    }
+
+    /// Create a new region for entities of type `type_name` with the given configuration
+    ///
+    /// The `sharding` section of the system's config is ignored in this case.
+    pub fn with_config<T, F>(type_name: T, config: ShardingConfig, factory: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(&str) -> C + Send + 'static,
+    {
+        let mut region = ShardRegion::new(type_name, factory);
+        region.config = Some(config);
+        region
+    }
+
+    fn config(&self) -> &ShardingConfig {
+        self.config.as_ref().expect("config")
+    }
+
+    fn region_path(&self, system: SystemPath) -> ActorPath {
+        ActorPath::Named(NamedPath::with_system(
+            system,
+            vec![SHARDING_ALIAS.to_string(), self.type_name.clone()],
+        ))
+    }
+
+    fn send(&self, system: &SystemPath, msg: RegionMsg) {
+        let dst = self.region_path(system.clone());
+        let mut src = self.region_path(self.ctx.system().system_path());
+        src.set_protocol(system.protocol());
+        dst.tell_with_sender(msg, self, src);
+    }
+
+    fn route(&mut self, mut msg: EntityMsg) {
+        let own_system = self.ctx.system().system_path();
+        let owner = match self.allocation {
+            Some((_, ref allocation)) => allocation.owner(&msg.entity_id).cloned(),
+            None => None,
+        };
+        match owner {
+            Some(owner) if owner == own_system => self.deliver_local(msg),
+            Some(owner) if msg.forwards < MAX_FORWARDS => {
+                msg.forwards += 1;
+                self.send(&owner, RegionMsg::Deliver(msg));
+            }
+            Some(owner) => warn!(
+                self.ctx.log(),
+                "Dropping message for entity {} after {} forwards, last to {}",
+                msg.entity_id,
+                msg.forwards,
+                owner
+            ),
+            None => self.pending.push(msg),
+        }
+    }
+
+    /// Delivers a message to a local entity, creating the entity if necessary
+    fn deliver_local(&mut self, msg: EntityMsg) {
+        if !self.entities.contains_key(&msg.entity_id) {
+            let system = self.ctx.system();
+            let entity = system.create(|| (self.factory)(&msg.entity_id));
+            system.start(&entity);
+            debug!(
+                self.ctx.log(),
+                "Created {} entity {}", self.type_name, msg.entity_id
+            );
+            self.entities.insert(msg.entity_id.clone(), entity);
+        }
+        let receiver = self.region_path(self.ctx.system().system_path());
+        let net_msg = NetMessage::with_bytes(msg.ser_id, msg.sender, receiver, msg.data);
+        self.entities[&msg.entity_id]
+            .actor_ref()
+            .dyn_ref()
+            .tell(net_msg);
+    }
+
+    /// Switches to `allocation`, handing off all entities of shards that moved elsewhere
+    fn apply_allocation(&mut self, allocator: SystemPath, allocation: Allocation) {
+        let own_system = self.ctx.system().system_path();
+        let moved: Vec<String> = self
+            .entities
+            .keys()
+            .filter(|entity_id| allocation.owner(entity_id) != Some(&own_system))
+            .cloned()
+            .collect();
+        if !moved.is_empty() {
+            debug!(
+                self.ctx.log(),
+                "Handing off {} {} entities",
+                moved.len(),
+                self.type_name
+            );
+        }
+        for entity_id in moved {
+            if let Some(entity) = self.entities.remove(&entity_id) {
+                self.ctx.system().kill(entity);
+            }
+        }
+        let local_shards = (0..allocation.owners.len())
+            .filter(|shard| allocation.shard_owner(*shard) == Some(&own_system))
+            .count();
+        debug!(
+            self.ctx.log(),
+            "Allocation {} from {} assigns {} of {} {} shards to this system",
+            allocation.version,
+            allocator,
+            local_shards,
+            allocation.owners.len(),
+            self.type_name
+        );
+        self.allocation = Some((allocator, allocation));
+        for msg in std::mem::take(&mut self.pending) {
+            self.route(msg);
+        }
+    }
+
+    /// Asks the coordinator for shards, unless this system is the coordinator or not up
+    fn register(&self) {
+        let own_system = self.ctx.system().system_path();
+        if let Some(ref coordinator) = self.coordinator {
+            if *coordinator != own_system && self.members.contains(&own_system) {
+                self.send(coordinator, RegionMsg::Register);
+            }
+        }
+    }
+
+    fn update_coordinator(&mut self) {
+        let own_system = self.ctx.system().system_path();
+        let coordinator = self.members.iter().next().cloned();
+        if coordinator != self.coordinator {
+            self.coordinator = coordinator;
+            self.shard_coordinator = None;
+            if self.coordinator.as_ref() == Some(&own_system) {
+                info!(
+                    self.ctx.log(),
+                    "Acting as shard coordinator for {} entities", self.type_name
+                );
+                // take over all regions that were registered with the previous coordinator
+                let mut regions: BTreeSet<SystemPath> = match self.allocation {
+                    Some((_, ref allocation)) => allocation
+                        .regions
+                        .iter()
+                        .filter(|region| self.members.contains(region))
+                        .cloned()
+                        .collect(),
+                    None => BTreeSet::new(),
+                };
+                regions.insert(own_system);
+                self.shard_coordinator = Some(ShardCoordinator::new(regions));
+                self.reallocate();
+            } else {
+                self.register();
+            }
+        } else if let Some(ref mut shard_coordinator) = self.shard_coordinator {
+            let members = &self.members;
+            let before = shard_coordinator.regions.len();
+            shard_coordinator
+                .regions
+                .retain(|region| members.contains(region));
+            if shard_coordinator.regions.len() != before {
+                self.reallocate();
+            }
+        }
+    }
+
+    /// Computes a new allocation as coordinator, and sends it to all regions if it changed
+    fn reallocate(&mut self) {
+        let own_system = self.ctx.system().system_path();
+        let allocation = match self.shard_coordinator {
+            Some(ref shard_coordinator) => shard_coordinator.allocate(
+                self.allocation.as_ref().map(|(_, allocation)| allocation),
+                self.config().get_number_of_shards(),
+            ),
+            None => return,
+        };
+        let unchanged = matches!(
+            self.allocation,
+            Some((ref allocator, ref current)) if *allocator == own_system
+                && current.regions == allocation.regions
+                && current.owners == allocation.owners
+        );
+        if !unchanged {
+            self.apply_allocation(own_system, allocation);
+            self.broadcast_allocation();
+        }
+    }
+
+    fn broadcast_allocation(&self) {
+        let own_system = self.ctx.system().system_path();
+        if let (Some(shard_coordinator), Some((_, allocation))) =
+            (&self.shard_coordinator, &self.allocation)
+        {
+            for region in shard_coordinator.regions.iter() {
+                if *region != own_system {
+                    self.send(region, RegionMsg::Allocation(allocation.clone()));
+                }
+            }
+        }
+    }
+
+    fn on_register(&mut self, region: SystemPath) {
+        if !self.members.contains(&region) {
+            // it will retry once this system has seen it come up
+            return;
+        }
+        let registered = match self.shard_coordinator {
+            Some(ref mut shard_coordinator) => shard_coordinator.regions.insert(region.clone()),
+            None => return,
+        };
+        if registered {
+            debug!(
+                self.ctx.log(),
+                "Region on {} registered for {} entities", region, self.type_name
+            );
+            self.reallocate();
+        } else if let Some((_, ref allocation)) = self.allocation {
+            self.send(&region, RegionMsg::Allocation(allocation.clone()));
+        }
+    }
+
+    fn on_allocation(&mut self, allocator: SystemPath, allocation: Allocation) {
+        if self.coordinator.as_ref() != Some(&allocator) {
+            // a previous coordinator that doesn't know yet it has been replaced
+            return;
+        }
+        let newer = match self.allocation {
+            Some((ref current_allocator, ref current)) => {
+                *current_allocator != allocator || allocation.version > current.version
+            }
+            None => true,
+        };
+        if newer {
+            self.apply_allocation(allocator, allocation);
+        }
+    }
+
+    fn retry(&mut self, _timeout_id: ScheduledTimer) -> Handled {
+        if self.shard_coordinator.is_some() {
+            self.broadcast_allocation();
+        } else {
+            let own_system = self.ctx.system().system_path();
+            let allocated = matches!(
+                (&self.allocation, &self.coordinator),
+                (Some((allocator, allocation)), Some(coordinator))
+                    if allocator == coordinator && allocation.regions.contains(&own_system)
+            );
+            if !allocated {
+                self.register();
+            }
+        }
+        Handled::Ok
+    }
+}
+
+impl<C: ComponentDefinition + 'static> ComponentLifecycle for ShardRegion<C> {
+    fn on_start(&mut self) -> Handled {
+        if self.config.is_none() {
+            self.config = Some(ShardingConfig::from_config(self.ctx.config()));
+        }
+        let alias = format!("{}/{}", SHARDING_ALIAS, self.type_name);
+        let registration = self.ctx.update_own_alias_registration(&alias);
+        self.spawn_local(move |async_self| async move {
+            match registration.await {
+                Ok(Ok(_)) => (),
+                Ok(Err(e)) => error!(
+                    async_self.ctx.log(),
+                    "Could not register shard region as {}: {:?}", alias, e
+                ),
+                Err(e) => error!(
+                    async_self.ctx.log(),
+                    "Shard region registration was dropped: {:?}", e
+                ),
+            }
+            Handled::Ok
+        });
+        let interval = Duration::from_millis(self.config().get_retry_interval());
+        self.timer = Some(self.schedule_periodic(interval, interval, Self::retry));
+        Handled::Ok
+    }
+
+    fn on_stop(&mut self) -> Handled {
+        if let Some(timer) = self.timer.take() {
+            self.cancel_timer(timer);
+        }
+        Handled::Ok
+    }
+
+    fn on_kill(&mut self) -> Handled {
+        self.on_stop()
+    }
+}
+
+impl<C: ComponentDefinition + 'static> Require<ClusterMembership> for ShardRegion<C> {
+    fn handle(&mut self, event: MembershipEvent) -> Handled {
+        match event {
+            MembershipEvent::Up(system) => {
+                let own_system = system == self.ctx.system().system_path();
+                self.members.insert(system);
+                self.update_coordinator();
+                if own_system {
+                    self.register();
+                }
+            }
+            MembershipEvent::Left(system) | MembershipEvent::Down(system) => {
+                self.members.remove(&system);
+                self.update_coordinator();
+            }
+            MembershipEvent::Joined(_) => (),
+        }
+        Handled::Ok
+    }
+}
+
+impl<C: ComponentDefinition + 'static> Actor for ShardRegion<C> {
+    type Message = ShardEnvelope;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        let ShardEnvelope {
+            entity_id,
+            sender,
+            msg,
+        } = msg;
+        self.route(EntityMsg {
+            entity_id,
+            sender,
+            ser_id: msg.ser_id,
+            data: msg.data,
+            forwards: 0,
+        });
+        Handled::Ok
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let sender = msg.sender.clone();
+        match_deser!(msg; {
+            region_msg: RegionMsg [RegionMsg] => match region_msg {
+                RegionMsg::Register => self.on_register(sender.system().clone()),
+                RegionMsg::Allocation(allocation) => {
+                    self.on_allocation(sender.system().clone(), allocation)
+                }
+                RegionMsg::Deliver(entity_msg) => self.route(entity_msg),
+            },
+            !Err(e) => warn!(self.ctx.log(), "Invalid sharding message: {:?}", e),
+            _ => warn!(self.ctx.log(), "Unexpected message from {}", sender),
+        });
+        Handled::Ok
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+    use hocon::HoconLoader;
+
+    fn system(port: u16) -> SystemPath {
+        SystemPath::new(Transport::TCP, "127.0.0.1".parse().unwrap(), port)
+    }
+
+    fn shard_counts(allocation: &Allocation) -> Vec<usize> {
+        (0..allocation.regions.len())
+            .map(|region| {
+                allocation
+                    .owners
+                    .iter()
+                    .filter(|owner| **owner as usize == region)
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn region_msg_serequiv() {
+        let sender: ActorPath = "tcp://127.0.0.1:8080/some/actor".parse().expect("path");
+        let msgs = vec![
+            RegionMsg::Register,
+            RegionMsg::Allocation(Allocation {
+                version: 7,
+                regions: vec![system(1), system(2)],
+                owners: vec![0, 1, 1, 0],
+            }),
+            RegionMsg::Deliver(EntityMsg {
+                entity_id: "alice".to_string(),
+                sender,
+                ser_id: serialisation_ids::U64,
+                data: Bytes::from_static(&[1, 2, 3, 4]),
+                forwards: 2,
+            }),
+        ];
+        for msg in msgs {
+            let mut buf = BytesMut::with_capacity(msg.size_hint().expect("size hint"));
+            msg.serialise(&mut buf).expect("serialise");
+            let mut bytes = buf.freeze();
+            let res = RegionMsg::deserialise(&mut bytes).expect("deserialise");
+            assert_eq!(msg, res);
+        }
+    }
+
+    #[test]
+    fn allocation_rejects_oversized_region_count() {
+        let mut buf = BytesMut::with_capacity(1 + 8 + 4);
+        buf.put_u8(RegionMsg::ALLOCATION);
+        buf.put_u64(1);
+        buf.put_u32(u32::MAX);
+        let mut bytes = buf.freeze();
+        assert!(RegionMsg::deserialise(&mut bytes).is_err());
+    }
+
+    #[test]
+    fn allocation_is_balanced_and_stable() {
+        let mut coordinator = ShardCoordinator::new((1..=3).map(system).collect());
+        let first = coordinator.allocate(None, 10);
+        assert_eq!(first.version, 1);
+        assert_eq!(shard_counts(&first), vec![4, 3, 3]);
+
+        // a new region only takes shards from the others
+        coordinator.regions.insert(system(4));
+        let second = coordinator.allocate(Some(&first), 10);
+        assert_eq!(second.version, 2);
+        assert_eq!(shard_counts(&second), vec![3, 3, 2, 2]);
+        for shard in 0..10 {
+            let owner = second.shard_owner(shard).expect("owner");
+            assert!(owner == &system(4) || Some(owner) == first.shard_owner(shard));
+        }
+
+        // only the shards of a removed region move
+        coordinator.regions.remove(&system(2));
+        let third = coordinator.allocate(Some(&second), 10);
+        assert_eq!(shard_counts(&third), vec![4, 3, 3]);
+        for shard in 0..10 {
+            if second.shard_owner(shard) != Some(&system(2)) {
+                assert_eq!(third.shard_owner(shard), second.shard_owner(shard));
+            }
+        }
+        assert_eq!(third.owner("alice"), third.owner("alice"));
+        assert_eq!(shard_of("alice", 10), shard_of("alice", 10));
+        assert!(shard_of("bob", 10) < 10);
+    }
+
+    #[test]
+    fn config_from_hocon() {
+        let hocon = HoconLoader::new()
+            .load_str("sharding { number_of_shards = 12, retry_interval = 200 ms }")
+            .unwrap()
+            .hocon()
+            .unwrap();
+        let config = ShardingConfig::from_config(&hocon);
+        assert_eq!(config.get_number_of_shards(), 12);
+        assert_eq!(config.get_retry_interval(), 200);
+        let hocon = HoconLoader::new().hocon().unwrap();
+        let config = ShardingConfig::from_config(&hocon);
+        assert_eq!(config.get_number_of_shards(), NUMBER_OF_SHARDS);
+        assert_eq!(config.get_retry_interval(), RETRY_INTERVAL);
+    }
+}
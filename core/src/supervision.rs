@@ -6,10 +6,12 @@ use crate::{
     KompactLogger,
 };
 
+use hocon::Hocon;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     fmt,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 use uuid::Uuid;
 
@@ -43,13 +45,41 @@ pub struct FaultContext {
     pub component_id: Uuid,
     /// The concrete error produced by [catch_unwind](std::panic::catch_unwind)
     pub fault: Box<dyn Any + Send>,
+    /// The parent of the component that faulted, if any
+    ///
+    /// Only known once the fault has reached the supervisor.
+    parent: Option<Arc<dyn CoreContainer>>,
 }
 impl FaultContext {
     pub(crate) fn new(component_id: Uuid, fault: Box<dyn Any + Send>) -> Self {
         FaultContext {
             component_id,
             fault,
+            parent: None,
+        }
+    }
+
+    /// Create a replacement for the faulty component with `f`
+    ///
+    /// If the faulty component was the child of another component,
+    /// the replacement becomes a child of the same parent.
+    fn create_replacement<C, F>(&self, system: &ContextSystemHandle, f: F) -> Arc<Component<C>>
+    where
+        F: FnOnce() -> C,
+        C: ComponentDefinition + 'static,
+    {
+        let cd = system.create(f);
+        if let Some(ref parent) = self.parent {
+            cd.set_recovery_function(|fault| fault.escalate());
+            parent
+                .system()
+                .supervision_port()
+                .enqueue(SupervisorMsg::Adopt {
+                    parent: parent.clone(),
+                    child: cd.clone(),
+                });
         }
+        cd
     }
 
     /// Produce a [Recoverhandler](RecoveryHandler) with `f` describing
@@ -69,7 +99,43 @@ impl FaultContext {
         let boxed = Box::new(f);
         RecoveryHandler {
             ctx: self,
-            action: boxed,
+            action: RecoveryAction::Now(boxed),
+        }
+    }
+
+    /// Like [recover_with](FaultContext::recover_with), but the supervisor
+    /// waits for `delay` before executing `f`
+    ///
+    /// If the system starts shutting down in the meantime, `f` is not executed at all.
+    pub fn recover_after<F>(self, delay: Duration, f: F) -> RecoveryHandler
+    where
+        F: FnOnce(Self, ContextSystemHandle, &KompactLogger) + Send + 'static,
+    {
+        let boxed = Box::new(f);
+        RecoveryHandler {
+            ctx: self,
+            action: RecoveryAction::After(delay, boxed),
+        }
+    }
+
+    /// Escalate the fault to the parent of the faulty component
+    ///
+    /// This faults the parent in turn, whose own recovery function then decides how to proceed.
+    /// This is the default for children created via [create_child](ComponentContext::create_child).
+    ///
+    /// For components without a parent, the fault is simply logged.
+    pub fn escalate(self) -> RecoveryHandler {
+        RecoveryHandler {
+            ctx: self,
+            action: RecoveryAction::Escalate(None),
+        }
+    }
+
+    /// Like [escalate](FaultContext::escalate), but also publish `event` on the supervisor
+    pub(crate) fn escalate_with(self, event: SupervisionEvent) -> RecoveryHandler {
+        RecoveryHandler {
+            ctx: self,
+            action: RecoveryAction::Escalate(Some(event)),
         }
     }
 
@@ -95,7 +161,7 @@ impl FaultContext {
                 C::type_name(),
                 ctx.component_id
             );
-            let cd = system.create(C::default);
+            let cd = ctx.create_replacement(&system, C::default);
             system.start(&cd);
         })
     }
@@ -135,18 +201,13 @@ pub struct RecoveryHandler {
     /// The context of the fault that occurred
     ctx: FaultContext,
     /// The actions to take in response to the fault
-    action: Box<dyn FnOnce(FaultContext, ContextSystemHandle, &KompactLogger) + Send>,
-}
-impl RecoveryHandler {
-    fn recover(self, system: ContextSystemHandle, logger: &KompactLogger) -> () {
-        (self.action)(self.ctx, system, logger)
-    }
+    action: RecoveryAction,
 }
 impl fmt::Debug for RecoveryHandler {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RecoveryHandler")
             .field("ctx", &self.ctx)
-            .field("action", &"<func>")
+            .field("action", &self.action)
             .finish()
     }
 }
@@ -158,6 +219,283 @@ impl Clone for RecoveryHandler {
     }
 }
 
+type RecoveryFn = dyn FnOnce(FaultContext, ContextSystemHandle, &KompactLogger) + Send;
+
+enum RecoveryAction {
+    Now(Box<RecoveryFn>),
+    After(Duration, Box<RecoveryFn>),
+    Escalate(Option<SupervisionEvent>),
+}
+impl fmt::Debug for RecoveryAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecoveryAction::Now(_) => write!(f, "Now(<func>)"),
+            RecoveryAction::After(delay, _) => write!(f, "After({:?}, <func>)", delay),
+            RecoveryAction::Escalate(event) => write!(f, "Escalate({:?})", event),
+        }
+    }
+}
+
+/// Events published by the supervisor
+///
+/// Subscribe to these via [subscribe_supervision_events](KompactSystem::subscribe_supervision_events).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SupervisionEvent {
+    /// A component faulted more often than its [RestartStrategy](RestartStrategy) allows,
+    /// and will not be restarted again
+    RestartLimitExceeded {
+        /// The id of the instance that faulted last
+        component_id: Uuid,
+        /// The type name of the faulty component
+        component_type: &'static str,
+        /// The number of restarts the strategy allows within `within`
+        max_restarts: usize,
+        /// The time window restarts are counted in
+        within: Duration,
+    },
+}
+
+/// A declarative policy for restarting faulty components
+///
+/// Strategies apply *one-for-one*, i.e. only the component that faulted is replaced
+/// with a fresh instance, and the replacement is supervised with the same strategy.
+/// By default a faulty component is restarted immediately and indefinitely.
+/// Use [with_backoff](RestartStrategy::with_backoff) to delay restarts exponentially
+/// and [with_limit](RestartStrategy::with_limit) to give up eventually.
+/// Once a limit is exceeded, the component is not restarted anymore,
+/// the supervisor publishes a [SupervisionEvent::RestartLimitExceeded](SupervisionEvent::RestartLimitExceeded),
+/// and the fault is [escalated](FaultContext::escalate) to the component's parent, if it has one.
+///
+/// # Example
+///
+/// ```
+/// use kompact::prelude::*;
+/// use std::time::Duration;
+///
+/// #[derive(ComponentDefinition, Actor)]
+/// struct Flaky {
+///     ctx: ComponentContext<Self>,
+/// }
+/// impl Flaky {
+///     fn new() -> Self {
+///         Flaky {
+///             ctx: ComponentContext::uninitialised(),
+///         }
+///     }
+/// }
+/// impl ComponentLifecycle for Flaky {
+///     fn on_start(&mut self) -> Handled {
+///         let strategy = RestartStrategy::one_for_one()
+///             .with_backoff(Duration::from_millis(10), Duration::from_secs(1))
+///             .with_limit(5, Duration::from_secs(60));
+///         self.ctx
+///             .set_recovery_function(strategy.recovery_function(Flaky::new));
+///         Handled::Ok
+///     }
+/// }
+///
+/// let system = KompactConfig::default().build().expect("system");
+/// let c = system.create(Flaky::new);
+/// system.start(&c);
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RestartStrategy {
+    backoff: Option<(Duration, Duration)>,
+    limit: Option<(usize, Duration)>,
+}
+
+impl RestartStrategy {
+    /// Restart only the faulty component, immediately and without limit
+    pub fn one_for_one() -> Self {
+        RestartStrategy::default()
+    }
+
+    /// Reads a strategy from the `supervision.restart` section of `config`
+    ///
+    /// Settings that are absent from the config leave the corresponding feature disabled.
+    ///
+    /// ```hocon
+    /// supervision.restart {
+    ///     min_backoff = 100 ms
+    ///     max_backoff = 10 s
+    ///     max_restarts = 5
+    ///     within = 1 minute
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if only one of `min_backoff` and `max_backoff` is given,
+    /// or only one of `max_restarts` and `within`.
+    pub fn from_config(config: &Hocon) -> Self {
+        let section = &config["supervision"]["restart"];
+        let mut strategy = RestartStrategy::one_for_one();
+        match (
+            section["min_backoff"].as_duration(),
+            section["max_backoff"].as_duration(),
+        ) {
+            (Some(min), Some(max)) => strategy = strategy.with_backoff(min, max),
+            (None, None) => (),
+            _ => panic!("Both min_backoff and max_backoff must be set to use backoff"),
+        }
+        match (
+            section["max_restarts"].as_i64(),
+            section["within"].as_duration(),
+        ) {
+            (Some(max_restarts), Some(within)) => {
+                strategy = strategy.with_limit(max_restarts as usize, within)
+            }
+            (None, None) => (),
+            _ => panic!("Both max_restarts and within must be set to limit restarts"),
+        }
+        strategy
+    }
+
+    /// Wait before every restart, starting at `min` and doubling up to `max`
+    ///
+    /// The delay goes back to `min` once a replacement has been running
+    /// for longer than `max` without faulting.
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        assert!(min <= max, "min_backoff must not exceed max_backoff");
+        self.backoff = Some((min, max));
+        self
+    }
+
+    /// Restart at most `max_restarts` times within any window of length `within`
+    ///
+    /// A fault beyond that is [escalated](FaultContext::escalate) instead.
+    pub fn with_limit(mut self, max_restarts: usize, within: Duration) -> Self {
+        self.limit = Some((max_restarts, within));
+        self
+    }
+
+    /// Produce a recovery function that restarts faults according to this strategy
+    ///
+    /// Replacements are created with `f` and get the same recovery function,
+    /// so that restarts are counted over the whole lineage of instances.
+    ///
+    /// Pass the result to [set_recovery_function](ComponentContext::set_recovery_function).
+    pub fn recovery_function<C, F>(
+        &self,
+        f: F,
+    ) -> impl FnOnce(FaultContext) -> RecoveryHandler + Send + 'static
+    where
+        C: ComponentDefinition + 'static,
+        F: Fn() -> C + Clone + Send + 'static,
+    {
+        let restarter = Restarter {
+            history: RestartHistory::new(self.clone()),
+            factory: f,
+        };
+        move |fault| restarter.on_fault(fault)
+    }
+}
+
+/// Keeps track of the restarts of a component and its replacements
+#[derive(Debug)]
+struct RestartHistory {
+    strategy: RestartStrategy,
+    faults: VecDeque<Instant>,
+    consecutive: u32,
+    last_restart: Option<Instant>,
+}
+
+impl RestartHistory {
+    fn new(strategy: RestartStrategy) -> Self {
+        RestartHistory {
+            strategy,
+            faults: VecDeque::new(),
+            consecutive: 0,
+            last_restart: None,
+        }
+    }
+
+    /// Records a fault at `now` and returns how long to wait before restarting,
+    /// or `None` if the restart limit is exceeded
+    fn on_fault(&mut self, now: Instant) -> Option<Duration> {
+        if let Some((max_restarts, within)) = self.strategy.limit {
+            self.faults.push_back(now);
+            while let Some(first) = self.faults.front() {
+                if now.saturating_duration_since(*first) > within {
+                    self.faults.pop_front();
+                } else {
+                    break;
+                }
+            }
+            if self.faults.len() > max_restarts {
+                return None;
+            }
+        }
+        match self.strategy.backoff {
+            Some((min, max)) => {
+                if let Some(last_restart) = self.last_restart {
+                    if now.saturating_duration_since(last_restart) > max {
+                        self.consecutive = 0;
+                    }
+                }
+                let factor = 2u32.saturating_pow(self.consecutive);
+                self.consecutive = self.consecutive.saturating_add(1);
+                Some(min.checked_mul(factor).map_or(max, |delay| delay.min(max)))
+            }
+            None => Some(Duration::from_millis(0)),
+        }
+    }
+
+    fn on_restart(&mut self, now: Instant) {
+        self.last_restart = Some(now);
+    }
+}
+
+struct Restarter<F> {
+    history: RestartHistory,
+    factory: F,
+}
+
+impl<C, F> Restarter<F>
+where
+    C: ComponentDefinition + 'static,
+    F: Fn() -> C + Clone + Send + 'static,
+{
+    fn on_fault(mut self, fault: FaultContext) -> RecoveryHandler {
+        match self.history.on_fault(Instant::now()) {
+            Some(delay) if delay == Duration::from_millis(0) => {
+                fault.recover_with(move |ctx, system, logger| self.restart(ctx, system, logger))
+            }
+            Some(delay) => fault.recover_after(delay, move |ctx, system, logger| {
+                self.restart(ctx, system, logger)
+            }),
+            None => {
+                let (max_restarts, within) = self
+                    .history
+                    .strategy
+                    .limit
+                    .expect("only a limit can be exceeded");
+                let event = SupervisionEvent::RestartLimitExceeded {
+                    component_id: fault.component_id,
+                    component_type: C::type_name(),
+                    max_restarts,
+                    within,
+                };
+                fault.escalate_with(event)
+            }
+        }
+    }
+
+    fn restart(mut self, ctx: FaultContext, system: ContextSystemHandle, logger: &KompactLogger) {
+        info!(
+            logger,
+            "Restarting a {} to replace instance with id={}",
+            C::type_name(),
+            ctx.component_id
+        );
+        let cd = ctx.create_replacement(&system, self.factory.clone());
+        self.history.on_restart(Instant::now());
+        cd.set_recovery_function(move |fault| self.on_fault(fault));
+        system.start(&cd);
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum SupervisorMsg {
     Started(Arc<dyn CoreContainer>),
@@ -166,6 +504,11 @@ pub(crate) enum SupervisorMsg {
     Faulty(RecoveryHandler),
     Listen(Arc<Mutex<KPromise<()>>>, ListenEvent),
     Shutdown(Arc<Mutex<KPromise<()>>>),
+    Subscribe(ActorRef<SupervisionEvent>),
+    Adopt {
+        parent: Arc<dyn CoreContainer>,
+        child: Arc<dyn CoreContainer>,
+    },
 }
 
 #[derive(ComponentDefinition, Actor)]
@@ -174,6 +517,13 @@ pub(crate) struct ComponentSupervisor {
     pub(crate) supervision: ProvidedPort<SupervisionPort>,
     children: HashMap<Uuid, Arc<dyn CoreContainer>>,
     listeners: HashMap<Uuid, Vec<(ListenEvent, KPromise<()>)>>,
+    subscribers: Vec<ActorRef<SupervisionEvent>>,
+    // the component tree formed via `create_child`
+    parents: HashMap<Uuid, Arc<dyn CoreContainer>>,
+    offspring: HashMap<Uuid, HashMap<Uuid, Arc<dyn CoreContainer>>>,
+    stopped: HashSet<Uuid>,
+    stopped_by_parent: HashSet<Uuid>,
+    dying: HashSet<Uuid>,
     shutdown: Option<KPromise<()>>,
 }
 
@@ -184,6 +534,12 @@ impl ComponentSupervisor {
             supervision: ProvidedPort::uninitialised(),
             children: HashMap::new(),
             listeners: HashMap::new(),
+            subscribers: Vec::new(),
+            parents: HashMap::new(),
+            offspring: HashMap::new(),
+            stopped: HashSet::new(),
+            stopped_by_parent: HashSet::new(),
+            dying: HashSet::new(),
             shutdown: None,
         }
     }
@@ -211,6 +567,133 @@ impl ComponentSupervisor {
         self.listeners.remove(id);
     }
 
+    fn recover(&mut self, handler: RecoveryHandler) {
+        let RecoveryHandler { ctx, action } = handler;
+        match action {
+            RecoveryAction::Now(f) => f(ctx, self.ctx.context_system(), self.ctx.log()),
+            RecoveryAction::After(delay, f) => {
+                debug!(
+                    self.ctx.log(),
+                    "Recovering Component({}) in {:?}.", ctx.component_id, delay
+                );
+                self.schedule_once(delay, move |this, _timer| {
+                    if this.shutdown.is_none() {
+                        f(ctx, this.ctx.context_system(), this.ctx.log());
+                    } else {
+                        warn!(
+                            this.log(),
+                            "Not running delayed recovery handler due to ongoing shutdown."
+                        );
+                    }
+                    Handled::Ok
+                });
+            }
+            RecoveryAction::Escalate(event) => {
+                if let Some(event) = event {
+                    error!(
+                        self.ctx.log(),
+                        "Component({}) can not be recovered: {:?}", ctx.component_id, event
+                    );
+                    for subscriber in self.subscribers.iter() {
+                        subscriber.tell(event.clone());
+                    }
+                }
+                match ctx.parent {
+                    Some(parent) => {
+                        warn!(
+                            self.ctx.log(),
+                            "Escalating fault of Component({}) to its parent Component({}).",
+                            ctx.component_id,
+                            parent.id()
+                        );
+                        parent.enqueue_control(ControlEvent::Escalate(ctx.component_id));
+                    }
+                    None => error!(
+                        self.ctx.log(),
+                        "Fault of Component({}) escalated to the top: {:?}", ctx.component_id, ctx
+                    ),
+                }
+            }
+        }
+    }
+
+    /// Whether `id` and all its started descendants are stopped
+    fn subtree_stopped(&self, id: &Uuid) -> bool {
+        self.stopped.contains(id)
+            && match self.offspring.get(id) {
+                Some(offspring) => offspring
+                    .keys()
+                    .all(|child| !self.children.contains_key(child) || self.subtree_stopped(child)),
+                None => true,
+            }
+    }
+
+    /// Notify stop listeners of `id` and its ancestors, once their whole subtree is stopped
+    fn notify_stopped(&mut self, id: Uuid) {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            if !self.subtree_stopped(&id) {
+                break;
+            }
+            self.notify_listeners(&id, |l| matches!(l, ListenEvent::Stopped(_)));
+            current = self.parents.get(&id).map(|parent| parent.id());
+        }
+    }
+
+    /// Notify kill listeners of `id` once all its descendants are destroyed as well
+    fn notify_destroyed(&mut self, id: Uuid) {
+        if matches!(self.offspring.get(&id), Some(offspring) if !offspring.is_empty()) {
+            trace!(
+                self.ctx.log(),
+                "Component({}) is waiting for its children to die.",
+                id
+            );
+            self.dying.insert(id);
+            return;
+        }
+        self.dying.remove(&id);
+        self.offspring.remove(&id);
+        self.notify_listeners(&id, |l| matches!(l, ListenEvent::Destroyed(_)));
+        self.drop_listeners(&id);
+        if let Some(parent) = self.parents.remove(&id) {
+            let parent_id = parent.id();
+            if let Some(siblings) = self.offspring.get_mut(&parent_id) {
+                siblings.remove(&id);
+            }
+            if self.dying.contains(&parent_id) {
+                self.notify_destroyed(parent_id);
+            }
+        }
+    }
+
+    /// Remove the faulty component `id` from the component tree and kill all its children
+    fn orphan_children(&mut self, id: &Uuid) {
+        if let Some(parent) = self.parents.remove(id) {
+            let parent_id = parent.id();
+            if let Some(siblings) = self.offspring.get_mut(&parent_id) {
+                siblings.remove(id);
+            }
+            // the parent may have been waiting for this child
+            if self.dying.contains(&parent_id) {
+                self.notify_destroyed(parent_id);
+            } else {
+                self.notify_stopped(parent_id);
+            }
+        }
+        if let Some(offspring) = self.offspring.remove(id) {
+            for (child_id, child) in offspring {
+                debug!(
+                    self.ctx.log(),
+                    "Killing Component({}), because its parent Component({}) faulted.",
+                    child_id,
+                    id
+                );
+                self.parents.remove(&child_id);
+                child.enqueue_control(ControlEvent::Kill);
+            }
+        }
+    }
+
     fn shutdown_if_no_more_children(&mut self) {
         if self.shutdown.is_some() {
             if self.children.is_empty() {
@@ -260,8 +743,16 @@ impl Provide<SupervisionPort> for ComponentSupervisor {
             SupervisorMsg::Started(c) => {
                 let id = c.id();
                 self.children.insert(id, c.clone());
+                self.stopped.remove(&id);
                 debug!(self.ctx.log(), "Component({}) was started.", id);
                 self.notify_listeners(&id, |l| matches!(l, ListenEvent::Started(_)));
+                if let Some(offspring) = self.offspring.get(&id) {
+                    for (child_id, child) in offspring.iter() {
+                        if self.stopped_by_parent.remove(child_id) {
+                            child.enqueue_control(ControlEvent::Start);
+                        }
+                    }
+                }
                 if self.shutdown.is_some() {
                     warn!(
                         self.ctx.log(),
@@ -272,7 +763,17 @@ impl Provide<SupervisionPort> for ComponentSupervisor {
             }
             SupervisorMsg::Stopped(id) => {
                 debug!(self.ctx.log(), "Component({}) was stopped.", id);
-                self.notify_listeners(&id, |l| matches!(l, ListenEvent::Stopped(_)));
+                self.stopped.insert(id);
+                if let Some(offspring) = self.offspring.get(&id) {
+                    for (child_id, child) in offspring.iter() {
+                        if self.children.contains_key(child_id) && !self.stopped.contains(child_id)
+                        {
+                            self.stopped_by_parent.insert(*child_id);
+                            child.enqueue_control(ControlEvent::Stop);
+                        }
+                    }
+                }
+                self.notify_stopped(id);
             }
             SupervisorMsg::Killed(id) => {
                 match self.children.remove(&id) {
@@ -288,14 +789,23 @@ impl Provide<SupervisionPort> for ComponentSupervisor {
                         } else {
                             debug!(self.ctx.log(), "Component({}) was killed but there are still outstanding references preventing deallocation.", id);
                         }
-                        self.notify_listeners(&id, |l| matches!(l, ListenEvent::Destroyed(_)));
-                        self.drop_listeners(&id);
+                    }
+                    None if self.parents.contains_key(&id) => {
+                        trace!(self.ctx.log(), "Unstarted Component({}) was killed.", id)
                     }
                     None => warn!(self.ctx.log(), "An untracked Component({}) was killed.", id),
                 }
+                self.stopped.remove(&id);
+                self.stopped_by_parent.remove(&id);
+                if let Some(offspring) = self.offspring.get(&id) {
+                    for child in offspring.values() {
+                        child.enqueue_control(ControlEvent::Kill);
+                    }
+                }
+                self.notify_destroyed(id);
                 self.shutdown_if_no_more_children()
             }
-            SupervisorMsg::Faulty(recover_handler) => {
+            SupervisorMsg::Faulty(mut recover_handler) => {
                 let id = recover_handler.ctx.component_id;
                 warn!(
                     self.ctx.log(),
@@ -306,8 +816,13 @@ impl Provide<SupervisionPort> for ComponentSupervisor {
                     Some(carc) => drop(carc),
                     None => warn!(self.ctx.log(), "Component({}) faulted during start!.", id),
                 }
+                self.stopped.remove(&id);
+                self.stopped_by_parent.remove(&id);
+                self.dying.remove(&id);
+                recover_handler.ctx.parent = self.parents.get(&id).cloned();
+                self.orphan_children(&id);
                 if self.shutdown.is_none() {
-                    recover_handler.recover(self.ctx.context_system(), self.ctx.log());
+                    self.recover(recover_handler);
                 } else {
                     warn!(
                         self.log(),
@@ -331,6 +846,33 @@ impl Provide<SupervisionPort> for ComponentSupervisor {
                     event.id()
                 ),
             },
+            SupervisorMsg::Subscribe(subscriber) => {
+                trace!(self.ctx.log(), "Subscribing to supervision events.");
+                self.subscribers.push(subscriber);
+            }
+            SupervisorMsg::Adopt { parent, child } => {
+                let parent_id = parent.id();
+                let child_id = child.id();
+                if parent.core().is_terminated() || self.shutdown.is_some() {
+                    debug!(
+                        self.ctx.log(),
+                        "Parent Component({}) of Component({}) is already gone. Killing the child immediately!",
+                        parent_id,
+                        child_id
+                    );
+                    child.enqueue_control(ControlEvent::Kill);
+                } else {
+                    debug!(
+                        self.ctx.log(),
+                        "Component({}) is a child of Component({}).", child_id, parent_id
+                    );
+                    self.offspring
+                        .entry(parent_id)
+                        .or_default()
+                        .insert(child_id, child);
+                    self.parents.insert(child_id, parent);
+                }
+            }
             SupervisorMsg::Shutdown(amp) => match Arc::try_unwrap(amp) {
                 Ok(mp) => {
                     let promise = mp
@@ -360,3 +902,87 @@ impl Provide<SupervisionPort> for ComponentSupervisor {
         Handled::Ok
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hocon::HoconLoader;
+
+    const NO_DELAY: Option<Duration> = Some(Duration::from_millis(0));
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn one_for_one_restarts_immediately_forever() {
+        let mut history = RestartHistory::new(RestartStrategy::one_for_one());
+        let start = Instant::now();
+        for i in 0..100 {
+            assert_eq!(NO_DELAY, history.on_fault(start + ms(i)));
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max_and_resets() {
+        let strategy = RestartStrategy::one_for_one().with_backoff(ms(10), ms(50));
+        let mut history = RestartHistory::new(strategy);
+        let start = Instant::now();
+        let delays: Vec<Option<Duration>> =
+            (0..5).map(|i| history.on_fault(start + ms(i))).collect();
+        assert_eq!(
+            vec![
+                Some(ms(10)),
+                Some(ms(20)),
+                Some(ms(40)),
+                Some(ms(50)),
+                Some(ms(50))
+            ],
+            delays
+        );
+        history.on_restart(start + ms(100));
+        // still within max of the last restart
+        assert_eq!(Some(ms(50)), history.on_fault(start + ms(120)));
+        history.on_restart(start + ms(200));
+        // the replacement ran for longer than max
+        assert_eq!(Some(ms(10)), history.on_fault(start + ms(300)));
+    }
+
+    #[test]
+    fn limit_is_counted_within_window() {
+        let strategy = RestartStrategy::one_for_one().with_limit(2, ms(1000));
+        let mut history = RestartHistory::new(strategy);
+        let start = Instant::now();
+        assert_eq!(NO_DELAY, history.on_fault(start));
+        assert_eq!(NO_DELAY, history.on_fault(start + ms(600)));
+        // the first fault has left the window
+        assert_eq!(NO_DELAY, history.on_fault(start + ms(1100)));
+        assert_eq!(None, history.on_fault(start + ms(1200)));
+    }
+
+    #[test]
+    fn strategy_from_config() {
+        let config = HoconLoader::new()
+            .load_str(
+                r#"supervision.restart {
+                    min_backoff = 100 ms
+                    max_backoff = 10 s
+                    max_restarts = 5
+                    within = 1 minute
+                }"#,
+            )
+            .expect("config")
+            .hocon()
+            .expect("hocon");
+        let expected = RestartStrategy::one_for_one()
+            .with_backoff(ms(100), ms(10_000))
+            .with_limit(5, ms(60_000));
+        assert_eq!(expected, RestartStrategy::from_config(&config));
+
+        let empty = HoconLoader::new().hocon().expect("hocon");
+        assert_eq!(
+            RestartStrategy::one_for_one(),
+            RestartStrategy::from_config(&empty)
+        );
+    }
+}
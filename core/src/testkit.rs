@@ -0,0 +1,791 @@
+//! Utilities for testing components and actors.
+//!
+//! A [TestProbe](TestProbe) is an actor that records every message sent to it,
+//! and a [PortProbe](PortProbe) is a component that records every event
+//! triggered on the port it is connected to.
+//! Both offer expectations with timeouts, which fail the test by panicking,
+//! so that tests neither have to sleep for an arbitrary amount of time,
+//! nor build throwaway components just to observe the component under test.
+//!
+//! # Example
+//!
+//! ```
+//! use kompact::{prelude::*, testkit::*};
+//! use std::time::Duration;
+//!
+//! let system = local_system();
+//! let probe: TestProbe<String> = TestProbe::new(&system);
+//! probe.actor_ref().tell("Hello".to_string());
+//! assert_eq!("Hello", probe.expect_msg(Duration::from_secs(1)));
+//! probe.expect_no_msg(Duration::from_millis(100));
+//! system.shutdown().expect("shutdown");
+//! ```
+
+use super::{
+    faults::NetworkFaults,
+    prelude::*,
+    runtime::DeterministicScheduler,
+    timer::VirtualTimer,
+};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+// How long to wait for probes to start before giving up
+const START_TIMEOUT: Duration = Duration::from_millis(5000);
+
+/// Builds a system for a single test, without networking
+pub fn local_system() -> KompactSystem {
+    KompactConfig::default().build().expect("KompactSystem")
+}
+
+/// Builds a system for a single test, with a [NetworkDispatcher](NetworkDispatcher)
+/// listening on a free port on localhost
+pub fn networked_system() -> KompactSystem {
+    let mut cfg = KompactConfig::default();
+    cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+    cfg.build().expect("KompactSystem")
+}
+
+/// Builds a system like [networked_system](networked_system), whose dispatcher
+/// injects the faults configured in `faults` into its outgoing messages
+///
+/// Pass clones of the same handle to all systems of a test to control the whole network at once.
+pub fn networked_system_with_faults(faults: &NetworkFaults) -> KompactSystem {
+    let mut net_cfg = NetworkConfig::default();
+    net_cfg.set_fault_injection(faults.clone());
+    let mut cfg = KompactConfig::default();
+    cfg.system_components(DeadletterBox::new, net_cfg.build());
+    cfg.build().expect("KompactSystem")
+}
+
+/// Polls `condition` until it holds, or `timeout` has expired
+///
+/// Returns whether `condition` held in the end.
+pub fn wait_until<F>(timeout: Duration, mut condition: F) -> bool
+where
+    F: FnMut() -> bool,
+{
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    condition()
+}
+
+/// A system that runs on a [DeterministicScheduler](DeterministicScheduler) and a [VirtualTimer](VirtualTimer)
+///
+/// Together, these make a whole run of the system, including timeouts, reproducible from its `seed`,
+/// as long as the test only interacts with the system while it is idle.
+/// That is, call [run_until_idle](Simulation::run_until_idle) or [advance](Simulation::advance)
+/// before each new input and before each assertion.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{prelude::*, testkit::*};
+/// use std::time::Duration;
+///
+/// let simulation = Simulation::new(42);
+/// let probe: TestProbe<u64> = TestProbe::new(simulation.system());
+/// probe.actor_ref().tell(1u64);
+/// simulation.run_until_idle();
+/// assert_eq!(1, probe.expect_msg(Duration::from_millis(0)));
+/// simulation.advance(Duration::from_secs(10));
+/// simulation.shutdown();
+/// ```
+pub struct Simulation {
+    system: KompactSystem,
+    scheduler: DeterministicScheduler,
+    timer: VirtualTimer,
+}
+
+impl Simulation {
+    /// Builds a simulated system with the default configuration, without networking
+    pub fn new(seed: u64) -> Self {
+        Simulation::with_config(KompactConfig::default(), seed)
+    }
+
+    /// Builds a simulated system from `cfg`, replacing its scheduler and timer
+    pub fn with_config(mut cfg: KompactConfig, seed: u64) -> Self {
+        let scheduler = DeterministicScheduler::with_seed(seed);
+        let timer = VirtualTimer::new();
+        cfg.scheduler({
+            let scheduler = scheduler.clone();
+            move |_| Box::new(scheduler.clone())
+        });
+        cfg.timer::<VirtualTimer, _>({
+            let timer = timer.clone();
+            move || Box::new(timer.clone())
+        });
+        let system = cfg.build().expect("KompactSystem");
+        scheduler.run_until_idle();
+        Simulation {
+            system,
+            scheduler,
+            timer,
+        }
+    }
+
+    /// The simulated system
+    pub fn system(&self) -> &KompactSystem {
+        &self.system
+    }
+
+    /// The scheduler running the simulated system
+    pub fn scheduler(&self) -> &DeterministicScheduler {
+        &self.scheduler
+    }
+
+    /// The timer of the simulated system
+    pub fn timer(&self) -> &VirtualTimer {
+        &self.timer
+    }
+
+    /// Runs the system until no component has any work left
+    pub fn run_until_idle(&self) -> () {
+        self.scheduler.run_until_idle();
+    }
+
+    /// Advances virtual time by `by`
+    ///
+    /// Virtual time jumps from deadline to deadline, and the system is run until idle
+    /// after the timeouts of each deadline have fired, so timeouts scheduled
+    /// in reaction to earlier ones fire as well.
+    pub fn advance(&self, by: Duration) -> () {
+        let target = self.timer.now() + by;
+        self.run_until_idle();
+        while let Some(deadline) = self.timer.next_deadline().filter(|d| *d <= target) {
+            self.fire_on_scheduler(deadline);
+        }
+        self.fire_on_scheduler(target);
+    }
+
+    // Firing from the test thread would race with the components it wakes up
+    fn fire_on_scheduler(&self, deadline: Duration) -> () {
+        let timer = self.timer.clone();
+        drop(self.system.spawn(async move { timer.fire_until(deadline) }));
+        self.run_until_idle();
+    }
+
+    /// Shuts down the simulated system
+    pub fn shutdown(self) -> () {
+        self.system.shutdown().expect("shutdown");
+    }
+}
+
+fn expect_event<T: Debug>(events: &Receiver<T>, kind: &str, timeout: Duration) -> T {
+    match events.recv_timeout(timeout) {
+        Ok(event) => event,
+        Err(RecvTimeoutError::Timeout) => panic!("Expected a {} within {:?}", kind, timeout),
+        Err(RecvTimeoutError::Disconnected) => {
+            panic!("Expected a {}, but the probe was deallocated", kind)
+        }
+    }
+}
+
+fn expect_no_event<T: Debug>(events: &Receiver<T>, kind: &str, duration: Duration) -> () {
+    if let Ok(event) = events.recv_timeout(duration) {
+        panic!(
+            "Expected no {} within {:?}, but got {:?}",
+            kind, duration, event
+        );
+    }
+}
+
+/// The component behind a [TestProbe](TestProbe)
+#[derive(ComponentDefinition)]
+pub struct ProbeActor<M: MessageBounds> {
+    ctx: ComponentContext<Self>,
+    messages: Sender<MsgEnvelope<M>>,
+}
+
+impl<M: MessageBounds> ComponentLifecycle for ProbeActor<M> {}
+
+impl<M: MessageBounds> Actor for ProbeActor<M> {
+    type Message = M;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        // the probe may have been dropped already, in which case nobody cares anymore
+        let _ = self.messages.send(MsgEnvelope::Typed(msg));
+        Handled::Ok
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let _ = self.messages.send(MsgEnvelope::Net(Box::new(msg)));
+        Handled::Ok
+    }
+}
+
+/// An actor that records all messages sent to it, for making assertions about them
+///
+/// Both local messages of type `M` and network messages are recorded,
+/// in the order they arrived.
+/// Hand out the probe's [actor reference](ActorRefFactory::actor_ref) or
+/// [registered path](TestProbe::register) to the component under test,
+/// and then check the messages it sent with the `expect_*` functions.
+pub struct TestProbe<M: MessageBounds> {
+    component: Arc<Component<ProbeActor<M>>>,
+    messages: Receiver<MsgEnvelope<M>>,
+}
+
+impl<M: MessageBounds> TestProbe<M> {
+    /// Creates and starts a new probe on `system`
+    pub fn new(system: &KompactSystem) -> Self {
+        let (sender, messages) = unbounded();
+        let component = system.create(move || ProbeActor {
+            ctx: ComponentContext::uninitialised(),
+            messages: sender,
+        });
+        system
+            .start_notify(&component)
+            .wait_timeout(START_TIMEOUT)
+            .expect("TestProbe never started");
+        TestProbe {
+            component,
+            messages,
+        }
+    }
+
+    /// Returns the probe's underlying component
+    pub fn component(&self) -> &Arc<Component<ProbeActor<M>>> {
+        &self.component
+    }
+
+    /// Registers the probe with the system's dispatcher and returns its unique path
+    ///
+    /// This is required for receiving messages via [ActorPath](ActorPath)s.
+    pub fn register(&self) -> ActorPath {
+        self.component
+            .system()
+            .register(&self.component)
+            .wait_timeout(START_TIMEOUT)
+            .expect("TestProbe registration timed out")
+            .expect("TestProbe registration failed")
+    }
+
+    /// Returns the next local message, failing if none arrives within `timeout`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the next message doesn't arrive in time or is a network message.
+    pub fn expect_msg(&self, timeout: Duration) -> M {
+        match expect_event(&self.messages, "message", timeout) {
+            MsgEnvelope::Typed(msg) => msg,
+            MsgEnvelope::Net(msg) => panic!(
+                "Expected a local message, but got a network message: {:?}",
+                msg
+            ),
+        }
+    }
+
+    /// Returns the next network message, failing if none arrives within `timeout`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the next message doesn't arrive in time or is a local message.
+    pub fn expect_net_msg(&self, timeout: Duration) -> NetMessage {
+        match expect_event(&self.messages, "message", timeout) {
+            MsgEnvelope::Net(msg) => *msg,
+            MsgEnvelope::Typed(msg) => panic!(
+                "Expected a network message, but got a local message: {:?}",
+                msg
+            ),
+        }
+    }
+
+    /// Fails if any message arrives within `duration`
+    ///
+    /// This always waits for the full `duration`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a message arrives in time.
+    pub fn expect_no_msg(&self, duration: Duration) -> () {
+        expect_no_event(&self.messages, "message", duration)
+    }
+}
+
+impl<M: MessageBounds> ActorRefFactory for TestProbe<M> {
+    type Message = M;
+
+    fn actor_ref(&self) -> ActorRef<M> {
+        self.component.actor_ref()
+    }
+}
+
+/// The component behind a [PortProbe](PortProbe)
+#[derive(ComponentDefinition)]
+pub struct PortProbeComponent<P: Port + 'static> {
+    ctx: ComponentContext<Self>,
+    provided: ProvidedPort<P>,
+    required: RequiredPort<P>,
+    requests: Sender<P::Request>,
+    indications: Sender<P::Indication>,
+}
+
+impl<P: Port + 'static> ComponentLifecycle for PortProbeComponent<P> {}
+
+impl<P: Port + 'static> Provide<P> for PortProbeComponent<P> {
+    fn handle(&mut self, event: P::Request) -> Handled {
+        let _ = self.requests.send(event);
+        Handled::Ok
+    }
+}
+
+impl<P: Port + 'static> Require<P> for PortProbeComponent<P> {
+    fn handle(&mut self, event: P::Indication) -> Handled {
+        let _ = self.indications.send(event);
+        Handled::Ok
+    }
+}
+
+impl<P: Port + 'static> Actor for PortProbeComponent<P> {
+    type Message = Never;
+
+    fn receive_local(&mut self, msg: Self::Message) -> Handled {
+        match msg {}
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        warn!(self.log(), "PortProbe ignoring network message: {:?}", msg);
+        Handled::Ok
+    }
+}
+
+/// A component that records all events on port `P`, for making assertions about them
+///
+/// The probe both provides and requires `P`.
+/// Connect it to a component providing `P` with [connect_to_provider](PortProbe::connect_to_provider)
+/// to record that component's indications, and to a component requiring `P` with
+/// [connect_to_requirer](PortProbe::connect_to_requirer) to record its requests.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{prelude::*, testkit::*};
+/// use std::time::Duration;
+///
+/// struct EchoPort;
+/// impl Port for EchoPort {
+///     type Indication = String;
+///     type Request = String;
+/// }
+///
+/// #[derive(ComponentDefinition, Actor)]
+/// struct Echo {
+///     ctx: ComponentContext<Self>,
+///     echo_port: ProvidedPort<EchoPort>,
+/// }
+/// ignore_lifecycle!(Echo);
+/// impl Provide<EchoPort> for Echo {
+///     fn handle(&mut self, event: String) -> Handled {
+///         self.echo_port.trigger(event);
+///         Handled::Ok
+///     }
+/// }
+///
+/// let system = local_system();
+/// let echo = system.create(|| Echo {
+///     ctx: ComponentContext::uninitialised(),
+///     echo_port: ProvidedPort::uninitialised(),
+/// });
+/// let probe: PortProbe<EchoPort> = PortProbe::new(&system);
+/// probe.connect_to_provider(&echo);
+/// system.start_notify(&echo).wait();
+///
+/// probe.trigger_request("Hello".to_string());
+/// assert_eq!("Hello", probe.expect_indication(Duration::from_secs(1)));
+/// system.shutdown().expect("shutdown");
+/// ```
+pub struct PortProbe<P: Port + 'static> {
+    component: Arc<Component<PortProbeComponent<P>>>,
+    requests: Receiver<P::Request>,
+    indications: Receiver<P::Indication>,
+}
+
+impl<P: Port + 'static> PortProbe<P> {
+    /// Creates and starts a new port probe on `system`
+    pub fn new(system: &KompactSystem) -> Self {
+        let (request_sender, requests) = unbounded();
+        let (indication_sender, indications) = unbounded();
+        let component = system.create(move || PortProbeComponent {
+            ctx: ComponentContext::uninitialised(),
+            provided: ProvidedPort::uninitialised(),
+            required: RequiredPort::uninitialised(),
+            requests: request_sender,
+            indications: indication_sender,
+        });
+        system
+            .start_notify(&component)
+            .wait_timeout(START_TIMEOUT)
+            .expect("PortProbe never started");
+        PortProbe {
+            component,
+            requests,
+            indications,
+        }
+    }
+
+    /// Returns the probe's underlying component
+    pub fn component(&self) -> &Arc<Component<PortProbeComponent<P>>> {
+        &self.component
+    }
+
+    /// Connects the probe to `provider`'s instance of `P`,
+    /// so that it records `provider`'s indications and can send requests to it
+    pub fn connect_to_provider<C>(&self, provider: &Arc<Component<C>>) -> ()
+    where
+        C: ComponentDefinition + Provide<P> + ProvideRef<P> + Sized + 'static,
+    {
+        let probe_ref: RequiredRef<P> = self.component.on_definition(|cd| cd.required.share());
+        let provider_ref: ProvidedRef<P> = provider.on_definition(|cd| {
+            cd.connect_to_required(probe_ref);
+            cd.provided_ref()
+        });
+        self.component
+            .on_definition(|cd| cd.required.connect(provider_ref));
+    }
+
+    /// Connects the probe to `requirer`'s instance of `P`,
+    /// so that it records `requirer`'s requests and can send indications to it
+    pub fn connect_to_requirer<C>(&self, requirer: &Arc<Component<C>>) -> ()
+    where
+        C: ComponentDefinition + Require<P> + RequireRef<P> + Sized + 'static,
+    {
+        let probe_ref: ProvidedRef<P> = self.component.on_definition(|cd| cd.provided.share());
+        let requirer_ref: RequiredRef<P> = requirer.on_definition(|cd| {
+            cd.connect_to_provided(probe_ref);
+            cd.required_ref()
+        });
+        self.component
+            .on_definition(|cd| cd.provided.connect(requirer_ref));
+    }
+
+    /// Triggers `request` on the probe's required port, i.e. towards connected providers
+    pub fn trigger_request(&self, request: P::Request) -> () {
+        self.component
+            .on_definition(|cd| cd.required.trigger(request));
+    }
+
+    /// Triggers `indication` on the probe's provided port, i.e. towards connected requirers
+    pub fn trigger_indication(&self, indication: P::Indication) -> () {
+        self.component
+            .on_definition(|cd| cd.provided.trigger(indication));
+    }
+
+    /// Returns the next indication from a connected provider,
+    /// failing if none arrives within `timeout`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no indication arrives in time.
+    pub fn expect_indication(&self, timeout: Duration) -> P::Indication {
+        expect_event(&self.indications, "indication", timeout)
+    }
+
+    /// Fails if any indication arrives within `duration`
+    ///
+    /// This always waits for the full `duration`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an indication arrives in time.
+    pub fn expect_no_indication(&self, duration: Duration) -> () {
+        expect_no_event(&self.indications, "indication", duration)
+    }
+
+    /// Returns the next request from a connected requirer,
+    /// failing if none arrives within `timeout`
+    ///
+    /// # Panics
+    ///
+    /// Panics if no request arrives in time.
+    pub fn expect_request(&self, timeout: Duration) -> P::Request {
+        expect_event(&self.requests, "request", timeout)
+    }
+
+    /// Fails if any request arrives within `duration`
+    ///
+    /// This always waits for the full `duration`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a request arrives in time.
+    pub fn expect_no_request(&self, duration: Duration) -> () {
+        expect_no_event(&self.requests, "request", duration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(1000);
+
+    struct CountPort;
+
+    impl Port for CountPort {
+        type Indication = u64;
+        type Request = u64;
+    }
+
+    // requests the sum of all numbers it has been sent
+    #[derive(ComponentDefinition)]
+    struct Counter {
+        ctx: ComponentContext<Self>,
+        count_port: RequiredPort<CountPort>,
+        total: u64,
+        last_indication: Option<u64>,
+    }
+
+    impl Counter {
+        fn new() -> Self {
+            Counter {
+                ctx: ComponentContext::uninitialised(),
+                count_port: RequiredPort::uninitialised(),
+                total: 0,
+                last_indication: None,
+            }
+        }
+    }
+
+    ignore_lifecycle!(Counter);
+
+    impl Require<CountPort> for Counter {
+        fn handle(&mut self, event: u64) -> Handled {
+            self.last_indication = Some(event);
+            Handled::Ok
+        }
+    }
+
+    impl Actor for Counter {
+        type Message = u64;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            self.total += msg;
+            self.count_port.trigger(self.total);
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn test_probe_messages() {
+        let system = local_system();
+        let probe: TestProbe<u64> = TestProbe::new(&system);
+        probe.actor_ref().tell(1u64);
+        probe.actor_ref().tell(2u64);
+        assert_eq!(1, probe.expect_msg(TIMEOUT));
+        assert_eq!(2, probe.expect_msg(TIMEOUT));
+        probe.expect_no_msg(Duration::from_millis(100));
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected a message within")]
+    fn test_probe_missing_message() {
+        let system = local_system();
+        let probe: TestProbe<u64> = TestProbe::new(&system);
+        probe.expect_msg(Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_probe_network_messages() {
+        let system = networked_system();
+        let probe: TestProbe<u64> = TestProbe::new(&system);
+        let probe_path = probe.register();
+
+        let remote = networked_system();
+        let sender: TestProbe<u64> = TestProbe::new(&remote);
+        let sender_path = sender.register();
+        probe_path.tell_with_sender(42u64, &remote, sender_path.clone());
+        let msg = probe.expect_net_msg(TIMEOUT);
+        assert_eq!(sender_path, *msg.sender());
+        assert_eq!(42, msg.try_deserialise::<u64, u64>().expect("u64"));
+
+        remote.shutdown().expect("shutdown");
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_port_probe() {
+        let system = local_system();
+        let counter = system.create(Counter::new);
+        let probe: PortProbe<CountPort> = PortProbe::new(&system);
+        probe.connect_to_requirer(&counter);
+        system
+            .start_notify(&counter)
+            .wait_timeout(TIMEOUT)
+            .expect("counter never started");
+
+        counter.actor_ref().tell(3u64);
+        counter.actor_ref().tell(4u64);
+        assert_eq!(3, probe.expect_request(TIMEOUT));
+        assert_eq!(7, probe.expect_request(TIMEOUT));
+        probe.expect_no_request(Duration::from_millis(100));
+
+        probe.trigger_indication(5);
+        assert!(
+            wait_until(TIMEOUT, || counter
+                .on_definition(|cd| cd.last_indication == Some(5))),
+            "Counter should have received the indication"
+        );
+        probe.expect_no_indication(Duration::from_millis(100));
+        system.shutdown().expect("shutdown");
+    }
+
+    // forwards a decreasing hop count around a ring of relays, reporting each hop to a probe
+    #[derive(ComponentDefinition)]
+    struct Relay {
+        ctx: ComponentContext<Self>,
+        id: u64,
+        next: Option<ActorRef<u64>>,
+        report: ActorRef<(u64, u64)>,
+    }
+
+    ignore_lifecycle!(Relay);
+
+    impl Actor for Relay {
+        type Message = u64;
+
+        fn receive_local(&mut self, hops: Self::Message) -> Handled {
+            self.report.tell((self.id, hops));
+            if hops > 0 {
+                self.next.as_ref().expect("next relay").tell(hops - 1);
+            }
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    fn relay_trace(seed: u64) -> Vec<(u64, u64)> {
+        const NUM_RELAYS: u64 = 5;
+        let simulation = Simulation::new(seed);
+        let system = simulation.system();
+        let probe: TestProbe<(u64, u64)> = TestProbe::new(system);
+        let relays: Vec<Arc<Component<Relay>>> = (0..NUM_RELAYS)
+            .map(|id| {
+                system.create(|| Relay {
+                    ctx: ComponentContext::uninitialised(),
+                    id,
+                    next: None,
+                    report: probe.actor_ref(),
+                })
+            })
+            .collect();
+        for (i, relay) in relays.iter().enumerate() {
+            let next = relays[(i + 1) % relays.len()].actor_ref();
+            relay.on_definition(|cd| cd.next = Some(next));
+            system.start(relay);
+        }
+        simulation.run_until_idle();
+        // inject all tokens through a single future, so they arrive in one scheduled step
+        let refs: Vec<ActorRef<u64>> = relays.iter().map(|r| r.actor_ref()).collect();
+        drop(system.spawn(async move {
+            for relay in refs {
+                relay.tell(3u64);
+            }
+        }));
+        simulation.run_until_idle();
+        let trace = (0..(NUM_RELAYS * 4))
+            .map(|_| probe.expect_msg(Duration::from_millis(0)))
+            .collect();
+        probe.expect_no_msg(Duration::from_millis(0));
+        simulation.shutdown();
+        trace
+    }
+
+    #[test]
+    fn test_simulation_is_reproducible() {
+        let trace = relay_trace(42);
+        assert_eq!(trace, relay_trace(42));
+        let distinct: std::collections::HashSet<Vec<(u64, u64)>> =
+            (0..10).map(relay_trace).collect();
+        assert!(
+            distinct.len() > 1,
+            "Different seeds should produce different interleavings"
+        );
+    }
+
+    // reports an increasing tick count every 100ms until told to stop
+    #[derive(ComponentDefinition)]
+    struct Ticker {
+        ctx: ComponentContext<Self>,
+        report: ActorRef<u64>,
+        ticks: u64,
+        timer: Option<ScheduledTimer>,
+    }
+
+    impl ComponentLifecycle for Ticker {
+        fn on_start(&mut self) -> Handled {
+            let period = Duration::from_millis(100);
+            let timer = self.schedule_periodic(period, period, |new_self, _id| {
+                new_self.ticks += 1;
+                new_self.report.tell(new_self.ticks);
+                Handled::Ok
+            });
+            self.timer = Some(timer);
+            Handled::Ok
+        }
+    }
+
+    impl Actor for Ticker {
+        type Message = ();
+
+        fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+            if let Some(timer) = self.timer.take() {
+                self.cancel_timer(timer);
+            }
+            Handled::Ok
+        }
+
+        fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+            unimplemented!();
+        }
+    }
+
+    #[test]
+    fn test_simulation_virtual_time() {
+        let simulation = Simulation::new(1);
+        let system = simulation.system();
+        let probe: TestProbe<u64> = TestProbe::new(system);
+        let report = probe.actor_ref();
+        let ticker = system.create(move || Ticker {
+            ctx: ComponentContext::uninitialised(),
+            report,
+            ticks: 0,
+            timer: None,
+        });
+        system.start(&ticker);
+        simulation.run_until_idle();
+        probe.expect_no_msg(Duration::from_millis(0));
+
+        simulation.advance(Duration::from_millis(350));
+        assert_eq!(Duration::from_millis(350), simulation.timer().now());
+        for tick in 1..=3u64 {
+            assert_eq!(tick, probe.expect_msg(Duration::from_millis(0)));
+        }
+        probe.expect_no_msg(Duration::from_millis(0));
+
+        ticker.actor_ref().tell(());
+        simulation.run_until_idle();
+        simulation.advance(Duration::from_secs(1));
+        probe.expect_no_msg(Duration::from_millis(0));
+        assert_eq!(0, simulation.timer().num_scheduled());
+        simulation.shutdown();
+    }
+}
@@ -4,15 +4,21 @@ use hierarchical_hash_wheel_timer::{
     thread_timer::{TimerRef as GenericTimerRef, TimerWithThread as GenericTimerWithThread},
     OneshotState,
     PeriodicState,
+    Timer as LowlevelTimer,
     TimerEntry as GenericTimerEntry,
     TimerReturn as GenericTimerReturn,
 };
+use std::time::Duration;
 
 pub use hierarchical_hash_wheel_timer::TimerError;
 
 pub(crate) mod timer_manager;
 use timer_manager::{Timeout, TimerActorRef};
 
+mod virtual_timer;
+pub use virtual_timer::VirtualTimer;
+use virtual_timer::{TimeoutTracker, VirtualTimerRef};
+
 /// Indicate whether or not to reschedule a periodic timer
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TimerReturn {
@@ -48,7 +54,69 @@ impl From<TimerReturn> for GenericTimerReturn<()> {
 pub type TimerEntry = GenericTimerEntry<Uuid, ActorRefState, ActorRefState>;
 
 /// The reference type for the timer thread
-pub type TimerRef = GenericTimerRef<Uuid, ActorRefState, ActorRefState>;
+pub type ThreadTimerRef = GenericTimerRef<Uuid, ActorRefState, ActorRefState>;
+
+/// A reference to the timer of a Kompact system
+///
+/// This is used to schedule and cancel timeouts, independently of
+/// whether the timer runs on its own thread, like the default timer,
+/// or in virtual time, like the [VirtualTimer](VirtualTimer).
+pub struct TimerRef {
+    inner: TimerRefImpl,
+}
+
+enum TimerRefImpl {
+    Thread(ThreadTimerRef),
+    Virtual(VirtualTimerRef),
+}
+
+impl From<ThreadTimerRef> for TimerRef {
+    fn from(timer: ThreadTimerRef) -> Self {
+        TimerRef {
+            inner: TimerRefImpl::Thread(timer),
+        }
+    }
+}
+
+impl From<VirtualTimerRef> for TimerRef {
+    fn from(timer: VirtualTimerRef) -> Self {
+        TimerRef {
+            inner: TimerRefImpl::Virtual(timer),
+        }
+    }
+}
+
+impl LowlevelTimer for TimerRef {
+    type Id = Uuid;
+    type OneshotState = ActorRefState;
+    type PeriodicState = ActorRefState;
+
+    fn schedule_once(&mut self, timeout: Duration, state: Self::OneshotState) -> () {
+        match self.inner {
+            TimerRefImpl::Thread(ref mut timer) => timer.schedule_once(timeout, state),
+            TimerRefImpl::Virtual(ref mut timer) => timer.schedule_once(timeout, state),
+        }
+    }
+
+    fn schedule_periodic(
+        &mut self,
+        delay: Duration,
+        period: Duration,
+        state: Self::PeriodicState,
+    ) -> () {
+        match self.inner {
+            TimerRefImpl::Thread(ref mut timer) => timer.schedule_periodic(delay, period, state),
+            TimerRefImpl::Virtual(ref mut timer) => timer.schedule_periodic(delay, period, state),
+        }
+    }
+
+    fn cancel(&mut self, id: &Self::Id) -> () {
+        match self.inner {
+            TimerRefImpl::Thread(ref mut timer) => timer.cancel(id),
+            TimerRefImpl::Virtual(ref mut timer) => timer.cancel(id),
+        }
+    }
+}
 
 /// The concrete vairant of timer thread used in Kompact
 pub type TimerWithThread = GenericTimerWithThread<Uuid, ActorRefState, ActorRefState>;
@@ -75,7 +143,7 @@ impl OneshotState for ActorRefState {
 
     fn trigger(self) -> () {
         // Ignore the Result, as we are anyway not trying to reschedule this timer
-        let _res = self.receiver.enqueue(Timeout(self.id));
+        let _res = self.receiver.enqueue(Timeout::new(self.id));
     }
 }
 
@@ -88,7 +156,7 @@ impl PeriodicState for ActorRefState {
 
     fn trigger(self) -> GenericTimerReturn<Self> {
         // Ignore the Result, as we are anyway not trying to reschedule this timer
-        match self.receiver.enqueue(Timeout(self.id)) {
+        match self.receiver.enqueue(Timeout::new(self.id)) {
             Ok(_) => GenericTimerReturn::Reschedule(self),
             Err(_) => GenericTimerReturn::Cancel, // Queue has probably been deallocated, so no point in trying this over and over again
         }
@@ -1,6 +1,7 @@
 use super::*;
 use hierarchical_hash_wheel_timer::Timer as LowlevelTimer;
 use std::{
+    cell::RefCell,
     collections::HashMap,
     fmt,
     rc::Rc,
@@ -203,8 +204,25 @@ where
     }
 }
 
-#[derive(Clone, Debug)]
-pub(crate) struct Timeout(pub(crate) Uuid);
+#[derive(Debug)]
+pub(crate) struct Timeout {
+    pub(crate) id: Uuid,
+    // lets a virtual timer know when the timeout has been handled
+    pub(crate) tracker: Option<TimeoutTracker>,
+}
+
+impl Timeout {
+    pub(crate) fn new(id: Uuid) -> Self {
+        Timeout { id, tracker: None }
+    }
+
+    pub(crate) fn tracked(id: Uuid, tracker: TimeoutTracker) -> Self {
+        Timeout {
+            id,
+            tracker: Some(tracker),
+        }
+    }
+}
 
 pub(crate) enum ExecuteAction<C: ComponentDefinition> {
     None,
@@ -231,21 +249,46 @@ impl<C: ComponentDefinition> TimerManager<C> {
         TimerActorRef::new(component, Arc::downgrade(&self.timer_queue))
     }
 
+    /// The number of expired timeouts waiting to be handled
+    pub(crate) fn backlog(&self) -> usize {
+        self.timer_queue.len()
+    }
+
+    /// The number of timers that are currently scheduled
+    pub(crate) fn scheduled(&self) -> usize {
+        self.handles.len()
+    }
+
     pub(crate) fn try_action(&mut self) -> ExecuteAction<C> {
-        if let Ok(timeout) = self.timer_queue.pop() {
-            let res = self.handles.remove(&timeout.0);
-            match res {
-                Some(TimerHandle::OneShot { action, .. }) => ExecuteAction::Once(timeout.0, action),
-                Some(TimerHandle::Periodic { action, .. }) => {
+        if let Ok(Timeout { id, tracker }) = self.timer_queue.pop() {
+            let res = self.handles.remove(&id);
+            match (res, tracker) {
+                (Some(TimerHandle::OneShot { action, .. }), None) => ExecuteAction::Once(id, action),
+                (Some(TimerHandle::OneShot { action, .. }), Some(tracker)) => {
+                    // only drop the tracker once the action has actually run
+                    let tracked = move |new_self: &mut C, id| {
+                        let res = action(new_self, id);
+                        drop(tracker);
+                        res
+                    };
+                    ExecuteAction::Once(id, Box::new(tracked))
+                }
+                (Some(TimerHandle::Periodic { action, .. }), tracker) => {
                     let action2 = action.clone();
-                    self.handles.insert(
-                        timeout.0,
-                        TimerHandle::Periodic {
-                            _id: timeout.0,
-                            action,
-                        },
-                    );
-                    ExecuteAction::Periodic(timeout.0, action2)
+                    self.handles
+                        .insert(id, TimerHandle::Periodic { _id: id, action });
+                    match tracker {
+                        None => ExecuteAction::Periodic(id, action2),
+                        Some(tracker) => {
+                            let tracker = RefCell::new(Some(tracker));
+                            let tracked = move |new_self: &mut C, id| {
+                                let res = action2(new_self, id);
+                                tracker.borrow_mut().take();
+                                res
+                            };
+                            ExecuteAction::Periodic(id, Rc::new(tracked))
+                        }
+                    }
                 }
                 _ => ExecuteAction::None,
             }
@@ -346,6 +389,14 @@ impl TimerActorRef {
         }
     }
 
+    /// Returns `true` if the target component is still around and running
+    pub(crate) fn is_target_active(&self) -> bool {
+        match self.component.upgrade() {
+            Some(c) => c.core().is_active(),
+            None => false,
+        }
+    }
+
     pub(crate) fn enqueue(&self, timeout: Timeout) -> Result<(), QueueingError> {
         match (self.msg_queue.upgrade(), self.component.upgrade()) {
             (Some(q), Some(c)) => {
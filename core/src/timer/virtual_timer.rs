@@ -0,0 +1,460 @@
+use super::*;
+use crate::{
+    runtime::TimerComponent,
+    timer::timer_manager::{QueueingError, TimerRefFactory},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+/// A timer that runs in virtual time, which only passes when it is advanced explicitly
+///
+/// Timeouts are fired strictly in the order of their deadlines,
+/// and timeouts with the same deadline in the order they were scheduled.
+/// Thus, unlike the default timer, the same sequence of calls always fires the same timeouts
+/// in the same order, no matter how long the system actually takes to run.
+/// This makes tests of timeout logic both fast and reliable, since they need not
+/// wait for any wall-clock time to pass.
+///
+/// Plug it into a system via [KompactConfig::timer](crate::prelude::KompactConfig::timer),
+/// keeping a clone around to [advance](VirtualTimer::advance) the clock.
+/// It works with any scheduler, but for fully reproducible runs, pair it with a
+/// [DeterministicScheduler](crate::runtime::DeterministicScheduler), as the
+/// [Simulation](crate::testkit::Simulation) in the testkit does.
+///
+/// # Example
+///
+/// ```
+/// use kompact::{prelude::*, timer::VirtualTimer};
+/// use std::time::Duration;
+///
+/// let timer = VirtualTimer::new();
+/// let mut cfg = KompactConfig::default();
+/// cfg.timer::<VirtualTimer, _>({
+///     let timer = timer.clone();
+///     move || Box::new(timer.clone())
+/// });
+/// let system = cfg.build().expect("system");
+/// timer.advance(Duration::from_secs(60));
+/// assert_eq!(Duration::from_secs(60), timer.now());
+/// # system.shutdown().expect("shutdown");
+/// ```
+#[derive(Clone)]
+pub struct VirtualTimer {
+    clock: Arc<Mutex<VirtualClock>>,
+    pending: Arc<PendingTimeouts>,
+}
+
+impl VirtualTimer {
+    /// Create a new virtual timer, starting at time zero
+    pub fn new() -> VirtualTimer {
+        let pending = Arc::new(PendingTimeouts::new());
+        VirtualTimer {
+            clock: Arc::new(Mutex::new(VirtualClock::new(pending.clone()))),
+            pending,
+        }
+    }
+
+    /// The amount of virtual time that has passed since this timer was created
+    pub fn now(&self) -> Duration {
+        lock(&self.clock).now
+    }
+
+    /// The deadline of the next scheduled timeout, if any
+    pub fn next_deadline(&self) -> Option<Duration> {
+        lock(&self.clock)
+            .entries
+            .keys()
+            .next()
+            .map(|(deadline, _)| *deadline)
+    }
+
+    /// The number of scheduled timeouts
+    ///
+    /// Each periodic timer counts as a single timeout.
+    pub fn num_scheduled(&self) -> usize {
+        lock(&self.clock).entries.len()
+    }
+
+    /// Advance the virtual time by `by`, firing all timeouts that become due on the way
+    ///
+    /// The clock jumps from deadline to deadline, and after firing the timeouts for
+    /// each deadline, it waits until their components have handled them, before moving on.
+    /// Thus periodic timeouts fire as many times as their period fits into `by`,
+    /// and timeouts that are scheduled in reaction to earlier ones fire as well,
+    /// if their deadline falls within `by`.
+    ///
+    /// Timeouts for components that are not active are fired, but not waited for.
+    ///
+    /// # Note
+    ///
+    /// Never call this from within a component, as it may have to wait for that component.
+    pub fn advance(&self, by: Duration) -> () {
+        let target = self.now() + by;
+        self.pending.wait_until_handled();
+        while let Some(deadline) = self.next_deadline().filter(|d| *d <= target) {
+            self.fire_until(deadline);
+            self.pending.wait_until_handled();
+        }
+        self.fire_until(target);
+    }
+
+    /// Fire all timeouts that are due at the current virtual time,
+    /// and wait until they, and any due timeouts scheduled in reaction to them, have been handled
+    ///
+    /// The same notes as for [advance](VirtualTimer::advance) apply.
+    pub fn run_until_idle(&self) -> () {
+        self.advance(Duration::from_millis(0));
+        self.pending.wait_until_handled();
+    }
+
+    /// Advance the virtual time to `deadline`, firing all timeouts that become due on the way,
+    /// but without waiting for any of them to be handled
+    pub(crate) fn fire_until(&self, deadline: Duration) -> () {
+        while let Some(entry) = pop_due(&self.clock, deadline) {
+            fire(&self.clock, entry);
+        }
+        let mut clock = lock(&self.clock);
+        if clock.now < deadline {
+            clock.now = deadline;
+        }
+    }
+}
+
+impl Default for VirtualTimer {
+    fn default() -> Self {
+        VirtualTimer::new()
+    }
+}
+
+impl TimerRefFactory for VirtualTimer {
+    fn timer_ref(&self) -> TimerRef {
+        VirtualTimerRef {
+            clock: self.clock.clone(),
+        }
+        .into()
+    }
+}
+
+impl TimerComponent for VirtualTimer {
+    fn shutdown(&self) -> Result<(), String> {
+        let mut clock = lock(&self.clock);
+        clock.entries.clear();
+        clock.index.clear();
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct VirtualTimerRef {
+    clock: Arc<Mutex<VirtualClock>>,
+}
+
+impl LowlevelTimer for VirtualTimerRef {
+    type Id = Uuid;
+    type OneshotState = ActorRefState;
+    type PeriodicState = ActorRefState;
+
+    fn schedule_once(&mut self, timeout: Duration, state: Self::OneshotState) -> () {
+        let entry = VirtualEntry::OneShot(state);
+        if timeout == Duration::from_millis(0) {
+            fire(&self.clock, entry);
+        } else {
+            lock(&self.clock).insert(timeout, entry);
+        }
+    }
+
+    fn schedule_periodic(
+        &mut self,
+        delay: Duration,
+        period: Duration,
+        state: Self::PeriodicState,
+    ) -> () {
+        assert!(
+            period > Duration::from_millis(0),
+            "Trying to insert periodic timer entry with 0ms period!"
+        );
+        let entry = VirtualEntry::Periodic { period, state };
+        if delay == Duration::from_millis(0) {
+            fire(&self.clock, entry);
+        } else {
+            lock(&self.clock).insert(delay, entry);
+        }
+    }
+
+    fn cancel(&mut self, id: &Self::Id) -> () {
+        lock(&self.clock).cancel(id);
+    }
+}
+
+#[derive(Debug)]
+enum VirtualEntry {
+    OneShot(ActorRefState),
+    Periodic {
+        period: Duration,
+        state: ActorRefState,
+    },
+}
+
+impl VirtualEntry {
+    fn id(&self) -> Uuid {
+        match self {
+            VirtualEntry::OneShot(state) => state.id,
+            VirtualEntry::Periodic { state, .. } => state.id,
+        }
+    }
+}
+
+type EntryKey = (Duration, u64);
+
+struct VirtualClock {
+    pending: Arc<PendingTimeouts>,
+    now: Duration,
+    next_seq: u64,
+    entries: BTreeMap<EntryKey, VirtualEntry>,
+    index: HashMap<Uuid, EntryKey>,
+    // the entry currently being fired, and whether it was cancelled meanwhile
+    firing: Option<(Uuid, bool)>,
+}
+
+impl VirtualClock {
+    fn new(pending: Arc<PendingTimeouts>) -> Self {
+        VirtualClock {
+            pending,
+            now: Duration::from_millis(0),
+            next_seq: 0,
+            entries: BTreeMap::new(),
+            index: HashMap::new(),
+            firing: None,
+        }
+    }
+
+    fn insert(&mut self, delay: Duration, entry: VirtualEntry) -> () {
+        let key = (self.now + delay, self.next_seq);
+        self.next_seq += 1;
+        self.index.insert(entry.id(), key);
+        self.entries.insert(key, entry);
+    }
+
+    fn cancel(&mut self, id: &Uuid) -> () {
+        if let Some(key) = self.index.remove(id) {
+            self.entries.remove(&key);
+        } else if let Some((firing_id, ref mut cancelled)) = self.firing {
+            if firing_id == *id {
+                *cancelled = true;
+            }
+        }
+    }
+}
+
+fn lock(clock: &Mutex<VirtualClock>) -> MutexGuard<'_, VirtualClock> {
+    clock.lock().expect("Virtual timer lock poisoned")
+}
+
+fn pop_due(clock: &Mutex<VirtualClock>, target: Duration) -> Option<VirtualEntry> {
+    let mut clock = lock(clock);
+    let key = *clock.entries.keys().next()?;
+    if key.0 <= target {
+        let entry = clock.entries.remove(&key).expect("key was just found");
+        clock.index.remove(&entry.id());
+        clock.now = key.0;
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+// Like the `trigger` functions of `ActorRefState`, but lets the timer know when the timeout was handled
+fn trigger(pending: &Arc<PendingTimeouts>, state: &ActorRefState) -> Result<(), QueueingError> {
+    let timeout = if state.receiver.is_target_active() {
+        Timeout::tracked(state.id, TimeoutTracker::new(pending))
+    } else {
+        Timeout::new(state.id)
+    };
+    state.receiver.enqueue(timeout)
+}
+
+// Triggers must happen without holding the lock, as they may cause timers to be scheduled
+fn fire(clock: &Mutex<VirtualClock>, entry: VirtualEntry) -> () {
+    let pending = lock(clock).pending.clone();
+    match entry {
+        VirtualEntry::OneShot(state) => {
+            // Ignore the Result, as we are anyway not trying to reschedule this timer
+            let _res = trigger(&pending, &state);
+        }
+        VirtualEntry::Periodic { period, state } => {
+            let id = state.id;
+            lock(clock).firing = Some((id, false));
+            let res = trigger(&pending, &state);
+            let mut clock = lock(clock);
+            let cancelled = matches!(clock.firing.take(), Some((_, true)));
+            // a failed trigger means the component has probably been deallocated
+            if res.is_ok() && !cancelled {
+                clock.insert(period, VirtualEntry::Periodic { period, state });
+            }
+        }
+    }
+}
+
+// Counts the timeouts that have been fired, but not handled, yet
+struct PendingTimeouts {
+    count: Mutex<usize>,
+    handled: Condvar,
+}
+
+impl PendingTimeouts {
+    fn new() -> Self {
+        PendingTimeouts {
+            count: Mutex::new(0),
+            handled: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, usize> {
+        self.count.lock().expect("Virtual timer lock poisoned")
+    }
+
+    fn wait_until_handled(&self) -> () {
+        let mut count = self.lock();
+        while *count > 0 {
+            count = self
+                .handled
+                .wait(count)
+                .expect("Virtual timer lock poisoned");
+        }
+    }
+}
+
+/// Marks a fired timeout as pending, until it is dropped after the timeout has been handled
+pub(crate) struct TimeoutTracker {
+    pending: Arc<PendingTimeouts>,
+}
+
+impl TimeoutTracker {
+    fn new(pending: &Arc<PendingTimeouts>) -> Self {
+        *pending.lock() += 1;
+        TimeoutTracker {
+            pending: pending.clone(),
+        }
+    }
+}
+
+impl Drop for TimeoutTracker {
+    fn drop(&mut self) {
+        let mut count = self.pending.lock();
+        *count -= 1;
+        if *count == 0 {
+            self.pending.handled.notify_all();
+        }
+    }
+}
+
+impl fmt::Debug for TimeoutTracker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<timeout-tracker>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(5000);
+
+    #[derive(ComponentDefinition, Actor)]
+    struct TimeoutRecorder {
+        ctx: ComponentContext<Self>,
+        log: Vec<&'static str>,
+        ticks: usize,
+        periodic: Option<ScheduledTimer>,
+    }
+
+    impl TimeoutRecorder {
+        fn new() -> Self {
+            TimeoutRecorder {
+                ctx: ComponentContext::uninitialised(),
+                log: Vec::new(),
+                ticks: 0,
+                periodic: None,
+            }
+        }
+
+        fn record(&mut self, label: &'static str) -> Handled {
+            self.log.push(label);
+            Handled::Ok
+        }
+    }
+
+    impl ComponentLifecycle for TimeoutRecorder {
+        fn on_start(&mut self) -> Handled {
+            self.schedule_once(Duration::from_millis(200), |c, _| c.record("b"));
+            self.schedule_once(Duration::from_millis(100), |c, _| c.record("a"));
+            self.schedule_once(Duration::from_millis(200), |c, _| c.record("c"));
+            self.schedule_once(Duration::from_millis(300), |c, _| {
+                c.schedule_once(Duration::from_millis(0), |c, _| c.record("immediate"));
+                c.schedule_once(Duration::from_millis(50), |c, _| c.record("e"));
+                c.record("d")
+            });
+            let periodic = self.schedule_periodic(
+                Duration::from_millis(100),
+                Duration::from_millis(100),
+                |c, _| {
+                    c.ticks += 1;
+                    Handled::Ok
+                },
+            );
+            self.periodic = Some(periodic);
+            Handled::Ok
+        }
+    }
+
+    fn setup() -> (KompactSystem, VirtualTimer, Arc<Component<TimeoutRecorder>>) {
+        let timer = VirtualTimer::new();
+        let mut cfg = KompactConfig::default();
+        cfg.timer::<VirtualTimer, _>({
+            let timer = timer.clone();
+            move || Box::new(timer.clone())
+        });
+        let system = cfg.build().expect("system");
+        let recorder = system.create(TimeoutRecorder::new);
+        system
+            .start_notify(&recorder)
+            .wait_timeout(TIMEOUT)
+            .expect("recorder never started");
+        timer.run_until_idle();
+        (system, timer, recorder)
+    }
+
+    #[test]
+    fn test_virtual_timer_order() {
+        let (system, timer, recorder) = setup();
+        assert!(recorder.on_definition(|cd| cd.log.is_empty()));
+
+        timer.advance(Duration::from_millis(320));
+        assert_eq!(Duration::from_millis(320), timer.now());
+        recorder.on_definition(|cd| assert_eq!(vec!["a", "b", "c", "d", "immediate"], cd.log));
+
+        timer.advance(Duration::from_millis(30));
+        recorder.on_definition(|cd| assert_eq!(Some(&"e"), cd.log.last()));
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_virtual_timer_periodic() {
+        let (system, timer, recorder) = setup();
+        timer.advance(Duration::from_millis(1050));
+        assert_eq!(10, recorder.on_definition(|cd| cd.ticks));
+
+        recorder.on_definition(|cd| {
+            let periodic = cd.periodic.take().expect("periodic timer");
+            cd.cancel_timer(periodic);
+        });
+        timer.advance(Duration::from_secs(1));
+        assert_eq!(10, recorder.on_definition(|cd| cd.ticks));
+        assert_eq!(0, timer.num_scheduled());
+        system.shutdown().expect("shutdown");
+    }
+}
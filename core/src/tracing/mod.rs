@@ -0,0 +1,520 @@
+//! Trace contexts and spans that follow messages across components and systems.
+//!
+//! A [TraceContext](TraceContext) identifies a trace and the span within it that caused a message.
+//! Whenever a component handles a message that carries a trace context,
+//! the handler runs in a new child span of that context, which becomes the *current* context.
+//! Anything the handler sends via [tell](crate::prelude::ActorPath::tell),
+//! [forward_with_original_sender](crate::prelude::ActorPath::forward_with_original_sender),
+//! or a local [ActorRef](crate::prelude::ActorRef) picks up the current context automatically,
+//! so the trace continues at the receiver, even across the network, where the context
+//! travels in the [frame header](crate::messaging::framing).
+//!
+//! Traces are started explicitly, for example with [KompactSystem::in_span](crate::prelude::KompactSystem::in_span)
+//! or [with_context](with_context). Messages sent outside of any trace are not traced.
+//!
+//! If a [SpanSink](SpanSink) is given to a system via [KompactConfig::tracing](crate::prelude::KompactConfig::tracing),
+//! the system records a [SpanRecord](SpanRecord) for every traced `receive_local` and `receive_network` invocation
+//! of its components. Systems without a sink still propagate trace contexts, they just don't record their spans.
+//! The [otlp](otlp) module provides a sink that writes spans as OpenTelemetry-compatible JSON to a file.
+
+use crate::messaging::MsgEnvelope;
+use std::{
+    cell::Cell,
+    fmt,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use uuid::Uuid;
+
+pub mod otlp;
+
+/// The 16 byte identity of a trace
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId([u8; 16]);
+
+impl TraceId {
+    /// Generates a new random trace id
+    pub fn random() -> Self {
+        TraceId(*Uuid::new_v4().as_bytes())
+    }
+
+    /// Creates a trace id from its raw bytes
+    pub fn from_bytes(bytes: [u8; 16]) -> Self {
+        TraceId(bytes)
+    }
+
+    /// Returns the raw bytes of this trace id
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+/// The 8 byte identity of a span within a trace
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId([u8; 8]);
+
+impl SpanId {
+    /// Generates a new random span id
+    pub fn random() -> Self {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&Uuid::new_v4().as_bytes()[..8]);
+        SpanId(bytes)
+    }
+
+    /// Creates a span id from its raw bytes
+    pub fn from_bytes(bytes: [u8; 8]) -> Self {
+        SpanId(bytes)
+    }
+
+    /// Returns the raw bytes of this span id
+    pub fn as_bytes(&self) -> &[u8; 8] {
+        &self.0
+    }
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    for b in bytes {
+        write!(f, "{:02x}", b)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl fmt::Debug for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TraceId({})", self)
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl fmt::Debug for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpanId({})", self)
+    }
+}
+
+/// The trace and span a message was sent in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TraceContext {
+    /// The trace the message belongs to
+    pub trace_id: TraceId,
+    /// The span the message was sent from
+    pub span_id: SpanId,
+}
+
+impl TraceContext {
+    /// Starts a new trace with a random trace id and a random root span id
+    pub fn new_root() -> Self {
+        TraceContext {
+            trace_id: TraceId::random(),
+            span_id: SpanId::random(),
+        }
+    }
+
+    /// Creates a new span in the same trace as this context
+    pub fn child(&self) -> Self {
+        TraceContext {
+            trace_id: self.trace_id,
+            span_id: SpanId::random(),
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<Option<TraceContext>> = const { Cell::new(None) };
+}
+
+/// Returns the trace context of the current thread, if any
+///
+/// While a component handles a traced message, this is the context of its handling span.
+pub fn current_context() -> Option<TraceContext> {
+    CURRENT.with(|current| current.get())
+}
+
+/// Runs `f` with `context` as the current trace context
+///
+/// Messages sent from within `f` carry `context`, and the previous context is restored afterwards.
+///
+/// # Example
+///
+/// ```
+/// use kompact::tracing::{self, TraceContext};
+///
+/// let context = TraceContext::new_root();
+/// tracing::with_context(context, || {
+///     assert_eq!(Some(context), tracing::current_context());
+/// });
+/// assert_eq!(None, tracing::current_context());
+/// ```
+pub fn with_context<R>(context: TraceContext, f: impl FnOnce() -> R) -> R {
+    let _guard = enter(Some(context));
+    f()
+}
+
+/// Sets the current trace context until the returned guard is dropped
+pub(crate) fn enter(context: Option<TraceContext>) -> ContextGuard {
+    let previous = CURRENT.with(|current| current.replace(context));
+    ContextGuard { previous }
+}
+
+/// Restores the previous trace context on drop, even if the handler panicked
+pub(crate) struct ContextGuard {
+    previous: Option<TraceContext>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// What a span describes, in OpenTelemetry's terms
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanKind {
+    /// Work that isn't the handling of a message, such as the root span of a trace
+    Internal,
+    /// The handling of a message by a component
+    Consumer,
+}
+
+/// A finished span
+#[derive(Clone, Debug)]
+pub struct SpanRecord {
+    /// The trace this span belongs to
+    pub trace_id: TraceId,
+    /// The id of this span
+    pub span_id: SpanId,
+    /// The span that caused this one, if it isn't the root of its trace
+    pub parent_span_id: Option<SpanId>,
+    /// The name of the span, e.g. `receive_network`
+    pub name: String,
+    /// What the span describes
+    pub kind: SpanKind,
+    /// When the span started
+    pub start: SystemTime,
+    /// When the span ended
+    pub end: SystemTime,
+    /// Additional key-value pairs describing the span
+    pub attributes: Vec<(String, String)>,
+}
+
+impl SpanRecord {
+    /// Returns the trace context of this span
+    pub fn context(&self) -> TraceContext {
+        TraceContext {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+        }
+    }
+}
+
+/// Something that finished spans can be written to
+///
+/// Sinks are shared by all components of a system and called from many threads,
+/// so they should not block for long in [record](SpanSink::record).
+pub trait SpanSink: Send + Sync {
+    /// Records a finished span
+    fn record(&self, span: SpanRecord) -> ();
+
+    /// Writes out any buffered spans
+    ///
+    /// This is called when the system shuts down.
+    fn flush(&self) -> () {}
+}
+
+pub(crate) fn unix_nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+/// Records spans for the message handlers of a single component
+pub(crate) struct ComponentTracer {
+    sink: Arc<dyn SpanSink>,
+    attributes: Vec<(String, String)>,
+}
+
+impl ComponentTracer {
+    pub(crate) fn new(sink: Arc<dyn SpanSink>, system: &str, type_name: &str, id: &Uuid) -> Self {
+        let attributes = vec![
+            ("kompact.system".to_string(), system.to_string()),
+            ("kompact.component.type".to_string(), type_name.to_string()),
+            ("kompact.component.id".to_string(), id.to_string()),
+        ];
+        ComponentTracer { sink, attributes }
+    }
+
+    /// Starts the span for handling `env`, which was sent in `parent`
+    pub(crate) fn start<M: crate::actors::MessageBounds>(
+        &self,
+        parent: TraceContext,
+        env: &MsgEnvelope<M>,
+    ) -> ActiveSpan<'_> {
+        let mut attributes = self.attributes.clone();
+        let name = match env {
+            MsgEnvelope::Typed(_) => "receive_local",
+            MsgEnvelope::Net(msg) => {
+                attributes.push(("kompact.message.sender".to_string(), msg.sender.to_string()));
+                attributes.push((
+                    "kompact.message.receiver".to_string(),
+                    msg.receiver.to_string(),
+                ));
+                attributes.push((
+                    "kompact.message.ser_id".to_string(),
+                    msg.data.ser_id.to_string(),
+                ));
+                "receive_network"
+            }
+        };
+        let context = parent.child();
+        ActiveSpan {
+            sink: &*self.sink,
+            record: SpanRecord {
+                trace_id: context.trace_id,
+                span_id: context.span_id,
+                parent_span_id: Some(parent.span_id),
+                name: name.to_string(),
+                kind: SpanKind::Consumer,
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                attributes,
+            },
+        }
+    }
+}
+
+/// A span that is recorded when it ends
+pub(crate) struct ActiveSpan<'a> {
+    sink: &'a dyn SpanSink,
+    record: SpanRecord,
+}
+
+impl<'a> ActiveSpan<'a> {
+    pub(crate) fn start_root(
+        sink: &'a dyn SpanSink,
+        name: &str,
+        attributes: Vec<(String, String)>,
+    ) -> Self {
+        let (context, parent_span_id) = match current_context() {
+            Some(parent) => (parent.child(), Some(parent.span_id)),
+            None => (TraceContext::new_root(), None),
+        };
+        ActiveSpan {
+            sink,
+            record: SpanRecord {
+                trace_id: context.trace_id,
+                span_id: context.span_id,
+                parent_span_id,
+                name: name.to_string(),
+                kind: SpanKind::Internal,
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                attributes,
+            },
+        }
+    }
+
+    pub(crate) fn context(&self) -> TraceContext {
+        self.record.context()
+    }
+
+    pub(crate) fn end(mut self) -> () {
+        self.record.end = SystemTime::now();
+        self.sink.record(self.record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::*,
+        testkit::{wait_until, TestProbe},
+    };
+    use std::{sync::Mutex, time::Duration};
+
+    const TIMEOUT: Duration = Duration::from_millis(5000);
+
+    #[derive(Default)]
+    pub(crate) struct CollectingSink {
+        spans: Mutex<Vec<SpanRecord>>,
+    }
+
+    impl CollectingSink {
+        pub(crate) fn spans(&self) -> Vec<SpanRecord> {
+            self.spans.lock().unwrap().clone()
+        }
+
+        fn named(&self, name: &str, component_type: &str) -> Vec<SpanRecord> {
+            self.spans()
+                .into_iter()
+                .filter(|s| {
+                    s.name == name
+                        && s.attributes.iter().any(|(k, v)| {
+                            k == "kompact.component.type" && v.as_str() == component_type
+                        })
+                })
+                .collect()
+        }
+    }
+
+    impl SpanSink for CollectingSink {
+        fn record(&self, span: SpanRecord) {
+            self.spans.lock().unwrap().push(span);
+        }
+    }
+
+    // forwards all network messages to `target`, keeping the original sender
+    #[derive(ComponentDefinition)]
+    struct Relay {
+        ctx: ComponentContext<Self>,
+        target: ActorPath,
+    }
+
+    ignore_lifecycle!(Relay);
+
+    impl Actor for Relay {
+        type Message = Never;
+
+        fn receive_local(&mut self, msg: Self::Message) -> Handled {
+            match msg {}
+        }
+
+        fn receive_network(&mut self, msg: NetMessage) -> Handled {
+            self.target.forward_with_original_sender(msg, self);
+            Handled::Ok
+        }
+    }
+
+    fn traced_networked_system(sink: Arc<CollectingSink>) -> KompactSystem {
+        let mut cfg = KompactConfig::default();
+        cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+        cfg.tracing(sink);
+        cfg.build().expect("KompactSystem")
+    }
+
+    #[test]
+    fn test_context_scoping() {
+        assert_eq!(None, current_context());
+        let outer = TraceContext::new_root();
+        with_context(outer, || {
+            let inner = outer.child();
+            assert_eq!(outer.trace_id, inner.trace_id);
+            assert_ne!(outer.span_id, inner.span_id);
+            let result = std::panic::catch_unwind(|| {
+                with_context(inner, || {
+                    assert_eq!(Some(inner), current_context());
+                    panic!("handler failed");
+                })
+            });
+            assert!(result.is_err());
+            assert_eq!(Some(outer), current_context());
+        });
+        assert_eq!(None, current_context());
+        assert_eq!(32, outer.trace_id.to_string().len());
+        assert_eq!(16, outer.span_id.to_string().len());
+    }
+
+    #[test]
+    fn test_local_spans() {
+        let sink = Arc::new(CollectingSink::default());
+        let mut conf = KompactConfig::default();
+        conf.tracing(sink.clone());
+        let system = conf.build().expect("system");
+        let probe = TestProbe::<String>::new(&system);
+        let probe_ref = probe.actor_ref();
+
+        probe_ref.tell("untraced".to_string());
+        assert_eq!("untraced", probe.expect_msg(TIMEOUT));
+
+        let root = system.in_span("test", || {
+            probe_ref.tell("traced".to_string());
+            current_context().expect("context")
+        });
+        assert_eq!("traced", probe.expect_msg(TIMEOUT));
+        assert!(wait_until(TIMEOUT, || sink.spans().len() == 2));
+        let spans = sink.spans();
+        let root_span = spans.iter().find(|s| s.name == "test").expect("root span");
+        assert_eq!(root, root_span.context());
+        assert_eq!(None, root_span.parent_span_id);
+        assert_eq!(SpanKind::Internal, root_span.kind);
+        let handler = spans
+            .iter()
+            .find(|s| s.name == "receive_local")
+            .expect("handler span");
+        assert_eq!(root.trace_id, handler.trace_id);
+        assert_eq!(Some(root.span_id), handler.parent_span_id);
+        assert!(handler.start <= handler.end);
+        system.shutdown().expect("shutdown");
+    }
+
+    #[test]
+    fn test_network_propagation() {
+        let sink = Arc::new(CollectingSink::default());
+        let system1 = traced_networked_system(sink.clone());
+        let system2 = traced_networked_system(sink.clone());
+        let probe = TestProbe::<Never>::new(&system2);
+        let probe_path = probe.register();
+        let sender = TestProbe::<Never>::new(&system1);
+        let sender_path = sender.register();
+
+        let root = system1.in_span("tell", || {
+            probe_path.tell_with_sender(7u64, &system1, sender_path.clone());
+            current_context().expect("context")
+        });
+        let msg = probe.expect_net_msg(TIMEOUT);
+        assert_eq!(Some(&root), msg.trace_context.as_deref());
+
+        let target = probe_path.clone();
+        let relay = system1.create(move || Relay {
+            ctx: ComponentContext::uninitialised(),
+            target,
+        });
+        let relay_path = system1
+            .register(&relay)
+            .wait_timeout(TIMEOUT)
+            .expect("registration timed out")
+            .expect("registration failed");
+        system1
+            .start_notify(&relay)
+            .wait_timeout(TIMEOUT)
+            .expect("relay never started");
+        let root = system1.in_span("forward", || {
+            relay_path.tell_with_sender(8u64, &system1, sender_path.clone());
+            current_context().expect("context")
+        });
+        let msg = probe.expect_net_msg(TIMEOUT);
+        let forwarded = *msg.trace_context.as_deref().expect("trace context");
+        assert_eq!(Some(8u64), msg.try_deserialise::<u64, u64>().ok());
+        assert_eq!(root.trace_id, forwarded.trace_id);
+
+        // the relay's span continues the root, and the probe's span continues the relay's
+        assert!(wait_until(TIMEOUT, || sink
+            .named("receive_network", "ProbeActor")
+            .iter()
+            .any(|s| s.parent_span_id == Some(forwarded.span_id))));
+        let relay_spans = sink.named("receive_network", "Relay");
+        assert_eq!(1, relay_spans.len());
+        let relay_span = &relay_spans[0];
+        assert_eq!(forwarded, relay_span.context());
+        assert_eq!(Some(root.span_id), relay_span.parent_span_id);
+        assert_eq!(SpanKind::Consumer, relay_span.kind);
+        assert!(relay_span
+            .attributes
+            .iter()
+            .any(|(k, v)| k == "kompact.message.receiver" && v == &relay_path.to_string()));
+
+        system1.shutdown().expect("shutdown");
+        system2.shutdown().expect("shutdown");
+    }
+}
@@ -0,0 +1,265 @@
+//! Writing spans as [OpenTelemetry](https://opentelemetry.io/docs/specs/otlp/#json-protobuf-encoding) JSON
+//!
+//! The [OtlpJsonSink](OtlpJsonSink) writes every span as an OTLP/JSON `ExportTraceServiceRequest`
+//! on its own line. This is the format of the OpenTelemetry Collector's file exporter,
+//! so the resulting files can be read by its `otlpjsonfile` receiver,
+//! or converted for any tracing backend that speaks OTLP.
+
+use super::*;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+/// The instrumentation scope name spans are reported under
+pub const SCOPE_NAME: &str = "kompact";
+
+/// A [SpanSink](SpanSink) writing OTLP/JSON lines to a file
+pub type OtlpJsonFileSink = OtlpJsonSink<BufWriter<File>>;
+
+/// A [SpanSink](SpanSink) writing spans as OTLP/JSON lines to a writer
+///
+/// Buffered writers, like the one of an [OtlpJsonFileSink](OtlpJsonFileSink), are flushed
+/// via [flush](SpanSink::flush), which the system calls when it shuts down.
+///
+/// Since spans are recorded from component threads, write errors can't be reported there.
+/// Instead, the first error stops all further writes and can be retrieved
+/// via [take_error](OtlpJsonSink::take_error).
+///
+/// # Example
+///
+/// ```
+/// use kompact::{prelude::*, tracing::otlp::OtlpJsonFileSink};
+/// use std::sync::Arc;
+///
+/// let path = std::env::temp_dir().join("kompact-otlp-doctest.jsonl");
+/// let sink = Arc::new(OtlpJsonFileSink::create(&path, "my-service").expect("sink"));
+/// let mut conf = KompactConfig::default();
+/// conf.tracing(sink.clone());
+/// let system = conf.build().expect("system");
+/// system.in_span("startup", || ());
+/// system.shutdown().expect("shutdown");
+/// assert!(sink.take_error().is_none());
+/// let lines = std::fs::read_to_string(&path).expect("spans");
+/// assert!(lines.contains("\"name\":\"startup\""));
+/// # std::fs::remove_file(&path).expect("remove");
+/// ```
+pub struct OtlpJsonSink<W: Write + Send> {
+    service_name: String,
+    writer: Mutex<SinkState<W>>,
+}
+
+struct SinkState<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl OtlpJsonSink<BufWriter<File>> {
+    /// Creates a sink appending to the file at `path`, creating it if necessary
+    ///
+    /// Spans are reported with `service_name` as their `service.name` resource attribute.
+    pub fn create<P: AsRef<Path>>(path: P, service_name: impl Into<String>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(OtlpJsonSink::new(BufWriter::new(file), service_name))
+    }
+}
+
+impl<W: Write + Send> OtlpJsonSink<W> {
+    /// Creates a sink writing to `writer`
+    ///
+    /// Spans are reported with `service_name` as their `service.name` resource attribute.
+    pub fn new(writer: W, service_name: impl Into<String>) -> Self {
+        OtlpJsonSink {
+            service_name: service_name.into(),
+            writer: Mutex::new(SinkState {
+                writer,
+                error: None,
+            }),
+        }
+    }
+
+    /// Returns the error that stopped this sink from writing, if any
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.lock().error.take()
+    }
+
+    /// Flushes and returns the underlying writer
+    pub fn into_inner(self) -> io::Result<W> {
+        let mut state = self
+            .writer
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.writer.flush()?;
+        Ok(state.writer)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SinkState<W>> {
+        // a panic while writing can't leave the state inconsistent, so just carry on
+        self.writer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write_with(&self, f: impl FnOnce(&mut W) -> io::Result<()>) -> () {
+        let mut state = self.lock();
+        if state.error.is_none() {
+            if let Err(e) = f(&mut state.writer) {
+                state.error = Some(e);
+            }
+        }
+    }
+}
+
+impl<W: Write + Send> SpanSink for OtlpJsonSink<W> {
+    fn record(&self, span: SpanRecord) -> () {
+        let mut line = render(&self.service_name, &[span]);
+        line.push('\n');
+        self.write_with(|writer| writer.write_all(line.as_bytes()));
+    }
+
+    fn flush(&self) -> () {
+        self.write_with(|writer| writer.flush());
+    }
+}
+
+/// Renders `spans` as a single OTLP/JSON `ExportTraceServiceRequest`
+///
+/// The spans are reported under a resource with `service_name` as its `service.name` attribute.
+pub fn render(service_name: &str, spans: &[SpanRecord]) -> String {
+    let mut out = String::new();
+    out.push_str("{\"resourceSpans\":[{\"resource\":{\"attributes\":[");
+    push_attribute(&mut out, "service.name", service_name);
+    out.push_str("]},\"scopeSpans\":[{\"scope\":{\"name\":");
+    push_string(&mut out, SCOPE_NAME);
+    out.push_str(",\"version\":");
+    push_string(&mut out, env!("CARGO_PKG_VERSION"));
+    out.push_str("},\"spans\":[");
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_span(&mut out, span);
+    }
+    out.push_str("]}]}]}");
+    out
+}
+
+fn push_span(out: &mut String, span: &SpanRecord) -> () {
+    let kind = match span.kind {
+        SpanKind::Internal => 1,
+        SpanKind::Consumer => 5,
+    };
+    out.push_str(&format!(
+        "{{\"traceId\":\"{}\",\"spanId\":\"{}\"",
+        span.trace_id, span.span_id
+    ));
+    if let Some(parent) = span.parent_span_id {
+        out.push_str(&format!(",\"parentSpanId\":\"{}\"", parent));
+    }
+    out.push_str(",\"name\":");
+    push_string(out, &span.name);
+    // 64bit integers are strings in the JSON encoding of protobuf
+    out.push_str(&format!(
+        ",\"kind\":{},\"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\"attributes\":[",
+        kind,
+        unix_nanos(span.start),
+        unix_nanos(span.end)
+    ));
+    for (i, (key, value)) in span.attributes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_attribute(out, key, value);
+    }
+    out.push_str("]}");
+}
+
+fn push_attribute(out: &mut String, key: &str, value: &str) -> () {
+    out.push_str("{\"key\":");
+    push_string(out, key);
+    out.push_str(",\"value\":{\"stringValue\":");
+    push_string(out, value);
+    out.push_str("}}");
+}
+
+fn push_string(out: &mut String, s: &str) -> () {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_render_json() {
+        let start = UNIX_EPOCH + Duration::from_nanos(1_000_000_123);
+        let span = SpanRecord {
+            trace_id: TraceId::from_bytes([0xab; 16]),
+            span_id: SpanId::from_bytes([1, 2, 3, 4, 5, 6, 7, 8]),
+            parent_span_id: Some(SpanId::from_bytes([0xff; 8])),
+            name: "receive_network".to_string(),
+            kind: SpanKind::Consumer,
+            start,
+            end: start + Duration::from_nanos(500),
+            attributes: vec![("path".to_string(), "tcp://\"a\"\\b\n".to_string())],
+        };
+        let root = SpanRecord {
+            parent_span_id: None,
+            name: "root".to_string(),
+            kind: SpanKind::Internal,
+            attributes: Vec::new(),
+            ..span.clone()
+        };
+        let json = render("svc", &[span, root]);
+        assert!(json.starts_with(
+            "{\"resourceSpans\":[{\"resource\":{\"attributes\":[{\"key\":\"service.name\",\"value\":{\"stringValue\":\"svc\"}}]}"
+        ));
+        assert!(json.contains(
+            "{\"traceId\":\"abababababababababababababababab\",\"spanId\":\"0102030405060708\",\"parentSpanId\":\"ffffffffffffffff\",\"name\":\"receive_network\",\"kind\":5,\"startTimeUnixNano\":\"1000000123\",\"endTimeUnixNano\":\"1000000623\",\"attributes\":[{\"key\":\"path\",\"value\":{\"stringValue\":\"tcp://\\\"a\\\"\\\\b\\n\"}}]}"
+        ));
+        assert!(json.contains(
+            ",{\"traceId\":\"abababababababababababababababab\",\"spanId\":\"0102030405060708\",\"name\":\"root\",\"kind\":1,"
+        ));
+        assert!(json.ends_with("\"attributes\":[]}]}]}]}"));
+    }
+
+    #[test]
+    fn test_sink_writes_lines() {
+        let sink = OtlpJsonSink::new(Vec::new(), "svc");
+        let context = TraceContext::new_root();
+        for name in ["first", "second"].iter() {
+            sink.record(SpanRecord {
+                trace_id: context.trace_id,
+                span_id: context.span_id,
+                parent_span_id: None,
+                name: name.to_string(),
+                kind: SpanKind::Internal,
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                attributes: Vec::new(),
+            });
+        }
+        assert!(sink.take_error().is_none());
+        let out = String::from_utf8(sink.into_inner().expect("flush")).expect("utf8");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].contains("\"name\":\"first\""));
+        assert!(lines[1].contains("\"name\":\"second\""));
+        assert!(lines[0].contains(&context.trace_id.to_string()));
+    }
+}
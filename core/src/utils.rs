@@ -574,6 +574,41 @@ macro_rules! ignore_indications {
     };
 }
 
+/// A tiny, but well distributed, seedable PRNG
+///
+/// See <http://xorshift.di.unimi.it/splitmix64.c>.
+pub(crate) struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub(crate) fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub(crate) fn for_epoch(seed: u64, epoch: u64) -> Self {
+        SplitMix64::new(seed ^ SplitMix64::new(epoch).next_u64())
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    pub(crate) fn next_below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % (bound as u64)) as usize
+    }
+
+    /// A uniformly distributed value in `[0, 1)`
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        // the top 53 bits fill the mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
 #[cfg(not(nightly))]
 mod iter_extras {
     use crate::serialisation::{SerError, TryClone};
@@ -841,4 +876,16 @@ mod tests {
             .shutdown()
             .expect("Kompact didn't shut down properly");
     }
+
+    #[test]
+    fn test_rng_is_reproducible() {
+        let mut rng1 = SplitMix64::new(7);
+        let mut rng2 = SplitMix64::new(7);
+        let mut rng3 = SplitMix64::new(8);
+        let seq1: Vec<u64> = (0..10).map(|_| rng1.next_u64()).collect();
+        let seq2: Vec<u64> = (0..10).map(|_| rng2.next_u64()).collect();
+        let seq3: Vec<u64> = (0..10).map(|_| rng3.next_u64()).collect();
+        assert_eq!(seq1, seq2);
+        assert_ne!(seq1, seq3);
+    }
 }
@@ -1,5 +1,25 @@
-use kompact::{prelude::*, prelude_test::net_test_helpers::*};
-use std::{net::SocketAddr, thread, time::Duration};
+use bytes::{BufMut, BytesMut};
+use kompact::{
+    loopback::LoopbackFabric,
+    net::{
+        auth::PROOF_LEN,
+        frames::{Frame, FrameType, Start, FRAME_HEAD_LEN, MAGIC_NUM},
+    },
+    prelude::*,
+    prelude_test::net_test_helpers::*,
+    testkit::{wait_until, TestProbe},
+};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    net::{SocketAddr, TcpStream},
+    path::Path,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use uuid::Uuid;
 
 fn system_from_network_config(network_config: NetworkConfig) -> KompactSystem {
     let mut cfg = KompactConfig::new();
@@ -7,6 +27,23 @@ fn system_from_network_config(network_config: NetworkConfig) -> KompactSystem {
     cfg.build().expect("KompactSystem")
 }
 
+/// Writes a self-signed certificate for the loopback address into `dir`
+/// and returns a `NetworkConfig` with TLS turned on that uses it.
+fn tls_network_config(dir: &Path) -> NetworkConfig {
+    let certified = rcgen::generate_simple_self_signed(vec![
+        "localhost".to_string(),
+        "127.0.0.1".to_string(),
+    ])
+    .expect("certificate");
+    let cert_file = dir.join("cert.pem");
+    let key_file = dir.join("key.pem");
+    fs::write(&cert_file, certified.cert.pem()).expect("write cert");
+    fs::write(&key_file, certified.key_pair.serialize_pem()).expect("write key");
+    let mut network_config = NetworkConfig::default();
+    network_config.set_tls_config(TlsConfig::new(cert_file, key_file));
+    network_config
+}
+
 #[test]
 fn named_registration() {
     const ACTOR_NAME: &str = "ponger";
@@ -105,6 +142,738 @@ fn remote_delivery_to_registered_actors_eager() {
         .expect("Kompact didn't shut down properly");
 }
 
+// Sends pings to a Ponger via a path with a domain name instead of an IP address,
+// which the dispatcher must resolve before the first message can go out.
+#[test]
+fn remote_delivery_to_domain_path() {
+    let system = system_from_network_config(NetworkConfig::default());
+    let remote = system_from_network_config(NetworkConfig::default());
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_eager);
+    let poaf = remote.register_by_alias(&ponger, "custom_name");
+    let _ = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    let _ = poaf.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+
+    let port = remote.system_path().port();
+    let ponger_path: ActorPath = format!("tcp://localhost:{}/custom_name", port)
+        .parse()
+        .expect("a proper path");
+    let (pinger, pif) = system.create_and_register(move || PingerAct::new_eager(ponger_path));
+    pif.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+
+    remote.start(&ponger);
+    system.start(&pinger);
+
+    thread::sleep(Duration::from_millis(7000));
+
+    let pingf = system.stop_notify(&pinger);
+    let pongf = remote.kill_notify(ponger);
+    pingf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Pinger never stopped!");
+    pongf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Ponger never died!");
+    pinger.on_definition(|c| {
+        assert_eq!(c.count, PING_COUNT);
+    });
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn remote_ask_receives_reply() {
+    let system = system_from_network_config(NetworkConfig::default());
+    let remote = system_from_network_config(NetworkConfig::default());
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_lazy);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    remote.start(&ponger);
+
+    for i in 0..10u64 {
+        let pong = ponger_path
+            .ask::<PongMsg, PingPongSer, _>(PingMsg { i }, &system, Duration::from_millis(1000))
+            .wait_timeout(Duration::from_millis(2000))
+            .expect("Ask future never completed!")
+            .expect("Ask should not fail");
+        assert_eq!(pong.i, i);
+    }
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn remote_ask_times_out() {
+    let system = system_from_network_config(NetworkConfig::default());
+    let remote = system_from_network_config(NetworkConfig::default());
+    let missing_path: ActorPath = remote
+        .system_path()
+        .into_named_with_string("nobody")
+        .expect("a proper path")
+        .into();
+
+    let res = missing_path
+        .ask::<PongMsg, PingPongSer, _>(PingMsg { i: 1 }, &system, Duration::from_millis(300))
+        .wait_timeout(Duration::from_millis(2000))
+        .expect("Ask future never completed!");
+    assert!(matches!(res, Err(AskError::Timeout(_))));
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn local_watch_notified_on_kill() {
+    let system = system_from_network_config(NetworkConfig::default());
+    let (ponger, pof) = system.create_and_register(PongerAct::new_lazy);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    system.start(&ponger);
+    let missing_path: ActorPath = system
+        .system_path()
+        .into_named_with_string("nobody")
+        .expect("a proper path")
+        .into();
+
+    let watched = ponger_path.clone();
+    let watcher = system.create(move || WatcherAct::new(watched));
+    let missing = missing_path.clone();
+    let missing_watcher = system.create(move || WatcherAct::new(missing));
+    system.start(&watcher);
+    system.start(&missing_watcher);
+
+    thread::sleep(Duration::from_millis(500));
+    watcher.on_definition(|c| assert!(c.terminated.is_empty()));
+    missing_watcher.on_definition(|c| assert_eq!(c.terminated, vec![missing_path]));
+
+    system
+        .kill_notify(ponger)
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Ponger never died!");
+    thread::sleep(Duration::from_millis(500));
+    watcher.on_definition(|c| assert_eq!(c.terminated, vec![ponger_path]));
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn remote_watch_notified_on_kill() {
+    let system = system_from_network_config(NetworkConfig::default());
+    let remote = system_from_network_config(NetworkConfig::default());
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_lazy);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    remote.start(&ponger);
+
+    let watched = ponger_path.clone();
+    let watcher = system.create(move || WatcherAct::new(watched));
+    system.start(&watcher);
+
+    thread::sleep(Duration::from_millis(1000));
+    watcher.on_definition(|c| assert!(c.terminated.is_empty()));
+
+    remote
+        .kill_notify(ponger)
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Ponger never died!");
+    thread::sleep(Duration::from_millis(1000));
+    watcher.on_definition(|c| assert_eq!(c.terminated, vec![ponger_path]));
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn remote_watch_notified_when_unreachable() {
+    let mut net_cfg = NetworkConfig::default();
+    net_cfg.set_unreachable_timeout(500);
+    let system = system_from_network_config(net_cfg);
+    let remote = system_from_network_config(NetworkConfig::default());
+    let lost_path: ActorPath = remote
+        .system_path()
+        .into_named_with_string("lost")
+        .expect("a proper path")
+        .into();
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+
+    let watched = lost_path.clone();
+    let watcher = system.create(move || WatcherAct::new(watched));
+    system.start(&watcher);
+
+    thread::sleep(Duration::from_millis(100));
+    watcher.on_definition(|c| assert!(c.terminated.is_empty()));
+    thread::sleep(Duration::from_millis(1500));
+    watcher.on_definition(|c| assert_eq!(c.terminated, vec![lost_path]));
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn failure_detector_suspects_stopped_system() {
+    let mut fd_cfg = FailureDetectorConfig::default();
+    fd_cfg.set_heartbeat_interval(50);
+    fd_cfg.set_min_std_deviation(20);
+    fd_cfg.set_acceptable_heartbeat_pause(200);
+    fd_cfg.set_first_heartbeat_estimate(50);
+    let fd_system = || {
+        let mut cfg = KompactConfig::default();
+        cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+        cfg.failure_detector(fd_cfg.clone());
+        cfg.build().expect("KompactSystem")
+    };
+    let system = fd_system();
+    let remote = fd_system();
+    let remote_path = remote.system_path();
+
+    let detector = system.failure_detector().expect("failure detector");
+    let target = remote_path.clone();
+    let recorder = system.create(move || LivenessRecorder::new(target));
+    biconnect_components::<FailureDetection, _, _>(detector, &recorder).expect("connection");
+    system.start(&recorder);
+
+    thread::sleep(Duration::from_millis(1000));
+    recorder.on_definition(|c| {
+        assert_eq!(
+            c.events.first(),
+            Some(&Liveness::Alive(remote_path.clone()))
+        );
+        assert_eq!(c.events.last(), Some(&Liveness::Alive(remote_path.clone())));
+    });
+
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    thread::sleep(Duration::from_millis(2000));
+    recorder.on_definition(|c| {
+        assert_eq!(
+            c.events.last(),
+            Some(&Liveness::Suspect(remote_path.clone()))
+        );
+    });
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+/// Starts a system with a `Membership` component that joins via `seeds`,
+/// and returns it together with the component created by `component`,
+/// which is connected to the membership.
+fn cluster_system<C, F>(seeds: &[SystemPath], component: F) -> (KompactSystem, Arc<Component<C>>)
+where
+    C: ComponentDefinition + Require<ClusterMembership> + RequireRef<ClusterMembership> + 'static,
+    F: FnOnce() -> C,
+{
+    let seeds: Vec<String> = seeds.iter().map(|seed| format!("\"{}\"", seed)).collect();
+    let mut cfg = KompactConfig::new();
+    cfg.load_config_str(format!(
+        "membership {{ seeds = [{}], gossip_interval = 50 ms, down_timeout = 500 ms }}\n\
+         pubsub.gossip_interval = 100 ms\n\
+         sharding {{ number_of_shards = 20, retry_interval = 100 ms }}",
+        seeds.join(", ")
+    ));
+    cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+    let system = cfg.build().expect("KompactSystem");
+    let membership = system.create(Membership::new);
+    let component = system.create(component);
+    biconnect_components::<ClusterMembership, _, _>(&membership, &component).expect("connection");
+    system.start(&component);
+    system.start(&membership);
+    (system, component)
+}
+
+#[test]
+fn membership_gossip_join_leave_down() {
+    let (seed, seed_recorder) = cluster_system(&[], MembershipRecorder::new);
+    let seed_path = seed.system_path();
+    let (leaver, leaver_recorder) =
+        cluster_system(std::slice::from_ref(&seed_path), MembershipRecorder::new);
+    let leaver_path = leaver.system_path();
+    let (crasher, _crasher_recorder) =
+        cluster_system(std::slice::from_ref(&seed_path), MembershipRecorder::new);
+    let crasher_path = crasher.system_path();
+
+    thread::sleep(Duration::from_millis(1000));
+    for path in &[&seed_path, &leaver_path, &crasher_path] {
+        let up = MembershipEvent::Up((*path).clone());
+        seed_recorder.on_definition(|c| assert!(c.events.contains(&up), "{:?}", c.events));
+        leaver_recorder.on_definition(|c| assert!(c.events.contains(&up), "{:?}", c.events));
+    }
+
+    leaver_recorder.on_definition(|c| c.membership.trigger(MembershipRequest::Leave));
+    crasher
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    thread::sleep(Duration::from_millis(1500));
+    seed_recorder.on_definition(|c| {
+        assert!(c
+            .events
+            .contains(&MembershipEvent::Left(leaver_path.clone())));
+        assert!(c
+            .events
+            .contains(&MembershipEvent::Down(crasher_path.clone())));
+        assert!(!c
+            .events
+            .contains(&MembershipEvent::Down(leaver_path.clone())));
+    });
+
+    leaver
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    seed.shutdown().expect("Kompact didn't shut down properly");
+}
+
+/// An actor that counts the network messages it receives
+#[derive(ComponentDefinition)]
+struct MessageCounter {
+    ctx: ComponentContext<Self>,
+    received: usize,
+}
+impl MessageCounter {
+    fn new() -> Self {
+        MessageCounter {
+            ctx: ComponentContext::uninitialised(),
+            received: 0,
+        }
+    }
+}
+ignore_lifecycle!(MessageCounter);
+impl Actor for MessageCounter {
+    type Message = Never;
+
+    fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+        unreachable!("Never type is empty")
+    }
+
+    fn receive_network(&mut self, _msg: NetMessage) -> Handled {
+        self.received += 1;
+        Handled::Ok
+    }
+}
+
+#[test]
+fn pubsub_across_systems() {
+    let timeout = Duration::from_millis(1000);
+    let expect_u64 = |probe: &TestProbe<u64>| {
+        probe
+            .expect_net_msg(timeout)
+            .try_deserialise::<u64, u64>()
+            .expect("u64")
+    };
+    let (seed, seed_mediator) = cluster_system(&[], PubSubMediator::new);
+    let (member, member_mediator) = cluster_system(&[seed.system_path()], PubSubMediator::new);
+    let seed_probe: TestProbe<u64> = TestProbe::new(&seed);
+    let seed_subscriber = seed_probe.register();
+    let member_probe: TestProbe<u64> = TestProbe::new(&member);
+    let member_subscriber = member_probe.register();
+    seed_mediator
+        .actor_ref()
+        .tell(PubSubRequest::subscribe("news", seed_subscriber.clone()));
+    member_mediator
+        .actor_ref()
+        .tell(PubSubRequest::subscribe("news", member_subscriber.clone()));
+    member_mediator.actor_ref().tell(PubSubRequest::subscribe(
+        "weather",
+        member_subscriber.clone(),
+    ));
+
+    let publish = |mediator: &Arc<Component<PubSubMediator>>, topic: &str, n: u64| {
+        let publisher = seed_subscriber.clone();
+        let request = PubSubRequest::publish(topic, publisher, &n).expect("serialise");
+        mediator.actor_ref().tell(request);
+    };
+    // Each system subscribes a counter to its own topic last, so once messages published by
+    // the other system reach the counter, the other system knows all the topics above.
+    let subscribe_counter = |system: &KompactSystem, mediator: &Arc<Component<PubSubMediator>>| {
+        let (counter, registration) = system.create_and_register(MessageCounter::new);
+        let path = registration.wait_expect(timeout, "Counter failed to register!");
+        system.start(&counter);
+        let topic = system.system_path().to_string();
+        mediator
+            .actor_ref()
+            .tell(PubSubRequest::subscribe(topic.as_str(), path));
+        (topic, counter)
+    };
+    let await_topics = |mediator, topic: &str, counter: &Arc<Component<MessageCounter>>| {
+        assert!(
+            wait_until(Duration::from_millis(5000), || {
+                publish(mediator, topic, 0);
+                counter.on_definition(|c| c.received > 0)
+            }),
+            "Topics never propagated"
+        );
+    };
+    let (seed_topic, seed_counter) = subscribe_counter(&seed, &seed_mediator);
+    let (member_topic, member_counter) = subscribe_counter(&member, &member_mediator);
+    await_topics(&seed_mediator, &member_topic, &member_counter);
+    await_topics(&member_mediator, &seed_topic, &seed_counter);
+
+    publish(&seed_mediator, "news", 1);
+    assert_eq!(1, expect_u64(&seed_probe));
+    assert_eq!(1, expect_u64(&member_probe));
+    publish(&member_mediator, "news", 2);
+    assert_eq!(2, expect_u64(&seed_probe));
+    assert_eq!(2, expect_u64(&member_probe));
+    publish(&seed_mediator, "weather", 3);
+    assert_eq!(3, expect_u64(&member_probe));
+    seed_probe.expect_no_msg(Duration::from_millis(200));
+    member_probe.expect_no_msg(Duration::from_millis(200));
+
+    // the member's mediator handles this before anything published afterwards reaches it
+    member_mediator
+        .actor_ref()
+        .tell(PubSubRequest::unsubscribe("news", member_subscriber));
+    publish(&seed_mediator, "news", 4);
+    assert_eq!(4, expect_u64(&seed_probe));
+    member_probe.expect_no_msg(Duration::from_millis(200));
+
+    member
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    seed.shutdown().expect("Kompact didn't shut down properly");
+}
+
+/// An entity that answers every message with its id and the port of the system it lives on
+#[derive(ComponentDefinition)]
+struct PortEntity {
+    ctx: ComponentContext<Self>,
+    id: String,
+}
+ignore_lifecycle!(PortEntity);
+impl Actor for PortEntity {
+    type Message = Never;
+
+    fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+        unreachable!("Never type is empty")
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let port = self.ctx.system().system_path().port();
+        msg.sender.tell(format!("{} {}", self.id, port), self);
+        Handled::Ok
+    }
+}
+
+type PortRegion = Arc<Component<ShardRegion<PortEntity>>>;
+
+fn port_region() -> ShardRegion<PortEntity> {
+    ShardRegion::new("port", |id: &str| PortEntity {
+        ctx: ComponentContext::uninitialised(),
+        id: id.to_string(),
+    })
+}
+
+/// Records the latest port each `PortEntity` answered with
+#[derive(ComponentDefinition)]
+struct PlacementRecorder {
+    ctx: ComponentContext<Self>,
+    ports: HashMap<String, u16>,
+}
+impl PlacementRecorder {
+    fn new() -> Self {
+        PlacementRecorder {
+            ctx: ComponentContext::uninitialised(),
+            ports: HashMap::new(),
+        }
+    }
+}
+ignore_lifecycle!(PlacementRecorder);
+impl Actor for PlacementRecorder {
+    type Message = Never;
+
+    fn receive_local(&mut self, _msg: Self::Message) -> Handled {
+        unreachable!("Never type is empty")
+    }
+
+    fn receive_network(&mut self, msg: NetMessage) -> Handled {
+        let answer = msg.try_deserialise::<String, String>().expect("String");
+        let mut parts = answer.split(' ');
+        let id = parts.next().expect("id").to_string();
+        let port = parts.next().expect("port").parse().expect("port");
+        self.ports.insert(id, port);
+        Handled::Ok
+    }
+}
+
+#[test]
+fn sharding_rebalances_entities() {
+    let timeout = Duration::from_millis(5000);
+    let (seed, seed_region) = cluster_system(&[], port_region);
+    let seed_port = seed.system_path().port();
+    let (recorder, registration) = seed.create_and_register(PlacementRecorder::new);
+    let recorder_path = registration.wait_expect(timeout, "Recorder failed to register!");
+    seed.start(&recorder);
+    let entity_ids: Vec<String> = (0..20).map(|i| format!("entity-{}", i)).collect();
+    // returns the port of the system each entity lives on,
+    // unless some messages got lost, e.g. while their shards moved
+    let locate = |region: &PortRegion| -> Option<Vec<u16>> {
+        recorder.on_definition(|c| c.ports.clear());
+        for id in entity_ids.iter() {
+            let envelope =
+                ShardEnvelope::new(id.as_str(), recorder_path.clone(), &0u64).expect("serialise");
+            region.actor_ref().tell(envelope);
+        }
+        let answered = || recorder.on_definition(|c| c.ports.len() == entity_ids.len());
+        if wait_until(Duration::from_millis(1000), answered) {
+            recorder.on_definition(|c| Some(entity_ids.iter().map(|id| c.ports[id]).collect()))
+        } else {
+            None
+        }
+    };
+    // messages are kept until the region received its first allocation
+    let placement = locate(&seed_region).expect("placement");
+    assert!(placement.iter().all(|port| *port == seed_port));
+
+    let (member, member_region) = cluster_system(&[seed.system_path()], port_region);
+    let member_port = member.system_path().port();
+    assert!(
+        wait_until(timeout, || {
+            match (locate(&seed_region), locate(&member_region)) {
+                (Some(placement), Some(member_placement)) => {
+                    placement.contains(&seed_port)
+                        && placement.contains(&member_port)
+                        && placement == member_placement
+                }
+                _ => false,
+            }
+        }),
+        "Entities were never rebalanced onto the new member"
+    );
+
+    member
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    assert!(
+        wait_until(timeout, || match locate(&seed_region) {
+            Some(placement) => placement.iter().all(|port| *port == seed_port),
+            None => false,
+        }),
+        "Entities were never moved off the stopped member"
+    );
+
+    seed.shutdown().expect("Kompact didn't shut down properly");
+}
+
+// Sets up two KompactSystems sharing one self-signed certificate, with a Pinger and a Ponger.
+// All messages travel over TLS-encrypted channels.
+#[test]
+fn remote_delivery_to_registered_actors_tls() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let network_config = tls_network_config(dir.path());
+    let system = system_from_network_config(network_config.clone());
+    let remote = system_from_network_config(network_config);
+    assert_eq!(remote.system_path().protocol(), Transport::TLS);
+
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_eager);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    let (pinger, pif) = system.create_and_register(move || PingerAct::new_eager(ponger_path));
+    pif.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+
+    remote.start(&ponger);
+    system.start(&pinger);
+
+    thread::sleep(Duration::from_millis(7000));
+
+    let pingf = system.stop_notify(&pinger);
+    let pongf = remote.kill_notify(ponger);
+    pingf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Pinger never stopped!");
+    pongf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Ponger never died!");
+    pinger.on_definition(|c| {
+        assert_eq!(c.count, PING_COUNT);
+    });
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn remote_delivery_with_shared_secret() {
+    let mut network_config = NetworkConfig::default();
+    network_config.set_auth_secret("kompact test secret");
+    let system = system_from_network_config(network_config.clone());
+    let remote = system_from_network_config(network_config);
+
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_eager);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    let (pinger, pif) = system.create_and_register(move || PingerAct::new_eager(ponger_path));
+    pif.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+
+    remote.start(&ponger);
+    system.start(&pinger);
+
+    thread::sleep(Duration::from_millis(7000));
+
+    let pingf = system.stop_notify(&pinger);
+    let pongf = remote.kill_notify(ponger);
+    pingf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Pinger never stopped!");
+    pongf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Ponger never died!");
+    pinger.on_definition(|c| {
+        assert_eq!(c.count, PING_COUNT);
+    });
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn remote_delivery_rejected_with_wrong_secret() {
+    let mut network_config = NetworkConfig::default();
+    network_config.set_auth_secret("kompact test secret");
+    let system = system_from_network_config(network_config);
+    let mut remote_config = NetworkConfig::default();
+    remote_config.set_auth_secret("some other secret");
+    let remote = system_from_network_config(remote_config);
+
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_eager);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    let (pinger, pif) = system.create_and_register(move || PingerAct::new_eager(ponger_path));
+    pif.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+
+    remote.start(&ponger);
+    system.start(&pinger);
+
+    thread::sleep(Duration::from_millis(2000));
+
+    let pingf = system.stop_notify(&pinger);
+    let pongf = remote.kill_notify(ponger);
+    pingf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Pinger never stopped!");
+    pongf
+        .wait_timeout(Duration::from_millis(1000))
+        .expect("Ponger never died!");
+    pinger.on_definition(|c| {
+        assert_eq!(c.count, 0);
+    });
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn failed_authentication_is_not_attributed_to_claimed_address() {
+    let mut network_config = NetworkConfig::default();
+    network_config.set_auth_secret("kompact test secret");
+    network_config.set_unreachable_timeout(500);
+    let system = system_from_network_config(network_config.clone());
+    let remote = system_from_network_config(network_config);
+
+    let (ponger, pof) = remote.create_and_register(PongerAct::new_lazy);
+    let ponger_path = pof.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    remote.start(&ponger);
+    let watched = ponger_path.clone();
+    let watcher = system.create(move || WatcherAct::new(watched));
+    system.start(&watcher);
+    thread::sleep(Duration::from_millis(1000));
+
+    // A third host without the secret claims to be the remote system
+    let claimed_addr = remote.system_path().socket_address().expect("ip address");
+    let start = Start::with_auth(claimed_addr, Uuid::new_v4(), [0u8; PROOF_LEN], 0);
+    let mut frame = BytesMut::with_capacity(1024);
+    Frame::Start(start)
+        .encode_into(&mut frame)
+        .expect("encode Start");
+    let target = system.system_path().socket_address().expect("ip address");
+    let mut impostor = TcpStream::connect(target).expect("connect");
+    impostor.write_all(&frame).expect("send Start");
+
+    thread::sleep(Duration::from_millis(1500));
+    watcher.on_definition(|c| assert!(c.terminated.is_empty(), "{:?}", c.terminated));
+    let (pinger, pif) = system.create_and_register(move || PingerAct::new_eager(ponger_path));
+    pif.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+    system.start(&pinger);
+    assert!(
+        wait_until(Duration::from_millis(2000), || {
+            pinger.on_definition(|c| c.count == PING_COUNT)
+        }),
+        "The remote system should still be reachable"
+    );
+
+    drop(impostor);
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn data_before_start_is_rejected() {
+    let mut network_config = NetworkConfig::default();
+    network_config.set_auth_secret("kompact test secret");
+    let system = system_from_network_config(network_config);
+    let probe: TestProbe<u64> = TestProbe::new(&system);
+    let probe_path = probe.register();
+
+    // A host without the secret skips the handshake and sends a message right away
+    let mut payload = BytesMut::with_capacity(1024);
+    probe_path
+        .serialise(&mut payload)
+        .expect("serialise source");
+    probe_path
+        .serialise(&mut payload)
+        .expect("serialise destination");
+    payload.put_u8(0); // no trace context
+    payload.put_ser_id(Serialisable::ser_id(&42u64));
+    Serialisable::serialise(&42u64, &mut payload).expect("serialise message");
+    let mut frame = BytesMut::with_capacity(FRAME_HEAD_LEN as usize + payload.len());
+    frame.put_u32(MAGIC_NUM);
+    frame.put_u32(payload.len() as u32);
+    frame.put_u8(FrameType::Data as u8);
+    frame.extend_from_slice(&payload);
+    let target = system.system_path().socket_address().expect("ip address");
+    let mut impostor = TcpStream::connect(target).expect("connect");
+    impostor.write_all(&frame).expect("send Data");
+
+    probe.expect_no_msg(Duration::from_millis(1000));
+
+    drop(impostor);
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
 #[test]
 // Sets up two KompactSystems, one with a BigPinger and one with a BigPonger.
 // BigPonger will validate the BigPing messages on reception, BigPinger counts replies
@@ -1052,3 +1821,122 @@ fn remote_forwarding_named() {
         .shutdown()
         .expect("Kompact didn't shut down properly");
 }
+
+// Same as remote_delivery_to_registered_actors_lazy, but over an in-memory LoopbackFabric,
+// so no sockets are bound and the test only waits as long as it has to.
+#[test]
+fn loopback_delivery_to_registered_actors_lazy() {
+    let fabric = LoopbackFabric::new();
+    let system = system_from_network_config(fabric.config());
+    let remote = system_from_network_config(fabric.config());
+
+    let (ponger_unique, pouf) = remote.create_and_register(PongerAct::new_lazy);
+    let (ponger_named, ponf) = remote.create_and_register(PongerAct::new_lazy);
+    let poaf = remote.register_by_alias(&ponger_named, "custom_name");
+
+    let unique_path = pouf.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    ponf.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    let named_path = poaf.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+
+    let (pinger_unique, piuf) =
+        system.create_and_register(move || PingerAct::new_lazy(unique_path));
+    let (pinger_named, pinf) = system.create_and_register(move || PingerAct::new_lazy(named_path));
+
+    piuf.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+    pinf.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+
+    remote.start(&ponger_unique);
+    remote.start(&ponger_named);
+    system.start(&pinger_unique);
+    system.start(&pinger_named);
+
+    assert!(
+        wait_until(Duration::from_millis(5000), || {
+            pinger_unique.on_definition(|c| c.count) == PING_COUNT
+                && pinger_named.on_definition(|c| c.count) == PING_COUNT
+        }),
+        "Pingers did not receive all pongs"
+    );
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+// Same as remote_delivery_bigger_than_buffer_messages_preserialised_tcp, but over an in-memory LoopbackFabric.
+#[test]
+fn loopback_delivery_bigger_than_buffer_messages_preserialised() {
+    let fabric = LoopbackFabric::new();
+    let mut buf_cfg = BufferConfig::default();
+    buf_cfg.chunk_size(128);
+    let mut net_cfg = fabric.config();
+    net_cfg.set_buffer_config(buf_cfg.clone());
+    let system = system_from_network_config(net_cfg.clone());
+    let remote = system_from_network_config(net_cfg);
+
+    let (ponger_named, ponf) =
+        remote.create_and_register(|| BigPongerAct::new_eager(buf_cfg.clone()));
+    let poaf = remote.register_by_alias(&ponger_named, "custom_name");
+    let _ = ponf.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+    let ponger_named_path =
+        poaf.wait_expect(Duration::from_millis(1000), "Ponger failed to register!");
+
+    let (pinger_named, pinf) = system.create_and_register(move || {
+        BigPingerAct::new_preserialised(ponger_named_path, 120, buf_cfg.clone())
+    });
+
+    pinf.wait_expect(Duration::from_millis(1000), "Pinger failed to register!");
+
+    remote.start(&ponger_named);
+    system.start(&pinger_named);
+
+    assert!(
+        wait_until(Duration::from_millis(5000), || pinger_named
+            .on_definition(|c| c.count)
+            == PING_COUNT),
+        "Pinger did not receive all pongs"
+    );
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
+
+#[test]
+fn loopback_watch_notified_when_unreachable() {
+    let fabric = LoopbackFabric::new();
+    let mut net_cfg = fabric.config();
+    net_cfg.set_unreachable_timeout(500);
+    let system = system_from_network_config(net_cfg);
+    let remote = system_from_network_config(fabric.config());
+    let lost_path: ActorPath = remote
+        .system_path()
+        .into_named_with_string("lost")
+        .expect("a proper path")
+        .into();
+    remote
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+
+    let watched = lost_path.clone();
+    let watcher = system.create(move || WatcherAct::new(watched));
+    system.start(&watcher);
+
+    thread::sleep(Duration::from_millis(100));
+    watcher.on_definition(|c| assert!(c.terminated.is_empty()));
+    assert!(
+        wait_until(Duration::from_millis(5000), || watcher
+            .on_definition(|c| c.terminated == vec![lost_path.clone()])),
+        "Watched actor was never considered terminated"
+    );
+
+    system
+        .shutdown()
+        .expect("Kompact didn't shut down properly");
+}
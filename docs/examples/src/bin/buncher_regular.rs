@@ -118,8 +118,35 @@ pub fn main() {
 mod tests {
     use super::*;
 
+    use kompact::testkit::{PortProbe, Simulation};
+
     #[test]
     fn test_buncher() {
         main();
     }
+
+    // ANCHOR: virtual_time
+    #[test]
+    fn test_buncher_timeout() {
+        let simulation = Simulation::new(0);
+        let system = simulation.system();
+        let buncher = system.create(move || Buncher::new(100, Duration::from_millis(150)));
+        let probe: PortProbe<Batching> = PortProbe::new(system);
+        probe.connect_to_provider(&buncher);
+        system.start(&buncher);
+        simulation.run_until_idle();
+
+        for i in 0..10 {
+            probe.trigger_request(Ping(i));
+        }
+        simulation.run_until_idle();
+        probe.expect_no_indication(Duration::from_millis(0));
+
+        // no need to actually wait for the timeout in virtual time
+        simulation.advance(Duration::from_millis(150));
+        let batch = probe.expect_indication(Duration::from_millis(0));
+        assert_eq!(10, batch.0.len());
+        simulation.shutdown();
+    }
+    // ANCHOR_END: virtual_time
 }
@@ -0,0 +1,100 @@
+#![allow(clippy::unused_unit)]
+use kompact::prelude::*;
+use std::time::Duration;
+
+// ANCHOR: printer
+#[derive(ComponentDefinition, Actor)]
+struct LivenessPrinter {
+    ctx: ComponentContext<Self>,
+    fd_port: RequiredPort<FailureDetection>,
+    monitored: Vec<SystemPath>,
+}
+impl LivenessPrinter {
+    fn new(monitored: Vec<SystemPath>) -> Self {
+        LivenessPrinter {
+            ctx: ComponentContext::uninitialised(),
+            fd_port: RequiredPort::uninitialised(),
+            monitored,
+        }
+    }
+}
+
+impl ComponentLifecycle for LivenessPrinter {
+    fn on_start(&mut self) -> Handled {
+        for system in self.monitored.iter() {
+            self.fd_port.trigger(Monitoring::Monitor(system.clone()));
+        }
+        Handled::Ok
+    }
+}
+
+impl Require<FailureDetection> for LivenessPrinter {
+    fn handle(&mut self, event: Liveness) -> Handled {
+        match event {
+            Liveness::Suspect(system) => info!(self.log(), "Suspecting {}.", system),
+            Liveness::Alive(system) => info!(self.log(), "{} is alive.", system),
+        }
+        Handled::Ok
+    }
+}
+// ANCHOR_END: printer
+
+// ANCHOR: main
+pub fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    assert_eq!(
+        2,
+        args.len(),
+        "Invalid arguments! Must give number of systems."
+    );
+    let num_systems: usize = args[1].parse().expect("number");
+    run_systems(num_systems);
+}
+
+pub fn run_systems(num_systems: usize) {
+    let mut fd_config = FailureDetectorConfig::default();
+    fd_config.set_heartbeat_interval(100);
+    fd_config.set_first_heartbeat_estimate(100);
+    fd_config.set_acceptable_heartbeat_pause(200);
+
+    let mut systems: Vec<KompactSystem> = (0..num_systems)
+        .map(|_i| {
+            let mut cfg = KompactConfig::new();
+            cfg.system_components(DeadletterBox::new, NetworkConfig::default().build());
+            cfg.failure_detector(fd_config.clone());
+            cfg.build().expect("KompactSystem")
+        })
+        .collect();
+    let paths: Vec<SystemPath> = systems.iter().map(|sys| sys.system_path()).collect();
+
+    for sys in systems.iter() {
+        let own_path = sys.system_path();
+        let others: Vec<SystemPath> = paths
+            .iter()
+            .filter(|path| **path != own_path)
+            .cloned()
+            .collect();
+        let printer = sys.create(move || LivenessPrinter::new(others));
+        let detector = sys.failure_detector().expect("failure detector");
+        biconnect_components::<FailureDetection, _, _>(detector, &printer).expect("connection");
+        sys.start(&printer);
+    }
+    // let them settle
+    std::thread::sleep(Duration::from_millis(1000));
+    // shut down systems one by one
+    for sys in systems.drain(..) {
+        std::thread::sleep(Duration::from_millis(1000));
+        sys.shutdown().expect("shutdown");
+    }
+}
+// ANCHOR_END: main
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_detection() {
+        run_systems(3);
+    }
+}